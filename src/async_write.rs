@@ -0,0 +1,234 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+// Writing multipart bodies to a tokio AsyncWrite.  The nodes are first laid out as the
+// segments of the body, which are then written one after another, so that the delimiters and
+// headers are made by the same code as for the blocking writers.
+
+use super::{check_boundaries, file_headers, hex_digest, nested_multipart, write_headers};
+use super::{Error, Node, WriteProfile, SHA256_TRAILER};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::future::poll_fn;
+use std::io::{self, Read};
+use std::path::Path;
+use std::pin::Pin;
+use tokio::io::AsyncWrite;
+
+// How much of a file to read at a time
+const FILE_BLOCK_SIZE: usize = 64 * 1024;
+
+// A piece of the body: delimiters and headers, made up front, or the content of a part
+enum Segment<'a> {
+    Framing(Vec<u8>),
+    Body(&'a [u8]),
+    File(&'a Path),
+}
+
+/// Like `write_multipart()`, but writing to an `AsyncWrite`, so that a body can be streamed
+/// into a socket within an async runtime.  Only the delimiters and headers are made ahead of
+/// time; part bodies are written from where they are, and files a block at a time, read with
+/// blocking reads.  The writer is flushed at the end.
+/// Returns the number of bytes written, or an error.
+pub async fn write_multipart_async<W: AsyncWrite + Unpin + ?Sized>(
+    writer: &mut W,
+    boundary: &[u8],
+    nodes: &[Node],
+) -> Result<usize, Error> {
+    write_multipart_async_with(writer, boundary, nodes, &WriteProfile::default()).await
+}
+
+/// Like `write_multipart_async()`, but emitting the body as directed by the `WriteProfile`
+/// given.
+/// Returns the number of bytes written, or an error.
+pub async fn write_multipart_async_with<W: AsyncWrite + Unpin + ?Sized>(
+    writer: &mut W,
+    boundary: &[u8],
+    nodes: &[Node],
+    profile: &WriteProfile,
+) -> Result<usize, Error> {
+    let segments = segments(boundary, nodes, profile)?;
+    let mut out = Output {
+        writer,
+        chunked: false,
+        digest: None,
+        count: 0,
+    };
+    out.write_segments(&segments).await?;
+    flush(out.writer).await?;
+    Ok(out.count)
+}
+
+/// Like `write_multipart_chunked()`, but writing to an `AsyncWrite`, as
+/// `write_multipart_async()` does.
+pub async fn write_multipart_chunked_async<W: AsyncWrite + Unpin + ?Sized>(
+    writer: &mut W,
+    boundary: &[u8],
+    nodes: &[Node],
+) -> Result<(), Error> {
+    write_multipart_chunked_async_with(writer, boundary, nodes, &WriteProfile::default()).await
+}
+
+/// Like `write_multipart_chunked_async()`, but emitting the body as directed by the
+/// `WriteProfile` given.
+pub async fn write_multipart_chunked_async_with<W: AsyncWrite + Unpin + ?Sized>(
+    writer: &mut W,
+    boundary: &[u8],
+    nodes: &[Node],
+    profile: &WriteProfile,
+) -> Result<(), Error> {
+    let segments = segments(boundary, nodes, profile)?;
+    let mut out = Output {
+        writer,
+        chunked: true,
+        digest: profile.sha256_trailer.then(Sha256::new),
+        count: 0,
+    };
+    out.write_segments(&segments).await?;
+
+    // Write an empty chunk to signal the end of the body, followed by any trailer
+    let end = match out.digest.take() {
+        Some(digest) => format!("0\r\n{}: {}\r\n\r\n", SHA256_TRAILER, hex_digest(digest)),
+        None => "0\r\n\r\n".to_owned(),
+    };
+    write_all(out.writer, end.as_bytes()).await?;
+    flush(out.writer).await?;
+    Ok(())
+}
+
+// Lay out the body `write_multipart_with()` would write as segments
+fn segments<'a>(
+    boundary: &[u8],
+    nodes: &'a [Node],
+    profile: &WriteProfile,
+) -> Result<Vec<Segment<'a>>, Error> {
+    check_boundaries(Some(boundary), nodes, &mut Vec::new())?;
+    let mut segments = Vec::new();
+    add_nodes(&mut segments, boundary, nodes, profile)?;
+    if profile.final_crlf {
+        add_framing(&mut segments, b"\r\n");
+    }
+    Ok(segments)
+}
+
+fn add_nodes<'a>(
+    segments: &mut Vec<Segment<'a>>,
+    boundary: &[u8],
+    nodes: &'a [Node],
+    profile: &WriteProfile,
+) -> Result<(), Error> {
+    for node in nodes {
+        let mut framing = b"--".to_vec();
+        framing.extend(boundary);
+        framing.extend(b"\r\n");
+        match *node {
+            Node::Part(ref part) => {
+                let length = profile.content_length.then_some(part.body.len() as u64);
+                write_headers(&mut framing, &part.headers, length)?;
+                add_framing(segments, &framing);
+                segments.push(Segment::Body(&part.body));
+            }
+            Node::File(ref filepart) => {
+                let size = std::fs::metadata(&filepart.path)?.len();
+                let headers = file_headers(&filepart.headers, size, profile)?;
+                let length = profile.content_length.then_some(size);
+                write_headers(&mut framing, &headers, length)?;
+                add_framing(segments, &framing);
+                segments.push(Segment::File(&filepart.path));
+            }
+            Node::Multipart((ref headers, ref subnodes)) => {
+                let (headers, boundary) = nested_multipart(headers, profile)?;
+                write_headers(&mut framing, &headers, None)?;
+                add_framing(segments, &framing);
+                add_nodes(segments, &boundary, subnodes, profile)?;
+            }
+        }
+        add_framing(segments, b"\r\n");
+    }
+    let mut framing = b"--".to_vec();
+    framing.extend(boundary);
+    framing.extend(b"--");
+    add_framing(segments, &framing);
+    Ok(())
+}
+
+// Add delimiters or headers, joining them to any just before
+fn add_framing(segments: &mut Vec<Segment>, bytes: &[u8]) {
+    match segments.last_mut() {
+        Some(Segment::Framing(framing)) => framing.extend(bytes),
+        _ => segments.push(Segment::Framing(bytes.to_vec())),
+    }
+}
+
+// Where the segments go, chunked or not, with the digest of the content if needed for a
+// trailer
+struct Output<'w, W: ?Sized> {
+    writer: &'w mut W,
+    chunked: bool,
+    digest: Option<Sha256>,
+    count: usize,
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Output<'_, W> {
+    async fn write_segments(&mut self, segments: &[Segment<'_>]) -> Result<(), Error> {
+        for segment in segments {
+            match *segment {
+                Segment::Framing(ref bytes) => self.write(bytes).await?,
+                Segment::Body(body) => self.write(body).await?,
+                Segment::File(path) => {
+                    let mut file = File::open(path)?;
+                    let mut block = vec![0; FILE_BLOCK_SIZE];
+                    loop {
+                        let read = file.read(&mut block)?;
+                        if read == 0 {
+                            break;
+                        }
+                        self.write(&block[..read]).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        // An empty chunk would end the body
+        if data.is_empty() {
+            return Ok(());
+        }
+        if self.chunked {
+            write_all(self.writer, format!("{:x}\r\n", data.len()).as_bytes()).await?;
+        }
+        write_all(self.writer, data).await?;
+        if self.chunked {
+            write_all(self.writer, b"\r\n").await?;
+        }
+        if let Some(ref mut digest) = self.digest {
+            digest.update(data);
+        }
+        self.count += data.len();
+        Ok(())
+    }
+}
+
+async fn write_all<W: AsyncWrite + Unpin + ?Sized>(
+    writer: &mut W,
+    mut data: &[u8],
+) -> io::Result<()> {
+    while !data.is_empty() {
+        let written = poll_fn(|cx| Pin::new(&mut *writer).poll_write(cx, data)).await?;
+        if written == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        data = &data[written..];
+    }
+    Ok(())
+}
+
+async fn flush<W: AsyncWrite + Unpin + ?Sized>(writer: &mut W) -> io::Result<()> {
+    poll_fn(|cx| Pin::new(&mut *writer).poll_flush(cx)).await
+}
@@ -0,0 +1,97 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{ContentDisposition, DispositionType, Error, Node};
+use http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use mime::Mime;
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+
+/// A file-like part found by `extract_attachments()`.
+#[derive(Clone, Debug)]
+pub struct Attachment<'a> {
+    /// Where the part is: the index of the node, then of the node within it, and so on
+    pub path: Vec<usize>,
+    /// The part itself, either a `Node::Part` or a `Node::File`
+    pub node: &'a Node,
+    /// The filename given by the part's `Content-Disposition`, if any
+    pub filename: Option<String>,
+    /// The part's `Content-Type`, if any and valid
+    pub content_type: Option<Mime>,
+}
+
+impl<'a> Attachment<'a> {
+    /// The size of the content in bytes.  The file of a file part is looked at if its size
+    /// was not recorded when it was parsed.
+    pub fn size(&self) -> Result<u64, Error> {
+        match *self.node {
+            Node::Part(ref part) => Ok(part.body.len() as u64),
+            Node::File(ref filepart) => match filepart.size {
+                Some(size) => Ok(size as u64),
+                None => Ok(std::fs::metadata(&filepart.path)?.len()),
+            },
+            Node::Multipart(_) => unreachable!(),
+        }
+    }
+
+    /// A reader over the content, opening the file of a file part.
+    pub fn reader(&self) -> Result<Box<dyn Read + 'a>, Error> {
+        match *self.node {
+            Node::Part(ref part) => Ok(Box::new(&part.body[..])),
+            Node::File(ref filepart) => Ok(Box::new(File::open(&filepart.path)?)),
+            Node::Multipart(_) => unreachable!(),
+        }
+    }
+}
+
+/// List the attachments among `nodes`, at any depth of nesting, in order: every part which
+/// was streamed to a file, has a filename, or has an `attachment` `Content-Disposition`.
+/// This gives mail processing and virus scanning an inventory of the files a message
+/// carries in one call.
+pub fn extract_attachments(nodes: &[Node]) -> Vec<Attachment<'_>> {
+    let mut attachments = Vec::new();
+    walk(nodes, &mut Vec::new(), &mut attachments);
+    attachments
+}
+
+fn walk<'a>(nodes: &'a [Node], path: &mut Vec<usize>, attachments: &mut Vec<Attachment<'a>>) {
+    for (index, node) in nodes.iter().enumerate() {
+        path.push(index);
+        let headers = node.headers();
+        let disposition = headers
+            .get(CONTENT_DISPOSITION)
+            .and_then(|cd| cd.to_str().ok())
+            .map(ContentDisposition::parse);
+        let filename = disposition
+            .as_ref()
+            .and_then(ContentDisposition::decoded_filename);
+        let is_attachment = match *node {
+            Node::Multipart((_, ref subnodes)) => {
+                walk(subnodes, path, attachments);
+                false
+            }
+            Node::File(_) => true,
+            Node::Part(_) => {
+                filename.is_some()
+                    || disposition.is_some_and(|cd| cd.disposition == DispositionType::Attachment)
+            }
+        };
+        if is_attachment {
+            attachments.push(Attachment {
+                path: path.clone(),
+                node,
+                filename,
+                content_type: headers
+                    .get(CONTENT_TYPE)
+                    .and_then(|ct| ct.to_str().ok())
+                    .and_then(|ct| Mime::from_str(ct).ok()),
+            });
+        }
+        path.pop();
+    }
+}
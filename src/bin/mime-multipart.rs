@@ -0,0 +1,269 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Command line tool for taking apart multipart documents, such as captured HTTP requests
+//! or mail bodies.  Build with `--features cli`.
+
+use http::header::{HeaderMap, HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE};
+use mime_multipart_hyper1::{
+    generate_boundary, multipart_content_type, read_multipart, read_multipart_body,
+    write_multipart, Error, FilePart, Node,
+};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage:
+  mime-multipart split [-o DIR] [-b BOUNDARY] [FILE]
+  mime-multipart compose [-o FILE] [-t SUBTYPE] [--body-only] (DIR | MANIFEST)
+
+Commands:
+  split   Extract each part of a multipart document into DIR (default: the current
+          directory), along with a manifest.txt listing the headers of every part.
+          The document is read from FILE, or from stdin if FILE is omitted or '-'.
+          It must start with its headers (an HTTP request or status line before them
+          is skipped), unless the boundary is given with -b.
+  compose Build a multipart/SUBTYPE document (default: form-data) and write it to FILE,
+          or to stdout if FILE is omitted or '-'.  Every file in DIR becomes a file
+          part named after it.  Alternatively, a MANIFEST lists one part per line as
+          'NAME TYPE FILE', where TYPE may be '-' to omit the Content-Type and FILE
+          is relative to the manifest; blank lines and lines starting with '#' are
+          ignored.  The document starts with its Content-Type header unless
+          --body-only is given.
+";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(|s| s.as_str()) {
+        Some("split") => split(&args[1..]),
+        Some("compose") => compose(&args[1..]),
+        Some("-h") | Some("--help") => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        _ => {
+            eprint!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("mime-multipart: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn split(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut outdir = PathBuf::from(".");
+    let mut boundary: Option<String> = None;
+    let mut input: Option<String> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => outdir = PathBuf::from(args.next().ok_or("-o requires a directory")?),
+            "-b" => boundary = Some(args.next().ok_or("-b requires a boundary")?.clone()),
+            _ if input.is_none() => input = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument '{}'", arg).into()),
+        }
+    }
+
+    let stream: Box<dyn Read> = match input.as_deref() {
+        None | Some("-") => Box::new(io::stdin()),
+        Some(path) => Box::new(File::open(path)?),
+    };
+    let mut stream = BufReader::new(stream);
+
+    let (headers, nodes) = match boundary {
+        Some(boundary) => {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                CONTENT_TYPE,
+                multipart_content_type("mixed", boundary.as_bytes())?,
+            );
+            let nodes = read_multipart_body(&mut stream, &headers, false)?;
+            (headers, nodes)
+        }
+        None => {
+            skip_start_line(&mut stream)?;
+            read_multipart(&mut stream, false)?
+        }
+    };
+
+    std::fs::create_dir_all(&outdir)?;
+    let mut manifest = File::create(outdir.join("manifest.txt"))?;
+    writeln!(manifest, "[document]")?;
+    write_headers(&mut manifest, &headers)?;
+    extract(&nodes, &outdir, "part", &mut manifest)?;
+    Ok(())
+}
+
+// Skip an HTTP request or status line, if the stream starts with one.
+fn skip_start_line<R: BufRead>(stream: &mut R) -> io::Result<()> {
+    let buf = stream.fill_buf()?;
+    let line_end = buf.iter().position(|&ch| ch == b'\n').unwrap_or(buf.len());
+    let line = &buf[..line_end];
+    let is_start_line = !line.contains(&b':')
+        || line.starts_with(b"HTTP/")
+        || line.windows(6).any(|w| w == b" HTTP/");
+    if is_start_line && line_end < buf.len() {
+        stream.consume(line_end + 1);
+    }
+    Ok(())
+}
+
+fn extract<W: Write>(
+    nodes: &[Node],
+    outdir: &Path,
+    prefix: &str,
+    manifest: &mut W,
+) -> Result<(), Error> {
+    for (index, node) in nodes.iter().enumerate() {
+        let name = format!("{}-{:03}", prefix, index + 1);
+        match *node {
+            Node::Part(ref part) => {
+                std::fs::write(outdir.join(&name), &part.body)?;
+                writeln!(manifest, "\n[{}]", name)?;
+                write_headers(manifest, &part.headers)?;
+            }
+            Node::File(ref filepart) => {
+                std::fs::copy(&filepart.path, outdir.join(&name))?;
+                writeln!(manifest, "\n[{}]", name)?;
+                write_headers(manifest, &filepart.headers)?;
+            }
+            Node::Multipart((ref headers, ref subnodes)) => {
+                writeln!(manifest, "\n[{}/]", name)?;
+                write_headers(manifest, headers)?;
+                extract(subnodes, outdir, &name, manifest)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_headers<W: Write>(manifest: &mut W, headers: &HeaderMap) -> io::Result<()> {
+    for (name, value) in headers.iter() {
+        manifest.write_all(name.as_str().as_bytes())?;
+        manifest.write_all(b": ")?;
+        manifest.write_all(value.as_bytes())?;
+        manifest.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+fn compose(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut output: Option<String> = None;
+    let mut subtype = "form-data".to_owned();
+    let mut body_only = false;
+    let mut input: Option<String> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(args.next().ok_or("-o requires a file")?.clone()),
+            "-t" => subtype = args.next().ok_or("-t requires a subtype")?.clone(),
+            "--body-only" => body_only = true,
+            _ if input.is_none() => input = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument '{}'", arg).into()),
+        }
+    }
+    let input = PathBuf::from(input.ok_or("compose requires a directory or manifest")?);
+
+    // Collect (name, content type, file) for each part
+    let mut entries: Vec<(String, Option<String>, PathBuf)> = Vec::new();
+    if input.is_dir() {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(&input)? {
+            let path = entry?.path();
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        for path in paths {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            entries.push((name, None, path));
+        }
+    } else {
+        let base = input.parent().unwrap_or(Path::new("."));
+        let manifest = std::fs::read_to_string(&input)?;
+        for (number, line) in manifest.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err(
+                    format!("manifest line {}: expected NAME TYPE FILE", number + 1).into(),
+                );
+            }
+            let content_type = match fields[1] {
+                "-" => None,
+                ct => Some(ct.to_owned()),
+            };
+            entries.push((fields[0].to_owned(), content_type, base.join(fields[2])));
+        }
+    }
+
+    let mut nodes: Vec<Node> = Vec::new();
+    for (name, content_type, path) in entries {
+        let filename = path
+            .file_name()
+            .ok_or("manifest entry has no file name")?
+            .to_string_lossy();
+        let disposition = match subtype.as_str() {
+            "form-data" => format!(
+                "form-data; name={}; filename={}",
+                quote(&name),
+                quote(&filename)
+            ),
+            _ => format!("attachment; filename={}", quote(&filename)),
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_DISPOSITION, HeaderValue::from_str(&disposition)?);
+        if let Some(content_type) = content_type {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_str(&content_type)?);
+        }
+        nodes.push(Node::File(FilePart::new(headers, &path)));
+    }
+
+    let mut stream: Box<dyn Write> = match output.as_deref() {
+        None | Some("-") => Box::new(io::stdout()),
+        Some(path) => Box::new(File::create(path)?),
+    };
+    let mut stream = io::BufWriter::new(&mut stream);
+
+    let boundary = generate_boundary();
+    if !body_only {
+        let content_type = multipart_content_type(&subtype, &boundary)?;
+        stream.write_all(b"Content-Type: ")?;
+        stream.write_all(content_type.as_bytes())?;
+        stream.write_all(b"\r\n\r\n")?;
+    }
+    write_multipart(&mut stream, &boundary, &nodes)?;
+    stream.flush()?;
+    Ok(())
+}
+
+// A quoted-string holding `value`, so that it cannot end the parameter early.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for ch in value.chars() {
+        if ch == '"' || ch == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(ch);
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,135 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A budget of bytes which may be spooled to disk, shared by any number of concurrent
+/// parses.  Clones of a `DiskBudget` share the same budget, so a server can create one and
+/// hand a clone to the `ParseProfile` of every request to bound the total size of spooled
+/// uploads across all requests in flight.
+///
+/// Bytes are taken from the budget as file parts are written, and returned to it when the
+/// resulting `FilePart`s are dropped.  A parse which would exceed the budget fails with
+/// `Error::DiskBudgetExceeded`.
+#[derive(Clone)]
+pub struct DiskBudget {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    limit: u64,
+    used: AtomicU64,
+}
+
+impl DiskBudget {
+    /// Create a budget of `limit` bytes.
+    pub fn new(limit: u64) -> DiskBudget {
+        DiskBudget {
+            inner: Arc::new(Inner {
+                limit,
+                used: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// The total number of bytes in the budget.
+    pub fn limit(&self) -> u64 {
+        self.inner.limit
+    }
+
+    /// The number of bytes currently held by file parts.
+    pub fn used(&self) -> u64 {
+        self.inner.used.load(Ordering::Acquire)
+    }
+
+    /// The number of bytes still available.
+    pub fn available(&self) -> u64 {
+        self.inner.limit.saturating_sub(self.used())
+    }
+
+    // Take `bytes` from the budget, if that many are available
+    #[cfg(feature = "parse")]
+    fn try_take(&self, bytes: u64) -> bool {
+        let mut used = self.inner.used.load(Ordering::Acquire);
+        loop {
+            let new = match used.checked_add(bytes) {
+                Some(new) if new <= self.inner.limit => new,
+                _ => return false,
+            };
+            match self.inner.used.compare_exchange_weak(
+                used,
+                new,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => used = actual,
+            }
+        }
+    }
+
+    fn give_back(&self, bytes: u64) {
+        self.inner.used.fetch_sub(bytes, Ordering::AcqRel);
+    }
+}
+
+impl PartialEq for DiskBudget {
+    fn eq(&self, other: &DiskBudget) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl fmt::Debug for DiskBudget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DiskBudget")
+            .field("limit", &self.limit())
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+// Bytes taken from a `DiskBudget` on behalf of one file part, given back on drop.  A clone
+// holds no bytes, so that cloned `FilePart`s do not give back the same bytes twice.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Reservation {
+    budget: DiskBudget,
+    bytes: u64,
+}
+
+impl Reservation {
+    pub(crate) fn new(budget: &DiskBudget) -> Reservation {
+        Reservation {
+            budget: budget.clone(),
+            bytes: 0,
+        }
+    }
+
+    // Take `bytes` more from the budget, if that many are available
+    #[cfg(feature = "parse")]
+    pub(crate) fn grow(&mut self, bytes: u64) -> bool {
+        if self.budget.try_take(bytes) {
+            self.bytes += bytes;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Clone for Reservation {
+    fn clone(&self) -> Reservation {
+        Reservation::new(&self.budget)
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.give_back(self.bytes);
+    }
+}
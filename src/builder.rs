@@ -0,0 +1,387 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+// Building the tree of nodes from input handed over piece by piece, for parsing from
+// sources which cannot be read from blockingly.
+
+#[cfg(feature = "zeroize")]
+use super::wipe;
+use super::{choose_content_type, expected_length, get_multipart_boundary, is_ignored};
+use super::{is_nested, part_disposition, part_limit, sanitize_filename, sniff_boundary};
+use super::{sync_dir_of, ContentDisposition, Disposition, Error, Event, FilePart};
+use super::{MultipartParser, Node, ParseProfile, ParseState, Part, Reservation, Scratch};
+use super::{StopAt, SNIFF_LEN, SPOOL_BUFFER_SIZE};
+use http::header::{HeaderMap, CONTENT_DISPOSITION};
+#[cfg(feature = "sha2")]
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Parses a whole body handed over piece by piece, doing what `parse_body()` does around the
+// parsing of the multipart itself: checking the length against the `Content-Length`, and
+// saying how much more to read once the close delimiter or the `required_fields` are reached.
+pub(crate) struct BodyParser<'p> {
+    state: ParseState<'p>,
+    builder: NodeBuilder,
+    expected: Option<u64>,
+    fed: u64,
+}
+
+impl<'p> BodyParser<'p> {
+    pub(crate) fn new(
+        headers: &HeaderMap,
+        profile: &'p ParseProfile,
+    ) -> Result<BodyParser<'p>, Error> {
+        let state = ParseState::new(profile);
+        let builder = NodeBuilder::new(headers, &state, 0)?;
+        Ok(BodyParser {
+            state,
+            builder,
+            expected: expected_length(headers, profile),
+            fed: 0,
+        })
+    }
+
+    // How many more bytes of the body to read at most, or None for all there are.  With a
+    // `Content-Length` to check, nothing past it is read.
+    pub(crate) fn wanted(&self) -> Option<u64> {
+        let stop_at = self.state.profile.stop_at;
+        match self.expected {
+            _ if self.state.stopped && stop_at == StopAt::Eof => None,
+            _ if self.state.stopped => Some(0),
+            Some(expected) => Some(expected - self.fed),
+            None if self.builder.finished && stop_at != StopAt::Eof => Some(0),
+            None => None,
+        }
+    }
+
+    // Parse the next piece of the body
+    pub(crate) fn feed(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.fed += data.len() as u64;
+        if self.state.stopped {
+            // Only being drained
+            return Ok(());
+        }
+        if self.expected.is_some_and(|expected| self.fed > expected) {
+            return Err(Error::ContentLengthMismatch);
+        }
+        self.builder.feed(data, &mut self.state)
+    }
+
+    // Parse what is left at the end of the body, returning the nodes
+    pub(crate) fn finish(mut self) -> Result<Vec<Node>, Error> {
+        if self.state.stopped {
+            return Ok(self.builder.nodes);
+        }
+        let expected = self.expected;
+        let short = expected.is_some_and(|expected| self.fed < expected);
+        match self.builder.finish(&mut self.state) {
+            // The multipart does not end within the length
+            Err(Error::EofBeforeFirstBoundary)
+            | Err(Error::EofInPartHeaders)
+            | Err(Error::EofInFile)
+            | Err(Error::EofInPart)
+                if expected.is_some() =>
+            {
+                Err(Error::ContentLengthMismatch)
+            }
+            Ok(_) if short => Err(Error::ContentLengthMismatch),
+            result => result,
+        }
+    }
+}
+
+// Where the body of the part being read goes
+enum Current {
+    None,
+    Skip,
+    Memory(HeaderMap, Vec<u8>),
+    // A spooled file, with the digest of its content so far when deduplicating
+    File {
+        filepart: FilePart,
+        spool: BufWriter<File>,
+        #[cfg(feature = "sha2")]
+        digest: Option<Box<Sha256>>,
+    },
+    Nested(HeaderMap, Box<NodeBuilder>),
+}
+
+// Parses input fed to it into nodes, placing part bodies as `read_multipart_body_with()`
+// does.  Nested multiparts are parsed by builders of their own, fed with the body of the part
+// containing them.
+pub(crate) struct NodeBuilder {
+    // None while the boundary is still to be sniffed from the start of the body, which is
+    // kept in `sniffed` until there is enough of it
+    parser: Option<MultipartParser>,
+    sniffed: Vec<u8>,
+    depth: usize,
+    nodes: Vec<Node>,
+    current: Current,
+    // The bytes of the current part's body so far, and the limit on them
+    written: u64,
+    part_limit: Option<(u64, Error)>,
+    finished: bool,
+}
+
+impl NodeBuilder {
+    // A builder for the body of a request or part with the given headers
+    pub(crate) fn new(
+        headers: &HeaderMap,
+        state: &ParseState,
+        depth: usize,
+    ) -> Result<NodeBuilder, Error> {
+        let profile = state.profile;
+        let chosen = choose_content_type(headers, profile.content_type_choice)?;
+        let headers = chosen.as_ref().unwrap_or(headers);
+        let parser = match get_multipart_boundary(headers) {
+            Err(Error::BoundaryNotSpecified) if profile.sniff_boundary => None,
+            _ => Some(MultipartParser::from_headers(headers, profile)?),
+        };
+        Ok(NodeBuilder {
+            parser,
+            sniffed: Vec::new(),
+            depth,
+            nodes: Vec::new(),
+            current: Current::None,
+            written: 0,
+            part_limit: None,
+            finished: false,
+        })
+    }
+
+    // Parse the next piece of input
+    pub(crate) fn feed(&mut self, data: &[u8], state: &mut ParseState) -> Result<(), Error> {
+        match self.parser {
+            Some(ref mut parser) => parser.feed(data),
+            None => {
+                self.sniffed.extend_from_slice(data);
+                if self.sniffed.len() < SNIFF_LEN {
+                    return Ok(());
+                }
+                self.sniff(state.profile)?;
+            }
+        }
+        self.drain(state)
+    }
+
+    // Parse what is left at the end of the input, returning the nodes
+    pub(crate) fn finish(mut self, state: &mut ParseState) -> Result<Vec<Node>, Error> {
+        if self.parser.is_none() {
+            self.sniff(state.profile)?;
+        }
+        if let Some(ref mut parser) = self.parser {
+            parser.feed_eof();
+        }
+        self.drain(state)?;
+        match self.finished {
+            true => Ok(self.nodes),
+            false => Err(Error::EofInPart),
+        }
+    }
+
+    // Take the boundary from the start of the body, and parse that by it
+    fn sniff(&mut self, profile: &ParseProfile) -> Result<(), Error> {
+        let boundary = sniff_boundary(&self.sniffed).ok_or(Error::BoundaryNotSpecified)?;
+        record!(
+            debug,
+            "no boundary in the Content-Type, sniffed {:?}",
+            boundary
+        );
+        if profile
+            .limits
+            .max_boundary_len
+            .is_some_and(|max| boundary.len() > max)
+        {
+            return Err(Error::BoundaryTooLong);
+        }
+        let mut parser = MultipartParser::with_profile(boundary.as_bytes(), profile);
+        parser.feed(&std::mem::take(&mut self.sniffed));
+        self.parser = Some(parser);
+        Ok(())
+    }
+
+    fn drain(&mut self, state: &mut ParseState) -> Result<(), Error> {
+        while !self.finished {
+            let event = match self.parser {
+                Some(ref mut parser) => parser.next_event()?,
+                None => None,
+            };
+            match event {
+                None => break,
+                Some(Event::PartHeaders(headers)) => self.start_part(headers, state)?,
+                Some(Event::BodyChunk(chunk)) => self.body_chunk(&chunk, state)?,
+                Some(Event::PartEnd) => self.end_part(state)?,
+                Some(Event::Finished) => self.finished = true,
+            }
+        }
+        Ok(())
+    }
+
+    fn start_part(&mut self, headers: HeaderMap, state: &mut ParseState) -> Result<(), Error> {
+        let profile = state.profile;
+        state.add_part()?;
+        self.written = 0;
+        if is_nested(&headers)? && !is_ignored(&headers, profile) {
+            if profile
+                .limits
+                .max_depth
+                .is_some_and(|max| self.depth + 1 > max)
+            {
+                return Err(Error::NestingTooDeep);
+            }
+            let nested = NodeBuilder::new(&headers, state, self.depth + 1)?;
+            self.current = Current::Nested(headers, Box::new(nested));
+            return Ok(());
+        }
+
+        let disposition = part_disposition(&headers, profile)?;
+        state.add_disposition(disposition)?;
+        self.part_limit = part_limit(profile, disposition, &headers);
+        self.current = match disposition {
+            Disposition::Skip => Current::Skip,
+            Disposition::Memory => Current::Memory(headers, Vec::new()),
+            Disposition::File => {
+                let name = match headers.get(CONTENT_DISPOSITION) {
+                    Some(cd) if profile.original_filenames => ContentDisposition::from_header(cd)?
+                        .form_param("filename", profile.html_names)
+                        .and_then(|name| sanitize_filename(&name)),
+                    _ => None,
+                };
+                let (filepart, spool) = spool_file(headers, name, profile)?;
+                Current::File {
+                    filepart,
+                    spool,
+                    #[cfg(feature = "sha2")]
+                    digest: profile.dedup_files.then(Box::default),
+                }
+            }
+        };
+        Ok(())
+    }
+
+    fn body_chunk(&mut self, chunk: &[u8], state: &mut ParseState) -> Result<(), Error> {
+        if let Current::Nested(_, ref mut nested) = self.current {
+            // The parts within count their own bytes
+            return nested.feed(chunk, state);
+        }
+        let size = self.written + chunk.len() as u64;
+        if state
+            .body_limit(&self.part_limit)
+            .is_some_and(|max| size > max)
+        {
+            return Err(state.body_limit_error(size, self.part_limit.take()));
+        }
+        self.written = size;
+        state.body_bytes += chunk.len() as u64;
+        if let Current::Memory(_, ref body) = self.current {
+            let over = |max: usize| body.len() + chunk.len() > max;
+            if state.profile.spill_threshold.is_some_and(over) {
+                self.spill(state.profile)?;
+            }
+        }
+        match self.current {
+            #[cfg(feature = "zeroize")]
+            Current::Memory(_, ref mut body) => wipe::Wiping(body).write_all(chunk)?,
+            #[cfg(not(feature = "zeroize"))]
+            Current::Memory(_, ref mut body) => body.extend_from_slice(chunk),
+            Current::File {
+                ref mut filepart,
+                ref mut spool,
+                #[cfg(feature = "sha2")]
+                ref mut digest,
+            } => {
+                spool_write(filepart, spool, chunk)?;
+                #[cfg(feature = "sha2")]
+                if let Some(ref mut digest) = digest {
+                    digest.update(chunk);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Move the body of the part kept in memory so far into a file, spooled as file parts are
+    fn spill(&mut self, profile: &ParseProfile) -> Result<(), Error> {
+        if let Current::Memory(headers, body) = std::mem::replace(&mut self.current, Current::None)
+        {
+            // Wiped on drop, with the `zeroize` feature
+            let body = Scratch(body);
+            let (mut filepart, mut spool) = spool_file(headers, None, profile)?;
+            spool_write(&mut filepart, &mut spool, &body)?;
+            self.current = Current::File {
+                filepart,
+                spool,
+                #[cfg(feature = "sha2")]
+                digest: None,
+            };
+        }
+        Ok(())
+    }
+
+    fn end_part(&mut self, state: &mut ParseState) -> Result<(), Error> {
+        let node = match std::mem::replace(&mut self.current, Current::None) {
+            Current::None | Current::Skip => return Ok(()),
+            Current::Memory(headers, body) => Node::Part(Part { headers, body }),
+            Current::File {
+                mut filepart,
+                spool,
+                #[cfg(feature = "sha2")]
+                digest,
+            } => {
+                let file = spool.into_inner().map_err(|err| err.into_error())?;
+                filepart.size = Some(self.written as usize);
+                if state.profile.sync_files {
+                    file.sync_all()?;
+                }
+                drop(file);
+                #[cfg(feature = "sha2")]
+                if let Some(digest) = digest {
+                    state.dedup(&mut filepart, digest.finalize().into());
+                }
+                if state.profile.sync_files {
+                    sync_dir_of(&filepart.path)?;
+                }
+                Node::File(filepart)
+            }
+            Current::Nested(headers, nested) => Node::Multipart((headers, nested.finish(state)?)),
+        };
+        self.nodes.push(node);
+        if self.depth == 0 && state.collected(self.nodes.last()) {
+            self.finished = true;
+        }
+        Ok(())
+    }
+}
+
+// Create the file a part's body is spooled to, as the profile says
+fn spool_file(
+    headers: HeaderMap,
+    name: Option<String>,
+    profile: &ParseProfile,
+) -> Result<(FilePart, BufWriter<File>), Error> {
+    let (mut filepart, file) = profile.spool.create(headers, name)?;
+    filepart.shred = profile.shred_files;
+    filepart.cleanup_hook = profile.cleanup_hook.clone();
+    filepart.reservation = profile.disk_budget.as_ref().map(Reservation::new);
+    Ok((filepart, BufWriter::with_capacity(SPOOL_BUFFER_SIZE, file)))
+}
+
+// Append to a spooled file, within its disk budget
+fn spool_write(
+    filepart: &mut FilePart,
+    spool: &mut BufWriter<File>,
+    data: &[u8],
+) -> Result<(), Error> {
+    if let Some(ref mut reservation) = filepart.reservation {
+        if !reservation.grow(data.len() as u64) {
+            return Err(Error::DiskBudgetExceeded);
+        }
+    }
+    spool.write_all(data)?;
+    Ok(())
+}
@@ -0,0 +1,186 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::frames::{TrailerInfo, Trailers};
+use super::{generate_boundary, multipart_content_type, write_headers};
+use super::{ContentRange, Error};
+use bytes::Bytes;
+use http::header::{HeaderMap, HeaderValue};
+use http::header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE};
+use http::{Response, StatusCode};
+use http_body::{Body, Frame, SizeHint};
+use mime::Mime;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::RangeInclusive;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// The most read from the file for a single frame
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Build a `206 Partial Content` response serving the `ranges` of `file`, whose content is
+/// of type `content_type`.  A single range is served as is, with a `Content-Range` header;
+/// several are served as a `multipart/byteranges` body.  `Content-Length` is always set.
+///
+/// The ranges are inclusive, as in a `Range` header, and must lie within the file.  They
+/// are served in the order given.  `RangePlan::new()` works them out from a request's
+/// `Range` header.
+pub fn byteranges_response(
+    file: File,
+    ranges: &[RangeInclusive<u64>],
+    content_type: &Mime,
+) -> Result<Response<ByteRangesBody>, Error> {
+    let length = file.metadata()?.len();
+    let content_type = match HeaderValue::from_str(content_type.as_ref()) {
+        Ok(value) => value,
+        Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+    };
+    let content_ranges = ranges
+        .iter()
+        .map(|range| ContentRange::new(*range.start(), *range.end(), length))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut segments = VecDeque::new();
+    let response = Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(ACCEPT_RANGES, "bytes");
+    let response = match content_ranges[..] {
+        [] => return Err(Error::InvalidContentRange),
+        [range] => {
+            segments.push_back(Segment::file(&ranges[0]));
+            response
+                .header(CONTENT_TYPE, content_type)
+                .header(CONTENT_RANGE, range.to_header_value())
+        }
+        _ => {
+            let boundary = generate_boundary();
+            for (range, content_range) in ranges.iter().zip(content_ranges) {
+                // Each part's boundary and headers, preceded by the line terminator ending
+                // the previous part's content
+                let mut head = match segments.is_empty() {
+                    true => b"--".to_vec(),
+                    false => b"\r\n--".to_vec(),
+                };
+                head.extend(&boundary);
+                head.extend(b"\r\n");
+                let mut headers = HeaderMap::new();
+                headers.insert(CONTENT_TYPE, content_type.clone());
+                headers.insert(CONTENT_RANGE, content_range.to_header_value());
+                write_headers(&mut head, &headers, None)?;
+                segments.push_back(Segment::Bytes(Bytes::from(head)));
+                segments.push_back(Segment::file(range));
+            }
+            let mut tail = b"\r\n--".to_vec();
+            tail.extend(&boundary);
+            tail.extend(b"--");
+            segments.push_back(Segment::Bytes(Bytes::from(tail)));
+            response.header(
+                CONTENT_TYPE,
+                multipart_content_type("byteranges", &boundary)?,
+            )
+        }
+    };
+
+    let body = ByteRangesBody {
+        file,
+        remaining: segments.iter().map(Segment::len).sum(),
+        segments,
+        trailers: None,
+    };
+    Ok(response.header(CONTENT_LENGTH, body.remaining).body(body)?)
+}
+
+// A piece of a `ByteRangesBody`
+enum Segment {
+    Bytes(Bytes),
+    File { offset: u64, len: u64 },
+}
+
+impl Segment {
+    fn file(range: &RangeInclusive<u64>) -> Segment {
+        Segment::File {
+            offset: *range.start(),
+            len: range.end() - range.start() + 1,
+        }
+    }
+
+    fn len(&self) -> u64 {
+        match *self {
+            Segment::Bytes(ref bytes) => bytes.len() as u64,
+            Segment::File { len, .. } => len,
+        }
+    }
+}
+
+/// The body of a response built by `byteranges_response()`.  The file is read as frames are
+/// polled, at most 64 KiB at a time, using blocking reads.
+pub struct ByteRangesBody {
+    file: File,
+    segments: VecDeque<Segment>,
+    remaining: u64,
+    trailers: Option<Trailers>,
+}
+
+impl ByteRangesBody {
+    /// End the body with a trailers frame holding the headers `trailers` returns, given the
+    /// length and digest of the body sent.  The trailers should be announced with a
+    /// `Trailer` header.
+    pub fn with_trailers<F>(mut self, trailers: F) -> ByteRangesBody
+    where
+        F: FnOnce(&TrailerInfo) -> HeaderMap + Send + 'static,
+    {
+        self.trailers = Some(Trailers::new(trailers));
+        self
+    }
+}
+
+impl Body for ByteRangesBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Error>>> {
+        let this = &mut *self;
+        let data = match this.segments.pop_front() {
+            None => {
+                let trailers = this.trailers.take();
+                return Poll::Ready(trailers.map(|trailers| Ok(trailers.frame())));
+            }
+            Some(Segment::Bytes(bytes)) => bytes,
+            Some(Segment::File { offset, len }) => {
+                let read = len.min(CHUNK_SIZE);
+                let mut buf = vec![0; read as usize];
+                this.file.seek(SeekFrom::Start(offset))?;
+                this.file.read_exact(&mut buf)?;
+                if read < len {
+                    this.segments.push_front(Segment::File {
+                        offset: offset + read,
+                        len: len - read,
+                    });
+                }
+                Bytes::from(buf)
+            }
+        };
+        this.remaining -= data.len() as u64;
+        if let Some(ref mut trailers) = this.trailers {
+            trailers.update(&data);
+        }
+        Poll::Ready(Some(Ok(Frame::data(data))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.segments.is_empty() && self.trailers.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
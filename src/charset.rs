@@ -0,0 +1,135 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{Error, Part};
+use http::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use mime::Mime;
+use std::fmt;
+
+// The characters windows-1252 puts at 0x80 to 0x9F, where ISO-8859-1 has C1 controls.  The
+// five bytes it leaves undefined are given as NUL, which no character maps to here.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\0', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\0', '\u{17d}', '\0', '\0',
+    '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}', '\u{2dc}',
+    '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\0', '\u{17e}', '\u{178}',
+];
+
+/// A character set text parts can be encoded in, for receivers which do not take UTF-8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Charset {
+    /// UTF-8, which Rust strings already are
+    Utf8,
+    /// US-ASCII
+    UsAscii,
+    /// ISO-8859-1 (Latin-1), covering U+0000 to U+00FF
+    Iso8859_1,
+    /// windows-1252, ISO-8859-1 with printable characters in place of the C1 controls
+    Windows1252,
+}
+
+impl Charset {
+    /// The name of the charset, as given in a `charset` parameter
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Charset::Utf8 => "UTF-8",
+            Charset::UsAscii => "US-ASCII",
+            Charset::Iso8859_1 => "ISO-8859-1",
+            Charset::Windows1252 => "windows-1252",
+        }
+    }
+
+    /// The charset with the given name, which is case-insensitive, if it is one of these.
+    /// `latin1` and `ascii` are accepted as aliases.
+    pub fn from_name(name: &str) -> Option<Charset> {
+        match &*name.to_ascii_lowercase() {
+            "utf-8" | "utf8" => Some(Charset::Utf8),
+            "us-ascii" | "ascii" => Some(Charset::UsAscii),
+            "iso-8859-1" | "latin1" => Some(Charset::Iso8859_1),
+            "windows-1252" | "cp1252" => Some(Charset::Windows1252),
+            _ => None,
+        }
+    }
+
+    /// Decode `bytes` from this charset, or `None` if they are not valid in it.
+    pub fn decode(&self, bytes: &[u8]) -> Option<String> {
+        match *self {
+            Charset::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+            Charset::UsAscii if bytes.is_ascii() => {
+                Some(bytes.iter().map(|&b| b as char).collect())
+            }
+            Charset::UsAscii => None,
+            Charset::Iso8859_1 => Some(bytes.iter().map(|&b| b as char).collect()),
+            Charset::Windows1252 => bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9F => {
+                        Some(WINDOWS_1252_HIGH[b as usize - 0x80]).filter(|&c| c != '\0')
+                    }
+                    b => Some(b as char),
+                })
+                .collect(),
+        }
+    }
+
+    /// Encode `text` in this charset.  Fails with `Error::Unencodable` if it has a
+    /// character the charset lacks.
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, Error> {
+        if *self == Charset::Utf8 {
+            return Ok(text.as_bytes().to_vec());
+        }
+        let mut output = Vec::with_capacity(text.len());
+        for ch in text.chars() {
+            let byte = match (*self, ch as u32) {
+                (_, code) if code < 0x80 => code as u8,
+                (Charset::Iso8859_1, code) if code < 0x100 => code as u8,
+                (Charset::Windows1252, code) if (0xA0..0x100).contains(&code) => code as u8,
+                (Charset::Windows1252, _) => {
+                    match WINDOWS_1252_HIGH.iter().position(|&c| c == ch) {
+                        Some(index) => 0x80 + index as u8,
+                        None => return Err(Error::Unencodable),
+                    }
+                }
+                _ => return Err(Error::Unencodable),
+            };
+            output.push(byte);
+        }
+        Ok(output)
+    }
+}
+
+impl fmt::Display for Charset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Part {
+    /// A text part holding `text` encoded in `charset`, with the given `content_type`
+    /// (e.g. `text/plain`), whose `charset` parameter is set to match.  For legacy
+    /// receivers which expect e.g. ISO-8859-1 rather than UTF-8.
+    pub fn text(content_type: &Mime, text: &str, charset: Charset) -> Result<Part, Error> {
+        let mut value = content_type.essence_str().to_owned();
+        for (name, param) in content_type.params() {
+            if name != mime::CHARSET {
+                value.push_str(&format!("; {}={}", name, param));
+            }
+        }
+        value.push_str("; charset=");
+        value.push_str(charset.name());
+
+        let mut headers = HeaderMap::new();
+        match HeaderValue::from_str(&value) {
+            Ok(value) => headers.insert(CONTENT_TYPE, value),
+            Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+        };
+        Ok(Part {
+            headers,
+            body: charset.encode(text)?,
+        })
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use bytes::{Buf, Bytes};
+use std::collections::VecDeque;
+use std::io::{BufRead, Read};
+
+/// A body held as a sequence of `Bytes` chunks, as collected from a hyper body, which can be
+/// parsed across chunk boundaries without concatenating the chunks first.  It is a `BufRead`
+/// handing out one chunk at a time, so pass it to `read_multipart_body_buffered()`.  It is
+/// also a `Buf`.
+///
+/// Any other `Buf` can be read the same way through `Buf::reader()`.
+#[derive(Clone, Debug, Default)]
+pub struct BytesChunks {
+    chunks: VecDeque<Bytes>,
+}
+
+impl BytesChunks {
+    /// Read the given chunks in order.
+    pub fn new<I: IntoIterator<Item = Bytes>>(chunks: I) -> BytesChunks {
+        chunks.into_iter().collect()
+    }
+
+    /// Add a chunk to the end.
+    pub fn push(&mut self, chunk: Bytes) {
+        if !chunk.is_empty() {
+            self.chunks.push_back(chunk);
+        }
+    }
+}
+
+impl FromIterator<Bytes> for BytesChunks {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(chunks: I) -> BytesChunks {
+        let mut output = BytesChunks::default();
+        for chunk in chunks {
+            output.push(chunk);
+        }
+        output
+    }
+}
+
+impl From<VecDeque<Bytes>> for BytesChunks {
+    fn from(chunks: VecDeque<Bytes>) -> BytesChunks {
+        chunks.into_iter().collect()
+    }
+}
+
+impl Buf for BytesChunks {
+    fn remaining(&self) -> usize {
+        self.chunks.iter().map(Bytes::len).sum()
+    }
+
+    fn chunk(&self) -> &[u8] {
+        self.chunks.front().map_or(&[], |chunk| chunk.as_ref())
+    }
+
+    fn advance(&mut self, mut cnt: usize) {
+        while cnt > 0 {
+            let front = match self.chunks.front_mut() {
+                Some(front) => front,
+                None => panic!("advanced past the end of BytesChunks"),
+            };
+            if cnt < front.len() {
+                front.advance(cnt);
+                return;
+            }
+            cnt -= front.len();
+            self.chunks.pop_front();
+        }
+    }
+}
+
+impl Read for BytesChunks {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let chunk = Buf::chunk(self);
+        let count = chunk.len().min(buf.len());
+        buf[..count].copy_from_slice(&chunk[..count]);
+        self.advance(count);
+        Ok(count)
+    }
+}
+
+impl BufRead for BytesChunks {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        Ok(Buf::chunk(self))
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.advance(amt);
+    }
+}
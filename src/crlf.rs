@@ -0,0 +1,147 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{get_multipart_boundary, is_nested, write_headers};
+use super::{Error, Event, MultipartParser, ParseMode, ParseProfile};
+use http::header::HeaderMap;
+use std::io::{self, Write};
+
+/// A writer which re-encodes a multipart body delimited by bare LFs (or CRs, or a mix) into
+/// canonical form as it is written, passing the result on to another writer: delimiters and
+/// headers end in CRLF, and the line terminator before each delimiter is a CRLF.  Part
+/// bodies are passed on untouched, except those of nested multiparts, which are re-encoded
+/// likewise.  This is for forwarding uploads from lenient clients to servers which accept
+/// nothing but CRLF.
+///
+/// The preamble and epilogue are dropped, as is the line terminator after the close
+/// delimiter, and header names are written in lowercase, as `write_multipart()` writes
+/// them.  Input which is canonical already comes out the same otherwise.  Call `finish()` at the end of
+/// the input, to find out whether it was complete.
+pub struct CrlfEncoder<W: Write> {
+    parser: MultipartParser,
+    profile: ParseProfile,
+    delimiter: Vec<u8>,
+    writer: W,
+    // The re-encoder of the nested multipart being passed on, if any
+    nested: Option<Box<CrlfEncoder<Vec<u8>>>>,
+    parts: usize,
+    finished: bool,
+}
+
+impl<W: Write> CrlfEncoder<W> {
+    /// Re-encode the body of a request or part with the given headers into `writer`,
+    /// parsing it in `ParseMode::Lenient`.
+    pub fn new(headers: &HeaderMap, writer: W) -> Result<CrlfEncoder<W>, Error> {
+        let profile = ParseProfile {
+            mode: ParseMode::Lenient,
+            ..ParseProfile::default()
+        };
+        CrlfEncoder::with_profile(headers, &profile, writer)
+    }
+
+    /// Like `new()`, but parsing as directed by the `ParseProfile` given, whose `Limits`
+    /// apply as they do to a `MultipartParser`.
+    pub fn with_profile(
+        headers: &HeaderMap,
+        profile: &ParseProfile,
+        writer: W,
+    ) -> Result<CrlfEncoder<W>, Error> {
+        Ok(CrlfEncoder {
+            parser: MultipartParser::from_headers(headers, profile)?,
+            profile: profile.clone(),
+            delimiter: get_multipart_boundary(headers)?,
+            writer,
+            nested: None,
+            parts: 0,
+            finished: false,
+        })
+    }
+
+    /// Re-encode the next piece of input.
+    pub fn encode(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.parser.feed(data);
+        self.drain()
+    }
+
+    /// Re-encode what is left of the input, which should end with the close delimiter, and
+    /// return the writer.  Fails as `MultipartParser` does if the input was cut short.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.parser.feed_eof();
+        self.drain()?;
+        match self.finished {
+            true => Ok(self.writer),
+            false => Err(Error::EofInPart),
+        }
+    }
+
+    // Write out what the input fed so far amounts to
+    fn drain(&mut self) -> Result<(), Error> {
+        while !self.finished {
+            match self.parser.next_event()? {
+                None => break,
+                Some(Event::PartHeaders(headers)) => {
+                    if self.parts > 0 {
+                        self.writer.write_all(b"\r\n")?;
+                    }
+                    self.parts += 1;
+                    self.writer.write_all(&self.delimiter)?;
+                    self.writer.write_all(b"\r\n")?;
+                    write_headers(&mut self.writer, &headers, None)?;
+                    self.nested = match has_nested_boundary(&headers) {
+                        true => Some(Box::new(CrlfEncoder::with_profile(
+                            &headers,
+                            &self.profile,
+                            Vec::new(),
+                        )?)),
+                        false => None,
+                    };
+                }
+                Some(Event::BodyChunk(chunk)) => match self.nested {
+                    Some(ref mut nested) => {
+                        nested.encode(&chunk)?;
+                        self.writer.write_all(&nested.writer)?;
+                        nested.writer.clear();
+                    }
+                    None => self.writer.write_all(&chunk)?,
+                },
+                Some(Event::PartEnd) => {
+                    if let Some(nested) = self.nested.take() {
+                        self.writer.write_all(&nested.finish()?)?;
+                    }
+                }
+                Some(Event::Finished) => {
+                    if self.parts > 0 {
+                        self.writer.write_all(b"\r\n")?;
+                    }
+                    self.writer.write_all(&self.delimiter)?;
+                    self.writer.write_all(b"--")?;
+                    self.finished = true;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for CrlfEncoder<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self.encode(data) {
+            Ok(()) => Ok(data.len()),
+            Err(Error::Io(err)) => Err(err),
+            Err(err) => Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Whether a part's headers declare it a nested multipart, with a boundary to parse it by
+fn has_nested_boundary(headers: &HeaderMap) -> bool {
+    is_nested(headers).unwrap_or(false) && get_multipart_boundary(headers).is_ok()
+}
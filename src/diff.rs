@@ -0,0 +1,223 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{ContentDisposition, Error, Node};
+use http::header::{HeaderMap, HeaderName, CONTENT_DISPOSITION, CONTENT_TYPE};
+use mime::Mime;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+
+/// A difference between two node trees found by `diff()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Difference {
+    /// Where the difference is: the index of the node, then of the node within it, and so
+    /// on.
+    pub path: Vec<usize>,
+    /// What the difference is
+    pub kind: DifferenceKind,
+}
+
+/// The kinds of `Difference`.  Values from the first tree come first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DifferenceKind {
+    /// The node is only in the first tree.
+    Removed,
+    /// The node is only in the second tree.
+    Added,
+    /// The node is a multipart in one tree but not in the other.
+    NotMultipart,
+    /// The `name` parameters of the nodes' `Content-Disposition` differ.
+    NameChanged(Option<String>, Option<String>),
+    /// The values of a header differ.  Multiple values are joined with `, `.
+    HeaderChanged(String, Option<String>, Option<String>),
+    /// The body sizes differ.
+    SizeChanged(u64, u64),
+    /// The bodies are the same size but their content differs.
+    ContentChanged,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path: Vec<String> = self.path.iter().map(|index| index.to_string()).collect();
+        write!(f, "part {}: {:?}", path.join("."), self.kind)
+    }
+}
+
+/// Compare two node trees, such as a generated multipart and a golden fixture, part by
+/// part.  Returns every difference found, in order, or nothing if the trees are the same.
+///
+/// Parts are matched by position.  Headers are compared by name regardless of order, and
+/// `Content-Type` and `Content-Disposition` by their parsed values, so that quoting and the
+/// multipart boundary do not count as differences.  Bodies are compared by size and by
+/// SHA-256 digest, reading file parts from disk.
+pub fn diff(a: &[Node], b: &[Node]) -> Result<Vec<Difference>, Error> {
+    let mut differences = Vec::new();
+    diff_nodes(a, b, &mut Vec::new(), &mut differences)?;
+    Ok(differences)
+}
+
+fn diff_nodes(
+    a: &[Node],
+    b: &[Node],
+    path: &mut Vec<usize>,
+    differences: &mut Vec<Difference>,
+) -> Result<(), Error> {
+    for index in 0..a.len().max(b.len()) {
+        path.push(index);
+        let mut add = |kind| {
+            differences.push(Difference {
+                path: path.clone(),
+                kind,
+            })
+        };
+        match (a.get(index), b.get(index)) {
+            (Some(_), None) => add(DifferenceKind::Removed),
+            (None, Some(_)) => add(DifferenceKind::Added),
+            (Some(a), Some(b)) => {
+                for kind in diff_headers(a.headers(), b.headers()) {
+                    add(kind);
+                }
+                match (a, b) {
+                    (Node::Multipart((_, a)), Node::Multipart((_, b))) => {
+                        diff_nodes(a, b, path, differences)?
+                    }
+                    (Node::Multipart(_), _) | (_, Node::Multipart(_)) => {
+                        add(DifferenceKind::NotMultipart)
+                    }
+                    _ => {
+                        let (a_size, a_digest) = digest(a)?;
+                        let (b_size, b_digest) = digest(b)?;
+                        if a_size != b_size {
+                            add(DifferenceKind::SizeChanged(a_size, b_size));
+                        } else if a_digest != b_digest {
+                            add(DifferenceKind::ContentChanged);
+                        }
+                    }
+                }
+            }
+            (None, None) => unreachable!(),
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+fn diff_headers(a: &HeaderMap, b: &HeaderMap) -> Vec<DifferenceKind> {
+    let mut differences = Vec::new();
+    let a_disposition = disposition(a);
+    let b_disposition = disposition(b);
+    let a_name = a_disposition.as_ref().and_then(|cd| cd.decoded_name());
+    let b_name = b_disposition.as_ref().and_then(|cd| cd.decoded_name());
+    if a_name != b_name {
+        differences.push(DifferenceKind::NameChanged(a_name, b_name));
+    }
+
+    let mut names: Vec<&HeaderName> = a.keys().chain(b.keys()).collect();
+    names.sort_by(|x, y| x.as_str().cmp(y.as_str()));
+    names.dedup();
+    for name in names {
+        let same = match *name {
+            CONTENT_TYPE => content_type(a) == content_type(b),
+            // The name is compared above
+            CONTENT_DISPOSITION => {
+                without_name(a_disposition.clone()) == without_name(b_disposition.clone())
+            }
+            _ => joined(a, name) == joined(b, name),
+        };
+        if !same {
+            differences.push(DifferenceKind::HeaderChanged(
+                name.as_str().to_owned(),
+                joined(a, name),
+                joined(b, name),
+            ));
+        }
+    }
+    differences
+}
+
+// All values of a header, joined, or None if it is missing
+fn joined(headers: &HeaderMap, name: &HeaderName) -> Option<String> {
+    let values: Vec<String> = headers
+        .get_all(name)
+        .iter()
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+        .collect();
+    match values.is_empty() {
+        true => None,
+        false => Some(values.join(", ")),
+    }
+}
+
+// The Content-Type as its lowercase essence and sorted parameters, without the boundary,
+// or as it is given if it does not parse
+fn content_type(headers: &HeaderMap) -> Option<String> {
+    let value = joined(headers, &CONTENT_TYPE)?;
+    let mime = match Mime::from_str(&value) {
+        Ok(mime) => mime,
+        Err(_) => return Some(value),
+    };
+    let mut params: Vec<String> = mime
+        .params()
+        .filter(|(name, _)| *name != mime::BOUNDARY)
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    params.sort();
+    params.insert(0, mime.essence_str().to_ascii_lowercase());
+    Some(params.join(";"))
+}
+
+fn disposition(headers: &HeaderMap) -> Option<ContentDisposition> {
+    ContentDisposition::from_header(headers.get(CONTENT_DISPOSITION)?).ok()
+}
+
+// A Content-Disposition's type and decoded parameters, other than the name, sorted
+fn without_name(
+    disposition: Option<ContentDisposition>,
+) -> Option<(String, Vec<(String, String)>)> {
+    let disposition = disposition?;
+    let mut params: Vec<(String, String)> = Vec::new();
+    for (name, _) in &disposition.params {
+        let name = name.split('*').next().unwrap_or_default();
+        if name == "name" || params.iter().any(|(seen, _)| seen == name) {
+            continue;
+        }
+        if let Some(value) = disposition.decoded_param(name) {
+            params.push((name.to_owned(), value));
+        }
+    }
+    params.sort();
+    Some((disposition.disposition.to_string(), params))
+}
+
+// The size and digest of a part body
+fn digest(node: &Node) -> Result<(u64, [u8; 32]), Error> {
+    let mut digest = Sha256::new();
+    match *node {
+        Node::Part(ref part) => {
+            digest.update(&part.body);
+            Ok((part.body.len() as u64, digest.finalize().into()))
+        }
+        Node::File(ref filepart) => {
+            let mut file = File::open(&filepart.path)?;
+            let mut buf = vec![0; 64 * 1024];
+            let mut size = 0;
+            loop {
+                let read = file.read(&mut buf)?;
+                if read == 0 {
+                    break;
+                }
+                digest.update(&buf[..read]);
+                size += read as u64;
+            }
+            Ok((size, digest.finalize().into()))
+        }
+        Node::Multipart(_) => unreachable!(),
+    }
+}
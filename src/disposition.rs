@@ -0,0 +1,337 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{is_token_char, Charset, Error};
+use http::header::HeaderValue;
+use std::fmt;
+use std::fmt::Write;
+
+/// The disposition type of a `Content-Disposition` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DispositionType {
+    /// `inline` (RFC 2183): to be displayed as part of the message
+    Inline,
+    /// `attachment` (RFC 2183): separate from the message, to be saved or opened on request
+    Attachment,
+    /// `form-data` (RFC 7578): a field of a submitted form
+    FormData,
+    /// Any other type, in lowercase
+    Other(String),
+}
+
+impl fmt::Display for DispositionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DispositionType::Inline => f.write_str("inline"),
+            DispositionType::Attachment => f.write_str("attachment"),
+            DispositionType::FormData => f.write_str("form-data"),
+            DispositionType::Other(ref other) => f.write_str(other),
+        }
+    }
+}
+
+/// A parsed `Content-Disposition` header (RFC 2183, RFC 6266, RFC 7578): its type and its
+/// parameters.  Parameter values may be quoted strings, which are unescaped, or bare tokens.
+/// Extended parameters (`name*`, RFC 2231 and RFC 5987) are kept as they are given, and are
+/// decoded by `decoded_param()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentDisposition {
+    /// The disposition type
+    pub disposition: DispositionType,
+    /// The parameters in the order given, with names in lowercase
+    pub params: Vec<(String, String)>,
+}
+
+impl ContentDisposition {
+    /// A `Content-Disposition` of the given type, without parameters.
+    pub fn new(disposition: DispositionType) -> ContentDisposition {
+        ContentDisposition {
+            disposition,
+            params: Vec::new(),
+        }
+    }
+
+    /// Add a parameter, which is written in the extended form if it is not ASCII.
+    pub fn with_param(mut self, name: &str, value: &str) -> ContentDisposition {
+        self.params
+            .push((name.to_ascii_lowercase(), value.to_owned()));
+        self
+    }
+
+    /// Parse a header value.  Parsing is lenient: parameters without a value are skipped.
+    pub fn parse(value: &str) -> ContentDisposition {
+        let mut pieces = split_params(value).into_iter();
+        let disposition = match &*pieces.next().unwrap_or("").trim().to_ascii_lowercase() {
+            "inline" => DispositionType::Inline,
+            "attachment" => DispositionType::Attachment,
+            "form-data" => DispositionType::FormData,
+            other => DispositionType::Other(other.to_owned()),
+        };
+        let params = pieces
+            .filter_map(|param| {
+                let (name, value) = param.split_once('=')?;
+                Some((name.trim().to_ascii_lowercase(), unquote(value.trim())))
+            })
+            .collect();
+        ContentDisposition {
+            disposition,
+            params,
+        }
+    }
+
+    /// Parse a header value, which must be visible ASCII.
+    pub fn from_header(value: &HeaderValue) -> Result<ContentDisposition, Error> {
+        match value.to_str() {
+            Ok(value) => Ok(ContentDisposition::parse(value)),
+            Err(err) => Err(Error::ToStr(err)),
+        }
+    }
+
+    /// The value of the first parameter with the given name, which is case-insensitive
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| &**value)
+    }
+
+    /// The `name` parameter, naming a form field
+    pub fn name(&self) -> Option<&str> {
+        self.param("name")
+    }
+
+    /// The `filename` parameter
+    pub fn filename(&self) -> Option<&str> {
+        self.param("filename")
+    }
+
+    /// The `name` parameter, with the escapes browsers apply to form field names undone.
+    /// See `unescape_html()`.
+    pub fn html_name(&self) -> Option<String> {
+        self.name().map(unescape_html)
+    }
+
+    /// The `filename` parameter, with the escapes browsers apply to file names undone.
+    /// See `unescape_html()`.
+    pub fn html_filename(&self) -> Option<String> {
+        self.filename().map(unescape_html)
+    }
+
+    /// Whether a file name is given, by either `filename` or its extended form
+    pub fn has_filename(&self) -> bool {
+        self.params
+            .iter()
+            .any(|(name, _)| name == "filename" || name.starts_with("filename*"))
+    }
+
+    /// The value of the parameter with the given name, taken from its extended form where
+    /// there is one in a supported charset (UTF-8, ISO-8859-1 or US-ASCII), and decoded:
+    /// either `name*=charset'language'percent-encoded` (RFC 5987), or RFC 2231 continuations
+    /// (`name*0*=...; name*1=...`).  Otherwise it is the plain parameter's value.
+    pub fn decoded_param(&self, name: &str) -> Option<String> {
+        self.extended_param(name)
+            .or_else(|| self.param(name).map(str::to_owned))
+    }
+
+    /// The field name: `name*` decoded, or else `name`
+    pub fn decoded_name(&self) -> Option<String> {
+        self.decoded_param("name")
+    }
+
+    /// The file name: `filename*` decoded, or else `filename`
+    pub fn decoded_filename(&self) -> Option<String> {
+        self.decoded_param("filename")
+    }
+
+    // A parameter as `decoded_param()` gives it, except that with `html`, a plain value is
+    // unescaped as browsers escape it
+    pub(crate) fn form_param(&self, name: &str, html: bool) -> Option<String> {
+        match self.extended_param(name) {
+            Some(value) => Some(value),
+            None if html => self.param(name).map(unescape_html),
+            None => self.param(name).map(str::to_owned),
+        }
+    }
+
+    /// The header value, with the parameters given in the extended form (`name*=UTF-8''...`)
+    /// where they are not ASCII, quoted where they are not tokens, and as they are
+    /// otherwise.  Fails if a value has an ASCII control character.
+    pub fn to_header_value(&self) -> Result<HeaderValue, Error> {
+        let mut value = self.disposition.to_string();
+        for (name, param) in &self.params {
+            if !param.is_ascii() {
+                let _ = write!(value, "; {}*=UTF-8''", name);
+                for &byte in param.as_bytes() {
+                    match byte {
+                        b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`'
+                        | b'|' | b'~' => value.push(byte as char),
+                        _ if byte.is_ascii_alphanumeric() => value.push(byte as char),
+                        _ => {
+                            let _ = write!(value, "%{:02X}", byte);
+                        }
+                    }
+                }
+            } else if !param.is_empty() && param.bytes().all(is_token_char) {
+                let _ = write!(value, "; {}={}", name, param);
+            } else {
+                let _ = write!(value, "; {}=\"", name);
+                for ch in param.chars() {
+                    if ch == '"' || ch == '\\' {
+                        value.push('\\');
+                    }
+                    value.push(ch);
+                }
+                value.push('"');
+            }
+        }
+        match HeaderValue::from_str(&value) {
+            Ok(value) => Ok(value),
+            Err(_) => Err(Error::InvalidHeaderNameOrValue),
+        }
+    }
+
+    // The decoded extended form of a parameter, if it has one
+    fn extended_param(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.param(&format!("{}*", name)) {
+            return decode_extended(value, true);
+        }
+
+        // RFC 2231 continuations, numbered from 0, each percent-encoded if marked with `*`
+        let mut value = String::new();
+        for index in 0.. {
+            let section = format!("{}*{}", name, index);
+            match (self.param(&format!("{}*", section)), self.param(&section)) {
+                (Some(encoded), _) => value.push_str(encoded),
+                (None, Some(plain)) => value.push_str(&percent_encode_all(plain)),
+                (None, None) if index == 0 => return None,
+                (None, None) => break,
+            }
+        }
+        // The first section carries the charset only if it is encoded
+        decode_extended(&value, self.param(&format!("{}*0*", name)).is_some())
+    }
+}
+
+// Decode `charset'language'percent-encoded`, or just the percent-encoded text if `charset`
+// is false, in which case it is ASCII
+fn decode_extended(value: &str, charset: bool) -> Option<String> {
+    let (charset, encoded) = match charset {
+        true => {
+            let (charset, rest) = value.split_once('\'')?;
+            let (_, encoded) = rest.split_once('\'')?;
+            (Charset::from_name(charset)?, encoded)
+        }
+        false => (Charset::UsAscii, value),
+    };
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match byte {
+            b'%' => {
+                let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                rest = &tail[2..];
+            }
+            byte => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    charset.decode(&bytes)
+}
+
+// Percent-encode every `%` of a plain continuation, so that it survives decoding
+fn percent_encode_all(value: &str) -> String {
+    value.replace('%', "%25")
+}
+
+// Split a header value on semicolons outside quoted strings
+fn split_params(value: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, ch) in value.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                params.push(&value[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(&value[start..]);
+    params
+}
+
+// The value of a parameter: a quoted string, unescaped, or else a token as it is
+fn unquote(value: &str) -> String {
+    let quoted = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(quoted) => quoted,
+        None => return value.to_owned(),
+    };
+    let mut output = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => output.extend(chars.next()),
+            ch => output.push(ch),
+        }
+    }
+    output
+}
+
+/// Undo the escaping which browsers apply to the field names and file names of a submitted
+/// form, so that they match what the page gave.  The HTML standard has `"`, CR and LF
+/// percent-encoded as `%22`, `%0D` and `%0A`, RFC 7578 leaves other characters alone, and
+/// characters which the form's encoding cannot represent are sent as numeric character
+/// references such as `&#8364;`.  Anything else, including a `%` which does not start one
+/// of these escapes, is kept as it is.
+pub fn unescape_html(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find(['%', '&']) {
+        output.push_str(&rest[..index]);
+        rest = &rest[index..];
+        let (ch, len) = match unescape_percent(rest).or_else(|| unescape_reference(rest)) {
+            Some(unescaped) => unescaped,
+            None => (rest.as_bytes()[0] as char, 1),
+        };
+        output.push(ch);
+        rest = &rest[len..];
+    }
+    output.push_str(rest);
+    output
+}
+
+// The character escaped as `%22`, `%0D` or `%0A` at the start of `value`, and the length of
+// the escape
+fn unescape_percent(value: &str) -> Option<(char, usize)> {
+    let ch = match &*value.get(..3)?.to_ascii_uppercase() {
+        "%22" => '"',
+        "%0D" => '\r',
+        "%0A" => '\n',
+        _ => return None,
+    };
+    Some((ch, 3))
+}
+
+// The character given by a numeric character reference (`&#NNN;` or `&#xHHH;`) at the start
+// of `value`, and the length of the reference
+fn unescape_reference(value: &str) -> Option<(char, usize)> {
+    let end = value.get(..12).unwrap_or(value).find(';')?;
+    let digits = value[..end].strip_prefix("&#")?;
+    let code = match digits.strip_prefix(['x', 'X']) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    Some((char::from_u32(code)?, end + 1))
+}
@@ -0,0 +1,87 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{parse_body, Counting, Error, Node, ParseProfile, ParseState, StopAt};
+use http::header::HeaderMap;
+use std::io::BufRead;
+
+/// An iterator over multipart documents following one another in a single stream, such as
+/// a batch file or connection, all delimited by the boundary in the headers given.  Each
+/// document is read through the end of its close delimiter's line (whatever
+/// `ParseProfile::stop_at` says), and the next one starts right there, so that the
+/// reader is never read past the last document.  Iteration ends at the end of the stream,
+/// or after the first error.
+pub struct MultipartDocuments<'a, R: ?Sized> {
+    reader: &'a mut R,
+    headers: HeaderMap,
+    profile: ParseProfile,
+    position: u64,
+    done: bool,
+}
+
+impl<'a, R: BufRead + ?Sized> MultipartDocuments<'a, R> {
+    /// Read documents with the given headers from `reader`, parsing them as directed by the
+    /// `ParseProfile` given.
+    pub fn new(
+        reader: &'a mut R,
+        headers: &HeaderMap,
+        profile: &ParseProfile,
+    ) -> MultipartDocuments<'a, R> {
+        MultipartDocuments {
+            reader,
+            headers: headers.clone(),
+            profile: ParseProfile {
+                stop_at: StopAt::LineEnd,
+                ..profile.clone()
+            },
+            position: 0,
+            done: false,
+        }
+    }
+
+    /// The number of bytes read so far, which is where the last document returned ended.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+impl<R: BufRead + ?Sized> Iterator for MultipartDocuments<'_, R> {
+    /// A document's nodes, and the position in the stream where it ended
+    type Item = Result<(Vec<Node>, u64), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.reader.fill_buf() {
+            Ok([]) => {
+                self.done = true;
+                return None;
+            }
+            Ok(_) => {}
+            Err(err) => {
+                self.done = true;
+                return Some(Err(Error::Io(err)));
+            }
+        }
+
+        let mut reader = Counting::new(&mut *self.reader);
+        let result = parse_body(
+            &mut reader,
+            &self.headers,
+            &mut ParseState::new(&self.profile),
+        );
+        self.position += reader.consumed;
+        match result {
+            Ok(nodes) => Some(Ok((nodes, self.position))),
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use std::sync::Arc;
+
+// The characters of random file names
+pub(crate) const ALPHANUMERIC: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// A source of random bytes, for generated boundaries and temporary file names, in place of
+/// the crate's own (`textnonce` and `fastrand`).  This is for deterministic simulation
+/// testing, or for environments where only an approved generator may be used.  Any
+/// `Fn(&mut [u8])` is one.
+pub trait EntropySource: Send + Sync {
+    /// Fill `dest` with random bytes.
+    fn fill_bytes(&self, dest: &mut [u8]);
+}
+
+impl<F: Fn(&mut [u8]) + Send + Sync> EntropySource for F {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        self(dest)
+    }
+}
+
+/// A shared `EntropySource`, for `WriteProfile::entropy` and `FilePartBuilder::entropy()`.
+/// Two are equal if they share the same source.
+#[derive(Clone)]
+pub struct Entropy(Arc<dyn EntropySource>);
+
+impl Entropy {
+    /// Take random bytes from `source`.
+    pub fn new<S: EntropySource + 'static>(source: S) -> Entropy {
+        Entropy(Arc::new(source))
+    }
+
+    // `len` characters from `alphabet`, which has at most 256, each drawn without bias
+    pub(crate) fn string(&self, alphabet: &[u8], len: usize) -> String {
+        // The largest multiple of the alphabet's size which bytes reach
+        let limit = 256 - 256 % alphabet.len();
+        let mut output = String::with_capacity(len);
+        let mut bytes = [0; 64];
+        while output.len() < len {
+            self.0.fill_bytes(&mut bytes);
+            for &byte in bytes.iter().filter(|&&byte| (byte as usize) < limit) {
+                if output.len() == len {
+                    break;
+                }
+                output.push(alphabet[byte as usize % alphabet.len()] as char);
+            }
+        }
+        output
+    }
+}
+
+impl PartialEq for Entropy {
+    fn eq(&self, other: &Entropy) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Entropy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Entropy(..)")
+    }
+}
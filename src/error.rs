@@ -10,11 +10,18 @@ use std::fmt::{self, Display};
 use std::io;
 use std::string::FromUtf8Error;
 
+#[cfg(feature = "sha2")]
+use super::hex_digest;
 use http;
 use http::header::ToStrError;
+#[cfg(feature = "parse")]
 use httparse;
+#[cfg(feature = "sha2")]
+use sha2::{Digest, Sha256};
 
-/// An error type for the `mime-multipart` crate.
+/// An error type for the `mime-multipart` crate.  More variants may be added, so matches on it
+/// need a wildcard arm.
+#[non_exhaustive]
 pub enum Error {
     /// The Hyper request did not have a Content-Type header.
     NoRequestContentType,
@@ -22,6 +29,11 @@ pub enum Error {
     NotMultipart,
     /// The Content-Type header failed to specify boundary token.
     BoundaryNotSpecified,
+    /// The headers carried several differing Content-Type values.
+    ConflictingContentTypes,
+    /// In `ParseMode::Strict`, a part carried its `Content-Type` or `Content-Disposition`
+    /// header more than once.  Holds the header name.
+    DuplicateHeader(String),
     /// A multipart section contained only partial headers.
     PartialHeaders,
     EofInMainHeaders,
@@ -34,9 +46,65 @@ pub enum Error {
     InvalidHeaderNameOrValue,
     HeaderValueNotMime,
     FilenameWithNonAsciiEncodingNotSupported,
+    /// A boundary was empty, longer than 70 characters, ended in a space, or contained
+    /// characters RFC 2046 does not permit.
+    InvalidBoundary,
+    /// A part body exceeded `Limits::max_part_size`.
+    PartTooLarge,
+    /// The part bodies together exceeded `Limits::max_total_size`.
+    BodyTooLarge,
+    /// The body contained more than `Limits::max_parts` parts.
+    TooManyParts,
+    /// Multipart parts were nested deeper than `Limits::max_depth`.
+    NestingTooDeep,
+    /// A part had more than `Limits::max_part_headers` headers, or the top-level headers
+    /// numbered more than `Limits::max_main_headers`.
+    TooManyHeaders,
+    /// The boundary was longer than `Limits::max_boundary_len`.
+    BoundaryTooLong,
+    /// A header block was larger than `Limits::max_header_block`.
+    HeadersTooLarge,
+    /// The preamble before the first boundary exceeded `Limits::max_preamble`.
+    PreambleTooLarge,
+    /// The body contained more file parts than `Limits::max_files`.
+    TooManyFiles,
+    /// A file part exceeded `Limits::max_file_size`.
+    FileTooLarge,
+    /// The body contained more in-memory parts than `Limits::max_fields`.
+    TooManyFields,
+    /// An in-memory part exceeded `Limits::max_field_size`.
+    FieldTooLarge,
+    /// A part exceeded the limit set for its field name in `Limits::max_size_by_name`.  Holds the name.
+    FieldSizeExceeded(String),
+    /// In `ParseMode::Strict`, unexpected data was found between parts.
+    DataBetweenParts,
+    /// Spooling a file part would have exceeded the `DiskBudget`.
+    DiskBudgetExceeded,
+    /// A `Content-Range` header value was malformed, or its range did not fit within the
+    /// complete length.
+    InvalidContentRange,
+    /// The body was neither `application/x-www-form-urlencoded` nor `multipart/form-data`.
+    NotFormData,
+    /// JSON was not the HTML JSON form or payload description that was expected.
+    InvalidJson,
+    /// A `multipart/form-data` part had no `name` in its `Content-Disposition` header.
+    NoFieldName,
+    /// A saved `MultipartParser` state could not be restored.
+    InvalidParserState,
+    /// A header value to be written contained a CR, LF or NUL, which would inject headers
+    /// or break the framing of the body.
+    UnsafeHeaderValue,
+    /// A header value or in-memory body to be written contained the delimiter of its
+    /// multipart, or of one enclosing it, so the body would have been cut short there.
+    BoundaryCollision,
+    /// The body was not as long as its `Content-Length` said, when
+    /// `ParseProfile::verify_content_length` is set.
+    ContentLengthMismatch,
+    /// Text had a character which the `Charset` asked for lacks.
+    Unencodable,
     ToStr(ToStrError),
     /// An HTTP parsing error from a multipart section.
-    Httparse(httparse::Error),
+    Httparse(HeaderParseError),
     /// An I/O error.
     Io(io::Error),
     /// An error was returned from Hyper.
@@ -45,15 +113,148 @@ pub enum Error {
     Utf8(FromUtf8Error),
 }
 
+/// The broad kind of an `Error`, which stays stable as more specific `Error` variants are
+/// added.  Match on `Error::category()` to tell malformed input from exceeded limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCategory {
+    /// The body was malformed.
+    Parse,
+    /// Reading, writing or spooling failed.
+    Io,
+    /// The input exceeded a configured limit or budget.
+    Limit,
+    /// A header was missing, malformed, or not what was required.
+    Header,
+}
+
+impl Error {
+    /// This error, displayed with the values taken from the input (such as field names)
+    /// replaced by the first 8 hex digits of their SHA-256 digest, so that it can be logged
+    /// without leaking user data while repeated values can still be told apart.  Needs the
+    /// `sha2` feature.
+    #[cfg(feature = "sha2")]
+    pub fn redacted(&self) -> Redacted<'_> {
+        Redacted(self)
+    }
+
+    /// The category of this error.
+    pub fn category(&self) -> ErrorCategory {
+        match *self {
+            Error::NoRequestContentType
+            | Error::NotMultipart
+            | Error::BoundaryNotSpecified
+            | Error::HeaderMissing
+            | Error::InvalidHeaderNameOrValue
+            | Error::HeaderValueNotMime
+            | Error::FilenameWithNonAsciiEncodingNotSupported
+            | Error::InvalidBoundary
+            | Error::InvalidContentRange
+            | Error::NotFormData
+            | Error::UnsafeHeaderValue
+            | Error::BoundaryCollision
+            | Error::ToStr(_)
+            | Error::Http(_)
+            | Error::Unencodable
+            | Error::ConflictingContentTypes
+            | Error::DuplicateHeader(_) => ErrorCategory::Header,
+            Error::PartialHeaders
+            | Error::EofInMainHeaders
+            | Error::EofBeforeFirstBoundary
+            | Error::NoCrLfAfterBoundary
+            | Error::EofInPartHeaders
+            | Error::EofInFile
+            | Error::EofInPart
+            | Error::DataBetweenParts
+            | Error::ContentLengthMismatch
+            | Error::NoFieldName
+            | Error::InvalidParserState
+            | Error::Utf8(_)
+            | Error::InvalidJson => ErrorCategory::Parse,
+            Error::Httparse(_) => ErrorCategory::Parse,
+            Error::PartTooLarge
+            | Error::BodyTooLarge
+            | Error::TooManyParts
+            | Error::NestingTooDeep
+            | Error::TooManyHeaders
+            | Error::BoundaryTooLong
+            | Error::HeadersTooLarge
+            | Error::PreambleTooLarge
+            | Error::DiskBudgetExceeded
+            | Error::TooManyFiles
+            | Error::FileTooLarge
+            | Error::TooManyFields
+            | Error::FieldTooLarge
+            | Error::FieldSizeExceeded(_) => ErrorCategory::Limit,
+            Error::Io(_) => ErrorCategory::Io,
+        }
+    }
+}
+
+/// A `Display` of an `Error` which is safe to log, returned by `Error::redacted()`.
+#[cfg(feature = "sha2")]
+pub struct Redacted<'a>(&'a Error);
+
+#[cfg(feature = "sha2")]
+impl Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.0 {
+            Error::FieldSizeExceeded(ref e) => write!(f, "FieldSizeExceeded: #{}", redact(e)),
+            ref err => err.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl fmt::Debug for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+// The first 8 hex digits of the SHA-256 digest of a value
+#[cfg(feature = "sha2")]
+fn redact(value: &str) -> String {
+    let mut digest = Sha256::new();
+    digest.update(value.as_bytes());
+    hex_digest(digest)[..8].to_owned()
+}
+
+/// An error of `httparse` in parsing the headers of a part, as `Error::Httparse` carries.
+/// The variant is there whatever the features, so that matches on `Error` do not depend on
+/// them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct HeaderParseError(#[cfg(feature = "parse")] httparse::Error);
+
+#[cfg(feature = "parse")]
+impl HeaderParseError {
+    /// The `httparse` error.
+    pub fn kind(&self) -> httparse::Error {
+        self.0
+    }
+}
+
+impl fmt::Debug for HeaderParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "parse")]
+            HeaderParseError(ref err) => fmt::Debug::fmt(err, f),
+            #[cfg(not(feature = "parse"))]
+            HeaderParseError() => f.write_str("HeaderParseError"),
+        }
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
         Error::Io(err)
     }
 }
 
+#[cfg(feature = "parse")]
 impl From<httparse::Error> for Error {
     fn from(err: httparse::Error) -> Error {
-        Error::Httparse(err)
+        Error::Httparse(HeaderParseError(err))
     }
 }
 
@@ -80,6 +281,8 @@ impl Display for Error {
             Error::NoRequestContentType => "NoRequestContentType".to_string().fmt(f),
             Error::NotMultipart => "NotMultipart".to_string().fmt(f),
             Error::BoundaryNotSpecified => "BoundaryNotSpecified".to_string().fmt(f),
+            Error::ConflictingContentTypes => "ConflictingContentTypes".to_string().fmt(f),
+            Error::DuplicateHeader(ref e) => format!("DuplicateHeader: {}", e).fmt(f),
             Error::PartialHeaders => "PartialHeaders".to_string().fmt(f),
             Error::EofBeforeFirstBoundary => "EofBeforeFirstBoundary".to_string().fmt(f),
             Error::NoCrLfAfterBoundary => "NoCrLfAfterBoundary".to_string().fmt(f),
@@ -93,6 +296,31 @@ impl Display for Error {
             Error::FilenameWithNonAsciiEncodingNotSupported => {
                 "NonAsciiFilenameNotSupported".to_string().fmt(f)
             }
+            Error::InvalidBoundary => "InvalidBoundary".to_string().fmt(f),
+            Error::PartTooLarge => "PartTooLarge".to_string().fmt(f),
+            Error::BodyTooLarge => "BodyTooLarge".to_string().fmt(f),
+            Error::TooManyParts => "TooManyParts".to_string().fmt(f),
+            Error::NestingTooDeep => "NestingTooDeep".to_string().fmt(f),
+            Error::TooManyHeaders => "TooManyHeaders".to_string().fmt(f),
+            Error::BoundaryTooLong => "BoundaryTooLong".to_string().fmt(f),
+            Error::HeadersTooLarge => "HeadersTooLarge".to_string().fmt(f),
+            Error::PreambleTooLarge => "PreambleTooLarge".to_string().fmt(f),
+            Error::TooManyFiles => "TooManyFiles".to_string().fmt(f),
+            Error::FileTooLarge => "FileTooLarge".to_string().fmt(f),
+            Error::TooManyFields => "TooManyFields".to_string().fmt(f),
+            Error::FieldTooLarge => "FieldTooLarge".to_string().fmt(f),
+            Error::FieldSizeExceeded(ref e) => format!("FieldSizeExceeded: {}", e).fmt(f),
+            Error::DataBetweenParts => "DataBetweenParts".to_string().fmt(f),
+            Error::DiskBudgetExceeded => "DiskBudgetExceeded".to_string().fmt(f),
+            Error::InvalidContentRange => "InvalidContentRange".to_string().fmt(f),
+            Error::NotFormData => "NotFormData".to_string().fmt(f),
+            Error::InvalidJson => "InvalidJson".to_string().fmt(f),
+            Error::NoFieldName => "NoFieldName".to_string().fmt(f),
+            Error::InvalidParserState => "InvalidParserState".to_string().fmt(f),
+            Error::UnsafeHeaderValue => "UnsafeHeaderValue".to_string().fmt(f),
+            Error::BoundaryCollision => "BoundaryCollision".to_string().fmt(f),
+            Error::ContentLengthMismatch => "ContentLengthMismatch".to_string().fmt(f),
+            Error::Unencodable => "Unencodable".to_string().fmt(f),
         }
     }
 }
@@ -117,6 +345,12 @@ impl StdError for Error {
             Error::BoundaryNotSpecified => {
                 "The Content-Type header failed to specify a boundary token."
             }
+            Error::ConflictingContentTypes => {
+                "The headers carried several differing Content-Type values."
+            }
+            Error::DuplicateHeader(_) => {
+                "A part carried its Content-Type or Content-Disposition header more than once."
+            }
             Error::PartialHeaders => "A multipart section contained only partial headers.",
             Error::EofInMainHeaders => "The request headers ended pre-maturely.",
             Error::EofBeforeFirstBoundary => {
@@ -143,6 +377,39 @@ impl StdError for Error {
             Error::FilenameWithNonAsciiEncodingNotSupported => {
                 "Non-ASCII filename parsing not supported"
             }
+            Error::InvalidBoundary => "The boundary is not permitted by RFC 2046.",
+            Error::PartTooLarge => "A part body exceeded the configured size limit.",
+            Error::BodyTooLarge => "The part bodies exceeded the configured total size limit.",
+            Error::TooManyParts => "The body contained more parts than the configured limit.",
+            Error::NestingTooDeep => {
+                "Multipart parts were nested deeper than the configured limit."
+            }
+            Error::TooManyHeaders => "There were more headers than the configured limit.",
+            Error::BoundaryTooLong => "The boundary was longer than the configured limit.",
+            Error::HeadersTooLarge => "A header block was larger than the configured limit.",
+            Error::PreambleTooLarge => "The preamble was larger than the configured limit.",
+            Error::TooManyFiles => "The body contained more file parts than the configured limit.",
+            Error::FileTooLarge => "A file part exceeded the configured size limit.",
+            Error::TooManyFields => {
+                "The body contained more in-memory parts than the configured limit."
+            }
+            Error::FieldTooLarge => "An in-memory part exceeded the configured size limit.",
+            Error::FieldSizeExceeded(_) => {
+                "A part exceeded the size limit configured for its field name."
+            }
+            Error::DataBetweenParts => "Unexpected data was found between parts.",
+            Error::DiskBudgetExceeded => "Spooling a file part would exceed the disk budget.",
+            Error::InvalidContentRange => "A Content-Range header value was invalid.",
+            Error::NotFormData => "The body was not a submitted form.",
+            Error::InvalidJson => {
+                "JSON was not the expected HTML JSON form or payload description."
+            }
+            Error::NoFieldName => "A form-data part had no name.",
+            Error::InvalidParserState => "A saved parser state was invalid.",
+            Error::UnsafeHeaderValue => "A header value to be written contained CR, LF or NUL.",
+            Error::BoundaryCollision => "Content to be written contained a multipart delimiter.",
+            Error::ContentLengthMismatch => "The body length did not match its Content-Length.",
+            Error::Unencodable => "Text had a character which the charset asked for lacks.",
         }
     }
 }
@@ -0,0 +1,191 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{percent_decode, read_multipart_body_with, ContentDisposition};
+use super::{Error, FilePart, Node, ParseProfile};
+use http::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_TYPE};
+use mime::Mime;
+use std::io::Read;
+use std::str::FromStr;
+
+/// The fields and files of a submitted HTML form, whether it was sent as
+/// `application/x-www-form-urlencoded` or as `multipart/form-data`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FormData {
+    /// Name-value pairs of the fields which are not files, in the order submitted
+    pub fields: Vec<(String, String)>,
+    /// Names and contents of the files, in the order submitted
+    pub files: Vec<(String, FilePart)>,
+}
+
+impl FormData {
+    /// The value of the first field named `name`, if any
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| &**value)
+    }
+
+    /// The first file named `name`, if any
+    pub fn get_file(&self, name: &str) -> Option<&FilePart> {
+        self.files
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, file)| file)
+    }
+
+    /// Like `get()`, but ignoring case, for clients which capitalize field names
+    /// inconsistently.  Case is compared by Unicode lowercase mapping.
+    pub fn get_ignore_case(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| eq_ignore_case(field, name))
+            .map(|(_, value)| &**value)
+    }
+
+    /// Like `get_file()`, but ignoring case as `get_ignore_case()` does.
+    pub fn get_file_ignore_case(&self, name: &str) -> Option<&FilePart> {
+        self.files
+            .iter()
+            .find(|(field, _)| eq_ignore_case(field, name))
+            .map(|(_, file)| file)
+    }
+
+    /// Replace the name of every field and file with what `map` makes of it.  This is the
+    /// place to apply Unicode normalization (e.g. NFC, from the `unicode-normalization`
+    /// crate), so that names which clients composed differently are found by `get()`.
+    pub fn map_names<F: FnMut(&str) -> String>(&mut self, mut map: F) {
+        for (name, _) in self.fields.iter_mut() {
+            *name = map(name);
+        }
+        for (name, _) in self.files.iter_mut() {
+            *name = map(name);
+        }
+    }
+}
+
+fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.chars()
+        .flat_map(char::to_lowercase)
+        .eq(b.chars().flat_map(char::to_lowercase))
+}
+
+/// Read a submitted form from `stream`, with the request's `headers`.  Depending on the
+/// `Content-Type`, the body is parsed either as `application/x-www-form-urlencoded` or as
+/// `multipart/form-data`; files are only found in the latter.
+pub fn read_form_data<S: Read + ?Sized>(
+    stream: &mut S,
+    headers: &HeaderMap,
+) -> Result<FormData, Error> {
+    read_form_data_with(stream, headers, &ParseProfile::default())
+}
+
+/// Like `read_form_data()`, but parsing as directed by the `ParseProfile` given.  For
+/// urlencoded bodies, `Limits::max_total_size` bounds the size of the body.
+pub fn read_form_data_with<S: Read + ?Sized>(
+    stream: &mut S,
+    headers: &HeaderMap,
+    profile: &ParseProfile,
+) -> Result<FormData, Error> {
+    let mime = match headers.get(CONTENT_TYPE) {
+        Some(ct) => match ct.to_str() {
+            Ok(value) => match Mime::from_str(value) {
+                Ok(value) => value,
+                Err(_) => return Err(Error::HeaderValueNotMime),
+            },
+            Err(err) => return Err(Error::ToStr(err)),
+        },
+        None => return Err(Error::NoRequestContentType),
+    };
+
+    match (mime.type_(), mime.subtype()) {
+        (mime::APPLICATION, mime::WWW_FORM_URLENCODED) => {
+            let mut body = Vec::new();
+            match profile.limits.max_total_size {
+                Some(max) => {
+                    stream.take(max + 1).read_to_end(&mut body)?;
+                    if body.len() as u64 > max {
+                        return Err(Error::BodyTooLarge);
+                    }
+                }
+                None => {
+                    stream.read_to_end(&mut body)?;
+                }
+            }
+            Ok(FormData {
+                fields: parse_urlencoded(&body)?,
+                files: Vec::new(),
+            })
+        }
+        (mime::MULTIPART, mime::FORM_DATA) => {
+            let nodes = read_multipart_body_with(stream, headers, profile)?;
+            let mut form = FormData::default();
+            for node in nodes {
+                add_node(&mut form, node, None, profile.html_names)?;
+            }
+            Ok(form)
+        }
+        _ => Err(Error::NotFormData),
+    }
+}
+
+// Add a `multipart/form-data` node to the form.  Nodes within a nested `multipart/mixed`
+// (the RFC 2388 way of sending several files) take the `name` of the part containing them.
+// With `html`, names are unescaped as browsers escape them.
+fn add_node(
+    form: &mut FormData,
+    node: Node,
+    outer_name: Option<&str>,
+    html: bool,
+) -> Result<(), Error> {
+    let headers = match node {
+        Node::Part(ref part) => &part.headers,
+        Node::File(ref filepart) => &filepart.headers,
+        Node::Multipart((ref headers, _)) => headers,
+    };
+    let name = match (headers.get(CONTENT_DISPOSITION), outer_name) {
+        (_, Some(name)) => name.to_owned(),
+        (Some(cd), None) => match ContentDisposition::from_header(cd)?.form_param("name", html) {
+            Some(name) => name,
+            None => return Err(Error::NoFieldName),
+        },
+        (None, None) => return Err(Error::NoFieldName),
+    };
+
+    match node {
+        Node::Part(mut part) => {
+            let body = std::mem::take(&mut part.body);
+            form.fields.push((name, String::from_utf8(body)?))
+        }
+        Node::File(filepart) => form.files.push((name, filepart)),
+        Node::Multipart((_, nodes)) => {
+            for node in nodes {
+                add_node(form, node, Some(&name), html)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Parse an `application/x-www-form-urlencoded` body into name-value pairs
+fn parse_urlencoded(body: &[u8]) -> Result<Vec<(String, String)>, Error> {
+    let mut pairs = Vec::new();
+    for pair in body.split(|&ch| ch == b'&') {
+        if pair.is_empty() {
+            continue;
+        }
+        let (name, value) = match pair.iter().position(|&ch| ch == b'=') {
+            Some(index) => (&pair[..index], &pair[index + 1..]),
+            None => (pair, &b""[..]),
+        };
+        let name = String::from_utf8(percent_decode(name, true))?;
+        let value = String::from_utf8(percent_decode(value, true))?;
+        pairs.push((name, value));
+    }
+    Ok(pairs)
+}
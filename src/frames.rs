@@ -0,0 +1,321 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{check_boundaries, file_headers, nested_multipart, write_headers};
+use super::{generate_boundary, generate_boundary_with, multipart_content_type};
+use super::{hex_digest, multipart_length, Error, Node, WriteProfile};
+use bytes::{Buf, Bytes};
+use futures_core::Stream;
+use http::header::{HeaderMap, HeaderValue};
+use http_body::{Body, Frame, SizeHint};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// The most read from a file for a single frame
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Turn `nodes` into a stream of `Frame`s carrying the multipart body delimited by
+/// `boundary`, exactly as `write_multipart()` would write it.  The stream suits
+/// `http_body_util::StreamBody`, so that a hyper 1 server can send the body as a response.
+/// The caller must send the `Content-Type` header, e.g. from `multipart_content_type()`.
+pub fn to_stream_body(boundary: &[u8], nodes: Vec<Node>) -> Result<MultipartFrames, Error> {
+    to_stream_body_with(boundary, nodes, &WriteProfile::default())
+}
+
+/// Like `to_stream_body()`, but emitting the body as directed by the `WriteProfile` given.
+/// `WriteProfile::sha256_trailer` is ignored, as the body is not written chunked.
+pub fn to_stream_body_with(
+    boundary: &[u8],
+    nodes: Vec<Node>,
+    profile: &WriteProfile,
+) -> Result<MultipartFrames, Error> {
+    check_boundaries(Some(boundary), &nodes, &mut Vec::new())?;
+    let mut plan = Plan::default();
+    plan.nodes(boundary, &nodes, profile)?;
+    if profile.final_crlf {
+        plan.pending.extend(b"\r\n");
+    }
+    plan.flush();
+    Ok(MultipartFrames {
+        segments: plan.segments,
+        file: None,
+        trailers: None,
+        _nodes: nodes,
+    })
+}
+
+// A piece of a `MultipartFrames` body
+enum Segment {
+    Bytes(Bytes),
+    File(PathBuf),
+}
+
+// The segments of a body being planned, with the bytes not yet made into a segment
+#[derive(Default)]
+struct Plan {
+    segments: VecDeque<Segment>,
+    pending: Vec<u8>,
+}
+
+impl Plan {
+    fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.segments
+                .push_back(Segment::Bytes(Bytes::from(pending)));
+        }
+    }
+
+    // Lay out nodes as `write_nodes()` writes them
+    fn nodes(
+        &mut self,
+        boundary: &[u8],
+        nodes: &[Node],
+        profile: &WriteProfile,
+    ) -> Result<(), Error> {
+        for node in nodes {
+            self.pending.extend(b"--");
+            self.pending.extend(boundary);
+            self.pending.extend(b"\r\n");
+
+            match *node {
+                Node::Part(ref part) => {
+                    let length = match profile.content_length {
+                        true => Some(part.body.len() as u64),
+                        false => None,
+                    };
+                    write_headers(&mut self.pending, &part.headers, length)?;
+                    self.pending.extend(&part.body);
+                }
+                Node::File(ref filepart) => {
+                    let size = std::fs::metadata(&filepart.path)?.len();
+                    let headers = file_headers(&filepart.headers, size, profile)?;
+                    let length = match profile.content_length {
+                        true => Some(size),
+                        false => None,
+                    };
+                    write_headers(&mut self.pending, &headers, length)?;
+                    self.flush();
+                    self.segments
+                        .push_back(Segment::File(filepart.path.clone()));
+                }
+                Node::Multipart((ref headers, ref subnodes)) => {
+                    let (headers, boundary) = nested_multipart(headers, profile)?;
+                    write_headers(&mut self.pending, &headers, None)?;
+                    self.nodes(&boundary, subnodes, profile)?;
+                }
+            }
+
+            self.pending.extend(b"\r\n");
+        }
+
+        self.pending.extend(b"--");
+        self.pending.extend(boundary);
+        self.pending.extend(b"--");
+        Ok(())
+    }
+}
+
+/// A multipart body as a `Stream` of `Frame`s, built by `to_stream_body()`.  Files are
+/// read as frames are polled, at most 64 KiB at a time, using blocking reads.  The stream
+/// ends once the close delimiter has been sent.
+pub struct MultipartFrames {
+    segments: VecDeque<Segment>,
+    file: Option<File>,
+    trailers: Option<Trailers>,
+    // The nodes being sent, held so that their temporary files are not deleted meanwhile
+    _nodes: Vec<Node>,
+}
+
+impl MultipartFrames {
+    /// End the stream with a trailers frame holding the headers `trailers` returns, given
+    /// the length and digest of the body sent.  The trailers should be announced with a
+    /// `Trailer` header.
+    pub fn with_trailers<F>(mut self, trailers: F) -> MultipartFrames
+    where
+        F: FnOnce(&TrailerInfo) -> HeaderMap + Send + 'static,
+    {
+        self.trailers = Some(Trailers::new(trailers));
+        self
+    }
+
+    // The next piece of the body, or `None` at its end
+    fn next_data(&mut self) -> Result<Option<Bytes>, Error> {
+        loop {
+            let path = match self.segments.front() {
+                None => return Ok(None),
+                Some(Segment::Bytes(_)) => match self.segments.pop_front() {
+                    Some(Segment::Bytes(bytes)) => return Ok(Some(bytes)),
+                    _ => unreachable!(),
+                },
+                Some(Segment::File(path)) => path,
+            };
+            let file = match self.file {
+                Some(ref mut file) => file,
+                None => self.file.insert(File::open(path)?),
+            };
+            let mut buf = vec![0; CHUNK_SIZE];
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                self.file = None;
+                self.segments.pop_front();
+                continue;
+            }
+            buf.truncate(read);
+            return Ok(Some(Bytes::from(buf)));
+        }
+    }
+}
+
+impl Stream for MultipartFrames {
+    type Item = Result<Frame<Bytes>, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.next_data() {
+            Ok(Some(data)) => {
+                if let Some(ref mut trailers) = self.trailers {
+                    trailers.update(&data);
+                }
+                Poll::Ready(Some(Ok(Frame::data(data))))
+            }
+            Ok(None) => Poll::Ready(self.trailers.take().map(|trailers| Ok(trailers.frame()))),
+            Err(err) => {
+                // End the stream after an error
+                self.segments.clear();
+                self.trailers = None;
+                Poll::Ready(Some(Err(err)))
+            }
+        }
+    }
+}
+
+/// A multipart body made of nodes, as an `http_body::Body` which a hyper 1 client or
+/// server can send as it is, without writing it out beforehand.  Its frames are those of
+/// `to_stream_body()`, made as they are polled, and its exact length is known ahead, so
+/// that hyper can send a `Content-Length`.  The boundary is generated.
+pub struct MultipartBody {
+    frames: MultipartFrames,
+    boundary: Vec<u8>,
+    remaining: u64,
+}
+
+impl MultipartBody {
+    /// The body made of `nodes`, written as `write_multipart()` writes them.
+    pub fn new(nodes: Vec<Node>) -> Result<MultipartBody, Error> {
+        MultipartBody::with_profile(nodes, &WriteProfile::default())
+    }
+
+    /// Like `new()`, but emitting the body as directed by the `WriteProfile` given, whose
+    /// `entropy` is also used for the boundary.  `WriteProfile::sha256_trailer` is ignored,
+    /// as for `to_stream_body_with()`.
+    pub fn with_profile(nodes: Vec<Node>, profile: &WriteProfile) -> Result<MultipartBody, Error> {
+        let boundary = match profile.entropy {
+            Some(ref entropy) => generate_boundary_with(entropy),
+            None => generate_boundary(),
+        };
+        let remaining = multipart_length(&boundary, &nodes, profile)?;
+        Ok(MultipartBody {
+            frames: to_stream_body_with(&boundary, nodes, profile)?,
+            boundary,
+            remaining,
+        })
+    }
+
+    /// The boundary delimiting the parts.
+    pub fn boundary(&self) -> &[u8] {
+        &self.boundary
+    }
+
+    /// The `Content-Type` header to send with the body, for a `multipart/<subtype>` such as
+    /// `form-data`.
+    pub fn content_type(&self, subtype: &str) -> Result<HeaderValue, Error> {
+        multipart_content_type(subtype, &self.boundary)
+    }
+
+    /// End the body with a trailers frame, as `MultipartFrames::with_trailers()` does.
+    pub fn with_trailers<F>(mut self, trailers: F) -> MultipartBody
+    where
+        F: FnOnce(&TrailerInfo) -> HeaderMap + Send + 'static,
+    {
+        self.frames = self.frames.with_trailers(trailers);
+        self
+    }
+}
+
+impl Body for MultipartBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Error>>> {
+        let this = &mut *self;
+        let frame = Pin::new(&mut this.frames).poll_next(cx);
+        if let Poll::Ready(Some(Ok(ref frame))) = frame {
+            if let Some(data) = frame.data_ref() {
+                this.remaining = this.remaining.saturating_sub(data.remaining() as u64);
+            }
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.frames.segments.is_empty() && self.frames.trailers.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
+
+/// What is known about a body once it has been sent, for computing its trailers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrailerInfo {
+    /// The number of bytes sent
+    pub length: u64,
+    /// The SHA-256 digest of the bytes sent, in lowercase hex
+    pub sha256: String,
+}
+
+// The running length and digest of a body whose trailers are computed at its end
+pub(crate) struct Trailers {
+    length: u64,
+    digest: Sha256,
+    make: Box<dyn FnOnce(&TrailerInfo) -> HeaderMap + Send>,
+}
+
+impl Trailers {
+    pub(crate) fn new<F>(make: F) -> Trailers
+    where
+        F: FnOnce(&TrailerInfo) -> HeaderMap + Send + 'static,
+    {
+        Trailers {
+            length: 0,
+            digest: Sha256::new(),
+            make: Box::new(make),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.length += data.len() as u64;
+        self.digest.update(data);
+    }
+
+    pub(crate) fn frame(self) -> Frame<Bytes> {
+        let info = TrailerInfo {
+            length: self.length,
+            sha256: hex_digest(self.digest),
+        };
+        Frame::trailers((self.make)(&info))
+    }
+}
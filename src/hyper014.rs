@@ -0,0 +1,71 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Interoperation with hyper 0.14, whose headers are those of version 0.2 of the `http`
+//! crate, for code running hyper 0.14 and 1.x side by side.
+
+use super::Error;
+#[cfg(feature = "parse")]
+use super::{Node, ParseProfile};
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+#[cfg(feature = "parse")]
+use std::io::Read;
+
+/// Convert hyper 0.14 (`http` 0.2) headers into the `HeaderMap` used by this crate.
+pub fn from_http02(headers: &http02::HeaderMap) -> Result<HeaderMap, Error> {
+    let mut map = HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers.iter() {
+        let name = match HeaderName::from_bytes(name.as_str().as_bytes()) {
+            Ok(name) => name,
+            Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+        };
+        let value = match HeaderValue::from_bytes(value.as_bytes()) {
+            Ok(value) => value,
+            Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+        };
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+/// Convert headers used by this crate, such as those of a parsed `Node`, into hyper 0.14
+/// (`http` 0.2) headers.
+pub fn to_http02(headers: &HeaderMap) -> Result<http02::HeaderMap, Error> {
+    let mut map = http02::HeaderMap::new();
+    for (name, value) in headers.iter() {
+        let name = match http02::header::HeaderName::from_bytes(name.as_str().as_bytes()) {
+            Ok(name) => name,
+            Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+        };
+        let value = match http02::header::HeaderValue::from_bytes(value.as_bytes()) {
+            Ok(value) => value,
+            Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+        };
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+/// Like `read_multipart_body()`, but taking hyper 0.14 headers.
+#[cfg(feature = "parse")]
+pub fn read_multipart_body<S: Read + ?Sized>(
+    stream: &mut S,
+    headers: &http02::HeaderMap,
+    always_use_files: bool,
+) -> Result<Vec<Node>, Error> {
+    super::read_multipart_body(stream, &from_http02(headers)?, always_use_files)
+}
+
+/// Like `read_multipart_body_with()`, but taking hyper 0.14 headers.
+#[cfg(feature = "parse")]
+pub fn read_multipart_body_with<S: Read + ?Sized>(
+    stream: &mut S,
+    headers: &http02::HeaderMap,
+    profile: &ParseProfile,
+) -> Result<Vec<Node>, Error> {
+    super::read_multipart_body_with(stream, &from_http02(headers)?, profile)
+}
@@ -0,0 +1,73 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+// Parsing the body of a hyper 1 request, or any other `http_body::Body`, as its frames arrive.
+
+use super::builder::BodyParser;
+use super::{always_use_files_policy, Error, Node, ParseProfile};
+use bytes::Buf;
+use http::header::HeaderMap;
+use http_body::Body;
+use std::future::poll_fn;
+use std::io;
+
+/// Like `read_multipart_body()`, but taking the body from an `http_body::Body`, such as the
+/// `hyper::body::Incoming` of a hyper 1 request, whose `headers` are given apart (as
+/// `Request::into_parts()` splits them).  Each data frame is parsed as it arrives, so the
+/// body is never collected in memory; trailers are ignored.  An error of the body is
+/// returned as `Error::Io`.
+pub async fn read_multipart_http_body<B>(
+    body: B,
+    headers: &HeaderMap,
+    always_use_files: bool,
+) -> Result<Vec<Node>, Error>
+where
+    B: Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let profile = ParseProfile {
+        disposition: always_use_files_policy(always_use_files),
+        ..ParseProfile::default()
+    };
+    read_multipart_http_body_with(body, headers, &profile).await
+}
+
+/// Like `read_multipart_http_body()`, but parsing as directed by the `ParseProfile` given,
+/// as `read_multipart_body_async_with()` does: no more frames are polled once the close
+/// delimiter, or the last of the `required_fields`, has been read, unless `stop_at` is
+/// `StopAt::Eof`, and data past a verified `Content-Length` is an error.  Parsing stops at the
+/// first error, leaving the rest of the body unread.
+pub async fn read_multipart_http_body_with<B>(
+    body: B,
+    headers: &HeaderMap,
+    profile: &ParseProfile,
+) -> Result<Vec<Node>, Error>
+where
+    B: Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let mut builder = BodyParser::new(headers, profile)?;
+    let mut body = std::pin::pin!(body);
+    loop {
+        if builder.wanted() == Some(0) {
+            return builder.finish();
+        }
+        let frame = match poll_fn(|cx| body.as_mut().poll_frame(cx)).await {
+            None => return builder.finish(),
+            Some(Ok(frame)) => frame,
+            Some(Err(err)) => return Err(Error::Io(io::Error::other(err))),
+        };
+        if let Ok(mut data) = frame.into_data() {
+            while data.has_remaining() {
+                let chunk = data.chunk();
+                let len = chunk.len();
+                builder.feed(chunk)?;
+                data.advance(len);
+            }
+        }
+    }
+}
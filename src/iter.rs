@@ -0,0 +1,99 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{expected_length, finish_body, next_part, read_preamble};
+use super::{Counting, Delimiters, Error, Node, ParseProfile, ParseState, PartStep};
+use http::header::HeaderMap;
+use std::io::{BufReader, Read};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    Parts,
+    // The last part has been returned; the end of the body is still to be read
+    Closing,
+    Done,
+}
+
+/// An iterator over the top-level parts of a multipart body, reading each part only when
+/// asked for it, so that a caller which has found what it needs can stop without the rest of
+/// the body being parsed.  Parts are parsed as by `read_multipart_body_with()`, files being
+/// spooled and nested multiparts read whole, and the `Limits` of the `ParseProfile` apply.
+/// Iteration ends after the close delimiter, or after the first error.
+pub struct MultipartIter<'p, R> {
+    reader: Counting<BufReader<R>>,
+    state: ParseState<'p>,
+    delimiters: Delimiters,
+    expected: Option<u64>,
+    phase: Phase,
+}
+
+impl<'p, R: Read> MultipartIter<'p, R> {
+    /// Read the body of a request or part with the given headers from `reader`, parsing it
+    /// as directed by the `ParseProfile` given.  The preamble is read right away, up to the
+    /// first part.
+    pub fn new(
+        reader: R,
+        headers: &HeaderMap,
+        profile: &'p ParseProfile,
+    ) -> Result<MultipartIter<'p, R>, Error> {
+        let mut reader = Counting::new(BufReader::with_capacity(4096, reader));
+        let mut state = ParseState::new(profile);
+        let delimiters = read_preamble(&mut reader, headers, &mut state)?;
+        Ok(MultipartIter {
+            reader,
+            state,
+            delimiters,
+            expected: expected_length(headers, profile),
+            phase: Phase::Parts,
+        })
+    }
+
+    /// The number of bytes read so far.
+    pub fn position(&self) -> u64 {
+        self.reader.consumed
+    }
+
+    // Read past the end of the body as the profile says, checking its length
+    fn finish(&mut self, result: Result<(), Error>) -> Result<(), Error> {
+        finish_body(&mut self.reader, &self.state, self.expected, result)
+    }
+}
+
+impl<R: Read> Iterator for MultipartIter<'_, R> {
+    type Item = Result<Node, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.phase {
+            Phase::Done => return None,
+            Phase::Closing => {
+                self.phase = Phase::Done;
+                return self.finish(Ok(())).err().map(Err);
+            }
+            Phase::Parts => {}
+        }
+        match next_part(&mut self.reader, &self.delimiters, &mut self.state) {
+            Ok(PartStep::Node(node)) => {
+                if self.state.collected(Some(&node)) {
+                    self.phase = Phase::Closing;
+                }
+                Some(Ok(node))
+            }
+            Ok(PartStep::Last(node)) => {
+                self.phase = Phase::Closing;
+                Some(Ok(node))
+            }
+            Ok(PartStep::End) => {
+                self.phase = Phase::Done;
+                self.finish(Ok(())).err().map(Err)
+            }
+            Err(err) => {
+                self.phase = Phase::Done;
+                self.finish(Err(err)).err().map(Err)
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{ContentDisposition, DispositionType, Error, FilePart, Node, Part};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use http::header::{HeaderMap, HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE};
+use serde_json::Value;
+use std::path::Path;
+
+/// Turn a JSON object describing a form into `multipart/form-data` nodes, for test
+/// harnesses and clients which describe their payloads as data.  Members are flattened into
+/// fields named by their path, as by `FormData::from_json_form()`: `{"a": {"b": 1}}` gives a
+/// field `a[b]` with the text `1`, array items repeat the name of the array, and nulls are
+/// left out.  File objects with the members `type`, `name` and `body` become parts holding
+/// the base64 `body` decoded, and those with `type`, `name` and `path` become `FilePart`s
+/// for the file at `path`, which is read when the nodes are written and never deleted.
+/// Paths are taken as given, so this is not for JSON from untrusted sources.
+pub fn nodes_from_json(value: &Value) -> Result<Vec<Node>, Error> {
+    let members = value.as_object().ok_or(Error::InvalidJson)?;
+    let mut nodes = Vec::new();
+    for (name, value) in members {
+        flatten(name.clone(), value, &mut |name, leaf| {
+            nodes.push(node_from_leaf(&name, leaf)?);
+            Ok(())
+        })?;
+    }
+    Ok(nodes)
+}
+
+fn node_from_leaf(name: &str, leaf: &Value) -> Result<Node, Error> {
+    if !is_file(leaf) {
+        let disposition =
+            ContentDisposition::new(DispositionType::FormData).with_param("name", name);
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_DISPOSITION, disposition.to_header_value()?);
+        let body = scalar_text(leaf).into_bytes();
+        return Ok(Node::Part(Part { headers, body }));
+    }
+    let headers = file_headers(name, leaf)?;
+    match (leaf.get("body"), leaf.get("path")) {
+        (Some(Value::String(body)), _) => {
+            let body = decode_base64(body).ok_or(Error::InvalidJson)?;
+            Ok(Node::Part(Part { headers, body }))
+        }
+        (_, Some(Value::String(path))) => Ok(Node::File(FilePart::new(headers, Path::new(path)))),
+        _ => Err(Error::InvalidJson),
+    }
+}
+
+// Call `leaf` with each scalar and file object within `value`, and the field name it has
+// when flattened under `name`
+pub(crate) fn flatten<F>(name: String, value: &Value, leaf: &mut F) -> Result<(), Error>
+where
+    F: FnMut(String, &Value) -> Result<(), Error>,
+{
+    match *value {
+        Value::Null => Ok(()),
+        Value::Array(ref items) => {
+            for (index, item) in items.iter().enumerate() {
+                let name = match *item {
+                    Value::Array(_) => format!("{}[{}]", name, index),
+                    Value::Object(_) if !is_file(item) => format!("{}[{}]", name, index),
+                    _ => name.clone(),
+                };
+                flatten(name, item, leaf)?;
+            }
+            Ok(())
+        }
+        Value::Object(ref members) if !is_file(value) => {
+            for (key, member) in members {
+                let name = match key.is_empty() {
+                    true => name.clone(),
+                    false => format!("{}[{}]", name, key),
+                };
+                flatten(name, member, leaf)?;
+            }
+            Ok(())
+        }
+        _ => leaf(name, value),
+    }
+}
+
+// Whether `value` is an object representing a file
+pub(crate) fn is_file(value: &Value) -> bool {
+    match *value {
+        Value::Object(ref members) => {
+            let has = |key: &str| value.get(key).and_then(Value::as_str).is_some();
+            members.len() == 3 && has("type") && has("name") && (has("body") || has("path"))
+        }
+        _ => false,
+    }
+}
+
+// The text of a field with a scalar value
+pub(crate) fn scalar_text(value: &Value) -> String {
+    match *value {
+        Value::String(ref value) => value.clone(),
+        ref value => value.to_string(),
+    }
+}
+
+// The headers of a part for the file object `value`, in the field `name`
+pub(crate) fn file_headers(name: &str, value: &Value) -> Result<HeaderMap, Error> {
+    let field = |key: &str| value.get(key).and_then(Value::as_str).unwrap_or_default();
+    let mut disposition =
+        ContentDisposition::new(DispositionType::FormData).with_param("name", name);
+    if !field("name").is_empty() {
+        disposition = disposition.with_param("filename", field("name"));
+    }
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_DISPOSITION, disposition.to_header_value()?);
+    if !field("type").is_empty() {
+        let content_type = HeaderValue::from_str(field("type")).map_err(|_| Error::InvalidJson)?;
+        headers.insert(CONTENT_TYPE, content_type);
+    }
+    Ok(headers)
+}
+
+// Decode base64, ignoring whitespace, or None if it is malformed
+pub(crate) fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    let input: Vec<u8> = input
+        .bytes()
+        .filter(|ch| !ch.is_ascii_whitespace())
+        .collect();
+    STANDARD.decode(input).ok()
+}
@@ -0,0 +1,222 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+// Conversion between `FormData` and the JSON encoding of HTML forms (the W3C HTML JSON form
+// submission note), in which a field `a[b][]` becomes `{"a": {"b": [...]}}`.
+
+use super::json::{decode_base64, file_headers, flatten, is_file, scalar_text};
+use super::{Error, FilePart, FormData};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{Map, Value};
+use std::io::{Read, Write};
+
+// The largest array index a field name may use; larger ones make the name an ordinary key,
+// so that `a[4000000000]` cannot make a huge array
+const MAX_INDEX: usize = 1024;
+
+/// How `FormData::to_json_form()` represents files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JsonFiles {
+    /// As `{"type", "name", "body"}`, with the content in base64, as the W3C note has it.
+    #[default]
+    Base64,
+    /// As `{"type", "name", "path"}`, giving the path of the file on disk rather than its
+    /// content, for passing forms on within the same host.
+    Reference,
+}
+
+// One step of the path a field name describes
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+impl FormData {
+    /// The form as an HTML JSON form: each field's name is taken as a path into nested
+    /// objects and arrays (`a[b]`, `a[0]`, with `a[]` appending), fields repeating a name
+    /// are gathered into an array, and names which are not valid paths are used as they
+    /// are.  Files are represented as `files` says.
+    pub fn to_json_form(&self, files: JsonFiles) -> Result<Value, Error> {
+        let mut root = Value::Object(Map::new());
+        for (name, value) in &self.fields {
+            set_value(&mut root, name, Value::String(value.clone()));
+        }
+        for (name, filepart) in &self.files {
+            let mut members = Map::new();
+            members.insert("type".to_owned(), file_type(filepart));
+            members.insert(
+                "name".to_owned(),
+                Value::String(filepart.filename()?.unwrap_or_default()),
+            );
+            match files {
+                JsonFiles::Base64 => {
+                    let mut content = Vec::new();
+                    std::fs::File::open(&filepart.path)?.read_to_end(&mut content)?;
+                    members.insert("body".to_owned(), Value::String(STANDARD.encode(&content)));
+                }
+                JsonFiles::Reference => {
+                    let path = filepart.path.to_string_lossy().into_owned();
+                    members.insert("path".to_owned(), Value::String(path));
+                }
+            }
+            set_value(&mut root, name, Value::Object(members));
+        }
+        Ok(root)
+    }
+
+    /// The form an HTML JSON form describes, as `to_json_form()` makes them: nested members
+    /// become fields named by their path, with array items repeating the name of the array
+    /// unless they are themselves objects or arrays.  Numbers and booleans become the text
+    /// of fields, and nulls are left out.  Objects with just the members `type`, `name` and
+    /// `body` are taken as files, which are written to temporary files as `FilePart::create()`
+    /// makes.  Files given by `path` are refused with `Error::InvalidJson`, as are documents
+    /// other than an object, since a client could otherwise name any file on the host.
+    pub fn from_json_form(value: &Value) -> Result<FormData, Error> {
+        let members = value.as_object().ok_or(Error::InvalidJson)?;
+        let mut form = FormData::default();
+        for (name, value) in members {
+            flatten(name.clone(), value, &mut |name, leaf| {
+                add_value(&mut form, name, leaf)
+            })?;
+        }
+        Ok(form)
+    }
+}
+
+fn file_type(filepart: &FilePart) -> Value {
+    let content_type = filepart
+        .content_type_raw()
+        .unwrap_or_else(|| "application/octet-stream".to_owned());
+    Value::String(content_type)
+}
+
+// Split a field name into steps as the W3C note does, or None if it is not a valid path
+fn parse_path(name: &str) -> Option<(Vec<Step>, bool)> {
+    let first = name.find('[').unwrap_or(name.len());
+    if first == 0 {
+        return None;
+    }
+    let mut steps = vec![Step::Key(name[..first].to_owned())];
+    let mut rest = &name[first..];
+    let mut append = false;
+    while !rest.is_empty() {
+        if append || !rest.starts_with('[') {
+            return None;
+        }
+        let end = rest.find(']')?;
+        let key = &rest[1..end];
+        if key.is_empty() {
+            append = true;
+        } else {
+            steps.push(match key.parse::<usize>() {
+                Ok(index) if index <= MAX_INDEX && key.bytes().all(|ch| ch.is_ascii_digit()) => {
+                    Step::Index(index)
+                }
+                _ => Step::Key(key.to_owned()),
+            });
+        }
+        rest = &rest[end + 1..];
+    }
+    Some((steps, append))
+}
+
+// Set `value` at the path `name` describes within `root`
+fn set_value(root: &mut Value, name: &str, value: Value) {
+    let (steps, append) =
+        parse_path(name).unwrap_or_else(|| (vec![Step::Key(name.to_owned())], false));
+    let mut context = root;
+    for (index, step) in steps.iter().enumerate() {
+        let slot = entry(context, step);
+        match steps.get(index + 1) {
+            None => {
+                match slot {
+                    Value::Null if append => *slot = Value::Array(vec![value]),
+                    Value::Null => *slot = value,
+                    Value::Array(items) => items.push(value),
+                    Value::Object(_) if !is_file(slot) => {
+                        *entry(slot, &Step::Key(String::new())) = value
+                    }
+                    _ => *slot = Value::Array(vec![slot.take(), value]),
+                }
+                return;
+            }
+            Some(next) => {
+                let next_is_index = matches!(next, Step::Index(_));
+                match slot {
+                    Value::Null if next_is_index => *slot = Value::Array(Vec::new()),
+                    Value::Null => *slot = Value::Object(Map::new()),
+                    Value::Array(_) if !next_is_index => array_to_object(slot),
+                    Value::Array(_) => {}
+                    Value::Object(_) if !is_file(slot) => {}
+                    _ => {
+                        let mut members = Map::new();
+                        members.insert(String::new(), slot.take());
+                        *slot = Value::Object(members);
+                    }
+                }
+                context = slot;
+            }
+        }
+    }
+}
+
+// The member of an object or item of an array which `step` names, added as a null if there
+// is none yet.  Arrays keyed by name are made objects first.
+fn entry<'a>(context: &'a mut Value, step: &Step) -> &'a mut Value {
+    if let (Value::Array(_), Step::Key(_)) = (&*context, step) {
+        array_to_object(context);
+    }
+    match (context, step) {
+        (Value::Array(items), &Step::Index(index)) => {
+            if items.len() <= index {
+                items.resize(index + 1, Value::Null);
+            }
+            &mut items[index]
+        }
+        (Value::Object(members), step) => {
+            let key = match *step {
+                Step::Key(ref key) => key.clone(),
+                Step::Index(index) => index.to_string(),
+            };
+            members.entry(key).or_insert(Value::Null)
+        }
+        _ => unreachable!("entries are only taken of objects and arrays"),
+    }
+}
+
+// Make an array an object keyed by the indexes of its items, leaving out the gaps
+fn array_to_object(value: &mut Value) {
+    if let Value::Array(items) = value.take() {
+        let members = items
+            .into_iter()
+            .enumerate()
+            .filter(|(_, item)| !item.is_null())
+            .map(|(index, item)| (index.to_string(), item))
+            .collect();
+        *value = Value::Object(members);
+    }
+}
+
+// Add the fields and files `value` holds to `form`, under the field name `name`
+fn add_value(form: &mut FormData, name: String, value: &Value) -> Result<(), Error> {
+    match *value {
+        Value::Object(_) => {
+            let headers = file_headers(&name, value)?;
+            let content = match value.get("body").and_then(Value::as_str) {
+                Some(body) => decode_base64(body).ok_or(Error::InvalidJson)?,
+                None => return Err(Error::InvalidJson),
+            };
+            let mut filepart = FilePart::create(headers)?;
+            filepart.create_file()?.write_all(&content)?;
+            filepart.size = Some(content.len());
+            form.files.push((name, filepart));
+        }
+        ref value => form.fields.push((name, scalar_text(value))),
+    }
+    Ok(())
+}
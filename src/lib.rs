@@ -5,38 +5,209 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-pub mod error;
+// Emit a record at `level` through the `log` crate, with the `log` feature.  Without it, the
+// arguments are still type checked, but never evaluated.
+macro_rules! record {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        log::$level!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
 
-#[cfg(test)]
+#[cfg(all(feature = "tokio", feature = "write"))]
+mod async_write;
+mod attachments;
+mod budget;
+#[cfg(any(feature = "tokio", all(feature = "http-body", feature = "parse")))]
+mod builder;
+#[cfg(feature = "http-body")]
+mod byteranges;
+mod charset;
+#[cfg(feature = "bytes")]
+mod chunks;
+#[cfg(all(feature = "parse", feature = "write"))]
+mod crlf;
+#[cfg(feature = "sha2")]
+mod diff;
+mod disposition;
+#[cfg(feature = "parse")]
+mod documents;
+mod entropy;
+pub mod error;
+#[cfg(feature = "parse")]
+mod form;
+#[cfg(feature = "http-body")]
+mod frames;
+#[cfg(feature = "hyper014")]
+pub mod hyper014;
+#[cfg(all(feature = "http-body", feature = "parse"))]
+mod incoming;
+#[cfg(feature = "parse")]
+mod iter;
+#[cfg(feature = "serde_json")]
+mod json;
+#[cfg(all(feature = "parse", feature = "serde_json"))]
+mod json_form;
+#[cfg(feature = "lettre")]
+pub mod mail;
+#[cfg(feature = "serde_json")]
+mod manifest;
+mod normalize;
+#[cfg(feature = "parse")]
+mod parser;
+pub mod profile;
+mod range;
+mod related;
+#[cfg(feature = "parse")]
+mod setters;
+#[cfg(feature = "parse")]
+mod source;
+mod spool;
+#[cfg(feature = "tokio")]
+mod stream;
+mod subtype;
+#[cfg(feature = "write")]
+mod swa;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "parse")]
+mod token;
+#[cfg(feature = "headers")]
+pub mod typed;
+mod uuencode;
+mod validate;
+#[cfg(feature = "zeroize")]
+mod wipe;
+
+// The tests exercise parsing and writing together
+#[cfg(all(test, feature = "parse", feature = "write"))]
 mod tests;
 
-pub use error::Error;
-
-use buf_read_ext::BufReadExt;
-use http::header::{HeaderMap, HeaderName, HeaderValue};
+#[cfg(not(any(feature = "parse", feature = "write")))]
+compile_error!("at least one of the `parse` and `write` features must be enabled");
+
+#[cfg(all(feature = "tokio", feature = "write"))]
+pub use async_write::{write_multipart_async, write_multipart_async_with};
+#[cfg(all(feature = "tokio", feature = "write"))]
+pub use async_write::{write_multipart_chunked_async, write_multipart_chunked_async_with};
+pub use attachments::{extract_attachments, Attachment};
+pub use budget::DiskBudget;
+#[cfg(feature = "http-body")]
+pub use byteranges::{byteranges_response, ByteRangesBody};
+pub use charset::Charset;
+#[cfg(feature = "bytes")]
+pub use chunks::BytesChunks;
+#[cfg(all(feature = "parse", feature = "write"))]
+pub use crlf::CrlfEncoder;
+#[cfg(feature = "sha2")]
+pub use diff::{diff, Difference, DifferenceKind};
+pub use disposition::{unescape_html, ContentDisposition, DispositionType};
+#[cfg(feature = "parse")]
+pub use documents::MultipartDocuments;
+pub use entropy::{Entropy, EntropySource};
+#[cfg(feature = "sha2")]
+pub use error::Redacted;
+pub use error::{Error, ErrorCategory, HeaderParseError};
+#[cfg(feature = "parse")]
+pub use form::{read_form_data, read_form_data_with, FormData};
+#[cfg(feature = "http-body")]
+pub use frames::{
+    to_stream_body, to_stream_body_with, MultipartBody, MultipartFrames, TrailerInfo,
+};
+#[cfg(all(feature = "http-body", feature = "parse"))]
+pub use incoming::{read_multipart_http_body, read_multipart_http_body_with};
+#[cfg(feature = "parse")]
+pub use iter::MultipartIter;
+#[cfg(feature = "serde_json")]
+pub use json::nodes_from_json;
+#[cfg(all(feature = "parse", feature = "serde_json"))]
+pub use json_form::JsonFiles;
+#[cfg(feature = "serde_json")]
+pub use manifest::add_manifest;
+pub use normalize::normalize;
+#[cfg(feature = "parse")]
+pub use parser::{Event, MultipartParser};
+pub use profile::{
+    CleanupHook, ContentTypeChoice, Disposition, DispositionPolicy, Limits, ParseMode,
+    ParseProfile, SpoolRules, StopAt, WriteProfile,
+};
+pub use range::{ContentRange, RangePlan};
+pub use related::Related;
+#[cfg(feature = "parse")]
+pub use setters::FormSetters;
+#[cfg(feature = "parse")]
+pub use source::{MultipartReader, MultipartSource};
+pub use spool::{FilePartBuilder, NameStrategy, SpoolName, TempNameStrategy};
+pub use spool::{RandomName, SequentialName, UuidName};
+#[cfg(feature = "tokio")]
+pub use stream::{read_multipart_async, read_multipart_async_with, Field, MultipartStream};
+#[cfg(feature = "tokio")]
+pub use stream::{read_multipart_body_async, read_multipart_body_async_with};
+pub use subtype::MultipartSubtype;
+#[cfg(feature = "write")]
+pub use swa::{SwaBuilder, SwaMessage};
+#[cfg(feature = "parse")]
+pub use token::{split_parts, stream_until_delimiter, stream_until_token, SplitParts};
+pub use uuencode::decode_uuencoded;
+pub use validate::{validate, Violation, ViolationKind};
+
+use budget::Reservation;
+use entropy::ALPHANUMERIC;
+use http::header::{
+    HeaderMap, HeaderName, HeaderValue, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE,
+};
 use mime::Mime;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Write};
+#[cfg(feature = "sha2")]
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
 use std::ops::Drop;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+
+#[cfg(feature = "parse")]
+use buf_read_ext::BufReadExt;
+#[cfg(all(feature = "parse", feature = "sha2"))]
+use std::collections::HashMap;
+#[cfg(feature = "parse")]
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom};
+
+#[cfg(feature = "write")]
+use std::borrow::Cow;
+#[cfg(feature = "write")]
+use std::io;
+#[cfg(feature = "write")]
 use textnonce::TextNonce;
 
 /// A multipart part which is not a file (stored in memory)
+///
+/// With the `zeroize` feature, the body is overwritten with zeros when the part is dropped,
+/// for forms carrying passwords or tokens.  Bytes moved out of it beforehand, e.g. into the
+/// fields of a `FormData`, are not covered.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Part {
     pub headers: HeaderMap,
     pub body: Vec<u8>,
 }
+
+// Implemented whatever the features, so that enabling `zeroize` does not stop fields being
+// moved out of a `Part` elsewhere in the dependency graph
+impl Drop for Part {
+    fn drop(&mut self) {
+        #[cfg(feature = "zeroize")]
+        wipe::wipe(&mut self.body);
+    }
+}
 impl Part {
     /// Mime content-type specified in the header
     pub fn content_type(&self) -> Option<Mime> {
         match self.headers.get("content-type") {
             Some(ct) => match ct.to_str() {
-                Ok(value) => match Mime::from_str(value) {
-                    Ok(value) => Some(value),
-                    Err(_) => None,
-                },
+                Ok(value) => Mime::from_str(value).ok(),
                 Err(_) => None,
             },
             None => None,
@@ -57,6 +228,12 @@ pub struct FilePart {
     pub size: Option<usize>,
     // The temporary directory the upload was put into, saved for the Drop trait
     tempdir: Option<PathBuf>,
+    // The disk budget bytes held by the upload, given back on drop
+    reservation: Option<Reservation>,
+    // Whether to overwrite the file before deleting it on drop
+    shred: bool,
+    // Told of failures to delete the file on drop
+    cleanup_hook: Option<CleanupHook>,
 }
 impl FilePart {
     pub fn new(headers: HeaderMap, path: &Path) -> FilePart {
@@ -65,6 +242,9 @@ impl FilePart {
             path: path.to_owned(),
             size: None,
             tempdir: None,
+            reservation: None,
+            shred: false,
+            cleanup_hook: None,
         }
     }
 
@@ -74,24 +254,117 @@ impl FilePart {
         self.tempdir = None;
     }
 
+    /// Overwrite the file with zeros, and sync it to disk, before deleting it when Self
+    /// drops, for sensitive uploads on shared disks.  This has no effect on a file which is
+    /// not deleted on drop.  Filesystems which write out of place (copy-on-write, log
+    /// structured, or on flash with wear leveling) may keep the old blocks regardless.  On
+    /// Unix, a file with other hard links to it, as `ParseProfile::dedup_files` makes, is
+    /// only overwritten when the last of them is deleted.
+    pub fn shred_on_drop(&mut self) {
+        self.shred = true;
+    }
+
+    /// Have `hook` called should the file or its temporary directory fail to be deleted when
+    /// Self drops, rather than the failure going unnoticed.
+    pub fn set_cleanup_hook(&mut self, hook: CleanupHook) {
+        self.cleanup_hook = Some(hook);
+    }
+
+    /// Delete the file and its temporary directory now, as would happen when Self drops,
+    /// returning any error in doing so.  This does nothing for a file which is not deleted
+    /// on drop.  A file which has already been moved away is not an error.
+    pub fn close(mut self) -> Result<(), Error> {
+        match self.tempdir.take() {
+            Some(tempdir) => Ok(self.remove(&tempdir)?),
+            None => Ok(()),
+        }
+    }
+
+    // Delete the file, shredding it first if asked to, and then its directory
+    fn remove(&self, tempdir: &Path) -> std::io::Result<()> {
+        let ignore_missing = |result: std::io::Result<()>| match result {
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            result => result,
+        };
+        let shredded = match self.shred {
+            true => ignore_missing(shred_file(&self.path)),
+            false => Ok(()),
+        };
+        let removed = ignore_missing(std::fs::remove_file(&self.path));
+        let removed_dir = ignore_missing(std::fs::remove_dir(tempdir));
+        shredded.and(removed).and(removed_dir)
+    }
+
     /// Create a new temporary FilePart (when created this way, the file will be
-    /// deleted once the FilePart object goes out of scope).
+    /// deleted once the FilePart object goes out of scope).  The file is named as by
+    /// `RandomName`; a `FilePartBuilder` with `SpoolName::Strategy` names it otherwise.
     pub fn create(headers: HeaderMap) -> Result<FilePart, Error> {
+        FilePart::create_in(headers, None, None, None)
+    }
+
+    // Create a new temporary FilePart in a directory of its own, made within `dir` if given
+    // or else the system's temporary directory, under `name` if given or else a random name.
+    // Random names are taken from `entropy` if given.
+    pub(crate) fn create_in(
+        headers: HeaderMap,
+        dir: Option<&Path>,
+        name: Option<String>,
+        entropy: Option<&Entropy>,
+    ) -> Result<FilePart, Error> {
         // Setup a file to capture the contents.
-        let mut path = tempfile::Builder::new()
-            .prefix("mime_multipart")
-            .tempdir()?
-            .into_path();
+        let mut path = match entropy {
+            Some(entropy) => create_tempdir(dir, entropy)?,
+            None => {
+                let mut builder = tempfile::Builder::new();
+                builder.prefix("mime_multipart");
+                match dir {
+                    Some(dir) => builder.tempdir_in(dir)?,
+                    None => builder.tempdir()?,
+                }
+                .keep()
+            }
+        };
         let tempdir = Some(path.clone());
-        path.push(TextNonce::sized_urlsafe(32).unwrap().into_string());
+        match (name, entropy) {
+            (Some(name), _) => path.push(name),
+            (None, Some(entropy)) => path.push(entropy.string(ALPHANUMERIC, 32)),
+            (None, None) => path.push(random_file_name()),
+        }
         Ok(FilePart {
             headers,
             path,
             size: None,
             tempdir,
+            reservation: None,
+            shred: false,
+            cleanup_hook: None,
         })
     }
 
+    // Create the file at `path`, refusing to open one which already exists.  Should one
+    // exist, a fresh random name in the same directory is tried instead.
+    pub(crate) fn create_file(&mut self) -> Result<File, Error> {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true);
+        self.create_file_with(&options)
+    }
+
+    // As `create_file()`, opening the file with `options`
+    pub(crate) fn create_file_with(&mut self, options: &OpenOptions) -> Result<File, Error> {
+        let mut attempts = 0;
+        loop {
+            let result = options.clone().create_new(true).open(&self.path);
+            match result {
+                Ok(file) => return Ok(file),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists && attempts < 8 => {
+                    attempts += 1;
+                    self.path.set_file_name(random_file_name());
+                }
+                Err(err) => return Err(Error::Io(err)),
+            }
+        }
+    }
+
     /// Filename that was specified when the file was uploaded.  Returns `Ok<None>` if there
     /// was no content-disposition header supplied.
     pub fn filename(&self) -> Result<Option<String>, Error> {
@@ -101,25 +374,87 @@ impl FilePart {
         }
     }
 
-    /// Mime content-type specified in the header
-    pub fn content_type(&self) -> Option<Mime> {
+    /// Mime content-type specified in the header.  Returns `Ok(None)` if there is none, and
+    /// an error if there is one which is not a valid `Mime`.
+    pub fn content_type(&self) -> Result<Option<Mime>, Error> {
         match self.headers.get("content-type") {
-            Some(ct) => match ct.to_str() {
-                Ok(value) => match Mime::from_str(value) {
-                    Ok(value) => Some(value),
-                    Err(_) => None,
-                },
-                Err(_) => None,
+            Some(ct) => match Mime::from_str(ct.to_str().map_err(Error::ToStr)?) {
+                Ok(mime) => Ok(Some(mime)),
+                Err(_) => Err(Error::HeaderValueNotMime),
             },
-            None => None,
+            None => Ok(None),
+        }
+    }
+
+    /// The content-type header as sent, parameters and all, whether or not it is a valid
+    /// `Mime`.  Bytes which are not UTF-8 are replaced with U+FFFD.
+    pub fn content_type_raw(&self) -> Option<String> {
+        self.headers
+            .get("content-type")
+            .map(|ct| String::from_utf8_lossy(ct.as_bytes()).into_owned())
+    }
+}
+// A random name for a spooled file, made as `tempfile` makes the names of its own
+fn random_file_name() -> String {
+    std::iter::repeat_with(fastrand::alphanumeric)
+        .take(32)
+        .collect()
+}
+
+// Create a directory for a spooled file as `tempfile` does, within `dir` if given or else the
+// system's temporary directory, its name taken from `entropy`
+fn create_tempdir(dir: Option<&Path>, entropy: &Entropy) -> Result<PathBuf, Error> {
+    let base = dir.map_or_else(std::env::temp_dir, Path::to_owned);
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    let mut attempts = 0;
+    loop {
+        let path = base.join(format!("mime_multipart{}", entropy.string(ALPHANUMERIC, 6)));
+        match builder.create(&path) {
+            Ok(()) => return Ok(path),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists && attempts < 8 => attempts += 1,
+            Err(err) => return Err(Error::Io(err)),
+        }
+    }
+}
+
+// Overwrite a file with zeros and sync it, unless other hard links still need its content
+fn shred_file(path: &Path) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let metadata = file.metadata()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if metadata.nlink() > 1 {
+            return Ok(());
         }
     }
+    let zeros = [0; 64 * 1024];
+    let mut left = metadata.len();
+    while left > 0 {
+        let take = left.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..take])?;
+        left -= take as u64;
+    }
+    file.sync_all()
 }
+
 impl Drop for FilePart {
     fn drop(&mut self) {
-        if self.tempdir.is_some() {
-            let _ = std::fs::remove_file(&self.path);
-            let _ = std::fs::remove_dir(self.tempdir.as_ref().unwrap());
+        if let Some(tempdir) = self.tempdir.take() {
+            match self.remove(&tempdir) {
+                Ok(()) => record!(trace, "deleted {}", self.path.display()),
+                Err(err) => {
+                    record!(warn, "failed to delete {}: {}", self.path.display(), err);
+                    if let Some(ref hook) = self.cleanup_hook {
+                        hook.call(&self.path, &err);
+                    }
+                }
+            }
         }
     }
 }
@@ -135,6 +470,100 @@ pub enum Node {
     /// A container of nested multipart parts
     Multipart((HeaderMap, Vec<Node>)),
 }
+impl Node {
+    /// The headers of the node
+    pub fn headers(&self) -> &HeaderMap {
+        match *self {
+            Node::Part(ref part) => &part.headers,
+            Node::File(ref filepart) => &filepart.headers,
+            Node::Multipart((ref headers, _)) => headers,
+        }
+    }
+
+    /// The subtype of a `Node::Multipart`, such as `MultipartSubtype::Parallel`, or `None`
+    /// for other nodes
+    pub fn multipart_subtype(&self) -> Option<MultipartSubtype> {
+        match *self {
+            Node::Multipart((ref headers, _)) => MultipartSubtype::from_headers(headers),
+            _ => None,
+        }
+    }
+
+    /// Move the part out of a `Node::Part`, or `None` for other nodes
+    pub fn into_part(self) -> Option<Part> {
+        match self {
+            Node::Part(part) => Some(part),
+            _ => None,
+        }
+    }
+
+    /// Move the file part out of a `Node::File`, or `None` for other nodes.  The file part
+    /// keeps ownership of its temporary file, which is deleted when it is dropped, unlike a
+    /// clone of it, whose file goes when the first of the two is dropped.
+    pub fn into_file(self) -> Option<FilePart> {
+        match self {
+            Node::File(filepart) => Some(filepart),
+            _ => None,
+        }
+    }
+
+    /// Move the headers and nested nodes out of a `Node::Multipart`, or `None` for other
+    /// nodes
+    pub fn into_multipart(self) -> Option<(HeaderMap, Vec<Node>)> {
+        match self {
+            Node::Multipart(multipart) => Some(multipart),
+            _ => None,
+        }
+    }
+}
+
+/// Move the `Node::Part`s and `Node::File`s out of `nodes`, at any depth of nesting, in
+/// order.  The headers of nested multiparts are dropped.  File parts are moved rather than
+/// cloned, so each temporary file still has exactly one owner.
+pub fn into_parts(nodes: Vec<Node>) -> IntoParts {
+    IntoParts {
+        stack: vec![nodes.into_iter()],
+    }
+}
+
+/// The iterator returned by `into_parts()`.
+#[derive(Debug)]
+pub struct IntoParts {
+    stack: Vec<std::vec::IntoIter<Node>>,
+}
+
+impl Iterator for IntoParts {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Node> {
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some(Node::Multipart((_, nodes))) => self.stack.push(nodes.into_iter()),
+                Some(node) => return Some(node),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+/// Where a part was found within the multipart body it was parsed from, as byte offsets from
+/// the start of that body.  Returned by `read_multipart_body_spans()` in the same order and
+/// shape as the `Node`s, so that audit logs and the like can point into captured traffic.
+#[cfg(feature = "parse")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartSpan {
+    /// The offset of the part's headers, just after the boundary line preceding them
+    pub start: u64,
+    /// The offset of the part's body, just after the blank line ending its headers
+    pub body_start: u64,
+    /// The offset just past the end of the part's body, before the line terminator
+    /// preceding the next boundary
+    pub end: u64,
+    /// The spans of the parts of a nested multipart
+    pub parts: Vec<PartSpan>,
+}
 
 /// Parse a MIME `multipart/*` from a `Read`able stream into a `Vec` of `Node`s, streaming
 /// files to disk and keeping the rest in memory.  Recursive `multipart/*` parts will are
@@ -145,13 +574,31 @@ pub enum Node {
 /// parameter will be streamed to files.
 ///
 /// It is presumed that the headers are still in the stream.  If you have them separately,
-/// use `read_multipart_body()` instead.
-pub fn read_multipart<S: Read>(stream: &mut S, always_use_files: bool) -> Result<Vec<Node>, Error> {
+/// use `read_multipart_body()` instead.  The headers parsed from the stream are returned
+/// along with the nodes, so that Content-Length, cookies and the like remain available.
+#[cfg(feature = "parse")]
+pub fn read_multipart<S: Read + ?Sized>(
+    stream: &mut S,
+    always_use_files: bool,
+) -> Result<(HeaderMap, Vec<Node>), Error> {
+    let profile = ParseProfile {
+        disposition: always_use_files_policy(always_use_files),
+        ..ParseProfile::default()
+    };
+    read_multipart_with(stream, &profile)
+}
+
+/// Like `read_multipart()`, but parsing as directed by the `ParseProfile` given.
+#[cfg(feature = "parse")]
+pub fn read_multipart_with<S: Read + ?Sized>(
+    stream: &mut S,
+    profile: &ParseProfile,
+) -> Result<(HeaderMap, Vec<Node>), Error> {
     let mut reader = BufReader::with_capacity(4096, stream);
 
     let mut buf: Vec<u8> = Vec::new();
 
-    let (_, found) = reader.stream_until_token(b"\r\n\r\n", &mut buf)?;
+    let (_, found) = read_header_block(&mut reader, b"\r\n\r\n", &mut buf, profile)?;
     if !found {
         return Err(Error::EofInMainHeaders);
     }
@@ -160,41 +607,11 @@ pub fn read_multipart<S: Read>(stream: &mut S, always_use_files: bool) -> Result
     buf.extend(b"\r\n\r\n".iter().cloned());
 
     // Parse the headers
-    let mut header_memory = [httparse::EMPTY_HEADER; 64];
-    let headers = match httparse::parse_headers(&buf, &mut header_memory) {
-        Ok(httparse::Status::Complete((_, raw_headers))) => {
-            let mut headers = HeaderMap::new();
-            for header in raw_headers {
-                if header.value.is_empty() {
-                    break;
-                }
-                let trim = header
-                    .value
-                    .iter()
-                    .rev()
-                    .take_while(|&&x| x == b' ')
-                    .count();
-                let value = &header.value[..header.value.len() - trim];
-
-                let header_value = match HeaderValue::from_bytes(value) {
-                    Ok(value) => value,
-                    Err(_) => return Err(Error::InvalidHeaderNameOrValue),
-                };
-
-                let header_name = header.name.to_owned();
-                let header_name = match HeaderName::from_str(&header_name) {
-                    Ok(value) => value,
-                    Err(_) => return Err(Error::InvalidHeaderNameOrValue),
-                };
-                headers.append(header_name, header_value);
-            }
-            Ok(headers)
-        }
-        Ok(httparse::Status::Partial) => Err(Error::PartialHeaders),
-        Err(err) => Err(From::from(err)),
-    }?;
+    let headers = parse_headers(&buf, profile.limits.max_main_headers)?;
 
-    inner(&mut reader, &headers, always_use_files)
+    let mut reader = Counting::new(&mut reader);
+    let nodes = parse_body(&mut reader, &headers, &mut ParseState::new(profile))?;
+    Ok((headers, nodes))
 }
 
 /// Parse a MIME `multipart/*` from a `Read`able stream into a `Vec` of `Node`s, streaming
@@ -207,233 +624,1777 @@ pub fn read_multipart<S: Read>(stream: &mut S, always_use_files: bool) -> Result
 ///
 /// It is presumed that you have the `Headers` already and the stream starts at the body.
 /// If the headers are still in the stream, use `read_multipart()` instead.
-pub fn read_multipart_body<S: Read>(
+#[cfg(feature = "parse")]
+pub fn read_multipart_body<S: Read + ?Sized>(
     stream: &mut S,
     headers: &HeaderMap,
     always_use_files: bool,
 ) -> Result<Vec<Node>, Error> {
-    let mut reader = BufReader::with_capacity(4096, stream);
-    inner(&mut reader, headers, always_use_files)
+    let profile = ParseProfile {
+        disposition: always_use_files_policy(always_use_files),
+        ..ParseProfile::default()
+    };
+    read_multipart_body_with(stream, headers, &profile)
 }
 
-fn inner<R: BufRead>(
+/// Like `read_multipart_body()`, but parsing as directed by the `ParseProfile` given.
+#[cfg(feature = "parse")]
+pub fn read_multipart_body_with<S: Read + ?Sized>(
+    stream: &mut S,
+    headers: &HeaderMap,
+    profile: &ParseProfile,
+) -> Result<Vec<Node>, Error> {
+    let mut reader = Counting::new(BufReader::with_capacity(4096, stream));
+    parse_body(&mut reader, headers, &mut ParseState::new(profile))
+}
+
+/// Like `read_multipart_body_with()`, but reading from a `BufRead` directly, rather than
+/// through a buffer of its own.  Input which is already in memory, such as a collected
+/// request body, is then parsed without being copied into another buffer first.
+#[cfg(feature = "parse")]
+pub fn read_multipart_body_buffered<R: BufRead + ?Sized>(
     reader: &mut R,
     headers: &HeaderMap,
-    always_use_files: bool,
+    profile: &ParseProfile,
 ) -> Result<Vec<Node>, Error> {
-    let mut nodes: Vec<Node> = Vec::new();
-    let mut buf: Vec<u8> = Vec::new();
+    parse_body(
+        &mut Counting::new(reader),
+        headers,
+        &mut ParseState::new(profile),
+    )
+}
 
-    let boundary = get_multipart_boundary(headers)?;
+/// Like `read_multipart_body_buffered()`, but also returning the number of bytes consumed
+/// from `reader`: up to the end of the close delimiter (`--boundary--`), or with
+/// `ParseProfile::verify_content_length`, up to the `Content-Length`.  Nothing after that is
+/// consumed, so data following the body can be read from `reader` afterwards.
+#[cfg(feature = "parse")]
+pub fn read_multipart_body_counted<R: BufRead + ?Sized>(
+    reader: &mut R,
+    headers: &HeaderMap,
+    profile: &ParseProfile,
+) -> Result<(Vec<Node>, u64), Error> {
+    let mut reader = Counting::new(reader);
+    let nodes = parse_body(&mut reader, headers, &mut ParseState::new(profile))?;
+    Ok((nodes, reader.consumed))
+}
 
-    // Read past the initial boundary
-    let (_, found) = reader.stream_until_token(&boundary, &mut buf)?;
-    if !found {
-        return Err(Error::EofBeforeFirstBoundary);
+/// Like `read_multipart_body_with()`, but also copying the raw input to `capture` (such as
+/// an audit file) as it is read, so that a disputed upload can be inspected byte for byte, or
+/// parsed again later by `replay()`.  The capture starts with `headers`, written as a
+/// header block, followed by every byte read from `stream`: the whole body, unless parsing
+/// stops early, and possibly some read ahead past its end.  What was read is captured even
+/// if parsing fails.
+#[cfg(feature = "parse")]
+pub fn read_multipart_body_captured<S: Read + ?Sized, W: Write + ?Sized>(
+    stream: &mut S,
+    headers: &HeaderMap,
+    profile: &ParseProfile,
+    capture: &mut W,
+) -> Result<Vec<Node>, Error> {
+    write_headers(capture, headers, None)?;
+    let tee = Tee {
+        inner: stream,
+        capture: &mut *capture,
+    };
+    let mut reader = Counting::new(BufReader::with_capacity(4096, tee));
+    let result = parse_body(&mut reader, headers, &mut ParseState::new(profile));
+    capture.flush()?;
+    result
+}
+
+/// Parse a capture written by `read_multipart_body_captured()` again, as directed by the
+/// `ParseProfile` given, which may differ from the one it was captured with (e.g. with
+/// stricter limits).  Returns the captured headers along with the nodes.
+#[cfg(feature = "parse")]
+pub fn replay<S: Read + ?Sized>(
+    capture: &mut S,
+    profile: &ParseProfile,
+) -> Result<(HeaderMap, Vec<Node>), Error> {
+    read_multipart_with(capture, profile)
+}
+
+/// Like `replay()`, but reading the capture from the file at `path`.
+#[cfg(feature = "parse")]
+pub fn replay_file(path: &Path, profile: &ParseProfile) -> Result<(HeaderMap, Vec<Node>), Error> {
+    replay(&mut File::open(path)?, profile)
+}
+
+/// Like `read_multipart_body_with()`, but also returning where each part was found in the
+/// body, as a tree of `PartSpan`s matching the `Node`s.
+#[cfg(feature = "parse")]
+pub fn read_multipart_body_spans<S: Read + ?Sized>(
+    stream: &mut S,
+    headers: &HeaderMap,
+    profile: &ParseProfile,
+) -> Result<(Vec<Node>, Vec<PartSpan>), Error> {
+    let mut reader = Counting::new(BufReader::with_capacity(4096, stream));
+    let mut state = ParseState::new(profile);
+    state.record_spans = true;
+    let nodes = parse_body(&mut reader, headers, &mut state)?;
+    Ok((nodes, state.spans))
+}
+
+// Parse a body with `inner()`, then check its length against the `Content-Length` in
+// `headers` if the profile asks for it.  The epilogue counts towards the length, so it is
+// read up to there.
+#[cfg(feature = "parse")]
+fn parse_body<R: BufRead>(
+    reader: &mut Counting<R>,
+    headers: &HeaderMap,
+    state: &mut ParseState,
+) -> Result<Vec<Node>, Error> {
+    let expected = expected_length(headers, state.profile);
+    let result = inner(reader, headers, state);
+    finish_body(reader, state, expected, result)
+}
+
+// The length a body must have, if the profile asks for it to be checked
+#[cfg(feature = "parse")]
+fn expected_length(headers: &HeaderMap, profile: &ParseProfile) -> Option<u64> {
+    match profile.verify_content_length {
+        true => content_length(headers),
+        false => None,
     }
+}
 
-    // Define the boundary, including the line terminator preceding it.
-    // Use their first line terminator to determine whether to use CRLF or LF.
-    let (lt, ltlt, lt_boundary) = {
-        let peeker = reader.fill_buf()?;
-        if peeker.len() > 1 && &peeker[..2] == b"\r\n" {
-            let mut output = Vec::with_capacity(2 + boundary.len());
-            output.push(b'\r');
-            output.push(b'\n');
-            output.extend(boundary.clone());
-            (vec![b'\r', b'\n'], vec![b'\r', b'\n', b'\r', b'\n'], output)
-        } else if !peeker.is_empty() && peeker[0] == b'\n' {
-            let mut output = Vec::with_capacity(1 + boundary.len());
-            output.push(b'\n');
-            output.extend(boundary.clone());
-            (vec![b'\n'], vec![b'\n', b'\n'], output)
-        } else {
-            return Err(Error::NoCrLfAfterBoundary);
+// Read past the end of a parsed body as the profile says, and check its length against
+// `expected`, given the `result` of parsing it
+#[cfg(feature = "parse")]
+fn finish_body<R: BufRead, T>(
+    reader: &mut Counting<R>,
+    state: &ParseState,
+    expected: Option<u64>,
+    result: Result<T, Error>,
+) -> Result<T, Error> {
+    if state.stopped {
+        if state.profile.stop_at == StopAt::Eof {
+            std::io::copy(reader, &mut std::io::sink())?;
         }
+        return result;
+    }
+    if result.is_ok() {
+        read_past_close(reader, state.profile.stop_at)?;
+    }
+    let expected = match expected {
+        Some(expected) => expected,
+        None => return result,
     };
-
-    loop {
-        // If the next two lookahead characters are '--', parsing is finished.
+    let output = match result {
+        Ok(output) => output,
+        Err(Error::EofBeforeFirstBoundary)
+        | Err(Error::EofInPartHeaders)
+        | Err(Error::EofInFile)
+        | Err(Error::EofInPart)
+            if reader.consumed < expected =>
         {
-            let peeker = reader.fill_buf()?;
-            if peeker.len() >= 2 && &peeker[..2] == b"--" {
-                return Ok(nodes);
-            }
+            return Err(Error::ContentLengthMismatch)
         }
+        Err(err) => return Err(err),
+    };
+    if reader.consumed > expected {
+        return Err(Error::ContentLengthMismatch);
+    }
+    let epilogue = expected - reader.consumed;
+    if std::io::copy(&mut reader.take(epilogue), &mut std::io::sink())? < epilogue {
+        return Err(Error::ContentLengthMismatch);
+    }
+    Ok(output)
+}
 
-        // Read the line terminator after the boundary
-        let (_, found) = reader.stream_until_token(&lt, &mut buf)?;
-        if !found {
-            return Err(Error::NoCrLfAfterBoundary);
+// Read past the close delimiter as far as `stop_at` says
+#[cfg(feature = "parse")]
+fn read_past_close<R: BufRead>(reader: &mut Counting<R>, stop_at: StopAt) -> Result<(), Error> {
+    match stop_at {
+        StopAt::CloseDelimiter => {}
+        StopAt::LineEnd => {
+            loop {
+                let padding = reader
+                    .fill_buf()?
+                    .iter()
+                    .take_while(|&&ch| ch == b' ' || ch == b'\t')
+                    .count();
+                if padding == 0 {
+                    break;
+                }
+                reader.consume(padding);
+            }
+            // Look a byte at a time, so that nothing past the line is taken from the
+            // underlying reader
+            if reader.peek(1)? == b"\r" {
+                reader.consume(1);
+            }
+            if reader.peek(1)? == b"\n" {
+                reader.consume(1);
+            }
         }
-
-        // Read the headers (which end in 2 line terminators)
-        buf.truncate(0); // start fresh
-        let (_, found) = reader.stream_until_token(&ltlt, &mut buf)?;
-        if !found {
-            return Err(Error::EofInPartHeaders);
+        StopAt::Eof => {
+            std::io::copy(reader, &mut std::io::sink())?;
         }
+    }
+    Ok(())
+}
 
-        // Keep the 2 line terminators as httparse will expect it
-        buf.extend(ltlt.iter().cloned());
-
-        // Parse the headers
-        let part_headers = {
-            let mut header_memory = [httparse::EMPTY_HEADER; 4];
-            match httparse::parse_headers(&buf, &mut header_memory) {
-                Ok(httparse::Status::Complete((_, raw_headers))) => {
-                    let mut headers = HeaderMap::new();
-                    for header in raw_headers {
-                        if header.value.is_empty() {
-                            break;
-                        }
-                        let trim = header
-                            .value
-                            .iter()
-                            .rev()
-                            .take_while(|&&x| x == b' ')
-                            .count();
-                        let value = &header.value[..header.value.len() - trim];
-
-                        let header_value = match HeaderValue::from_bytes(value) {
-                            Ok(value) => value,
-                            Err(_) => return Err(Error::InvalidHeaderNameOrValue),
-                        };
-
-                        let header_name = header.name.to_owned();
-                        let header_name = match HeaderName::from_str(&header_name) {
-                            Ok(value) => value,
-                            Err(_) => return Err(Error::InvalidHeaderNameOrValue),
-                        };
-                        headers.append(header_name, header_value);
-                    }
-                    Ok(headers)
-                }
-                Ok(httparse::Status::Partial) => Err(Error::PartialHeaders),
-                Err(err) => Err(From::from(err)),
-            }?
-        };
+// The running totals of a single parse, which are checked against the profile's `Limits`.
+#[cfg(feature = "parse")]
+struct ParseState<'p> {
+    profile: &'p ParseProfile,
+    parts: usize,
+    files: usize,
+    fields: usize,
+    body_bytes: u64,
+    depth: usize,
+    // The `required_fields` not read yet, and whether parsing stopped once they were
+    missing: Vec<&'p str>,
+    stopped: bool,
+    // The spans of the parts read so far at the current depth, if they are wanted
+    record_spans: bool,
+    spans: Vec<PartSpan>,
+    // The files spooled so far, by content digest, when deduplicating
+    #[cfg(feature = "sha2")]
+    spooled: HashMap<[u8; 32], PathBuf>,
+    // Scratch space for reading and parsing part headers, shared by all parts at all depths
+    header_buf: Scratch,
+    header_memory: Vec<httparse::Header<'static>>,
+}
 
-        // Check for a nested multipart
-        let nested = {
-            match part_headers.get("content-type") {
-                Some(ct) => match ct.to_str() {
-                    Ok(value) => match Mime::from_str(value) {
-                        Ok(mime) => mime.type_() == mime::MULTIPART,
-                        Err(_) => return Err(Error::HeaderValueNotMime),
-                    },
-                    Err(err) => return Err(Error::ToStr(err)),
-                },
-                None => false,
-            }
-        };
-        if nested {
-            // Recurse:
-            let inner_nodes = inner(reader, &part_headers, always_use_files)?;
-            nodes.push(Node::Multipart((part_headers, inner_nodes)));
-            continue;
+#[cfg(feature = "parse")]
+impl<'p> ParseState<'p> {
+    fn new(profile: &'p ParseProfile) -> ParseState<'p> {
+        ParseState {
+            profile,
+            parts: 0,
+            files: 0,
+            fields: 0,
+            body_bytes: 0,
+            depth: 0,
+            missing: profile.required_fields.iter().map(|s| &**s).collect(),
+            stopped: false,
+            record_spans: false,
+            spans: Vec::new(),
+            #[cfg(feature = "sha2")]
+            spooled: HashMap::new(),
+            header_buf: Scratch::default(),
+            header_memory: Vec::new(),
         }
+    }
 
-        let is_file = always_use_files || {
-            match part_headers.get("content-disposition") {
-                Some(content) => match content.to_str() {
-                    Ok(value) => value.contains("attachment") || value.contains("filename"),
-                    Err(err) => return Err(Error::ToStr(err)),
-                },
-                None => false,
+    // Replace a spooled file with a hard link to an earlier one with the same `digest`, if
+    // there is one, giving back its disk budget; or else remember it.  The file is left as it
+    // is if linking fails.
+    #[cfg(feature = "sha2")]
+    fn dedup(&mut self, filepart: &mut FilePart, digest: [u8; 32]) {
+        let existing = match self.spooled.get(&digest) {
+            Some(existing) => existing,
+            None => {
+                self.spooled.insert(digest, filepart.path.clone());
+                return;
             }
         };
-        if is_file {
-            // Setup a file to capture the contents.
-            let mut filepart = FilePart::create(part_headers)?;
-            let mut file = File::create(filepart.path.clone())?;
-
-            // Stream out the file.
-            let (read, found) = reader.stream_until_token(&lt_boundary, &mut file)?;
-            if !found {
-                return Err(Error::EofInFile);
+        let mut link = filepart.path.clone().into_os_string();
+        link.push(".link");
+        if std::fs::hard_link(existing, &link).is_err() {
+            return;
+        }
+        match std::fs::rename(&link, &filepart.path) {
+            Ok(()) => filepart.reservation = None,
+            Err(_) => {
+                let _ = std::fs::remove_file(&link);
             }
-            filepart.size = Some(read);
+        }
+    }
 
-            // TODO: Handle Content-Transfer-Encoding.  RFC 7578 section 4.7 deprecated
-            // this, and the authors state "Currently, no deployed implementations that
-            // send such bodies have been discovered", so this is very low priority.
+    // Note where the part just read lay
+    fn add_span(&mut self, start: u64, body_start: u64, end: u64, parts: Vec<PartSpan>) {
+        if !self.record_spans {
+            return;
+        }
+        self.spans.push(PartSpan {
+            start,
+            body_start,
+            end,
+            parts,
+        });
+    }
 
-            nodes.push(Node::File(filepart));
-        } else {
-            buf.truncate(0); // start fresh
-            let (_, found) = reader.stream_until_token(&lt_boundary, &mut buf)?;
-            if !found {
-                return Err(Error::EofInPart);
-            }
+    // Whether to stop parsing, given the last top-level node read, because all of the
+    // `required_fields` have now been read
+    fn collected(&mut self, last: Option<&Node>) -> bool {
+        if self.profile.required_fields.is_empty() {
+            return false;
+        }
+        if let Some(name) = last.and_then(|node| field_name(node.headers(), self.profile)) {
+            self.missing.retain(|&field| field != name);
+        }
+        self.stopped = self.missing.is_empty();
+        self.stopped
+    }
 
-            nodes.push(Node::Part(Part {
-                headers: part_headers,
-                body: buf.clone(),
-            }));
+    // Count another part
+    fn add_part(&mut self) -> Result<(), Error> {
+        self.parts += 1;
+        match self.profile.limits.max_parts {
+            Some(max) if self.parts > max => Err(Error::TooManyParts),
+            _ => Ok(()),
         }
     }
-}
 
-/// Get the `multipart/*` boundary string from `hyper::Headers`
-pub fn get_multipart_boundary(headers: &HeaderMap) -> Result<Vec<u8>, Error> {
-    // Verify that the request is 'Content-Type: multipart/*'.
-    let mime = match headers.get("content-type") {
-        Some(ct) => match ct.to_str() {
-            Ok(value) => match Mime::from_str(value) {
-                Ok(value) => value,
-                Err(_) => return Err(Error::HeaderValueNotMime),
-            },
-            Err(err) => return Err(Error::ToStr(err)),
-        },
-        None => return Err(Error::NoRequestContentType),
-    };
-    let top_level = mime.type_();
+    // Count another file or field, as the disposition of a part makes it
+    fn add_disposition(&mut self, disposition: Disposition) -> Result<(), Error> {
+        let limits = &self.profile.limits;
+        match disposition {
+            Disposition::File => {
+                self.files += 1;
+                match limits.max_files {
+                    Some(max) if self.files > max => Err(Error::TooManyFiles),
+                    _ => Ok(()),
+                }
+            }
+            Disposition::Memory => {
+                self.fields += 1;
+                match limits.max_fields {
+                    Some(max) if self.fields > max => Err(Error::TooManyFields),
+                    _ => Ok(()),
+                }
+            }
+            Disposition::Skip => Ok(()),
+        }
+    }
 
-    if top_level != mime::MULTIPART {
-        return Err(Error::NotMultipart);
+    // The number of bytes the next part body, with the given `part_limit()`, may hold
+    fn body_limit(&self, part_limit: &Option<(u64, Error)>) -> Option<u64> {
+        let total = self
+            .profile
+            .limits
+            .max_total_size
+            .map(|max| max.saturating_sub(self.body_bytes));
+        match (part_limit.as_ref().map(|&(max, _)| max), total) {
+            (Some(part), Some(total)) => Some(part.min(total)),
+            (part, total) => part.or(total),
+        }
     }
 
-    match mime.get_param(mime::BOUNDARY) {
-        None => Err(Error::BoundaryNotSpecified),
-        Some(content) => {
-            let mut boundary = vec![];
-            boundary.extend(b"--".iter().cloned());
-            boundary.extend(content.to_string().as_bytes());
-            Ok(boundary)
+    // The error to report for a part body which wanted to hold `size` bytes, more than
+    // `body_limit()` allows
+    fn body_limit_error(&self, size: u64, part_limit: Option<(u64, Error)>) -> Error {
+        match part_limit {
+            Some((max, error)) if size > max => error,
+            _ => Error::BodyTooLarge,
         }
     }
 }
 
-#[inline]
-fn get_content_disposition_filename(cd: &HeaderValue) -> Result<Option<String>, Error> {
-    match cd.to_str() {
-        Ok(value) => match value.contains("filename") {
-            true => match value.find("filename=") {
+// A sink for bytes skipped over between parts, noting whether they were all transport
+// padding (linear whitespace)
+#[cfg(feature = "parse")]
+#[derive(Default)]
+struct Skipped {
+    other: usize,
+}
+
+#[cfg(feature = "parse")]
+impl Skipped {
+    fn padding_only(&self) -> bool {
+        self.other == 0
+    }
+}
+
+#[cfg(feature = "parse")]
+impl Write for Skipped {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.other += buf.iter().filter(|&&ch| ch != b' ' && ch != b'\t').count();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// A reader which keeps count of the bytes consumed through it, and which can look further
+// ahead than the underlying reader's buffer happens to reach
+#[cfg(feature = "parse")]
+struct Counting<R> {
+    inner: R,
+    consumed: u64,
+    // Bytes taken from `inner` to look ahead, which are yet to be consumed
+    ahead: Vec<u8>,
+}
+
+#[cfg(feature = "parse")]
+impl<R> Counting<R> {
+    fn new(inner: R) -> Counting<R> {
+        Counting {
+            inner,
+            consumed: 0,
+            ahead: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "parse")]
+impl<R: BufRead> Counting<R> {
+    // Look at the next `len` bytes without consuming them.  Fewer are returned only at EOF.
+    fn peek(&mut self, len: usize) -> std::io::Result<&[u8]> {
+        if self.ahead.is_empty() && self.inner.fill_buf()?.len() >= len {
+            return Ok(&self.inner.fill_buf()?[..len]);
+        }
+        while self.ahead.len() < len {
+            let buf = self.inner.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let take = buf.len().min(len - self.ahead.len());
+            self.ahead.extend_from_slice(&buf[..take]);
+            self.inner.consume(take);
+        }
+        Ok(&self.ahead[..self.ahead.len().min(len)])
+    }
+}
+
+#[cfg(feature = "parse")]
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = match self.ahead.is_empty() {
+            true => self.inner.read(buf)?,
+            false => {
+                let count = buf.len().min(self.ahead.len());
+                buf[..count].copy_from_slice(&self.ahead[..count]);
+                self.ahead.drain(..count);
+                count
+            }
+        };
+        self.consumed += count as u64;
+        Ok(count)
+    }
+}
+
+#[cfg(feature = "parse")]
+impl<R: BufRead> BufRead for Counting<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        match self.ahead.is_empty() {
+            true => self.inner.fill_buf(),
+            false => Ok(&self.ahead),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self.ahead.is_empty() {
+            true => self.inner.consume(amt),
+            false => {
+                self.ahead.drain(..amt);
+            }
+        }
+        self.consumed += amt as u64;
+    }
+}
+
+// A reader copying everything read from `inner` to `capture`
+#[cfg(feature = "parse")]
+struct Tee<'a, S: ?Sized, W: ?Sized> {
+    inner: &'a mut S,
+    capture: &'a mut W,
+}
+
+#[cfg(feature = "parse")]
+impl<S: Read + ?Sized, W: Write + ?Sized> Read for Tee<'_, S, W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.capture.write_all(&buf[..count])?;
+        Ok(count)
+    }
+}
+
+// A sink for the body of a skipped part, keeping only the last `keep` bytes so that a close
+// delimiter can still be found at the end
+#[cfg(feature = "parse")]
+struct Discard {
+    tail: Vec<u8>,
+    keep: usize,
+}
+
+#[cfg(feature = "parse")]
+impl Discard {
+    fn new(keep: usize) -> Discard {
+        Discard {
+            tail: Vec::with_capacity(keep),
+            keep,
+        }
+    }
+}
+
+#[cfg(all(feature = "parse", feature = "zeroize"))]
+impl Drop for Discard {
+    fn drop(&mut self) {
+        wipe::wipe(&mut self.tail);
+    }
+}
+
+// A scratch buffer within the parser, wiped when dropped with the `zeroize` feature
+#[cfg(feature = "parse")]
+#[derive(Default)]
+struct Scratch(Vec<u8>);
+
+#[cfg(feature = "parse")]
+impl Scratch {
+    // Replace the contents, wiping the old ones
+    fn replace(&mut self, data: Vec<u8>) {
+        drop(Scratch(std::mem::replace(&mut self.0, data)));
+    }
+}
+
+#[cfg(feature = "parse")]
+impl std::ops::Deref for Scratch {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+#[cfg(feature = "parse")]
+impl std::ops::DerefMut for Scratch {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+#[cfg(all(feature = "parse", feature = "zeroize"))]
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        wipe::wipe(&mut self.0);
+    }
+}
+
+#[cfg(feature = "parse")]
+impl Write for Discard {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.tail.extend_from_slice(buf);
+        if self.tail.len() > self.keep {
+            self.tail.drain(..self.tail.len() - self.keep);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// A writer which passes bytes through, digesting them if asked to
+#[cfg(all(feature = "parse", feature = "sha2"))]
+struct Digesting<W: Write> {
+    inner: W,
+    digest: Option<Sha256>,
+}
+
+#[cfg(all(feature = "parse", feature = "sha2"))]
+impl<W: Write> Digesting<W> {
+    fn new(inner: W, digest: bool) -> Digesting<W> {
+        Digesting {
+            inner,
+            digest: digest.then(Sha256::new),
+        }
+    }
+}
+
+#[cfg(all(feature = "parse", feature = "sha2"))]
+impl<W: Write> Write for Digesting<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        if let Some(ref mut digest) = self.digest {
+            digest.update(&buf[..count]);
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// A writer which refuses to take more than `limit` bytes, or more than its `reservation` can
+// grow to hold, remembering that it refused.
+#[cfg(feature = "parse")]
+struct LimitedWriter<'a, W: Write> {
+    inner: &'a mut W,
+    limit: Option<u64>,
+    reservation: Option<&'a mut Reservation>,
+    written: u64,
+    exceeded: bool,
+    over_budget: bool,
+}
+
+#[cfg(feature = "parse")]
+impl<'a, W: Write> LimitedWriter<'a, W> {
+    fn new(inner: &'a mut W, limit: Option<u64>) -> LimitedWriter<'a, W> {
+        LimitedWriter {
+            inner,
+            limit,
+            reservation: None,
+            written: 0,
+            exceeded: false,
+            over_budget: false,
+        }
+    }
+}
+
+#[cfg(feature = "parse")]
+impl<W: Write> Write for LimitedWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(limit) = self.limit {
+            if self.written + buf.len() as u64 > limit {
+                self.exceeded = true;
+                self.written += buf.len() as u64;
+                return Err(std::io::Error::other("size limit exceeded"));
+            }
+        }
+        if let Some(ref mut reservation) = self.reservation {
+            if !reservation.grow(buf.len() as u64) {
+                self.over_budget = true;
+                return Err(std::io::Error::other("disk budget exceeded"));
+            }
+        }
+        let count = self.inner.write(buf)?;
+        self.written += count as u64;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// How much of a file part to buffer before writing it out, as the boundary scanner hands over
+// small pieces
+#[cfg(feature = "parse")]
+const SPOOL_BUFFER_SIZE: usize = 64 * 1024;
+
+#[cfg(feature = "parse")]
+fn inner<R: BufRead>(
+    reader: &mut Counting<R>,
+    headers: &HeaderMap,
+    state: &mut ParseState,
+) -> Result<Vec<Node>, Error> {
+    let profile = state.profile;
+    let mut nodes: Vec<Node> = match state.depth {
+        0 => Vec::with_capacity(
+            profile
+                .limits
+                .max_parts
+                .map_or(profile.expected_parts, |max| {
+                    profile.expected_parts.min(max)
+                }),
+        ),
+        _ => Vec::new(),
+    };
+    let delimiters = read_preamble(reader, headers, state)?;
+
+    loop {
+        if state.depth == 0 && state.collected(nodes.last()) {
+            record!(debug, "stopping, all required fields read");
+            return Ok(nodes);
+        }
+        match next_part(reader, &delimiters, state)? {
+            PartStep::Node(node) => nodes.push(node),
+            PartStep::Last(node) => {
+                nodes.push(node);
+                return Ok(nodes);
+            }
+            PartStep::End => return Ok(nodes),
+        }
+    }
+}
+
+// The delimiters of a multipart, as found at its start
+#[cfg(feature = "parse")]
+struct Delimiters {
+    // The boundary with its leading dashes
+    boundary: Vec<u8>,
+    // The line terminator in use, two of them, and the boundary preceded by one
+    lt: Vec<u8>,
+    ltlt: Vec<u8>,
+    lt_boundary: Vec<u8>,
+}
+
+// What `next_part()` found
+#[cfg(feature = "parse")]
+enum PartStep {
+    Node(Node),
+    // The node closing the multipart, when its close delimiter lacks the line terminator
+    // before it and the parse is lenient
+    Last(Node),
+    End,
+}
+
+// Find the boundary of a multipart and read up to its first part, learning the line
+// terminator in use
+#[cfg(feature = "parse")]
+fn read_preamble<R: BufRead>(
+    reader: &mut Counting<R>,
+    headers: &HeaderMap,
+    state: &mut ParseState,
+) -> Result<Delimiters, Error> {
+    let profile = state.profile;
+    let chosen = choose_content_type(headers, profile.content_type_choice)?;
+    let headers = chosen.as_ref().unwrap_or(headers);
+    let boundary = match get_multipart_boundary(headers) {
+        Err(Error::BoundaryNotSpecified) if profile.sniff_boundary => {
+            match sniff_boundary(reader.peek(SNIFF_LEN)?) {
+                Some(boundary) => {
+                    record!(
+                        debug,
+                        "no boundary in the Content-Type, sniffed {:?}",
+                        boundary
+                    );
+                    [&b"--"[..], boundary.as_bytes()].concat()
+                }
+                None => return Err(Error::BoundaryNotSpecified),
+            }
+        }
+        result => result?,
+    };
+    if let Some(max) = profile.limits.max_boundary_len {
+        if boundary.len() - 2 > max {
+            return Err(Error::BoundaryTooLong);
+        }
+    }
+
+    // Read past the initial boundary
+    let (_, found) = {
+        let mut sink = std::io::sink();
+        let mut writer = LimitedWriter::new(&mut sink, profile.limits.max_preamble);
+        let result = reader.stream_until_token(&boundary, &mut writer);
+        if writer.exceeded {
+            return Err(Error::PreambleTooLarge);
+        }
+        result?
+    };
+    if !found {
+        record!(
+            debug,
+            "boundary {:?} not found at depth {}",
+            String::from_utf8_lossy(&boundary[2..]),
+            state.depth
+        );
+        return Err(Error::EofBeforeFirstBoundary);
+    }
+    record!(
+        debug,
+        "boundary {:?} found at depth {}, offset {}",
+        String::from_utf8_lossy(&boundary[2..]),
+        state.depth,
+        reader.consumed
+    );
+
+    // Skip any transport padding following it
+    loop {
+        let peeker = reader.fill_buf()?;
+        let padding = peeker
+            .iter()
+            .take_while(|&&ch| ch == b' ' || ch == b'\t')
+            .count();
+        if padding == 0 {
+            break;
+        }
+        reader.consume(padding);
+    }
+
+    // Define the boundary, including the line terminator preceding it.
+    // Use their first line terminator to determine whether to use CRLF or LF.
+    let (lt, ltlt, lt_boundary) = {
+        let peeker = reader.peek(2)?;
+        if peeker.len() > 1 && &peeker[..2] == b"\r\n" {
+            let mut output = Vec::with_capacity(2 + boundary.len());
+            output.push(b'\r');
+            output.push(b'\n');
+            output.extend(boundary.clone());
+            (vec![b'\r', b'\n'], vec![b'\r', b'\n', b'\r', b'\n'], output)
+        } else if !peeker.is_empty() && peeker[0] == b'\n' {
+            let mut output = Vec::with_capacity(1 + boundary.len());
+            output.push(b'\n');
+            output.extend(boundary.clone());
+            (vec![b'\n'], vec![b'\n', b'\n'], output)
+        } else if !peeker.is_empty() && peeker[0] == b'\r' && profile.mode == ParseMode::Lenient {
+            let mut output = Vec::with_capacity(1 + boundary.len());
+            output.push(b'\r');
+            output.extend(boundary.clone());
+            (vec![b'\r'], vec![b'\r', b'\r'], output)
+        } else {
+            return Err(Error::NoCrLfAfterBoundary);
+        }
+    };
+    record!(trace, "line terminator {:?}", String::from_utf8_lossy(&lt));
+
+    Ok(Delimiters {
+        boundary,
+        lt,
+        ltlt,
+        lt_boundary,
+    })
+}
+
+// Read the next part of a multipart, after its delimiter
+#[cfg(feature = "parse")]
+fn next_part<R: BufRead>(
+    reader: &mut Counting<R>,
+    delimiters: &Delimiters,
+    state: &mut ParseState,
+) -> Result<PartStep, Error> {
+    let profile = state.profile;
+    let Delimiters {
+        ref boundary,
+        ref lt,
+        ref ltlt,
+        ref lt_boundary,
+    } = *delimiters;
+    loop {
+        // If the next two lookahead characters are '--', parsing is finished.
+        {
+            let peeker = reader.peek(2)?;
+            if peeker.len() >= 2 && &peeker[..2] == b"--" {
+                reader.consume(2);
+                record!(
+                    debug,
+                    "close delimiter at depth {}, offset {}",
+                    state.depth,
+                    reader.consumed
+                );
+                return Ok(PartStep::End);
+            }
+        }
+
+        // Read the line terminator after the boundary, skipping transport padding
+        let mut skipped = Skipped::default();
+        let (_, found) = reader.stream_until_token(lt, &mut skipped)?;
+        if !found {
+            return Err(Error::NoCrLfAfterBoundary);
+        }
+        if profile.mode == ParseMode::Strict && !skipped.padding_only() {
+            return Err(Error::DataBetweenParts);
+        }
+        state.add_part()?;
+        let index = state.parts;
+        let start = reader.consumed;
+
+        // Read the headers (which end in 2 line terminators), unless the part has none, when
+        // only the second follows the boundary
+        let buf = &mut state.header_buf;
+        buf.truncate(0); // start fresh
+        if reader.peek(lt.len())? == lt {
+            reader.consume(lt.len());
+        } else {
+            let (_, found) = read_header_block(reader, ltlt, buf, profile)?;
+            if !found {
+                return Err(Error::EofInPartHeaders);
+            }
+        }
+
+        // Keep the 2 line terminators as httparse will expect it.  httparse knows no
+        // CR-only line endings, so make those CRLF.
+        buf.extend(ltlt.iter().cloned());
+        if lt == b"\r" {
+            buf.replace(cr_to_crlf(buf));
+        }
+
+        // Parse the headers
+        let mut part_headers = parse_part_headers(
+            buf,
+            profile.limits.max_part_headers,
+            &mut state.header_memory,
+        )?;
+        check_duplicate_headers(&mut part_headers, profile.mode)?;
+        let body_start = reader.consumed;
+
+        // Check for a nested multipart
+        let nested = is_nested(&part_headers)?;
+        if nested && !is_ignored(&part_headers, profile) {
+            record!(
+                debug,
+                "part {} at depth {}: nested multipart",
+                index,
+                state.depth
+            );
+            // Recurse:
+            state.depth += 1;
+            if let Some(max) = profile.limits.max_depth {
+                if state.depth > max {
+                    return Err(Error::NestingTooDeep);
+                }
+            }
+            let outer_spans = std::mem::take(&mut state.spans);
+            let inner_nodes = inner(reader, &part_headers, state)?;
+            let inner_spans = std::mem::replace(&mut state.spans, outer_spans);
+            state.depth -= 1;
+            let node = Node::Multipart((part_headers, inner_nodes));
+
+            // Skip the epilogue of the nested multipart, up to our next boundary
+            let mut skipped = Skipped::default();
+            let (_, found) = reader.stream_until_token(lt_boundary, &mut skipped)?;
+            if !found {
+                return Err(Error::EofInPart);
+            }
+            if profile.mode == ParseMode::Strict && !skipped.padding_only() {
+                return Err(Error::DataBetweenParts);
+            }
+            let end = reader.consumed - lt_boundary.len() as u64;
+            state.add_span(start, body_start, end, inner_spans);
+            return Ok(PartStep::Node(node));
+        }
+
+        let disposition = part_disposition(&part_headers, profile)?;
+        state.add_disposition(disposition)?;
+        let part_limit = part_limit(profile, disposition, &part_headers);
+        record!(
+            debug,
+            "part {} ({:?}) at depth {}: {:?}",
+            index,
+            field_name(&part_headers, profile),
+            state.depth,
+            disposition
+        );
+        if disposition == Disposition::Skip {
+            let mut discard = Discard::new(boundary.len() + 4);
+            let (read, found) = {
+                let mut writer = LimitedWriter::new(&mut discard, state.body_limit(&part_limit));
+                let result = reader.stream_until_token(lt_boundary, &mut writer);
+                if writer.exceeded {
+                    return Err(state.body_limit_error(writer.written, part_limit));
+                }
+                result?
+            };
+            state.body_bytes += read as u64;
+            if !found {
+                if profile.mode == ParseMode::Lenient
+                    && close_delimiter_len(&discard.tail, boundary).is_some()
+                {
+                    return Ok(PartStep::End);
+                }
+                return Err(Error::EofInPart);
+            }
+            record!(debug, "part {}: skipped {} bytes", index, read);
+            continue;
+        }
+
+        if disposition == Disposition::File {
+            // Setup a file to capture the contents.
+            let name = match part_headers.get(CONTENT_DISPOSITION) {
+                Some(cd) if profile.original_filenames => ContentDisposition::from_header(cd)?
+                    .form_param("filename", profile.html_names)
+                    .and_then(|name| sanitize_filename(&name)),
+                _ => None,
+            };
+            let (mut filepart, mut file) = profile.spool.create(part_headers, name)?;
+            filepart.shred = profile.shred_files;
+            filepart.cleanup_hook = profile.cleanup_hook.clone();
+
+            // Stream out the file.
+            filepart.reservation = profile.disk_budget.as_ref().map(Reservation::new);
+            let mut spool = BufWriter::with_capacity(SPOOL_BUFFER_SIZE, &mut file);
+            #[cfg(feature = "sha2")]
+            let mut sink = Digesting::new(&mut spool, profile.dedup_files);
+            #[cfg(not(feature = "sha2"))]
+            let mut sink = &mut spool;
+            let (read, found) = {
+                let mut writer = LimitedWriter::new(&mut sink, state.body_limit(&part_limit));
+                writer.reservation = filepart.reservation.as_mut();
+                let result = reader.stream_until_token(lt_boundary, &mut writer);
+                if writer.exceeded {
+                    return Err(state.body_limit_error(writer.written, part_limit));
+                }
+                if writer.over_budget {
+                    return Err(Error::DiskBudgetExceeded);
+                }
+                result?
+            };
+            #[cfg(feature = "sha2")]
+            let digest = sink.digest.take();
+            spool.into_inner().map_err(|err| err.into_error())?;
+            state.body_bytes += read as u64;
+            if !found {
+                if profile.mode == ParseMode::Lenient {
+                    if let Some(size) = spooled_close_delimiter(&mut file, boundary)? {
+                        file.set_len(size)?;
+                        filepart.size = Some(size as usize);
+                        if profile.sync_files {
+                            file.sync_all()?;
+                            sync_dir_of(&filepart.path)?;
+                        }
+                        let end = body_start + size;
+                        state.add_span(start, body_start, end, Vec::new());
+                        return Ok(PartStep::Last(Node::File(filepart)));
+                    }
+                }
+                return Err(Error::EofInFile);
+            }
+            filepart.size = Some(read);
+            if profile.sync_files {
+                file.sync_all()?;
+            }
+            drop(file);
+            #[cfg(feature = "sha2")]
+            if let Some(digest) = digest {
+                state.dedup(&mut filepart, digest.finalize().into());
+            }
+            if profile.sync_files {
+                sync_dir_of(&filepart.path)?;
+            }
+            state.add_span(start, body_start, body_start + read as u64, Vec::new());
+            record!(
+                debug,
+                "part {}: {} bytes spooled to {}",
+                index,
+                read,
+                filepart.path.display()
+            );
+
+            // TODO: Handle Content-Transfer-Encoding.  RFC 7578 section 4.7 deprecated
+            // this, and the authors state "Currently, no deployed implementations that
+            // send such bodies have been discovered", so this is very low priority.
+
+            return Ok(PartStep::Node(Node::File(filepart)));
+        } else {
+            // A buffer of its own, to be moved into the part
+            let mut body = Vec::new();
+            let mut sink = Spilling {
+                body: &mut body,
+                threshold: profile.spill_threshold,
+                profile,
+                headers: &part_headers,
+                spilled: None,
+                error: None,
+                over_budget: false,
+            };
+            let result = {
+                let mut writer = LimitedWriter::new(&mut sink, state.body_limit(&part_limit));
+                let result = reader.stream_until_token(lt_boundary, &mut writer);
+                if writer.exceeded {
+                    return Err(state.body_limit_error(writer.written, part_limit));
+                }
+                result
+            };
+            if let Some(err) = sink.error.take() {
+                return Err(err);
+            }
+            if sink.over_budget {
+                return Err(Error::DiskBudgetExceeded);
+            }
+            let (read, found) = result?;
+            state.body_bytes += read as u64;
+
+            if let Some((mut filepart, spool)) = sink.spilled.take() {
+                let mut file = spool.into_inner().map_err(|err| err.into_error())?;
+                let mut size = read;
+                if !found {
+                    let content = match profile.mode {
+                        ParseMode::Lenient => spooled_close_delimiter(&mut file, boundary)?,
+                        _ => None,
+                    };
+                    match content {
+                        Some(content) => {
+                            size = content as usize;
+                            file.set_len(content)?;
+                        }
+                        None => return Err(Error::EofInPart),
+                    }
+                }
+                filepart.size = Some(size);
+                if profile.sync_files {
+                    file.sync_all()?;
+                    sync_dir_of(&filepart.path)?;
+                }
+                state.add_span(start, body_start, body_start + size as u64, Vec::new());
+                record!(
+                    debug,
+                    "part {}: {} bytes spilled to {}",
+                    index,
+                    size,
+                    filepart.path.display()
+                );
+                return Ok(match found {
+                    true => PartStep::Node(Node::File(filepart)),
+                    false => PartStep::Last(Node::File(filepart)),
+                });
+            }
+            if !found {
+                if profile.mode == ParseMode::Lenient {
+                    if let Some(strip) = close_delimiter_len(&body, boundary) {
+                        body.truncate(body.len() - strip);
+                        let end = body_start + body.len() as u64;
+                        state.add_span(start, body_start, end, Vec::new());
+                        return Ok(PartStep::Last(Node::Part(Part {
+                            headers: part_headers,
+                            body,
+                        })));
+                    }
+                }
+                return Err(Error::EofInPart);
+            }
+
+            state.add_span(start, body_start, body_start + read as u64, Vec::new());
+            record!(debug, "part {}: {} bytes in memory", index, read);
+            return Ok(PartStep::Node(Node::Part(Part {
+                headers: part_headers,
+                body,
+            })));
+        }
+    }
+}
+
+// The length of a spooled file's content without the close delimiter (and any line
+// terminator before it) ending it, if it does end in one.  This goes by the file's length,
+// as the bytes the scanner counts at the end of the input may be held back from it.
+#[cfg(feature = "parse")]
+fn spooled_close_delimiter(file: &mut File, boundary: &[u8]) -> Result<Option<u64>, Error> {
+    let len = file.metadata()?.len();
+    let window = len.min(boundary.len() as u64 + 4);
+    let mut tail = Scratch(Vec::with_capacity(window as usize));
+    file.seek(SeekFrom::End(-(window as i64)))?;
+    file.read_to_end(&mut tail)?;
+    Ok(close_delimiter_len(&tail, boundary).map(|strip| len - strip as u64))
+}
+
+// Where the body of a part kept in memory goes: into memory until it grows past
+// `ParseProfile::spill_threshold`, and from then on into a file spooled as file parts are
+#[cfg(feature = "parse")]
+struct Spilling<'a> {
+    body: &'a mut Vec<u8>,
+    threshold: Option<usize>,
+    profile: &'a ParseProfile,
+    headers: &'a HeaderMap,
+    spilled: Option<(FilePart, BufWriter<File>)>,
+    // The error creating the file met, which the boundary scanner would lose
+    error: Option<Error>,
+    over_budget: bool,
+}
+
+#[cfg(feature = "parse")]
+impl Spilling<'_> {
+    // Move the body so far into a new file
+    fn spill(&mut self) -> std::io::Result<()> {
+        let created = self.profile.spool.create(self.headers.clone(), None);
+        let (mut filepart, file) = match created {
+            Ok(created) => created,
+            Err(err) => {
+                self.error = Some(err);
+                return Err(std::io::Error::other("spilling failed"));
+            }
+        };
+        filepart.shred = self.profile.shred_files;
+        filepart.cleanup_hook = self.profile.cleanup_hook.clone();
+        filepart.reservation = self.profile.disk_budget.as_ref().map(Reservation::new);
+        self.spilled = Some((filepart, BufWriter::with_capacity(SPOOL_BUFFER_SIZE, file)));
+        // Wiped on drop, with the `zeroize` feature
+        let body = Scratch(std::mem::take(self.body));
+        self.write_all(&body)
+    }
+}
+
+#[cfg(feature = "parse")]
+impl Write for Spilling<'_> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let over = |max: usize| self.body.len() + data.len() > max;
+        if self.spilled.is_none() && self.threshold.is_some_and(over) {
+            self.spill()?;
+        }
+        match self.spilled {
+            Some((ref mut filepart, ref mut spool)) => {
+                if let Some(ref mut reservation) = filepart.reservation {
+                    if !reservation.grow(data.len() as u64) {
+                        self.over_budget = true;
+                        return Err(std::io::Error::other("disk budget exceeded"));
+                    }
+                }
+                spool.write_all(data)?;
+                Ok(data.len())
+            }
+            #[cfg(feature = "zeroize")]
+            None => wipe::Wiping(self.body).write(data),
+            #[cfg(not(feature = "zeroize"))]
+            None => {
+                self.body.extend_from_slice(data);
+                Ok(data.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.spilled {
+            Some((_, ref mut spool)) => spool.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+// Flush the directory entry naming a spooled file to disk.  Directories cannot be opened
+// as files everywhere, so this does nothing except on Unix.
+#[cfg(feature = "parse")]
+fn sync_dir_of(path: &Path) -> Result<(), Error> {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+// Parse a header block, growing the httparse scratch space as needed for up to `max` headers.
+// Most parts have a handful of headers, which fit scratch space on the stack, so that forms
+// with many small fields do not allocate it for every part.
+#[cfg(feature = "parse")]
+fn parse_headers(buf: &[u8], max: Option<usize>) -> Result<HeaderMap, Error> {
+    parse_headers_in(buf, max, &mut Vec::new())
+}
+
+// As `parse_headers()`, keeping any scratch space grown on the heap in `spare` for the next
+// header block
+#[cfg(feature = "parse")]
+fn parse_headers_in(
+    buf: &[u8],
+    max: Option<usize>,
+    spare: &mut Vec<httparse::Header<'static>>,
+) -> Result<HeaderMap, Error> {
+    let mut heap = recycle(std::mem::take(spare));
+    let result = parse_headers_with(buf, max, &mut heap);
+    *spare = recycle(heap);
+    result
+}
+
+// How large a header block may be when `Limits::max_header_block` is not set, as header
+// blocks are kept in memory whole
+#[cfg(feature = "parse")]
+const DEFAULT_HEADER_BLOCK: usize = 1024 * 1024;
+
+// The limit on the size of a header block
+#[cfg(feature = "parse")]
+fn header_block_limit(limits: &Limits) -> usize {
+    limits.max_header_block.unwrap_or(DEFAULT_HEADER_BLOCK)
+}
+
+// How many headers a part may have when `Limits::max_part_headers` is not set
+#[cfg(feature = "parse")]
+const DEFAULT_PART_HEADERS: usize = 4;
+
+// As `parse_headers_in()`, for the header block of a part.  Without a limit, 4 headers are
+// accepted, as they always have been, and more fail with the error httparse gives.
+#[cfg(feature = "parse")]
+fn parse_part_headers(
+    buf: &[u8],
+    max: Option<usize>,
+    spare: &mut Vec<httparse::Header<'static>>,
+) -> Result<HeaderMap, Error> {
+    match max {
+        Some(max) => parse_headers_in(buf, Some(max), spare),
+        None => match parse_headers_in(buf, Some(DEFAULT_PART_HEADERS), spare) {
+            Err(Error::TooManyHeaders) => Err(httparse::Error::TooManyHeaders.into()),
+            result => result,
+        },
+    }
+}
+
+// An empty Vec of httparse headers borrowing for another lifetime, in the same allocation
+#[cfg(feature = "parse")]
+fn recycle<'b>(mut headers: Vec<httparse::Header<'_>>) -> Vec<httparse::Header<'b>> {
+    headers.clear();
+    headers
+        .into_iter()
+        .map(|_| httparse::EMPTY_HEADER)
+        .collect()
+}
+
+// The parsing itself, with `heap` as the scratch space beyond what fits on the stack
+#[cfg(feature = "parse")]
+fn parse_headers_with<'b>(
+    buf: &'b [u8],
+    max: Option<usize>,
+    heap: &mut Vec<httparse::Header<'b>>,
+) -> Result<HeaderMap, Error> {
+    let mut stack = [httparse::EMPTY_HEADER; 16];
+    let mut capacity = max.map_or(stack.len(), |max| max.min(stack.len()));
+    loop {
+        let header_memory = match capacity <= stack.len() {
+            true => &mut stack[..capacity],
+            false => {
+                heap.clear();
+                heap.resize(capacity, httparse::EMPTY_HEADER);
+                &mut heap[..]
+            }
+        };
+        match httparse::parse_headers(buf, header_memory) {
+            Ok(httparse::Status::Complete((_, raw_headers))) => {
+                let mut headers = HeaderMap::with_capacity(raw_headers.len());
+                for header in raw_headers {
+                    if header.value.is_empty() {
+                        break;
+                    }
+                    let trim = header
+                        .value
+                        .iter()
+                        .rev()
+                        .take_while(|&&x| x == b' ')
+                        .count();
+                    let value = &header.value[..header.value.len() - trim];
+
+                    let header_value = match HeaderValue::from_bytes(value) {
+                        Ok(value) => value,
+                        Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+                    };
+
+                    let header_name = match HeaderName::from_str(header.name) {
+                        Ok(value) => value,
+                        Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+                    };
+                    headers.append(header_name, header_value);
+                }
+                return Ok(headers);
+            }
+            Ok(httparse::Status::Partial) => return Err(Error::PartialHeaders),
+            Err(httparse::Error::TooManyHeaders) => match max {
+                Some(max) if capacity >= max => return Err(Error::TooManyHeaders),
+                Some(max) => capacity = max.min(capacity * 2),
+                None => capacity *= 2,
+            },
+            Err(err) => return Err(From::from(err)),
+        }
+    }
+}
+
+// A header block with CR-only line endings made CRLF
+#[cfg(feature = "parse")]
+pub(crate) fn cr_to_crlf(block: &[u8]) -> Vec<u8> {
+    block
+        .iter()
+        .flat_map(|ch| match ch {
+            b'\r' => &b"\r\n"[..],
+            _ => std::slice::from_ref(ch),
+        })
+        .copied()
+        .collect()
+}
+
+// Read a header block up to the `ltlt` ending it, within its limit
+#[cfg(feature = "parse")]
+fn read_header_block<R: BufRead>(
+    reader: &mut R,
+    ltlt: &[u8],
+    buf: &mut Vec<u8>,
+    profile: &ParseProfile,
+) -> Result<(usize, bool), Error> {
+    let limit = header_block_limit(&profile.limits).saturating_sub(ltlt.len()) as u64;
+    let mut writer = LimitedWriter::new(buf, Some(limit));
+    let result = reader.stream_until_token(ltlt, &mut writer);
+    if writer.exceeded {
+        return Err(Error::HeadersTooLarge);
+    }
+    Ok(result?)
+}
+
+// The policy the `always_use_files` argument of the older functions asks for
+#[cfg(feature = "parse")]
+fn always_use_files_policy(always_use_files: bool) -> DispositionPolicy {
+    match always_use_files {
+        true => DispositionPolicy::AlwaysFile,
+        false => DispositionPolicy::Auto,
+    }
+}
+
+// The limit on the size of a part body, given its disposition and headers, and the error
+// for exceeding it
+fn part_limit(
+    profile: &ParseProfile,
+    disposition: Disposition,
+    headers: &HeaderMap,
+) -> Option<(u64, Error)> {
+    let limits = &profile.limits;
+    if !limits.max_size_by_name.is_empty() {
+        if let Some(name) = field_name(headers, profile) {
+            let limit = limits
+                .max_size_by_name
+                .iter()
+                .find(|(field, _)| *field == name);
+            if let Some(&(_, max)) = limit {
+                return Some((max, Error::FieldSizeExceeded(name)));
+            }
+        }
+    }
+    match (disposition, limits.max_file_size, limits.max_field_size) {
+        (Disposition::File, Some(max), _) => Some((max, Error::FileTooLarge)),
+        (Disposition::Memory, _, Some(max)) => Some((max, Error::FieldTooLarge)),
+        _ => limits.max_part_size.map(|max| (max, Error::PartTooLarge)),
+    }
+}
+
+// The field name of a part, from its Content-Disposition, unescaped if the profile says so
+fn field_name(headers: &HeaderMap, profile: &ParseProfile) -> Option<String> {
+    let cd = ContentDisposition::parse(headers.get(CONTENT_DISPOSITION)?.to_str().ok()?);
+    cd.form_param("name", profile.html_names)
+}
+
+// Whether a part is one of the profile's `ignored_fields`
+fn is_ignored(headers: &HeaderMap, profile: &ParseProfile) -> bool {
+    if profile.ignored_fields.is_empty() {
+        return false;
+    }
+    match field_name(headers, profile) {
+        Some(name) => profile.ignored_fields.contains(&name),
+        None => false,
+    }
+}
+
+// Where the profile's policy puts a part with the given headers
+fn part_disposition(headers: &HeaderMap, profile: &ParseProfile) -> Result<Disposition, Error> {
+    if is_ignored(headers, profile) {
+        return Ok(Disposition::Skip);
+    }
+    match profile.disposition {
+        DispositionPolicy::Auto => {}
+        DispositionPolicy::AlwaysFile => return Ok(Disposition::File),
+        DispositionPolicy::Custom(ref decide) => return Ok(decide(headers)),
+    }
+    let cd = match headers.get(CONTENT_DISPOSITION) {
+        Some(value) => ContentDisposition::from_header(value)?,
+        None => return Ok(Disposition::Memory),
+    };
+    let rules = &profile.spool_rules;
+    if cd.filename() == Some("") && cd.param("filename*").is_none() {
+        return Ok(rules.empty_filename);
+    }
+    Ok(match (&cd.disposition, cd.has_filename()) {
+        (DispositionType::Inline, true) => rules.inline_with_filename,
+        (_, true) => Disposition::File,
+        (DispositionType::Attachment, false) => rules.attachment_without_filename,
+        (_, false) => Disposition::Memory,
+    })
+}
+
+// If `content` (which ran up to EOF) ends in a close delimiter that lacks its preceding line
+// terminator, returns the length of that delimiter and any line terminator following it.
+#[cfg(feature = "parse")]
+fn close_delimiter_len(content: &[u8], boundary: &[u8]) -> Option<usize> {
+    let mut end = content.len();
+    if content[..end].ends_with(b"\n") {
+        end -= 1;
+    }
+    if content[..end].ends_with(b"\r") {
+        end -= 1;
+    }
+    match content[..end].strip_suffix(b"--") {
+        Some(rest) if rest.ends_with(boundary) => Some(content.len() - rest.len() + boundary.len()),
+        _ => None,
+    }
+}
+
+/// Get the `multipart/*` boundary string from `hyper::Headers`
+pub fn get_multipart_boundary(headers: &HeaderMap) -> Result<Vec<u8>, Error> {
+    // Verify that the request is 'Content-Type: multipart/*'.
+    let mime = match headers.get("content-type") {
+        Some(ct) => match ct.to_str() {
+            Ok(value) => match Mime::from_str(value) {
+                Ok(value) => value,
+                Err(_) => return Err(Error::HeaderValueNotMime),
+            },
+            Err(err) => return Err(Error::ToStr(err)),
+        },
+        None => return Err(Error::NoRequestContentType),
+    };
+    let top_level = mime.type_();
+
+    if top_level != mime::MULTIPART {
+        return Err(Error::NotMultipart);
+    }
+
+    match mime.get_param(mime::BOUNDARY) {
+        None => Err(Error::BoundaryNotSpecified),
+        Some(content) => {
+            let mut boundary = vec![];
+            boundary.extend(b"--".iter().cloned());
+            boundary.extend(content.to_string().as_bytes());
+            Ok(boundary)
+        }
+    }
+}
+
+// Deal with a part carrying its Content-Type or Content-Disposition more than once, which
+// would leave which of them applies up to whoever looks: refused in `ParseMode::Strict`, and
+// otherwise the first is kept and the others dropped
+#[cfg(feature = "parse")]
+fn check_duplicate_headers(headers: &mut HeaderMap, mode: ParseMode) -> Result<(), Error> {
+    for name in [CONTENT_TYPE, CONTENT_DISPOSITION] {
+        let count = headers.get_all(&name).iter().count();
+        if count < 2 {
+            continue;
+        }
+        if mode == ParseMode::Strict {
+            return Err(Error::DuplicateHeader(name.to_string()));
+        }
+        record!(
+            warn,
+            "{} {} headers in a part, keeping the first",
+            count,
+            name
+        );
+        if let Some(first) = headers.get(&name).cloned() {
+            headers.insert(name, first);
+        }
+    }
+    Ok(())
+}
+
+// Pick the Content-Type to go by from headers carrying several.  Returns headers with just
+// that one, if it is not the first.
+#[cfg(feature = "parse")]
+fn choose_content_type(
+    headers: &HeaderMap,
+    choice: ContentTypeChoice,
+) -> Result<Option<HeaderMap>, Error> {
+    let values: Vec<&HeaderValue> = headers.get_all(CONTENT_TYPE).iter().collect();
+    if values.len() < 2 {
+        return Ok(None);
+    }
+    record!(
+        warn,
+        "{} Content-Type headers, going by {:?}",
+        values.len(),
+        choice
+    );
+    match choice {
+        ContentTypeChoice::First => Ok(None),
+        ContentTypeChoice::Reject if values.iter().all(|&value| value == values[0]) => Ok(None),
+        ContentTypeChoice::Reject => Err(Error::ConflictingContentTypes),
+        ContentTypeChoice::FirstMultipart => {
+            let is_multipart = |value: &HeaderValue| {
+                let mime = value.to_str().ok().and_then(|v| Mime::from_str(v).ok());
+                mime.is_some_and(|mime| {
+                    mime.type_() == mime::MULTIPART && mime.get_param(mime::BOUNDARY).is_some()
+                })
+            };
+            match values.iter().position(|&value| is_multipart(value)) {
+                None | Some(0) => Ok(None),
                 Some(index) => {
-                    let start = index + "filename=".len();
-                    Ok(Some(
-                        value.get(start..).unwrap().trim_matches('\"').to_owned(),
-                    ))
+                    let mut chosen = headers.clone();
+                    chosen.insert(CONTENT_TYPE, values[index].clone());
+                    Ok(Some(chosen))
                 }
-                None => match value.find("filename*=UTF-8''") {
-                    Some(index) => {
-                        let start = index + "filename*=UTF-8''".len();
-                        Ok(Some(
-                            value.get(start..).unwrap().trim_matches('\"').to_owned(),
-                        ))
-                    }
-                    None => Ok(None),
-                },
+            }
+        }
+    }
+}
+
+// How much of a body `sniff_boundary()` needs to see: the dashes, the longest boundary RFC
+// 2046 allows, some transport padding and the line terminator
+#[cfg(feature = "parse")]
+const SNIFF_LEN: usize = 2 + 70 + 8 + 2;
+
+/// Find the boundary of a multipart body from its start, for bodies whose `Content-Type`
+/// lacks one: the body must begin with a `--boundary` line, the boundary being valid by RFC
+/// 2046.  Returns the boundary without the leading dashes, or `None`.  The first 82 bytes
+/// are enough to find any boundary.
+pub fn sniff_boundary(start: &[u8]) -> Option<String> {
+    let line = start.strip_prefix(b"--")?;
+    let end = line.iter().position(|&ch| ch == b'\r' || ch == b'\n')?;
+    let boundary = std::str::from_utf8(&line[..end])
+        .ok()?
+        .trim_end_matches([' ', '\t']);
+    let valid = |ch: char| ch.is_ascii_alphanumeric() || "'()+_,-./:=? ".contains(ch);
+    if boundary.is_empty()
+        || boundary.len() > 70
+        || boundary.ends_with(' ')
+        || !boundary.chars().all(valid)
+    {
+        return None;
+    }
+    Some(boundary.to_owned())
+}
+
+/// Whether `headers` carry a valid `multipart/*` Content-Type
+pub fn is_multipart(headers: &HeaderMap) -> bool {
+    multipart_subtype(headers).is_some()
+}
+
+/// The subtype of the `multipart/*` Content-Type in `headers`, in lowercase (such as
+/// `form-data` or `mixed`), or `None` if there is no valid multipart Content-Type.
+pub fn multipart_subtype(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get("content-type")?.to_str().ok()?;
+    let mime = Mime::from_str(value).ok()?;
+    match mime.type_() == mime::MULTIPART {
+        true => Some(mime.subtype().as_str().to_ascii_lowercase()),
+        false => None,
+    }
+}
+
+/// The Content-Length in `headers`, or `None` if it is missing or not a valid length
+pub fn content_length(headers: &HeaderMap) -> Option<u64> {
+    let value = headers.get(CONTENT_LENGTH)?.to_str().ok()?.trim();
+    match !value.is_empty() && value.bytes().all(|ch| ch.is_ascii_digit()) {
+        true => value.parse().ok(),
+        false => None,
+    }
+}
+
+/// Build a `Content-Type` header value for a `multipart/<subtype>` body delimited by
+/// `boundary`.  The boundary is quoted if it contains characters (such as spaces, `?` or `=`)
+/// which are not allowed in a bare parameter value, so that it survives being read back by
+/// `get_multipart_boundary()`.
+///
+/// Returns `Error::InvalidBoundary` if the boundary is not permitted by RFC 2046.
+pub fn multipart_content_type(subtype: &str, boundary: &[u8]) -> Result<HeaderValue, Error> {
+    validate_boundary(boundary)?;
+
+    let mut value = format!("multipart/{}", subtype).into_bytes();
+    push_boundary_param(&mut value, boundary);
+
+    match HeaderValue::from_bytes(&value) {
+        Ok(value) => Ok(value),
+        Err(_) => Err(Error::InvalidHeaderNameOrValue),
+    }
+}
+
+// Append a `boundary` parameter to a Content-Type value, quoting it if need be
+fn push_boundary_param(value: &mut Vec<u8>, boundary: &[u8]) {
+    value.extend(b"; boundary=");
+    if boundary.iter().all(|&ch| is_token_char(ch)) {
+        value.extend(boundary);
+    } else {
+        value.push(b'"');
+        value.extend(boundary);
+        value.push(b'"');
+    }
+}
+
+// The headers to write for a nested multipart, and its boundary.  If the headers lack a
+// boundary, one is generated as the profile says and added to the Content-Type, which is
+// `multipart/mixed` if there is none.
+#[cfg(feature = "write")]
+fn nested_multipart<'a>(
+    headers: &'a HeaderMap,
+    profile: &WriteProfile,
+) -> Result<(Cow<'a, HeaderMap>, Vec<u8>), Error> {
+    let mut value = match get_multipart_boundary(headers) {
+        Ok(mut boundary) => return Ok((Cow::Borrowed(headers), boundary.split_off(2))),
+        Err(Error::BoundaryNotSpecified) => headers[CONTENT_TYPE].as_bytes().to_vec(),
+        Err(Error::NoRequestContentType) => b"multipart/mixed".to_vec(),
+        Err(err) => return Err(err),
+    };
+    while value.last().is_some_and(|&ch| ch == b';' || ch == b' ') {
+        value.pop();
+    }
+    let boundary = match profile.entropy {
+        Some(ref entropy) => generate_boundary_with(entropy),
+        None => generate_boundary(),
+    };
+    push_boundary_param(&mut value, &boundary);
+    let mut headers = headers.clone();
+    match HeaderValue::from_bytes(&value) {
+        Ok(value) => headers.insert(CONTENT_TYPE, value),
+        Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+    };
+    Ok((Cow::Owned(headers), boundary))
+}
+
+/// Check that `boundary` is a valid RFC 2046 boundary: 1 to 70 characters from the
+/// `bchars` set, not ending in a space.
+pub fn validate_boundary(boundary: &[u8]) -> Result<(), Error> {
+    if boundary.is_empty() || boundary.len() > 70 || boundary.ends_with(b" ") {
+        return Err(Error::InvalidBoundary);
+    }
+    let bchar = |ch: u8| ch.is_ascii_alphanumeric() || b"'()+_,-./:=? ".contains(&ch);
+    if !boundary.iter().all(|&ch| bchar(ch)) {
+        return Err(Error::InvalidBoundary);
+    }
+    Ok(())
+}
+
+// A character permitted in an RFC 2045 token, and thus in an unquoted parameter value
+#[inline]
+fn is_token_char(ch: u8) -> bool {
+    ch > b' ' && ch < 0x7f && !b"()<>@,;:\\\"/[]?=".contains(&ch)
+}
+
+// A SHA-256 digest as lowercase hex
+#[cfg(feature = "sha2")]
+fn hex_digest(digest: Sha256) -> String {
+    digest
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Decode `%XX` escapes, and `+` as space if `plus_as_space`.  A `%` not followed by two hex
+// digits is kept as is, as browsers do.
+fn percent_decode(input: &[u8], plus_as_space: bool) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut index = 0;
+    while index < input.len() {
+        match input[index] {
+            b'+' if plus_as_space => output.push(b' '),
+            b'%' => match input.get(index + 1..index + 3).and_then(hex_byte) {
+                Some(byte) => {
+                    output.push(byte);
+                    index += 2;
+                }
+                None => output.push(b'%'),
             },
-            false => Ok(None),
+            ch => output.push(ch),
+        }
+        index += 1;
+    }
+    output
+}
+
+fn hex_byte(digits: &[u8]) -> Option<u8> {
+    let digits = std::str::from_utf8(digits).ok()?;
+    match digits.bytes().all(|ch| ch.is_ascii_hexdigit()) {
+        true => u8::from_str_radix(digits, 16).ok(),
+        false => None,
+    }
+}
+
+// Whether a part's headers make it a nested multipart
+#[cfg(feature = "parse")]
+fn is_nested(headers: &HeaderMap) -> Result<bool, Error> {
+    match headers.get(CONTENT_TYPE) {
+        Some(ct) => match Mime::from_str(ct.to_str().map_err(Error::ToStr)?) {
+            Ok(mime) => Ok(mime.type_() == mime::MULTIPART),
+            Err(_) => Err(Error::HeaderValueNotMime),
         },
-        Err(err) => Err(Error::ToStr(err)),
+        None => Ok(false),
+    }
+}
+
+// Get the `filename` parameter of a `Content-Disposition` header value, or else the value of
+// a `filename*` parameter in UTF-8
+fn get_content_disposition_filename(cd: &HeaderValue) -> Result<Option<String>, Error> {
+    Ok(ContentDisposition::from_header(cd)?.decoded_filename())
+}
+
+// Make an uploaded filename safe to create: only its last path component is kept, characters
+// which are unsafe in paths on common platforms are replaced, leading dots are removed, and it
+// is cut to 200 bytes.  Returns `None` if nothing usable is left.
+fn sanitize_filename(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let mut output = String::with_capacity(name.len());
+    for ch in name.trim_start_matches('.').trim().chars() {
+        if output.len() + ch.len_utf8() > 200 {
+            break;
+        }
+        match ch {
+            ':' | '*' | '?' | '"' | '<' | '>' | '|' => output.push('_'),
+            ch if ch.is_control() => output.push('_'),
+            ch => output.push(ch),
+        }
+    }
+    let output = output.trim_end_matches(['.', ' ']);
+    match output.is_empty() {
+        true => None,
+        false => Some(output.to_owned()),
     }
 }
 
 /// Generate a valid multipart boundary, statistically unlikely to be found within
 /// the content of the parts.
+#[cfg(feature = "write")]
 pub fn generate_boundary() -> Vec<u8> {
     TextNonce::sized(68)
         .unwrap()
@@ -452,11 +2413,22 @@ pub fn generate_boundary() -> Vec<u8> {
         .collect()
 }
 
+/// Like `generate_boundary()`, but taking the randomness from `entropy`.  The boundary is
+/// made of the same characters, and is as long.
+#[cfg(feature = "write")]
+pub fn generate_boundary_with(entropy: &Entropy) -> Vec<u8> {
+    entropy.string(BOUNDARY_CHARS, 68).into_bytes()
+}
+
+// The characters of generated boundaries: those of base64, with `.` in place of `/`
+#[cfg(feature = "write")]
+const BOUNDARY_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+.";
+
 // Convenience method, like write_all(), but returns the count of bytes written.
 trait WriteAllCount {
     fn write_all_count(&mut self, buf: &[u8]) -> std::io::Result<usize>;
 }
-impl<T: Write> WriteAllCount for T {
+impl<T: Write + ?Sized> WriteAllCount for T {
     fn write_all_count(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         self.write_all(buf)?;
         Ok(buf.len())
@@ -466,11 +2438,93 @@ impl<T: Write> WriteAllCount for T {
 /// Stream a multipart body to the output `stream` given, made up of the `parts`
 /// given.  Top-level headers are NOT included in this stream; the caller must send
 /// those prior to calling write_multipart().
+/// Fails with `Error::BoundaryCollision`, before writing anything, if a header value or
+/// in-memory body contains a delimiter.  A nested multipart whose Content-Type lacks a
+/// boundary is written with a generated one.
+/// Returns the number of bytes written, or an error.
+#[cfg(feature = "write")]
+pub fn write_multipart<S: Write + ?Sized>(
+    stream: &mut S,
+    boundary: &[u8],
+    nodes: &Vec<Node>,
+) -> Result<usize, Error> {
+    write_multipart_with(stream, boundary, nodes, &WriteProfile::default())
+}
+
+/// Like `write_multipart()`, but emitting the body as directed by the `WriteProfile`
+/// given.
 /// Returns the number of bytes written, or an error.
-pub fn write_multipart<S: Write>(
+#[cfg(feature = "write")]
+pub fn write_multipart_with<S: Write + ?Sized>(
+    stream: &mut S,
+    boundary: &[u8],
+    nodes: &Vec<Node>,
+    profile: &WriteProfile,
+) -> Result<usize, Error> {
+    check_boundaries(Some(boundary), nodes, &mut Vec::new())?;
+    let mut count = write_nodes(stream, boundary, nodes, profile)?;
+    if profile.final_crlf {
+        count += stream.write_all_count(b"\r\n")?;
+    }
+    Ok(count)
+}
+
+/// The number of bytes `write_multipart_with()` would write for `nodes` with the given
+/// `boundary` and `WriteProfile`, so that a `Content-Length` can be sent ahead of the body.
+/// File sizes are taken from the files' metadata, so the files must not change before they
+/// are written.  Nested multiparts without a boundary are counted with one of the length
+/// `generate_boundary()` gives.
+#[cfg(feature = "write")]
+pub fn multipart_length(
+    boundary: &[u8],
+    nodes: &[Node],
+    profile: &WriteProfile,
+) -> Result<u64, Error> {
+    let mut length = nodes_length(boundary, nodes, profile)?;
+    if profile.final_crlf {
+        length += 2;
+    }
+    Ok(length)
+}
+
+// The length `write_nodes()` would write, headers being written to a sink to count them
+#[cfg(feature = "write")]
+fn nodes_length(boundary: &[u8], nodes: &[Node], profile: &WriteProfile) -> Result<u64, Error> {
+    let delimiter = 2 + boundary.len() as u64;
+    let mut length = 0;
+    for node in nodes {
+        length += delimiter + 2;
+        match *node {
+            Node::Part(ref part) => {
+                let size = part.body.len() as u64;
+                let content_length = profile.content_length.then_some(size);
+                length += write_headers(&mut io::sink(), &part.headers, content_length)? as u64;
+                length += size;
+            }
+            Node::File(ref filepart) => {
+                let size = std::fs::metadata(&filepart.path)?.len();
+                let headers = file_headers(&filepart.headers, size, profile)?;
+                let content_length = profile.content_length.then_some(size);
+                length += write_headers(&mut io::sink(), &headers, content_length)? as u64;
+                length += size;
+            }
+            Node::Multipart((ref headers, ref subnodes)) => {
+                let (headers, boundary) = nested_multipart(headers, profile)?;
+                length += write_headers(&mut io::sink(), &headers, None)? as u64;
+                length += nodes_length(&boundary, subnodes, profile)?;
+            }
+        }
+        length += 2;
+    }
+    Ok(length + delimiter + 2)
+}
+
+#[cfg(feature = "write")]
+fn write_nodes<S: Write + ?Sized>(
     stream: &mut S,
     boundary: &[u8],
     nodes: &Vec<Node>,
+    profile: &WriteProfile,
 ) -> Result<usize, Error> {
     let mut count: usize = 0;
 
@@ -482,53 +2536,39 @@ pub fn write_multipart<S: Write>(
 
         match *node {
             Node::Part(ref part) => {
-                // write the part's headers
-                for header in part.headers.iter() {
-                    count += stream.write_all_count(header.0.as_str().as_bytes())?;
-                    count += stream.write_all_count(b": ")?;
-                    count += stream.write_all_count(header.1.as_bytes())?;
-                    count += stream.write_all_count(b"\r\n")?;
-                }
-
-                // write the blank line
-                count += stream.write_all_count(b"\r\n")?;
+                // write the part's headers and the blank line
+                let length = match profile.content_length {
+                    true => Some(part.body.len() as u64),
+                    false => None,
+                };
+                count += write_headers(stream, &part.headers, length)?;
 
                 // Write the part's content
                 count += stream.write_all_count(&part.body)?;
             }
             Node::File(ref filepart) => {
-                // write the part's headers
-                for header in filepart.headers.iter() {
-                    count += stream.write_all_count(header.0.as_str().as_bytes())?;
-                    count += stream.write_all_count(b": ")?;
-                    count += stream.write_all_count(header.1.as_bytes())?;
-                    count += stream.write_all_count(b"\r\n")?;
-                }
-
-                // write the blank line
-                count += stream.write_all_count(b"\r\n")?;
+                // write the part's headers and the blank line
+                let size = std::fs::metadata(&filepart.path)?.len();
+                let headers = file_headers(&filepart.headers, size, profile)?;
+                let length = match profile.content_length {
+                    true => Some(size),
+                    false => None,
+                };
+                count += write_headers(stream, &headers, length)?;
 
                 // Write out the files's content
                 let mut file = File::open(&filepart.path)?;
                 count += std::io::copy(&mut file, stream)? as usize;
             }
             Node::Multipart((ref headers, ref subnodes)) => {
-                // Get boundary
-                let boundary = get_multipart_boundary(headers)?;
-
-                // write the multipart headers
-                for header in headers.iter() {
-                    count += stream.write_all_count(header.0.as_str().as_bytes())?;
-                    count += stream.write_all_count(b": ")?;
-                    count += stream.write_all_count(header.1.as_bytes())?;
-                    count += stream.write_all_count(b"\r\n")?;
-                }
+                // Get boundary, without the leading "--" of the delimiter
+                let (headers, boundary) = nested_multipart(headers, profile)?;
 
-                // write the blank line
-                count += stream.write_all_count(b"\r\n")?;
+                // write the multipart headers and the blank line
+                count += write_headers(stream, &headers, None)?;
 
                 // Recurse
-                count += write_multipart(stream, &boundary, subnodes)?;
+                count += write_nodes(stream, &boundary, subnodes, profile)?;
             }
         }
 
@@ -544,7 +2584,150 @@ pub fn write_multipart<S: Write>(
     Ok(count)
 }
 
-pub fn write_chunk<S: Write>(stream: &mut S, chunk: &[u8]) -> Result<(), ::std::io::Error> {
+// Check that no header value or in-memory body of `nodes` contains the delimiter of
+// `boundary` or of an enclosing multipart, whose delimiters are in `outer`.  A delimiter
+// within a body would end it early; within a header value it is harmless to this parser,
+// but not to ones which look for delimiters anywhere.  Files are not read.
+#[cfg(feature = "write")]
+fn check_boundaries(
+    boundary: Option<&[u8]>,
+    nodes: &[Node],
+    outer: &mut Vec<Vec<u8>>,
+) -> Result<(), Error> {
+    // A generated boundary, not yet known, is statistically unlikely to collide
+    if let Some(boundary) = boundary {
+        let mut delimiter = b"--".to_vec();
+        delimiter.extend(boundary);
+        outer.push(delimiter);
+    }
+    for node in nodes {
+        let headers = node.headers();
+        if headers
+            .values()
+            .any(|value| collides(value.as_bytes(), outer))
+        {
+            return Err(Error::BoundaryCollision);
+        }
+        match *node {
+            Node::Part(ref part) if collides(&part.body, outer) => {
+                return Err(Error::BoundaryCollision)
+            }
+            Node::Multipart((ref headers, ref subnodes)) => match get_multipart_boundary(headers) {
+                Ok(mut boundary) => {
+                    check_boundaries(Some(&boundary.split_off(2)), subnodes, outer)?
+                }
+                Err(Error::BoundaryNotSpecified) | Err(Error::NoRequestContentType) => {
+                    check_boundaries(None, subnodes, outer)?
+                }
+                Err(err) => return Err(err),
+            },
+            _ => {}
+        }
+    }
+    if boundary.is_some() {
+        outer.pop();
+    }
+    Ok(())
+}
+
+// Whether `content` contains any of the `delimiters`
+#[cfg(feature = "write")]
+fn collides(content: &[u8], delimiters: &[Vec<u8>]) -> bool {
+    delimiters.iter().any(|delimiter| {
+        content.len() >= delimiter.len()
+            && content
+                .windows(delimiter.len())
+                .any(|window| window == &delimiter[..])
+    })
+}
+
+// Apply the file part conventions of the `WriteProfile` to the headers of a file part of
+// `size` bytes.  The headers are only copied if something needs to be added.
+#[cfg(feature = "write")]
+fn file_headers<'a>(
+    headers: &'a HeaderMap,
+    size: u64,
+    profile: &WriteProfile,
+) -> Result<Cow<'a, HeaderMap>, Error> {
+    let mut headers = Cow::Borrowed(headers);
+
+    if profile.file_transfer_encoding && !headers.contains_key("content-transfer-encoding") {
+        headers.to_mut().insert(
+            HeaderName::from_static("content-transfer-encoding"),
+            HeaderValue::from_static("binary"),
+        );
+    }
+
+    if profile.file_disposition_size {
+        let disposition = match headers.get(CONTENT_DISPOSITION) {
+            Some(cd) => match cd.to_str() {
+                Ok(value) => value.to_owned(),
+                Err(err) => return Err(Error::ToStr(err)),
+            },
+            None => "attachment".to_owned(),
+        };
+        let has_size = disposition
+            .split(';')
+            .skip(1)
+            .any(|param| param.trim().to_ascii_lowercase().starts_with("size="));
+        if !has_size {
+            let value = format!("{}; size={}", disposition, size);
+            let value = match HeaderValue::from_str(&value) {
+                Ok(value) => value,
+                Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+            };
+            headers.to_mut().insert(CONTENT_DISPOSITION, value);
+        }
+    }
+
+    Ok(headers)
+}
+
+// Write out a part's headers followed by the blank line.  If a `length` is given, it is
+// written as the Content-Length header in place of any the part already carries.
+fn write_headers<S: Write + ?Sized>(
+    stream: &mut S,
+    headers: &HeaderMap,
+    length: Option<u64>,
+) -> Result<usize, Error> {
+    let mut count: usize = 0;
+    for header in headers.iter() {
+        if length.is_some() && header.0 == CONTENT_LENGTH {
+            continue;
+        }
+        check_header_value(header.1.as_bytes())?;
+        count += stream.write_all_count(header.0.as_str().as_bytes())?;
+        count += stream.write_all_count(b": ")?;
+        count += stream.write_all_count(header.1.as_bytes())?;
+        count += stream.write_all_count(b"\r\n")?;
+    }
+    if let Some(length) = length {
+        count += stream.write_all_count(CONTENT_LENGTH.as_str().as_bytes())?;
+        count += stream.write_all_count(b": ")?;
+        count += stream.write_all_count(length.to_string().as_bytes())?;
+        count += stream.write_all_count(b"\r\n")?;
+    }
+    count += stream.write_all_count(b"\r\n")?;
+    Ok(count)
+}
+
+// `HeaderValue`'s checked constructors refuse CR, LF and NUL, but the unchecked ones do not
+// in release builds, and a value holding one would be written as more than one header.
+fn check_header_value(value: &[u8]) -> Result<(), Error> {
+    match value
+        .iter()
+        .any(|&ch| ch == b'\r' || ch == b'\n' || ch == 0)
+    {
+        true => Err(Error::UnsafeHeaderValue),
+        false => Ok(()),
+    }
+}
+
+#[cfg(feature = "write")]
+pub fn write_chunk<S: Write + ?Sized>(
+    stream: &mut S,
+    chunk: &[u8],
+) -> Result<(), ::std::io::Error> {
     write!(stream, "{:x}\r\n", chunk.len())?;
     stream.write_all(chunk)?;
     stream.write_all(b"\r\n")?;
@@ -554,85 +2737,175 @@ pub fn write_chunk<S: Write>(stream: &mut S, chunk: &[u8]) -> Result<(), ::std::
 /// Stream a multipart body to the output `stream` given, made up of the `parts`
 /// given, using Tranfer-Encoding: Chunked.  Top-level headers are NOT included in this
 /// stream; the caller must send those prior to calling write_multipart_chunked().
-pub fn write_multipart_chunked<S: Write>(
+#[cfg(feature = "write")]
+pub fn write_multipart_chunked<S: Write + ?Sized>(
+    stream: &mut S,
+    boundary: &[u8],
+    nodes: &Vec<Node>,
+) -> Result<(), Error> {
+    write_multipart_chunked_with(stream, boundary, nodes, &WriteProfile::default())
+}
+
+/// Like `write_multipart_chunked()`, but emitting the body as directed by the
+/// `WriteProfile` given.
+#[cfg(feature = "write")]
+pub fn write_multipart_chunked_with<S: Write + ?Sized>(
     stream: &mut S,
     boundary: &[u8],
     nodes: &Vec<Node>,
+    profile: &WriteProfile,
+) -> Result<(), Error> {
+    check_boundaries(Some(boundary), nodes, &mut Vec::new())?;
+    let mut out = Chunked {
+        stream,
+        digest: profile.sha256_trailer.then(Sha256::new),
+    };
+    write_nodes_chunked(&mut out, boundary, nodes, profile)?;
+    if profile.final_crlf {
+        out.chunk(b"\r\n")?;
+    }
+
+    // Write an empty chunk to signal the end of the body, followed by any trailer
+    match out.digest {
+        Some(digest) => {
+            write!(
+                out.stream,
+                "0\r\n{}: {}\r\n\r\n",
+                SHA256_TRAILER,
+                hex_digest(digest)
+            )?;
+        }
+        None => write_chunk(out.stream, b"")?,
+    }
+
+    Ok(())
+}
+
+/// The name of the trailer carrying the body's digest when `WriteProfile::sha256_trailer`
+/// is set.
+pub const SHA256_TRAILER: &str = "x-content-sha256";
+
+// A chunked output stream, which computes the digest of the content written to it if
+// needed for a trailer.
+#[cfg(feature = "write")]
+struct Chunked<'a, S: ?Sized> {
+    stream: &'a mut S,
+    digest: Option<Sha256>,
+}
+
+#[cfg(feature = "write")]
+impl<S: Write + ?Sized> Chunked<'_, S> {
+    fn chunk(&mut self, data: &[u8]) -> Result<(), ::std::io::Error> {
+        // An empty chunk would end the body
+        if data.is_empty() {
+            return Ok(());
+        }
+        if let Some(ref mut digest) = self.digest {
+            digest.update(data);
+        }
+        write_chunk(self.stream, data)
+    }
+
+    // Write out a file's content, a chunk for each read, so that the chunk sizes are those of
+    // what was read rather than what the file's length was beforehand
+    fn file_chunk(&mut self, file: &mut File) -> Result<(), ::std::io::Error> {
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.chunk(&buf[..read])?;
+        }
+    }
+}
+
+#[cfg(feature = "write")]
+fn write_nodes_chunked<S: Write + ?Sized>(
+    out: &mut Chunked<S>,
+    boundary: &[u8],
+    nodes: &Vec<Node>,
+    profile: &WriteProfile,
 ) -> Result<(), Error> {
     for node in nodes {
         // write a boundary
-        write_chunk(stream, b"--")?;
-        write_chunk(stream, boundary)?;
-        write_chunk(stream, b"\r\n")?;
+        out.chunk(b"--")?;
+        out.chunk(boundary)?;
+        out.chunk(b"\r\n")?;
 
         match *node {
             Node::Part(ref part) => {
-                // write the part's headers
-                for header in part.headers.iter() {
-                    write_chunk(stream, header.0.as_str().as_bytes())?;
-                    write_chunk(stream, b": ")?;
-                    write_chunk(stream, header.1.as_bytes())?;
-                    write_chunk(stream, b"\r\n")?;
-                }
-
-                // write the blank line
-                write_chunk(stream, b"\r\n")?;
+                // write the part's headers and the blank line
+                let length = match profile.content_length {
+                    true => Some(part.body.len() as u64),
+                    false => None,
+                };
+                write_headers_chunked(out, &part.headers, length)?;
 
                 // Write the part's content
-                write_chunk(stream, &part.body)?;
+                out.chunk(&part.body)?;
             }
             Node::File(ref filepart) => {
-                // write the part's headers
-                for header in filepart.headers.iter() {
-                    write_chunk(stream, header.0.as_str().as_bytes())?;
-                    write_chunk(stream, b": ")?;
-                    write_chunk(stream, header.1.as_bytes())?;
-                    write_chunk(stream, b"\r\n")?;
-                }
-
-                // write the blank line
-                write_chunk(stream, b"\r\n")?;
-
-                // Write out the files's length
+                // write the part's headers and the blank line
                 let metadata = std::fs::metadata(&filepart.path)?;
-                write!(stream, "{:x}\r\n", metadata.len())?;
+                let headers = file_headers(&filepart.headers, metadata.len(), profile)?;
+                let length = match profile.content_length {
+                    true => Some(metadata.len()),
+                    false => None,
+                };
+                write_headers_chunked(out, &headers, length)?;
 
                 // Write out the file's content
                 let mut file = File::open(&filepart.path)?;
-                std::io::copy(&mut file, stream)?;
-                stream.write_all(b"\r\n")?;
+                out.file_chunk(&mut file)?;
             }
             Node::Multipart((ref headers, ref subnodes)) => {
-                // Get boundary
-                let boundary = get_multipart_boundary(headers)?;
-
-                // write the multipart headers
-                for header in headers.iter() {
-                    write_chunk(stream, header.0.as_str().as_bytes())?;
-                    write_chunk(stream, b": ")?;
-                    write_chunk(stream, header.1.as_bytes())?;
-                    write_chunk(stream, b"\r\n")?;
-                }
+                // Get boundary, without the leading "--" of the delimiter
+                let (headers, boundary) = nested_multipart(headers, profile)?;
 
-                // write the blank line
-                write_chunk(stream, b"\r\n")?;
+                // write the multipart headers and the blank line
+                write_headers_chunked(out, &headers, None)?;
 
                 // Recurse
-                write_multipart_chunked(stream, &boundary, subnodes)?;
+                write_nodes_chunked(out, &boundary, subnodes, profile)?;
             }
         }
 
         // write a line terminator
-        write_chunk(stream, b"\r\n")?;
+        out.chunk(b"\r\n")?;
     }
 
     // write a final boundary
-    write_chunk(stream, b"--")?;
-    write_chunk(stream, boundary)?;
-    write_chunk(stream, b"--")?;
+    out.chunk(b"--")?;
+    out.chunk(boundary)?;
+    out.chunk(b"--")?;
 
-    // Write an empty chunk to signal the end of the body
-    write_chunk(stream, b"")?;
+    Ok(())
+}
 
+// The chunked counterpart of `write_headers()`.
+#[cfg(feature = "write")]
+fn write_headers_chunked<S: Write + ?Sized>(
+    out: &mut Chunked<S>,
+    headers: &HeaderMap,
+    length: Option<u64>,
+) -> Result<(), Error> {
+    for header in headers.iter() {
+        if length.is_some() && header.0 == CONTENT_LENGTH {
+            continue;
+        }
+        check_header_value(header.1.as_bytes())?;
+        out.chunk(header.0.as_str().as_bytes())?;
+        out.chunk(b": ")?;
+        out.chunk(header.1.as_bytes())?;
+        out.chunk(b"\r\n")?;
+    }
+    if let Some(length) = length {
+        out.chunk(CONTENT_LENGTH.as_str().as_bytes())?;
+        out.chunk(b": ")?;
+        out.chunk(length.to_string().as_bytes())?;
+        out.chunk(b"\r\n")?;
+    }
+    out.chunk(b"\r\n")?;
     Ok(())
 }
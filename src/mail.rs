@@ -0,0 +1,80 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Conversion of a `Node` tree into the MIME parts of the `lettre` mail builder, so that a
+//! parsed upload can be forwarded by email.
+
+use super::{multipart_subtype, ContentDisposition, DispositionType, Error, Node};
+use http::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_TYPE};
+use lettre::message::header::{self, ContentId};
+use lettre::message::{MultiPart, MultiPartKind, SinglePart};
+use std::fs;
+
+/// Convert `nodes` into a `lettre` multipart of the given kind, such as
+/// `MultiPartKind::Mixed` for a message with attachments.
+///
+/// Each part keeps its `Content-Type` and `Content-ID`.  Parts with a filename become
+/// attachments (or stay `inline`, if they were) under that filename; `form-data`
+/// dispositions, which have no meaning in mail, are dropped otherwise.  Other headers are
+/// not carried over, and `lettre` chooses a `Content-Transfer-Encoding` for each body.  File
+/// parts are read into memory.  Nested multiparts keep their subtype if `lettre` supports it
+/// (`mixed`, `alternative` or `related`), and become `mixed` otherwise.
+pub fn to_multipart(kind: MultiPartKind, nodes: &[Node]) -> Result<MultiPart, Error> {
+    let mut multipart = MultiPart::builder().kind(kind).build();
+    for node in nodes {
+        multipart = match *node {
+            Node::Part(ref part) => multipart.singlepart(to_singlepart(&part.headers, &part.body)?),
+            Node::File(ref filepart) => {
+                let body = fs::read(&filepart.path)?;
+                multipart.singlepart(to_singlepart(&filepart.headers, &body)?)
+            }
+            Node::Multipart((ref headers, ref subnodes)) => {
+                multipart.multipart(to_multipart(multipart_kind(headers), subnodes)?)
+            }
+        };
+    }
+    Ok(multipart)
+}
+
+fn to_singlepart(headers: &HeaderMap, body: &[u8]) -> Result<SinglePart, Error> {
+    let mut builder = SinglePart::builder();
+    if let Some(value) = headers.get(CONTENT_TYPE) {
+        let value = value.to_str().map_err(Error::ToStr)?;
+        match header::ContentType::parse(value) {
+            Ok(content_type) => builder = builder.content_type(content_type),
+            Err(_) => return Err(Error::HeaderValueNotMime),
+        }
+    }
+    if let Some(value) = headers.get(CONTENT_DISPOSITION) {
+        let disposition = ContentDisposition::from_header(value)?;
+        let inline = disposition.disposition == DispositionType::Inline;
+        match disposition.decoded_filename() {
+            Some(filename) if inline => {
+                builder = builder.header(header::ContentDisposition::inline_with_name(&filename))
+            }
+            Some(filename) => {
+                builder = builder.header(header::ContentDisposition::attachment(&filename))
+            }
+            None if inline => builder = builder.header(header::ContentDisposition::inline()),
+            None => {}
+        }
+    }
+    if let Some(value) = headers.get("content-id") {
+        let value = value.to_str().map_err(Error::ToStr)?;
+        builder = builder.header(ContentId::from(value.to_owned()));
+    }
+    Ok(builder.body(body.to_vec()))
+}
+
+// The lettre kind for a nested multipart
+fn multipart_kind(headers: &HeaderMap) -> MultiPartKind {
+    match multipart_subtype(headers).as_deref() {
+        Some("alternative") => MultiPartKind::Alternative,
+        Some("related") => MultiPartKind::Related,
+        _ => MultiPartKind::Mixed,
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{hex_digest, ContentDisposition, Error, Node, Part};
+use http::header::{HeaderMap, HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+
+/// Prepend a manifest part to `nodes`, so that a receiver can validate a large upload
+/// before processing it.  The manifest is a JSON document listing each part's `name` and
+/// `filename` (from its `Content-Disposition`), `type`, `size` in bytes and hex `sha256`
+/// digest, in order:
+///
+/// ```text
+/// {"parts":[{"name":"file","filename":"a.txt","type":"text/plain","size":5,"sha256":"..."}]}
+/// ```
+///
+/// Missing values are `null`.  Nested multiparts are listed with their `name` and `type`,
+/// and their own `parts`.  The manifest part is sent as `application/json`, with
+/// `Content-Disposition: form-data; name="manifest"`.  File parts are read to digest them.
+pub fn add_manifest(nodes: Vec<Node>) -> Result<Vec<Node>, Error> {
+    let manifest = json!({ "parts": list_parts(&nodes)? });
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("form-data; name=\"manifest\""),
+    );
+    let mut output = Vec::with_capacity(nodes.len() + 1);
+    output.push(Node::Part(Part {
+        headers,
+        body: manifest.to_string().into_bytes(),
+    }));
+    output.extend(nodes);
+    Ok(output)
+}
+
+fn list_parts(nodes: &[Node]) -> Result<Vec<Value>, Error> {
+    let mut parts = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let headers = node.headers();
+        let disposition = match headers.get(CONTENT_DISPOSITION) {
+            Some(cd) => Some(ContentDisposition::from_header(cd)?),
+            None => None,
+        };
+        let name = disposition
+            .as_ref()
+            .and_then(ContentDisposition::decoded_name);
+        let content_type = headers.get(CONTENT_TYPE).and_then(|ct| ct.to_str().ok());
+
+        if let Node::Multipart((_, ref subnodes)) = *node {
+            parts.push(json!({
+                "name": name,
+                "type": content_type,
+                "parts": list_parts(subnodes)?,
+            }));
+            continue;
+        }
+
+        let filename = disposition
+            .as_ref()
+            .and_then(ContentDisposition::decoded_filename);
+        let mut digest = Sha256::new();
+        let size = match *node {
+            Node::Part(ref part) => {
+                digest.update(&part.body);
+                part.body.len() as u64
+            }
+            Node::File(ref filepart) => {
+                let mut file = File::open(&filepart.path)?;
+                let mut buf = vec![0; 64 * 1024];
+                let mut size = 0;
+                loop {
+                    let read = file.read(&mut buf)?;
+                    if read == 0 {
+                        break;
+                    }
+                    digest.update(&buf[..read]);
+                    size += read as u64;
+                }
+                size
+            }
+            Node::Multipart(_) => unreachable!(),
+        };
+        parts.push(json!({
+            "name": name,
+            "filename": filename,
+            "type": content_type,
+            "size": size,
+            "sha256": hex_digest(digest),
+        }));
+    }
+    Ok(parts)
+}
@@ -0,0 +1,88 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{part_disposition, Disposition, Error, FilePart, Node, ParseProfile, Part};
+use http::header::{HeaderMap, HeaderValue};
+use std::fs::File;
+use std::io::{Read, Write};
+
+/// Rewrite `nodes` into the exact form that parsing them back from the output of
+/// `write_multipart()` produces, so that for any tree `x` which `write_multipart()` accepts,
+/// `read_multipart_body(write_multipart(normalize(x)))` yields `normalize(x)` again.  This is
+/// meant for round-trip and property-based testing.
+///
+/// Specifically:
+///
+/// * leading and trailing whitespace is trimmed from header values, and headers left empty
+///   are removed, which may leave a part with no headers at all;
+/// * parts are moved in or out of files as `read_multipart_body()` with the default
+///   `DispositionPolicy` would place them: `Part`s which look like file uploads are written
+///   to temporary `FilePart`s, and `FilePart`s which do not are read into memory;
+/// * `FilePart`s have their `size` filled in;
+/// * nested multiparts are normalized recursively.
+pub fn normalize(nodes: Vec<Node>) -> Result<Vec<Node>, Error> {
+    let profile = ParseProfile::default();
+    let mut output = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        output.push(match node {
+            Node::Part(mut part) => {
+                let headers = normalize_headers(&part.headers)?;
+                if part_disposition(&headers, &profile)? == Disposition::File {
+                    let mut filepart = FilePart::create(headers)?;
+                    filepart.create_file()?.write_all(&part.body)?;
+                    filepart.size = Some(part.body.len());
+                    Node::File(filepart)
+                } else {
+                    Node::Part(Part {
+                        headers,
+                        body: std::mem::take(&mut part.body),
+                    })
+                }
+            }
+            Node::File(mut filepart) => {
+                let headers = normalize_headers(&filepart.headers)?;
+                if part_disposition(&headers, &profile)? == Disposition::File {
+                    filepart.headers = headers;
+                    filepart.size = Some(std::fs::metadata(&filepart.path)?.len() as usize);
+                    Node::File(filepart)
+                } else {
+                    let mut body = Vec::new();
+                    File::open(&filepart.path)?.read_to_end(&mut body)?;
+                    Node::Part(Part { headers, body })
+                }
+            }
+            Node::Multipart((headers, subnodes)) => {
+                Node::Multipart((normalize_headers(&headers)?, normalize(subnodes)?))
+            }
+        });
+    }
+    Ok(output)
+}
+
+// Trim header values the way header parsing does, dropping those left empty
+fn normalize_headers(headers: &HeaderMap) -> Result<HeaderMap, Error> {
+    let mut output = HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers.iter() {
+        let value = value.as_bytes();
+        let start = value
+            .iter()
+            .position(|&ch| ch != b' ' && ch != b'\t')
+            .unwrap_or(value.len());
+        let end = value
+            .iter()
+            .rposition(|&ch| ch != b' ' && ch != b'\t')
+            .map_or(start, |pos| pos + 1);
+        if start == end {
+            continue;
+        }
+        match HeaderValue::from_bytes(&value[start..end]) {
+            Ok(value) => output.append(name.clone(), value),
+            Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+        };
+    }
+    Ok(output)
+}
@@ -0,0 +1,534 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{check_duplicate_headers, close_delimiter_len, cr_to_crlf};
+use super::{get_multipart_boundary, header_block_limit, parse_part_headers};
+use super::{Error, ParseMode, ParseProfile};
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+
+// Identifies (the version of) the format written by `MultipartParser::suspend()`
+const SNAPSHOT_MAGIC: &[u8] = b"mime-multipart parser 1\n";
+
+/// An event produced by a `MultipartParser`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// The headers of the next part.  Its body follows as `BodyChunk`s.
+    PartHeaders(HeaderMap),
+    /// A piece of the current part's body.
+    BodyChunk(Vec<u8>),
+    /// The current part's body is complete.
+    PartEnd,
+    /// The close delimiter was reached.  Any further input is epilogue, and is ignored.
+    Finished,
+}
+
+enum Step {
+    Emit(Event),
+    Continue,
+    NeedInput,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Preamble,
+    Delimiter { first: bool },
+    Headers,
+    Body,
+    Finished,
+}
+
+/// An incremental multipart parser which does no I/O of its own: input is handed to it with
+/// `feed()` as it arrives, and parsed into `Event`s with `next_event()`.  This lets it be
+/// driven from async runtimes or custom I/O loops.
+///
+/// Unlike `read_multipart_body()`, it does not descend into nested multiparts; their raw
+/// content is delivered as the body of the part containing them.  The `Limits` of the
+/// `ParseProfile` are enforced, except for `max_depth` and the limits on files and fields,
+/// as where parts go is up to the caller.
+#[derive(Clone, Debug)]
+pub struct MultipartParser {
+    profile: ParseProfile,
+    delimiter: Vec<u8>,
+    lt: Vec<u8>,
+    buf: Vec<u8>,
+    pos: usize,
+    // How far past `pos` the end of the header block being read is known not to start
+    scanned: usize,
+    eof: bool,
+    state: State,
+    parts: usize,
+    part_bytes: u64,
+    body_bytes: u64,
+    fed: u64,
+    headers: Option<HeaderMap>,
+}
+
+impl MultipartParser {
+    /// Create a parser for a body delimited by `boundary` (as found in the `Content-Type`
+    /// header, without the leading `--`).
+    pub fn new(boundary: &[u8]) -> MultipartParser {
+        MultipartParser::with_profile(boundary, &ParseProfile::default())
+    }
+
+    /// Create a parser for a body delimited by `boundary`, parsing as directed by the
+    /// `ParseProfile` given.
+    pub fn with_profile(boundary: &[u8], profile: &ParseProfile) -> MultipartParser {
+        let mut delimiter = b"--".to_vec();
+        delimiter.extend(boundary);
+        MultipartParser {
+            profile: profile.clone(),
+            delimiter,
+            lt: Vec::new(),
+            buf: Vec::new(),
+            pos: 0,
+            scanned: 0,
+            eof: false,
+            state: State::Preamble,
+            parts: 0,
+            part_bytes: 0,
+            body_bytes: 0,
+            fed: 0,
+            headers: None,
+        }
+    }
+
+    /// Create a parser for the body of a request or part with the given headers, taking the
+    /// boundary from its `Content-Type`.
+    pub fn from_headers(
+        headers: &HeaderMap,
+        profile: &ParseProfile,
+    ) -> Result<MultipartParser, Error> {
+        let delimiter = get_multipart_boundary(headers)?;
+        if let Some(max) = profile.limits.max_boundary_len {
+            if delimiter.len() - 2 > max {
+                return Err(Error::BoundaryTooLong);
+            }
+        }
+        Ok(MultipartParser::with_profile(&delimiter[2..], profile))
+    }
+
+    /// Hand the next piece of input to the parser.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        if self.state != State::Finished {
+            self.buf.extend_from_slice(data);
+        }
+        self.fed += data.len() as u64;
+    }
+
+    /// Tell the parser that the input has ended.
+    pub fn feed_eof(&mut self) {
+        self.eof = true;
+    }
+
+    /// Whether the close delimiter has been reached.
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Finished
+    }
+
+    /// The total number of bytes fed to the parser.  When resuming a suspended parser, input
+    /// continues from this offset in the body.
+    pub fn bytes_fed(&self) -> u64 {
+        self.fed
+    }
+
+    /// The headers of the part whose body is being parsed, if any
+    pub fn current_headers(&self) -> Option<&HeaderMap> {
+        self.headers.as_ref()
+    }
+
+    /// The number of body bytes of the current part delivered so far
+    pub fn part_bytes(&self) -> u64 {
+        self.part_bytes
+    }
+
+    /// Save the parser's state, so that parsing can be resumed later, possibly by another
+    /// process, with `resume()`.  Together with what was done with the events so far (such as
+    /// how much of the current part was spooled), this lets an interrupted upload be resumed
+    /// from `bytes_fed()` when the transfer protocol allows it.
+    ///
+    /// The `ParseProfile` is not saved, and must be supplied again on resuming.
+    pub fn suspend(&self) -> Vec<u8> {
+        let mut out = SNAPSHOT_MAGIC.to_vec();
+        put_bytes(&mut out, &self.delimiter);
+        put_bytes(&mut out, &self.lt);
+        put_bytes(&mut out, &self.buf[self.pos..]);
+        out.push(self.eof as u8);
+        out.push(match self.state {
+            State::Preamble => 0,
+            State::Delimiter { first: true } => 1,
+            State::Delimiter { first: false } => 2,
+            State::Headers => 3,
+            State::Body => 4,
+            State::Finished => 5,
+        });
+        put_u64(&mut out, self.parts as u64);
+        put_u64(&mut out, self.part_bytes);
+        put_u64(&mut out, self.body_bytes);
+        put_u64(&mut out, self.fed);
+        match self.headers {
+            Some(ref headers) => {
+                out.push(1);
+                put_u64(&mut out, headers.len() as u64);
+                for (name, value) in headers.iter() {
+                    put_bytes(&mut out, name.as_str().as_bytes());
+                    put_bytes(&mut out, value.as_bytes());
+                }
+            }
+            None => out.push(0),
+        }
+        out
+    }
+
+    /// Restore a parser saved with `suspend()`, to parse as directed by `profile`.  A
+    /// boundary longer than its `Limits::max_boundary_len` is `Error::BoundaryTooLong`.
+    pub fn resume(snapshot: &[u8], profile: &ParseProfile) -> Result<MultipartParser, Error> {
+        let mut input = match snapshot.strip_prefix(SNAPSHOT_MAGIC) {
+            Some(input) => input,
+            None => return Err(Error::InvalidParserState),
+        };
+        let input = &mut input;
+        let delimiter = take_bytes(input)?.to_vec();
+        let lt = take_bytes(input)?.to_vec();
+        let buf = take_bytes(input)?.to_vec();
+        let eof = take_byte(input)? != 0;
+        let state = match take_byte(input)? {
+            0 => State::Preamble,
+            1 => State::Delimiter { first: true },
+            2 => State::Delimiter { first: false },
+            3 => State::Headers,
+            4 => State::Body,
+            5 => State::Finished,
+            _ => return Err(Error::InvalidParserState),
+        };
+        let parts = take_u64(input)? as usize;
+        let part_bytes = take_u64(input)?;
+        let body_bytes = take_u64(input)?;
+        let fed = take_u64(input)?;
+        let headers = match take_byte(input)? {
+            0 => None,
+            _ => {
+                let count = take_u64(input)?;
+                let mut headers = HeaderMap::new();
+                for _ in 0..count {
+                    let name = HeaderName::from_bytes(take_bytes(input)?);
+                    let value = HeaderValue::from_bytes(take_bytes(input)?);
+                    match (name, value) {
+                        (Ok(name), Ok(value)) => headers.append(name, value),
+                        _ => return Err(Error::InvalidParserState),
+                    };
+                }
+                Some(headers)
+            }
+        };
+        // The line terminator is learned from the first delimiter, and known from then on
+        let lt_ok = match state {
+            State::Preamble | State::Delimiter { first: true } => lt.is_empty(),
+            State::Finished if parts == 0 => lt.is_empty(),
+            _ => lt == b"\r\n" || lt == b"\n" || lt == b"\r",
+        };
+        if !input.is_empty() || !delimiter.starts_with(b"--") || !lt_ok {
+            return Err(Error::InvalidParserState);
+        }
+        if let Some(max) = profile.limits.max_boundary_len {
+            if delimiter.len() - 2 > max {
+                return Err(Error::BoundaryTooLong);
+            }
+        }
+
+        Ok(MultipartParser {
+            profile: profile.clone(),
+            delimiter,
+            lt,
+            buf,
+            pos: 0,
+            scanned: 0,
+            eof,
+            state,
+            parts,
+            part_bytes,
+            body_bytes,
+            fed,
+            headers,
+        })
+    }
+
+    /// Parse the next event out of the input fed so far.  Returns `Ok(None)` if more input
+    /// is needed to make progress, in which case `feed()` (or `feed_eof()`) should be called
+    /// before trying again.  Once the close delimiter is reached, `Event::Finished` is
+    /// returned from then on.
+    pub fn next_event(&mut self) -> Result<Option<Event>, Error> {
+        loop {
+            let step = match self.state {
+                State::Preamble => self.preamble()?,
+                State::Delimiter { first } => self.delimiter(first)?,
+                State::Headers => self.headers()?,
+                State::Body => self.body()?,
+                State::Finished => Step::Emit(Event::Finished),
+            };
+            match step {
+                Step::Emit(event) => return Ok(Some(event)),
+                Step::Continue => continue,
+                Step::NeedInput => return Ok(None),
+            }
+        }
+    }
+
+    // Skip to the first delimiter
+    fn preamble(&mut self) -> Result<Step, Error> {
+        let input = &self.buf[self.pos..];
+        // The input runs up to the end of what was fed, so this is where it starts
+        let start = self.fed - input.len() as u64;
+        let max = self.profile.limits.max_preamble;
+        match find(input, &self.delimiter) {
+            Some(index) => {
+                if max.is_some_and(|max| start + index as u64 > max) {
+                    return Err(Error::PreambleTooLarge);
+                }
+                self.pos += index + self.delimiter.len();
+                self.state = State::Delimiter { first: true };
+                Ok(Step::Continue)
+            }
+            None if self.eof => Err(Error::EofBeforeFirstBoundary),
+            None => {
+                // Keep what could be the start of the delimiter
+                let skip = input.len().saturating_sub(self.delimiter.len() - 1);
+                if max.is_some_and(|max| start + skip as u64 > max) {
+                    return Err(Error::PreambleTooLarge);
+                }
+                self.pos += skip;
+                Ok(Step::NeedInput)
+            }
+        }
+    }
+
+    // Handle what follows a delimiter: either the line terminator starting the next part,
+    // or the "--" of the close delimiter.
+    fn delimiter(&mut self, first: bool) -> Result<Step, Error> {
+        let input = &self.buf[self.pos..];
+        if input.len() < 2 && !self.eof {
+            return Ok(Step::NeedInput);
+        }
+        if input.starts_with(b"--") {
+            self.pos += 2;
+            self.state = State::Finished;
+            return Ok(Step::Emit(Event::Finished));
+        }
+
+        if first {
+            // Skip transport padding, then use the first line terminator to determine
+            // whether to use CRLF or LF.
+            let padding = input
+                .iter()
+                .take_while(|&&ch| ch == b' ' || ch == b'\t')
+                .count();
+            let rest = &input[padding..];
+            if rest.starts_with(b"\r\n") {
+                self.lt = b"\r\n".to_vec();
+            } else if rest.starts_with(b"\n") {
+                self.lt = b"\n".to_vec();
+            } else if (rest.is_empty() || rest == b"\r") && !self.eof {
+                self.pos += padding;
+                return Ok(Step::NeedInput);
+            } else if rest.starts_with(b"\r") && self.profile.mode == ParseMode::Lenient {
+                self.lt = b"\r".to_vec();
+            } else {
+                return Err(Error::NoCrLfAfterBoundary);
+            }
+            self.pos += padding + self.lt.len();
+        } else {
+            match find(input, &self.lt) {
+                Some(index) => {
+                    let padding_only = input[..index].iter().all(|&ch| ch == b' ' || ch == b'\t');
+                    if self.profile.mode == ParseMode::Strict && !padding_only {
+                        return Err(Error::DataBetweenParts);
+                    }
+                    self.pos += index + self.lt.len();
+                }
+                None if self.eof => return Err(Error::NoCrLfAfterBoundary),
+                None => {
+                    if self.profile.mode == ParseMode::Strict
+                        && !input
+                            .iter()
+                            .all(|&ch| ch == b' ' || ch == b'\t' || ch == b'\r')
+                    {
+                        return Err(Error::DataBetweenParts);
+                    }
+                    // Keep what could be the start of the line terminator
+                    self.pos += input.len().saturating_sub(self.lt.len() - 1);
+                    return Ok(Step::NeedInput);
+                }
+            }
+        }
+
+        self.parts += 1;
+        if let Some(max) = self.profile.limits.max_parts {
+            if self.parts > max {
+                return Err(Error::TooManyParts);
+            }
+        }
+        self.state = State::Headers;
+        Ok(Step::Continue)
+    }
+
+    fn headers(&mut self) -> Result<Step, Error> {
+        let input = &self.buf[self.pos..];
+        if input.starts_with(&self.lt) {
+            // A part without headers
+            self.pos += self.lt.len();
+            self.start_body(HeaderMap::new());
+            return Ok(Step::Emit(Event::PartHeaders(HeaderMap::new())));
+        }
+
+        let ltlt = [&self.lt[..], &self.lt[..]].concat();
+        let limit = header_block_limit(&self.profile.limits);
+        match find(&input[self.scanned..], &ltlt) {
+            Some(index) => {
+                let block_len = self.scanned + index + ltlt.len();
+                if block_len > limit {
+                    return Err(Error::HeadersTooLarge);
+                }
+                let max = self.profile.limits.max_part_headers;
+                let mut spare = Vec::new();
+                let mut headers = match &self.lt[..] {
+                    b"\r" => parse_part_headers(&cr_to_crlf(&input[..block_len]), max, &mut spare)?,
+                    _ => parse_part_headers(&input[..block_len], max, &mut spare)?,
+                };
+                check_duplicate_headers(&mut headers, self.profile.mode)?;
+                self.pos += block_len;
+                self.start_body(headers.clone());
+                Ok(Step::Emit(Event::PartHeaders(headers)))
+            }
+            None => {
+                if input.len() >= limit {
+                    return Err(Error::HeadersTooLarge);
+                }
+                // Only the end of the input could start the blank line once there is more
+                self.scanned = (input.len() + 1).saturating_sub(ltlt.len());
+                match self.eof {
+                    true => Err(Error::EofInPartHeaders),
+                    false => Ok(Step::NeedInput),
+                }
+            }
+        }
+    }
+
+    fn start_body(&mut self, headers: HeaderMap) {
+        self.scanned = 0;
+        self.headers = Some(headers);
+        self.part_bytes = 0;
+        self.state = State::Body;
+    }
+
+    fn body(&mut self) -> Result<Step, Error> {
+        let input = &self.buf[self.pos..];
+        let token = [&self.lt[..], &self.delimiter[..]].concat();
+
+        let chunk_len = match find(input, &token) {
+            Some(0) => {
+                self.pos += token.len();
+                self.headers = None;
+                self.state = State::Delimiter { first: false };
+                return Ok(Step::Emit(Event::PartEnd));
+            }
+            Some(index) => index,
+            None if self.eof => match close_delimiter_len(input, &self.delimiter) {
+                Some(len) if self.profile.mode == ParseMode::Lenient => {
+                    // A close delimiter lacking its line terminator ends the body.  Put the
+                    // line terminator in, and carry on as normal.
+                    let at = self.buf.len() - len;
+                    self.buf.splice(at..at, self.lt.iter().cloned());
+                    return Ok(Step::Continue);
+                }
+                _ => return Err(Error::EofInPart),
+            },
+            None => {
+                // Keep what could be the start of the delimiter (or, when lenient, of a close
+                // delimiter lacking its line terminator)
+                let keep = match self.profile.mode {
+                    ParseMode::Lenient => token.len().max(self.delimiter.len() + 4),
+                    _ => token.len() - 1,
+                };
+                input.len().saturating_sub(keep)
+            }
+        };
+        if chunk_len == 0 {
+            return Ok(Step::NeedInput);
+        }
+
+        let chunk = input[..chunk_len].to_vec();
+        self.count_body(chunk_len as u64)?;
+        self.pos += chunk_len;
+        Ok(Step::Emit(Event::BodyChunk(chunk)))
+    }
+
+    // Count body bytes against the limits
+    fn count_body(&mut self, len: u64) -> Result<(), Error> {
+        self.part_bytes += len;
+        self.body_bytes += len;
+        let limits = &self.profile.limits;
+        if let Some(max) = limits.max_part_size {
+            if self.part_bytes > max {
+                return Err(Error::PartTooLarge);
+            }
+        }
+        if let Some(max) = limits.max_total_size {
+            if self.body_bytes > max {
+                return Err(Error::BodyTooLarge);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Find the first occurrence of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() || haystack.len() < needle.len() {
+        return None;
+    }
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend(value.to_be_bytes());
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_u64(out, bytes.len() as u64);
+    out.extend(bytes);
+}
+
+fn take_byte(input: &mut &[u8]) -> Result<u8, Error> {
+    match input.split_first() {
+        Some((&byte, rest)) => {
+            *input = rest;
+            Ok(byte)
+        }
+        None => Err(Error::InvalidParserState),
+    }
+}
+
+fn take_u64(input: &mut &[u8]) -> Result<u64, Error> {
+    match input.split_first_chunk::<8>() {
+        Some((bytes, rest)) => {
+            *input = rest;
+            Ok(u64::from_be_bytes(*bytes))
+        }
+        None => Err(Error::InvalidParserState),
+    }
+}
+
+fn take_bytes<'a>(input: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let len = take_u64(input)?;
+    if len > input.len() as u64 {
+        return Err(Error::InvalidParserState);
+    }
+    let (bytes, rest) = input.split_at(len as usize);
+    *input = rest;
+    Ok(bytes)
+}
@@ -0,0 +1,424 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::{DiskBudget, Entropy, FilePartBuilder};
+use http::header::HeaderMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// How forgiving the parser is of bodies which deviate from RFC 2046.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Accept deviations commonly produced by buggy or embedded clients:
+    ///
+    /// * the line terminator preceding the close delimiter may be missing, as in
+    ///   `...data--boundary--`, provided the close delimiter ends the body;
+    /// * lines may end in a bare CR, as produced by old Mac tools, if the first boundary
+    ///   is followed by one.
+    ///
+    /// As in `Standard`, a part carrying its `Content-Type` or `Content-Disposition` more
+    /// than once keeps the first, the others being dropped with a warning logged.
+    Lenient,
+    /// The behavior of `read_multipart()`.
+    #[default]
+    Standard,
+    /// Reject input which RFC 2046 does not permit, as well as input which is permitted but
+    /// usually indicates boundary confusion or request smuggling:
+    ///
+    /// * anything other than transport padding (spaces and tabs) between a boundary and the
+    ///   line terminator ending it;
+    /// * an epilogue after a nested multipart's close delimiter;
+    /// * a part carrying its `Content-Type` or `Content-Disposition` more than once.
+    Strict,
+}
+
+/// Options controlling how `read_multipart_with()` and `read_multipart_body_with()` parse
+/// a multipart body.  The default behaves exactly as `read_multipart()` does with
+/// `always_use_files` set to false.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParseProfile {
+    /// Where to put each part's body.
+    pub disposition: DispositionPolicy,
+    /// How `DispositionPolicy::Auto` treats the less clear-cut `Content-Disposition`s.
+    pub spool_rules: SpoolRules,
+    /// Store each spooled file under its part's original `filename`, sanitized, in a
+    /// directory of its own, instead of under a random name.  This suits tools which look
+    /// at file names.  Files without a usable name get a random one as usual.
+    pub original_filenames: bool,
+    /// How spooled files are created: in which directory, under which names (unless
+    /// `original_filenames` is set), with which permissions, and whether they are deleted
+    /// when their `FilePart` drops.
+    pub spool: FilePartBuilder,
+    /// Move the body of a part being kept in memory into a file, spooled as `spool` says,
+    /// once it grows past this many bytes, so that a text field of any size does not have to
+    /// fit in memory.  Such a part is returned as a `Node::File`, and so is among the files
+    /// of a `FormData`, but is otherwise still counted as kept in memory, so that
+    /// `Limits::max_field_size` and `Limits::max_fields` apply to it.  To refuse large
+    /// fields outright instead, set only the former.
+    pub spill_threshold: Option<usize>,
+    /// Undo the escaping browsers apply to field names and file names (see
+    /// `unescape_html()`) in the field names `read_form_data_with()` reports and the file
+    /// names `original_filenames` uses.  `FilePart::filename()` is unaffected.
+    pub html_names: bool,
+    /// Detect spooled files with the same content as one spooled earlier in the same parse,
+    /// by their SHA-256 digests, and make them hard links to it rather than copies.  Each
+    /// `FilePart` keeps a path of its own.  Where hard links are not supported, copies are
+    /// kept.  Needs the `sha2` feature.
+    #[cfg(feature = "sha2")]
+    pub dedup_files: bool,
+    /// Sync each spooled file, and on Unix the directory holding it, to disk before moving
+    /// on, so that the file survives a crash once parsing has returned.  This is slow, and
+    /// only worthwhile where the file is handed to another process.
+    pub sync_files: bool,
+    /// Overwrite each spooled file before deleting it, as by `FilePart::shred_on_drop()`,
+    /// including the files of a parse which fails part way.
+    pub shred_files: bool,
+    /// Called with the path and error when a spooled file or its directory cannot be
+    /// deleted as its `FilePart` drops, as by `FilePart::set_cleanup_hook()`.
+    pub cleanup_hook: Option<CleanupHook>,
+    /// When the headers carry a `Content-Length`, check that the body is exactly that long,
+    /// failing with `Error::ContentLengthMismatch` if it ends early or the multipart runs
+    /// past it.  Truncated or padded bodies are a sign of a mangling proxy or request
+    /// smuggling.  The epilogue is read up to the given length.
+    pub verify_content_length: bool,
+    /// Field names (the `name` of a part's `Content-Disposition`) whose parts are read past
+    /// and dropped, as by `Disposition::Skip`, whatever the `disposition` policy says.  Their
+    /// bodies are never buffered or spooled, which suits proxies which only care about some
+    /// fields.  A nested multipart with an ignored name is skipped as a whole.
+    pub ignored_fields: Vec<String>,
+    /// Stop parsing as soon as top-level parts with all of these field names (the `name` of
+    /// their `Content-Disposition`) have been read, returning the parts read so far, so that
+    /// an endpoint needing only some small fields does not spool a large file sent after
+    /// them.  The rest of the body is left unread, or drained if `stop_at` is
+    /// `StopAt::Eof`, and is not checked against `verify_content_length`.  Empty, the
+    /// default, reads the whole body.
+    pub required_fields: Vec<String>,
+    /// How far past the close delimiter to read.  Only `read_multipart_body_buffered()` and
+    /// `read_multipart_body_counted()` leave what they do not read in the stream; the other
+    /// functions read ahead into a buffer of their own.
+    pub stop_at: StopAt,
+    /// The number of top-level parts expected, for which room is reserved ahead, so that a
+    /// form with thousands of small fields is not copied over as the list of them grows.
+    /// This is only a hint, capped at `Limits::max_parts`; `0`, the default, reserves
+    /// nothing.
+    pub expected_parts: usize,
+    /// When a multipart `Content-Type` has no `boundary` parameter, as some buggy clients
+    /// send, take the boundary from the body's first line if that is a `--boundary` line,
+    /// rather than failing with `Error::BoundaryNotSpecified`.  This applies to nested
+    /// multiparts too.  `MultipartParser` does not sniff; see `sniff_boundary()`.
+    pub sniff_boundary: bool,
+    /// Which `Content-Type` to go by when the headers carry several, as misbehaving proxies
+    /// sometimes produce.  With the `log` feature, a warning is logged whenever they do.
+    pub content_type_choice: ContentTypeChoice,
+    /// How forgiving to be of malformed input.
+    pub mode: ParseMode,
+    /// Limits protecting against oversized or abusive input.
+    pub limits: Limits,
+    /// A budget for spooled file parts shared with other parses, if any.
+    pub disk_budget: Option<DiskBudget>,
+}
+
+impl ParseProfile {
+    /// A profile which accepts the deviations listed under `ParseMode::Lenient`.
+    pub fn lenient() -> ParseProfile {
+        ParseProfile {
+            mode: ParseMode::Lenient,
+            ..ParseProfile::default()
+        }
+    }
+
+    /// A profile which rejects input that RFC 2046 does not permit.
+    pub fn strict() -> ParseProfile {
+        ParseProfile {
+            mode: ParseMode::Strict,
+            ..ParseProfile::default()
+        }
+    }
+
+    /// A profile with conservative `Limits` on everything that can be limited, for parsing
+    /// untrusted input in servers.  See `Limits::hardened()` for the values.
+    pub fn hardened() -> ParseProfile {
+        ParseProfile {
+            limits: Limits::hardened(),
+            ..ParseProfile::default()
+        }
+    }
+}
+
+/// Which of several `Content-Type` values in the headers a parse goes by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ContentTypeChoice {
+    /// The first, as `HeaderMap::get()` returns.
+    #[default]
+    First,
+    /// The first which is a `multipart/*` type with a boundary, or else the first.
+    FirstMultipart,
+    /// Fail with `Error::ConflictingContentTypes` unless they are all the same.
+    Reject,
+}
+
+/// How far past the close delimiter (`--boundary--`) ending a body the parser reads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StopAt {
+    /// Stop right after the close delimiter.
+    #[default]
+    CloseDelimiter,
+    /// Also read the rest of the close delimiter's line: any transport padding, and the line
+    /// terminator if there is one.
+    LineEnd,
+    /// Read to the end of the stream, discarding the epilogue.
+    Eof,
+}
+
+/// Where the parser puts the body of a part.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Disposition {
+    /// Keep it in memory, as a `Node::Part`.
+    Memory,
+    /// Stream it to a temporary file, as a `Node::File`.
+    File,
+    /// Read past it, leaving the part out of the result.  Limits still apply to it.
+    Skip,
+}
+
+/// Decides the `Disposition` of each part from its headers.  Nested multiparts are always
+/// parsed, and the policy applies to the parts within them.
+#[derive(Clone, Default)]
+pub enum DispositionPolicy {
+    /// Decide by the part's `Content-Disposition`, parsed as a `ContentDisposition`:
+    ///
+    /// * a part with an empty `filename`, as browsers send for a file input left empty,
+    ///   goes where `SpoolRules::empty_filename` says;
+    /// * a part with a `filename` (or `filename*`) parameter goes to a file, except that
+    ///   an `inline` one goes where `SpoolRules::inline_with_filename` says;
+    /// * an `attachment` without a file name goes where
+    ///   `SpoolRules::attachment_without_filename` says;
+    /// * anything else, including a part without a `Content-Disposition`, is kept in memory.
+    #[default]
+    Auto,
+    /// Stream all parts to files.
+    AlwaysFile,
+    /// Ask a function, given the part's headers.
+    Custom(Arc<dyn Fn(&HeaderMap) -> Disposition + Send + Sync>),
+}
+
+impl DispositionPolicy {
+    /// A policy which asks `decide`, given each part's headers.
+    pub fn custom<F>(decide: F) -> DispositionPolicy
+    where
+        F: Fn(&HeaderMap) -> Disposition + Send + Sync + 'static,
+    {
+        DispositionPolicy::Custom(Arc::new(decide))
+    }
+}
+
+impl PartialEq for DispositionPolicy {
+    fn eq(&self, other: &DispositionPolicy) -> bool {
+        match (self, other) {
+            (DispositionPolicy::Auto, DispositionPolicy::Auto) => true,
+            (DispositionPolicy::AlwaysFile, DispositionPolicy::AlwaysFile) => true,
+            (DispositionPolicy::Custom(a), DispositionPolicy::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for DispositionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DispositionPolicy::Auto => f.write_str("Auto"),
+            DispositionPolicy::AlwaysFile => f.write_str("AlwaysFile"),
+            DispositionPolicy::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// A function told of the files which could not be deleted when their `FilePart` dropped,
+/// so that leaked temporary files can be logged and monitored.  It is given the path of
+/// the file and the error.
+#[derive(Clone)]
+pub struct CleanupHook(Arc<CleanupFn>);
+
+type CleanupFn = dyn Fn(&Path, &io::Error) + Send + Sync;
+
+impl CleanupHook {
+    /// A hook which calls `hook`.
+    pub fn new<F>(hook: F) -> CleanupHook
+    where
+        F: Fn(&Path, &io::Error) + Send + Sync + 'static,
+    {
+        CleanupHook(Arc::new(hook))
+    }
+
+    pub(crate) fn call(&self, path: &Path, err: &io::Error) {
+        (self.0)(path, err)
+    }
+}
+
+impl PartialEq for CleanupHook {
+    fn eq(&self, other: &CleanupHook) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for CleanupHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("CleanupHook(..)")
+    }
+}
+
+/// The configurable cases of `DispositionPolicy::Auto`.  The default streams all of them to
+/// files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpoolRules {
+    /// Where to put an `inline` part with a file name, such as an image embedded in a mail.
+    pub inline_with_filename: Disposition,
+    /// Where to put an `attachment` part without a file name.
+    pub attachment_without_filename: Disposition,
+    /// Where to put a part with `filename=""`, which is how browsers submit a file input
+    /// in which no file was selected.  `Disposition::Memory` turns these into a `Part`
+    /// (with an empty body, normally) which is easily told apart from an upload, and
+    /// `Disposition::Skip` drops them.
+    pub empty_filename: Disposition,
+}
+
+impl Default for SpoolRules {
+    fn default() -> SpoolRules {
+        SpoolRules {
+            inline_with_filename: Disposition::File,
+            attachment_without_filename: Disposition::File,
+            empty_filename: Disposition::File,
+        }
+    }
+}
+
+/// Limits enforced while parsing.  A limit of `None` means unlimited (except for
+/// `max_part_headers`), which is the default for all of them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Limits {
+    /// Maximum number of parts, counting nested parts and the multipart parts containing
+    /// them.
+    pub max_parts: Option<usize>,
+    /// Maximum size in bytes of a single part's body.
+    pub max_part_size: Option<u64>,
+    /// Maximum number of parts streamed to files.
+    pub max_files: Option<usize>,
+    /// Maximum size in bytes of a single part streamed to a file.  Where it is set, it
+    /// replaces `max_part_size` for files, so that a form can take one large upload while
+    /// its text fields stay small.
+    pub max_file_size: Option<u64>,
+    /// Maximum number of parts kept in memory, such as the text fields of a form.
+    pub max_fields: Option<usize>,
+    /// Maximum size in bytes of a single part kept in memory, which likewise replaces
+    /// `max_part_size` for them where it is set.
+    pub max_field_size: Option<u64>,
+    /// Maximum sizes in bytes of the parts with the given field names (the `name` of their
+    /// `Content-Disposition`), such as `("avatar", 5 << 20)` and `("title", 256)`.  These
+    /// replace the other limits on single parts for the parts named, and exceeding one fails
+    /// with `Error::FieldSizeExceeded` naming the field.  Parts within a nested multipart are
+    /// matched by their own names only.
+    pub max_size_by_name: Vec<(String, u64)>,
+    /// Maximum size in bytes of all part bodies together.
+    pub max_total_size: Option<u64>,
+    /// Maximum nesting depth of `multipart/*` parts.  A limit of 0 rejects any nesting.
+    pub max_depth: Option<usize>,
+    /// Maximum number of headers on a single part.  Unlike the other limits, `None` does
+    /// not lift it entirely: 4 headers are then accepted, as always, and more fail with
+    /// `Error::Httparse`.
+    pub max_part_headers: Option<usize>,
+    /// Maximum number of top-level headers read by `read_multipart_with()`.
+    pub max_main_headers: Option<usize>,
+    /// Maximum length of a boundary, which RFC 2046 limits to 70 characters.
+    pub max_boundary_len: Option<usize>,
+    /// Maximum size in bytes of a part's header block, up to and including the blank line
+    /// ending it.  This also applies to the top-level headers read by
+    /// `read_multipart_with()`.  As header blocks are kept in memory whole, `None` does not
+    /// lift it entirely: blocks of up to 1 MiB are then accepted.
+    pub max_header_block: Option<usize>,
+    /// Maximum size in bytes of the preamble before the first boundary, of the body and of
+    /// each nested multipart.
+    pub max_preamble: Option<u64>,
+}
+
+impl Limits {
+    /// Conservative limits for untrusted input:
+    ///
+    /// * 1000 parts
+    /// * 64 MiB per part and 256 MiB in total
+    /// * 4 levels of nesting
+    /// * 16 headers per part and 128 top-level headers
+    /// * 70 character boundaries
+    /// * 8 KiB header blocks
+    /// * 64 KiB of preamble
+    pub fn hardened() -> Limits {
+        Limits {
+            max_parts: Some(1000),
+            max_part_size: Some(64 * 1024 * 1024),
+            max_files: None,
+            max_file_size: None,
+            max_fields: None,
+            max_field_size: None,
+            max_size_by_name: Vec::new(),
+            max_total_size: Some(256 * 1024 * 1024),
+            max_depth: Some(4),
+            max_part_headers: Some(16),
+            max_main_headers: Some(128),
+            max_boundary_len: Some(70),
+            max_header_block: Some(8 * 1024),
+            max_preamble: Some(64 * 1024),
+        }
+    }
+}
+
+/// Options controlling how `write_multipart_with()` and `write_multipart_chunked_with()`
+/// emit a multipart body.  The default produces exactly what `write_multipart()` does.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WriteProfile {
+    /// Write a `Content-Length` header on each part, computed from the in-memory body or
+    /// from the file's metadata.  Any `Content-Length` already present in the part's headers
+    /// is replaced.  Nested multipart parts do not get one.
+    pub content_length: bool,
+    /// Stamp `Content-Transfer-Encoding: binary` on file parts which do not declare a
+    /// transfer encoding of their own.
+    pub file_transfer_encoding: bool,
+    /// Add a `size` parameter (RFC 2183) carrying the file's length to the
+    /// `Content-Disposition` header of file parts, unless one is already present.  File parts
+    /// without a `Content-Disposition` header get `attachment; size=...`.
+    pub file_disposition_size: bool,
+    /// Terminate the body with a CRLF after the closing delimiter (`--boundary--\r\n`), which
+    /// some servers insist upon.  The parser accepts either form.
+    pub final_crlf: bool,
+    /// When writing chunked, end the body with an `x-content-sha256` trailer carrying the
+    /// hex SHA-256 digest of the body (as it is before chunking), so that the receiver can
+    /// verify it.  The trailer should be announced with a `Trailer: x-content-sha256`
+    /// header.  Ignored when not writing chunked.
+    pub sha256_trailer: bool,
+    /// Where to take the randomness for the boundaries generated for nested multiparts
+    /// which lack one, as by `generate_boundary_with()`.  `None`, the default, uses
+    /// `generate_boundary()`.
+    pub entropy: Option<Entropy>,
+}
+
+impl WriteProfile {
+    /// HTTP conventions (RFC 7578), which deprecate transfer encodings and disposition
+    /// parameters other than `name` and `filename`.  This is the default.
+    pub fn http() -> WriteProfile {
+        WriteProfile::default()
+    }
+
+    /// Mail conventions, where file parts carry `Content-Transfer-Encoding: binary` and
+    /// their size in the `Content-Disposition` header.
+    pub fn email() -> WriteProfile {
+        WriteProfile {
+            file_transfer_encoding: true,
+            file_disposition_size: true,
+            ..WriteProfile::default()
+        }
+    }
+}
@@ -0,0 +1,276 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::Error;
+use http::header::HeaderValue;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// The value of a `Content-Range` header (RFC 7233) in bytes, as carried by each part of a
+/// `multipart/byteranges` body or by a single-range `206 Partial Content` response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentRange {
+    /// `bytes first-last/complete_length`, where the complete length may be unknown (`*`).
+    /// Both `first` and `last` are inclusive.
+    Bytes {
+        first: u64,
+        last: u64,
+        complete_length: Option<u64>,
+    },
+    /// `bytes */complete_length`, sent with `416 Range Not Satisfiable`.
+    Unsatisfied { complete_length: u64 },
+}
+
+impl ContentRange {
+    /// The range `first..=last` of a representation `complete_length` bytes long.  Fails if
+    /// the range is empty or does not fit.
+    pub fn new(first: u64, last: u64, complete_length: u64) -> Result<ContentRange, Error> {
+        let range = ContentRange::Bytes {
+            first,
+            last,
+            complete_length: Some(complete_length),
+        };
+        range.validate()?;
+        Ok(range)
+    }
+
+    /// Parse a `Content-Range` header value.
+    pub fn parse(value: &[u8]) -> Result<ContentRange, Error> {
+        let value = match std::str::from_utf8(value) {
+            Ok(value) => value.trim(),
+            Err(_) => return Err(Error::InvalidContentRange),
+        };
+        let (range, complete_length) = match value
+            .strip_prefix("bytes ")
+            .and_then(|value| value.split_once('/'))
+        {
+            Some(parts) => parts,
+            None => return Err(Error::InvalidContentRange),
+        };
+        let complete_length = match complete_length {
+            "*" => None,
+            length => Some(parse_number(length)?),
+        };
+
+        let range = match (range, complete_length) {
+            ("*", Some(complete_length)) => ContentRange::Unsatisfied { complete_length },
+            (range, complete_length) => match range.split_once('-') {
+                Some((first, last)) => ContentRange::Bytes {
+                    first: parse_number(first)?,
+                    last: parse_number(last)?,
+                    complete_length,
+                },
+                None => return Err(Error::InvalidContentRange),
+            },
+        };
+        range.validate()?;
+        Ok(range)
+    }
+
+    /// The number of bytes in the range, which is 0 if unsatisfied.  A range built by hand
+    /// which ends before it starts is empty too.
+    pub fn len(&self) -> u64 {
+        match *self {
+            ContentRange::Bytes { first, last, .. } => last
+                .checked_sub(first)
+                .and_then(|len| len.checked_add(1))
+                .unwrap_or(0),
+            ContentRange::Unsatisfied { .. } => 0,
+        }
+    }
+
+    /// Whether the range is unsatisfied
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The complete length of the representation, if known
+    pub fn complete_length(&self) -> Option<u64> {
+        match *self {
+            ContentRange::Bytes {
+                complete_length, ..
+            } => complete_length,
+            ContentRange::Unsatisfied { complete_length } => Some(complete_length),
+        }
+    }
+
+    /// Format as a `Content-Range` header value.
+    pub fn to_header_value(&self) -> HeaderValue {
+        // Only digits, spaces and "bytes*-/", all of which are valid
+        HeaderValue::from_str(&self.to_string()).unwrap()
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if let ContentRange::Bytes {
+            first,
+            last,
+            complete_length,
+        } = *self
+        {
+            // A range ending at `u64::MAX` would be longer than a `u64` can say
+            if first > last
+                || last == u64::MAX
+                || complete_length.is_some_and(|length| last >= length)
+            {
+                return Err(Error::InvalidContentRange);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ContentRange {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<ContentRange, Error> {
+        ContentRange::parse(value.as_bytes())
+    }
+}
+
+impl fmt::Display for ContentRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContentRange::Bytes {
+                first,
+                last,
+                complete_length: Some(length),
+            } => write!(f, "bytes {}-{}/{}", first, last, length),
+            ContentRange::Bytes {
+                first,
+                last,
+                complete_length: None,
+            } => write!(f, "bytes {}-{}/*", first, last),
+            ContentRange::Unsatisfied { complete_length } => {
+                write!(f, "bytes */{}", complete_length)
+            }
+        }
+    }
+}
+
+/// How to answer a request for a representation `length` bytes long carrying a `Range`
+/// header (RFC 7233), as worked out by `RangePlan::new()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RangePlan {
+    /// Ignore the header and send the whole representation with `200 OK`: it is not a
+    /// valid `bytes` range set, or it asks for too many ranges.
+    Full,
+    /// Send the ranges with `206 Partial Content`, e.g. with `byteranges_response()`.  They
+    /// are clamped to the representation, and lie within it.
+    Partial(Vec<RangeInclusive<u64>>),
+    /// Send `416 Range Not Satisfiable` with this `Content-Range`, as no range overlaps the
+    /// representation.
+    Unsatisfiable(ContentRange),
+}
+
+impl RangePlan {
+    /// The most ranges served; a request for more is answered with the full representation.
+    pub const MAX_RANGES: usize = 100;
+
+    /// Plan the answer to a request with the `Range` header value `range` for a
+    /// representation `length` bytes long.  Ranges beyond the end are clamped to it, and
+    /// ranges which overlap or adjoin are merged, in ascending order, so that a client
+    /// cannot have the same bytes sent many times over.  Otherwise the order requested is
+    /// kept.
+    pub fn new(range: &HeaderValue, length: u64) -> RangePlan {
+        let specs = match parse_range_set(range.as_bytes()) {
+            Some(specs) if specs.len() <= RangePlan::MAX_RANGES => specs,
+            _ => return RangePlan::Full,
+        };
+        let mut ranges: Vec<RangeInclusive<u64>> = specs
+            .into_iter()
+            .filter_map(|spec| match spec {
+                (Some(first), _) if first >= length => None,
+                (Some(first), Some(last)) => Some(first..=last.min(length - 1)),
+                (Some(first), None) => Some(first..=length - 1),
+                (None, Some(0)) | (None, None) => None,
+                (None, Some(suffix)) if length > 0 => {
+                    Some(length.saturating_sub(suffix)..=length - 1)
+                }
+                (None, Some(_)) => None,
+            })
+            .collect();
+        if ranges.is_empty() {
+            return RangePlan::Unsatisfiable(ContentRange::Unsatisfied {
+                complete_length: length,
+            });
+        }
+
+        let overlapping = ranges.iter().enumerate().any(|(index, range)| {
+            ranges[index + 1..].iter().any(|other| {
+                range.start() <= &other.end().saturating_add(1)
+                    && other.start() <= &range.end().saturating_add(1)
+            })
+        });
+        if overlapping {
+            ranges.sort_by_key(|range| *range.start());
+            let mut merged: Vec<RangeInclusive<u64>> = Vec::with_capacity(ranges.len());
+            for range in ranges {
+                match merged.last_mut() {
+                    Some(last) if *range.start() <= last.end().saturating_add(1) => {
+                        *last = *last.start()..=*last.end().max(range.end());
+                    }
+                    _ => merged.push(range),
+                }
+            }
+            ranges = merged;
+        }
+        RangePlan::Partial(ranges)
+    }
+
+    /// Whether the answer is a `multipart/byteranges` body, which is the case for more than
+    /// one range.
+    pub fn is_multipart(&self) -> bool {
+        matches!(*self, RangePlan::Partial(ref ranges) if ranges.len() > 1)
+    }
+}
+
+// Parse a `bytes=` range set into (first, last) pairs, where a suffix range `-n` has no
+// first.  Returns `None` if the value is not a valid range set.
+fn parse_range_set(value: &[u8]) -> Option<Vec<(Option<u64>, Option<u64>)>> {
+    let value = std::str::from_utf8(value).ok()?.trim();
+    let unit = value.get(..6)?;
+    if !unit.eq_ignore_ascii_case("bytes=") {
+        return None;
+    }
+    let mut specs = Vec::new();
+    for spec in value[6..].split(',').map(str::trim) {
+        if spec.is_empty() {
+            continue;
+        }
+        let (first, last) = spec.split_once('-')?;
+        let first = match first {
+            "" => None,
+            first => Some(parse_number(first).ok()?),
+        };
+        let last = match last {
+            "" => None,
+            last => Some(parse_number(last).ok()?),
+        };
+        match (first, last) {
+            (None, None) => return None,
+            (Some(first), Some(last)) if first > last => return None,
+            spec => specs.push(spec),
+        }
+    }
+    match specs.is_empty() {
+        true => None,
+        false => Some(specs),
+    }
+}
+
+// Parse a string of decimal digits, rejecting signs and whitespace which `u64::from_str`
+// would accept or which RFC 7233 does not permit
+fn parse_number(digits: &str) -> Result<u64, Error> {
+    if digits.is_empty() || !digits.bytes().all(|ch| ch.is_ascii_digit()) {
+        return Err(Error::InvalidContentRange);
+    }
+    match digits.parse() {
+        Ok(number) => Ok(number),
+        Err(_) => Err(Error::InvalidContentRange),
+    }
+}
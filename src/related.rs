@@ -0,0 +1,292 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{percent_decode, Node};
+use http::header::{HeaderMap, CONTENT_LOCATION, CONTENT_TYPE};
+use mime::Mime;
+use std::str::FromStr;
+
+/// The parts of a `multipart/related` body (RFC 2387), indexed for resolving the URIs by
+/// which they refer to each other, as in MHTML (RFC 2557) and SOAP with attachments.
+///
+/// Parts are found by `Content-ID`, through `cid:` URIs, and by `Content-Location`.  A
+/// relative `Content-Location` is resolved against the `Content-Location` of the multipart
+/// itself, or failing that its `Content-Base`, and is matched as is if there is neither.
+pub struct Related<'a> {
+    nodes: &'a [Node],
+    root: usize,
+    // Each part's Content-ID (without angle brackets) and resolved Content-Location
+    ids: Vec<Option<String>>,
+    locations: Vec<Option<String>>,
+}
+
+impl<'a> Related<'a> {
+    /// Index the `nodes` of a multipart with the given `headers`.
+    pub fn new(headers: &HeaderMap, nodes: &'a [Node]) -> Related<'a> {
+        let base = header_str(headers, "content-location")
+            .filter(|location| is_absolute(location))
+            .or_else(|| header_str(headers, "content-base"));
+        let ids: Vec<Option<String>> = nodes
+            .iter()
+            .map(|node| header_str(node.headers(), "content-id").map(strip_angle_brackets))
+            .collect();
+        let locations = nodes
+            .iter()
+            .map(|node| {
+                let location = header_str(node.headers(), CONTENT_LOCATION.as_str())?;
+                Some(match base {
+                    Some(base) => resolve_uri(base, location).unwrap_or(location.to_owned()),
+                    None => location.to_owned(),
+                })
+            })
+            .collect();
+
+        // The root is named by the `start` parameter, or is the first part
+        let start = headers
+            .get(CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .and_then(|ct| Mime::from_str(ct).ok())
+            .and_then(|mime| mime.get_param("start").map(|s| s.as_str().to_owned()));
+        let root = start
+            .and_then(|start| {
+                let start = strip_angle_brackets(&start);
+                ids.iter().position(|id| id.as_deref() == Some(&*start))
+            })
+            .unwrap_or(0);
+
+        Related {
+            nodes,
+            root,
+            ids,
+            locations,
+        }
+    }
+
+    /// The root part, if there are any parts
+    pub fn root(&self) -> Option<&'a Node> {
+        self.nodes.get(self.root)
+    }
+
+    /// The part with the given `Content-ID`, with or without angle brackets
+    pub fn by_content_id(&self, id: &str) -> Option<&'a Node> {
+        let id = strip_angle_brackets(id);
+        let index = self
+            .ids
+            .iter()
+            .position(|other| other.as_deref() == Some(&*id))?;
+        Some(&self.nodes[index])
+    }
+
+    /// The part referred to by `reference`, a `cid:` URI or a URI found in the root part.
+    /// A relative URI is resolved against the root part's `Content-Location`.
+    pub fn resolve(&self, reference: &str) -> Option<&'a Node> {
+        let base = self
+            .locations
+            .get(self.root)
+            .and_then(|base| base.as_deref());
+        self.resolve_from(reference, base)
+    }
+
+    /// The part referred to by `reference`, a `cid:` URI or a URI found in a part whose
+    /// location is `base`.  A relative URI is resolved against `base`, if given.
+    pub fn resolve_from(&self, reference: &str, base: Option<&str>) -> Option<&'a Node> {
+        let reference = reference.trim();
+        if let Some(id) = strip_scheme(reference, "cid") {
+            let id = percent_decode(id.as_bytes(), false);
+            return self.by_content_id(&String::from_utf8_lossy(&id));
+        }
+
+        let resolved = base.and_then(|base| resolve_uri(base, reference));
+        let candidates = [resolved.as_deref(), Some(reference)];
+        for candidate in candidates.into_iter().flatten() {
+            let found = self
+                .locations
+                .iter()
+                .position(|location| location.as_deref() == Some(candidate));
+            if let Some(index) = found {
+                return Some(&self.nodes[index]);
+            }
+        }
+        None
+    }
+}
+
+fn header_str<'h>(headers: &'h HeaderMap, name: &str) -> Option<&'h str> {
+    let value = headers.get(name)?.to_str().ok()?.trim();
+    Some(value.trim_matches('"'))
+}
+
+fn strip_angle_brackets(id: &str) -> String {
+    let id = id.trim();
+    id.strip_prefix('<')
+        .and_then(|id| id.strip_suffix('>'))
+        .unwrap_or(id)
+        .to_owned()
+}
+
+// The rest of `uri` if it has the given scheme, which is case-insensitive
+fn strip_scheme<'u>(uri: &'u str, scheme: &str) -> Option<&'u str> {
+    let (prefix, rest) = uri.split_once(':')?;
+    match prefix.eq_ignore_ascii_case(scheme) {
+        true => Some(rest),
+        false => None,
+    }
+}
+
+// The components of a URI reference (RFC 3986 section 3)
+struct UriRef<'u> {
+    scheme: Option<&'u str>,
+    authority: Option<&'u str>,
+    path: &'u str,
+    query: Option<&'u str>,
+    fragment: Option<&'u str>,
+}
+
+impl<'u> UriRef<'u> {
+    fn parse(uri: &'u str) -> UriRef<'u> {
+        let (uri, fragment) = match uri.split_once('#') {
+            Some((uri, fragment)) => (uri, Some(fragment)),
+            None => (uri, None),
+        };
+        let (uri, query) = match uri.split_once('?') {
+            Some((uri, query)) => (uri, Some(query)),
+            None => (uri, None),
+        };
+        let (scheme, rest) = match uri.split_once(':') {
+            Some((scheme, rest)) if is_scheme(scheme) => (Some(scheme), rest),
+            _ => (None, uri),
+        };
+        let (authority, path) = match rest.strip_prefix("//") {
+            Some(rest) => {
+                let end = rest.find('/').unwrap_or(rest.len());
+                (Some(&rest[..end]), &rest[end..])
+            }
+            None => (None, rest),
+        };
+        UriRef {
+            scheme,
+            authority,
+            path,
+            query,
+            fragment,
+        }
+    }
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    let mut chars = scheme.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() => {
+            chars.all(|ch| ch.is_ascii_alphanumeric() || "+-.".contains(ch))
+        }
+        _ => false,
+    }
+}
+
+fn is_absolute(uri: &str) -> bool {
+    UriRef::parse(uri).scheme.is_some()
+}
+
+// Resolve `reference` against the absolute URI `base` (RFC 3986 section 5.2).  Returns `None`
+// if `base` is not absolute.
+fn resolve_uri(base: &str, reference: &str) -> Option<String> {
+    let base = UriRef::parse(base);
+    let reference = UriRef::parse(reference);
+    let scheme = base.scheme?;
+
+    let (scheme, authority, path, query) = if let Some(scheme) = reference.scheme {
+        (
+            scheme,
+            reference.authority,
+            remove_dot_segments(reference.path),
+            reference.query,
+        )
+    } else if reference.authority.is_some() {
+        (
+            scheme,
+            reference.authority,
+            remove_dot_segments(reference.path),
+            reference.query,
+        )
+    } else if reference.path.is_empty() {
+        (
+            scheme,
+            base.authority,
+            base.path.to_owned(),
+            reference.query.or(base.query),
+        )
+    } else if reference.path.starts_with('/') {
+        (
+            scheme,
+            base.authority,
+            remove_dot_segments(reference.path),
+            reference.query,
+        )
+    } else {
+        let merged = match (base.authority.is_some(), base.path.is_empty()) {
+            (true, true) => format!("/{}", reference.path),
+            _ => match base.path.rfind('/') {
+                Some(index) => format!("{}{}", &base.path[..=index], reference.path),
+                None => reference.path.to_owned(),
+            },
+        };
+        (
+            scheme,
+            base.authority,
+            remove_dot_segments(&merged),
+            reference.query,
+        )
+    };
+
+    let mut uri = format!("{}:", scheme);
+    if let Some(authority) = authority {
+        uri.push_str("//");
+        uri.push_str(authority);
+    }
+    uri.push_str(&path);
+    if let Some(query) = query {
+        uri.push('?');
+        uri.push_str(query);
+    }
+    if let Some(fragment) = reference.fragment {
+        uri.push('#');
+        uri.push_str(fragment);
+    }
+    Some(uri)
+}
+
+// RFC 3986 section 5.2.4
+fn remove_dot_segments(path: &str) -> String {
+    let mut input = path;
+    let mut output = String::with_capacity(path.len());
+    while !input.is_empty() {
+        if let Some(rest) = input.strip_prefix("../").or(input.strip_prefix("./")) {
+            input = rest;
+        } else if input.starts_with("/./") || input == "/." {
+            input = &input[2..];
+            if input.is_empty() {
+                input = "/";
+            }
+        } else if input.starts_with("/../") || input == "/.." {
+            input = &input[3..];
+            if input.is_empty() {
+                input = "/";
+            }
+            output.truncate(output.rfind('/').unwrap_or(0));
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            let end = match input[1..].find('/') {
+                Some(index) => index + 1,
+                None => input.len(),
+            };
+            output.push_str(&input[..end]);
+            input = &input[end..];
+        }
+    }
+    output
+}
@@ -0,0 +1,111 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::parser::{Event, MultipartParser};
+use super::{ContentDisposition, Error, ParseProfile};
+use http::header::{HeaderMap, CONTENT_DISPOSITION};
+use std::io::{ErrorKind, Read};
+
+// How much to read from the stream at a time
+const READ_SIZE: usize = 8 * 1024;
+
+// A setter storing a field's value into the target
+type Setter<'s, T> = Box<dyn FnMut(&mut T, Vec<u8>) -> Result<(), Error> + 's>;
+
+/// Populates a value of type `T` straight from a `multipart/form-data` body, by calling the
+/// setter registered for each field's name as the field is read.  No `Node`s are built, and
+/// fields without a setter are skipped without being kept, which makes this the cheapest
+/// way to read a form whose fields are known in advance.
+///
+/// Each field the target takes is held in memory in full, within the profile's `Limits`.
+/// Nested multiparts are not descended into: their raw content is the field's value.
+pub struct FormSetters<'s, T> {
+    setters: Vec<(String, Setter<'s, T>)>,
+}
+
+impl<'s, T> FormSetters<'s, T> {
+    /// No setters; add them with `field()`.
+    pub fn new() -> FormSetters<'s, T> {
+        FormSetters {
+            setters: Vec::new(),
+        }
+    }
+
+    /// Store fields named `name` with `setter`, which is given the target and the field's
+    /// value, and is called once for each such field, in order.  An error it returns ends
+    /// the read.
+    pub fn field<F>(mut self, name: &str, setter: F) -> FormSetters<'s, T>
+    where
+        F: FnMut(&mut T, Vec<u8>) -> Result<(), Error> + 's,
+    {
+        self.setters.push((name.to_owned(), Box::new(setter)));
+        self
+    }
+
+    /// Read a `multipart/form-data` body from `stream`, with the request's `headers`, into
+    /// `target`, as directed by the `ParseProfile` given.
+    pub fn read<S: Read + ?Sized>(
+        &mut self,
+        stream: &mut S,
+        headers: &HeaderMap,
+        profile: &ParseProfile,
+        target: &mut T,
+    ) -> Result<(), Error> {
+        let mut parser = MultipartParser::from_headers(headers, profile)?;
+        let mut buf = vec![0; READ_SIZE];
+        // The setter of the current field, if it has one, and its value so far
+        let mut current: Option<usize> = None;
+        let mut value = Vec::new();
+        loop {
+            let event = match parser.next_event()? {
+                Some(event) => event,
+                None => {
+                    match stream.read(&mut buf) {
+                        Ok(0) => parser.feed_eof(),
+                        Ok(count) => parser.feed(&buf[..count]),
+                        Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                        Err(err) => return Err(Error::Io(err)),
+                    }
+                    continue;
+                }
+            };
+            match event {
+                Event::PartHeaders(part_headers) => {
+                    let name = match part_headers.get(CONTENT_DISPOSITION) {
+                        Some(cd) => ContentDisposition::from_header(cd)?,
+                        None => return Err(Error::NoFieldName),
+                    };
+                    let name = match name.form_param("name", profile.html_names) {
+                        Some(name) => name,
+                        None => return Err(Error::NoFieldName),
+                    };
+                    current = self.setters.iter().position(|(field, _)| *field == name);
+                }
+                Event::BodyChunk(chunk) => {
+                    if current.is_some() {
+                        match value.is_empty() {
+                            true => value = chunk,
+                            false => value.extend(chunk),
+                        }
+                    }
+                }
+                Event::PartEnd => {
+                    if let Some(index) = current.take() {
+                        (self.setters[index].1)(target, std::mem::take(&mut value))?;
+                    }
+                }
+                Event::Finished => return Ok(()),
+            }
+        }
+    }
+}
+
+impl<T> Default for FormSetters<'_, T> {
+    fn default() -> Self {
+        FormSetters::new()
+    }
+}
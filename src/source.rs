@@ -0,0 +1,70 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::parser::{Event, MultipartParser};
+use super::{Error, ParseProfile};
+use http::header::HeaderMap;
+use std::io::{ErrorKind, Read};
+
+// How much to read from the underlying reader at a time
+const READ_SIZE: usize = 8 * 1024;
+
+/// A source of parsing `Event`s which is object safe, so that it can be passed around as a
+/// `Box<dyn MultipartSource>` by plugin systems and other code which cannot be generic over
+/// the reader.
+pub trait MultipartSource {
+    /// The next event, reading input as needed.  After `Event::Finished` has been returned,
+    /// this returns `None`.
+    fn next_event(&mut self) -> Result<Option<Event>, Error>;
+}
+
+/// A `MultipartParser` reading from a `&mut dyn Read`, so that it is not generic over the
+/// reader.  As with `MultipartParser`, nested multiparts are delivered as the raw body of
+/// their part.
+pub struct MultipartReader<'a> {
+    reader: &'a mut dyn Read,
+    parser: MultipartParser,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<'a> MultipartReader<'a> {
+    /// Read the body of a request or part with the given headers from `reader`, as directed
+    /// by the `ParseProfile` given.
+    pub fn new(
+        reader: &'a mut dyn Read,
+        headers: &HeaderMap,
+        profile: &ParseProfile,
+    ) -> Result<MultipartReader<'a>, Error> {
+        Ok(MultipartReader {
+            reader,
+            parser: MultipartParser::from_headers(headers, profile)?,
+            buf: vec![0; READ_SIZE],
+            done: false,
+        })
+    }
+}
+
+impl MultipartSource for MultipartReader<'_> {
+    fn next_event(&mut self) -> Result<Option<Event>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+        loop {
+            if let Some(event) = self.parser.next_event()? {
+                self.done = matches!(event, Event::Finished);
+                return Ok(Some(event));
+            }
+            match self.reader.read(&mut self.buf) {
+                Ok(0) => self.parser.feed_eof(),
+                Ok(count) => self.parser.feed(&self.buf[..count]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(Error::Io(err)),
+            }
+        }
+    }
+}
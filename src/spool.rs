@@ -0,0 +1,256 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{get_content_disposition_filename, random_file_name, sanitize_filename};
+use super::{Entropy, Error, FilePart};
+use http::header::{HeaderMap, CONTENT_DISPOSITION};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// How a `FilePartBuilder` names the file it creates.  Each file is put in a directory of
+/// its own, so names only need to be unique among the files of that directory.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SpoolName {
+    /// A random name.
+    #[default]
+    Random,
+    /// The part's `filename`, sanitized as for `ParseProfile::original_filenames`, or a
+    /// random name if it has none which is usable.
+    Original,
+    /// The given name, sanitized likewise.
+    Given(String),
+    /// The name the strategy gives, sanitized likewise, or a random name if nothing usable
+    /// is left.
+    Strategy(NameStrategy),
+}
+
+/// Names spool files, for deployments whose sharding or monitoring goes by file name.
+/// `RandomName`, `UuidName` and `SequentialName` are provided, and any
+/// `Fn(&HeaderMap) -> String` is one too.  Use it through `SpoolName::Strategy`.
+pub trait TempNameStrategy: Send + Sync {
+    /// The name of the file for a part with the given headers.
+    fn file_name(&self, headers: &HeaderMap) -> String;
+}
+
+impl<F: Fn(&HeaderMap) -> String + Send + Sync> TempNameStrategy for F {
+    fn file_name(&self, headers: &HeaderMap) -> String {
+        self(headers)
+    }
+}
+
+/// 32 random letters and digits, as `SpoolName::Random` names files.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomName;
+
+impl TempNameStrategy for RandomName {
+    fn file_name(&self, _: &HeaderMap) -> String {
+        random_file_name()
+    }
+}
+
+/// A random (version 4) UUID, in lowercase hyphenated form.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UuidName;
+
+impl TempNameStrategy for UuidName {
+    fn file_name(&self, _: &HeaderMap) -> String {
+        let bits = fastrand::u128(..) & !(0xf << 76) & !(0x3 << 62) | 0x4 << 76 | 0x2 << 62;
+        let hex = format!("{:032x}", bits);
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+/// A prefix followed by a counter, starting from 0, which is shared by all the files the
+/// strategy names.
+#[derive(Debug, Default)]
+pub struct SequentialName {
+    prefix: String,
+    next: AtomicU64,
+}
+
+impl SequentialName {
+    /// Names made of `prefix` and the counter.
+    pub fn new(prefix: &str) -> SequentialName {
+        SequentialName {
+            prefix: prefix.to_owned(),
+            next: AtomicU64::new(0),
+        }
+    }
+}
+
+impl TempNameStrategy for SequentialName {
+    fn file_name(&self, _: &HeaderMap) -> String {
+        format!(
+            "{}{}",
+            self.prefix,
+            self.next.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+}
+
+/// A shared `TempNameStrategy`, for `SpoolName::Strategy`.  Two are equal if they share the
+/// same strategy.
+#[derive(Clone)]
+pub struct NameStrategy(Arc<dyn TempNameStrategy>);
+
+impl NameStrategy {
+    /// Name files as `strategy` does.
+    pub fn new<S: TempNameStrategy + 'static>(strategy: S) -> NameStrategy {
+        NameStrategy(Arc::new(strategy))
+    }
+}
+
+impl PartialEq for NameStrategy {
+    fn eq(&self, other: &NameStrategy) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for NameStrategy {}
+
+impl fmt::Debug for NameStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("NameStrategy(..)")
+    }
+}
+
+/// Builds temporary `FilePart`s, where the defaults of `FilePart::create()` do not suit: a
+/// directory on a particular disk, file names which monitoring recognizes, permissions, or
+/// keeping the file once the `FilePart` drops.  As `ParseProfile::spool`, it also says how
+/// the parser creates the files it spools parts to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilePartBuilder {
+    dir: Option<PathBuf>,
+    name: SpoolName,
+    mode: Option<u32>,
+    delete_on_drop: bool,
+    preallocate: Option<u64>,
+    entropy: Option<Entropy>,
+}
+
+impl Default for FilePartBuilder {
+    fn default() -> FilePartBuilder {
+        FilePartBuilder::new()
+    }
+}
+
+impl FilePartBuilder {
+    /// A builder with the defaults of `FilePart::create()`.
+    pub fn new() -> FilePartBuilder {
+        FilePartBuilder {
+            dir: None,
+            name: SpoolName::Random,
+            mode: None,
+            delete_on_drop: true,
+            preallocate: None,
+            entropy: None,
+        }
+    }
+
+    /// Create each file's directory within `dir`, rather than the system's temporary
+    /// directory.
+    pub fn dir(mut self, dir: &Path) -> FilePartBuilder {
+        self.dir = Some(dir.to_owned());
+        self
+    }
+
+    /// Name the file as `name` says.  The default is `SpoolName::Random`.
+    pub fn name(mut self, name: SpoolName) -> FilePartBuilder {
+        self.name = name;
+        self
+    }
+
+    /// Create the file with the Unix permission bits `mode`, rather than `0o666` less the
+    /// umask.
+    #[cfg(unix)]
+    pub fn mode(mut self, mode: u32) -> FilePartBuilder {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Whether to delete the file and its directory when the `FilePart` drops, which is the
+    /// default.  Without, as after `FilePart::do_not_delete_on_drop()`, cleaning up is up to
+    /// you.
+    pub fn delete_on_drop(mut self, delete: bool) -> FilePartBuilder {
+        self.delete_on_drop = delete;
+        self
+    }
+
+    /// Set the file's length to `size` when it is created, for content of a known size, so
+    /// that filesystems which allocate eagerly reserve the space up front.  Many make a
+    /// sparse file instead.  Content shorter than `size` leaves zeros at the end, unless the
+    /// file is truncated after.  The parser ignores this, not knowing sizes ahead.
+    pub fn preallocate(mut self, size: u64) -> FilePartBuilder {
+        self.preallocate = Some(size);
+        self
+    }
+
+    /// Take the random names of files and of their directories from `entropy`, rather than
+    /// from `fastrand` and `tempfile`.  As `ParseProfile::spool`, this makes the names of
+    /// the files a parse spools to reproducible.
+    pub fn entropy(mut self, entropy: Entropy) -> FilePartBuilder {
+        self.entropy = Some(entropy);
+        self
+    }
+
+    /// Create the file, returning the `FilePart` and the file opened for reading and
+    /// writing.
+    pub fn build(&self, headers: HeaderMap) -> Result<(FilePart, File), Error> {
+        let (filepart, file) = self.create(headers, None)?;
+        if let Some(size) = self.preallocate {
+            file.set_len(size)?;
+        }
+        Ok((filepart, file))
+    }
+
+    // Create the file, under `name` if given or else as the builder says
+    pub(crate) fn create(
+        &self,
+        headers: HeaderMap,
+        name: Option<String>,
+    ) -> Result<(FilePart, File), Error> {
+        let name = match (name, &self.name) {
+            (Some(name), _) => Some(name),
+            (None, SpoolName::Random) => None,
+            (None, SpoolName::Original) => match headers.get(CONTENT_DISPOSITION) {
+                Some(cd) => {
+                    get_content_disposition_filename(cd)?.and_then(|name| sanitize_filename(&name))
+                }
+                None => None,
+            },
+            (None, SpoolName::Given(name)) => sanitize_filename(name),
+            (None, SpoolName::Strategy(strategy)) => {
+                sanitize_filename(&strategy.0.file_name(&headers))
+            }
+        };
+        let mut filepart =
+            FilePart::create_in(headers, self.dir.as_deref(), name, self.entropy.as_ref())?;
+
+        let mut options = OpenOptions::new();
+        options.read(true).write(true);
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(mode);
+        }
+        let file = filepart.create_file_with(&options)?;
+        if !self.delete_on_drop {
+            filepart.do_not_delete_on_drop();
+        }
+        Ok((filepart, file))
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::builder::BodyParser;
+use super::parser::{Event, MultipartParser};
+use super::{always_use_files_policy, get_content_disposition_filename, header_block_limit};
+use super::{parse_headers, Error, Node, ParseProfile};
+use bytes::Bytes;
+use futures_core::Stream;
+use http::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_TYPE};
+use mime::Mime;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+
+// How much to read from the underlying reader at a time
+const READ_SIZE: usize = 8 * 1024;
+
+/// Like `read_multipart()`, but reading from an `AsyncRead`, so that an upload can be parsed
+/// within an async runtime without a blocking task to bridge to.  The headers are presumed
+/// to still be in the stream.
+pub async fn read_multipart_async<R: AsyncRead + Unpin + ?Sized>(
+    reader: &mut R,
+    always_use_files: bool,
+) -> Result<(HeaderMap, Vec<Node>), Error> {
+    let profile = ParseProfile {
+        disposition: always_use_files_policy(always_use_files),
+        ..ParseProfile::default()
+    };
+    read_multipart_async_with(reader, &profile).await
+}
+
+/// Like `read_multipart_async()`, but parsing as directed by the `ParseProfile` given, as
+/// `read_multipart_body_async_with()` does.
+pub async fn read_multipart_async_with<R: AsyncRead + Unpin + ?Sized>(
+    reader: &mut R,
+    profile: &ParseProfile,
+) -> Result<(HeaderMap, Vec<Node>), Error> {
+    let limit = header_block_limit(&profile.limits);
+    let mut buf = Vec::new();
+    // How far into `buf` the blank line is known not to start
+    let mut scanned = 0;
+    let end = loop {
+        let found = buf[scanned..]
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n");
+        if let Some(index) = found {
+            break scanned + index + 4;
+        }
+        if buf.len() > limit {
+            return Err(Error::HeadersTooLarge);
+        }
+        scanned = buf.len().saturating_sub(3);
+        let mut chunk = [0; READ_SIZE];
+        match read_some(reader, &mut chunk).await? {
+            0 => return Err(Error::EofInMainHeaders),
+            read => buf.extend_from_slice(&chunk[..read]),
+        }
+    };
+    if end > limit {
+        return Err(Error::HeadersTooLarge);
+    }
+    let headers = parse_headers(&buf[..end], profile.limits.max_main_headers)?;
+    let nodes = read_body(reader, &headers, profile, &buf[end..]).await?;
+    Ok((headers, nodes))
+}
+
+/// Like `read_multipart_body()`, but reading from an `AsyncRead`.  Parts are placed as by
+/// `read_multipart_body()`, files being spooled to disk with blocking writes, as is usual
+/// for local files.
+pub async fn read_multipart_body_async<R: AsyncRead + Unpin + ?Sized>(
+    reader: &mut R,
+    headers: &HeaderMap,
+    always_use_files: bool,
+) -> Result<Vec<Node>, Error> {
+    let profile = ParseProfile {
+        disposition: always_use_files_policy(always_use_files),
+        ..ParseProfile::default()
+    };
+    read_multipart_body_async_with(reader, headers, &profile).await
+}
+
+/// Like `read_multipart_body_async()`, but parsing as directed by the `ParseProfile` given.
+/// As with `MultipartParser`, the body is parsed in the `ParseMode` and within the `Limits`
+/// of the profile, and parts are placed as its `disposition`, `spool` and the like say.
+/// Once the close delimiter, or the last of the `required_fields`, has been read, nothing more
+/// is read unless `stop_at` is `StopAt::Eof`; when the `Content-Length` is verified, the body
+/// is read up to it, and never past it.
+pub async fn read_multipart_body_async_with<R: AsyncRead + Unpin + ?Sized>(
+    reader: &mut R,
+    headers: &HeaderMap,
+    profile: &ParseProfile,
+) -> Result<Vec<Node>, Error> {
+    read_body(reader, headers, profile, &[]).await
+}
+
+// Parse the body from `reader`, starting with the bytes `read` already taken from it
+async fn read_body<R: AsyncRead + Unpin + ?Sized>(
+    reader: &mut R,
+    headers: &HeaderMap,
+    profile: &ParseProfile,
+    read: &[u8],
+) -> Result<Vec<Node>, Error> {
+    let mut builder = BodyParser::new(headers, profile)?;
+    let mut read = read;
+    let mut chunk = [0; READ_SIZE];
+    loop {
+        let wanted = builder.wanted().map_or(usize::MAX, |wanted| {
+            usize::try_from(wanted).unwrap_or(usize::MAX)
+        });
+        if !read.is_empty() {
+            builder.feed(&read[..read.len().min(wanted)])?;
+            read = &[];
+            continue;
+        }
+        if wanted == 0 {
+            return builder.finish();
+        }
+        match read_some(reader, &mut chunk[..READ_SIZE.min(wanted)]).await? {
+            0 => return builder.finish(),
+            read => builder.feed(&chunk[..read])?,
+        }
+    }
+}
+
+// Read what is available into `buf`, returning how much that was, or 0 at the end
+async fn read_some<R: AsyncRead + Unpin + ?Sized>(
+    reader: &mut R,
+    buf: &mut [u8],
+) -> std::io::Result<usize> {
+    let mut buf = ReadBuf::new(buf);
+    poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, &mut buf)).await?;
+    Ok(buf.filled().len())
+}
+
+/// Reads a multipart body from an `AsyncRead` one field at a time, without buffering any
+/// field's body in memory or on disk.  Each field's body is a `Stream` of `Bytes`, read from
+/// the underlying reader only as it is polled, so a slow consumer (such as an upload to
+/// object storage) slows down the reading.
+///
+/// As with `MultipartParser`, nested multiparts are delivered as the raw body of their field.
+pub struct MultipartStream<R> {
+    reader: R,
+    parser: MultipartParser,
+}
+
+impl<R: AsyncRead + Unpin> MultipartStream<R> {
+    /// Read the body of a request or part with the given headers from `reader`.
+    pub fn new(reader: R, headers: &HeaderMap) -> Result<MultipartStream<R>, Error> {
+        MultipartStream::with_profile(reader, headers, &ParseProfile::default())
+    }
+
+    /// Read the body of a request or part with the given headers from `reader`, as directed
+    /// by the `ParseProfile` given.
+    pub fn with_profile(
+        reader: R,
+        headers: &HeaderMap,
+        profile: &ParseProfile,
+    ) -> Result<MultipartStream<R>, Error> {
+        Ok(MultipartStream {
+            reader,
+            parser: MultipartParser::from_headers(headers, profile)?,
+        })
+    }
+
+    /// Get the next field, or `None` once the close delimiter is reached.  Whatever is left
+    /// of the previous field's body is skipped.
+    pub async fn next_field(&mut self) -> Result<Option<Field<'_, R>>, Error> {
+        let headers = poll_fn(|cx| self.poll_headers(cx)).await?;
+        Ok(headers.map(move |headers| Field {
+            headers,
+            stream: self,
+            done: false,
+        }))
+    }
+
+    fn poll_headers(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<HeaderMap>, Error>> {
+        loop {
+            match ready!(self.poll_event(cx))? {
+                Event::PartHeaders(headers) => return Poll::Ready(Ok(Some(headers))),
+                Event::Finished => return Poll::Ready(Ok(None)),
+                // The rest of a field which was not read to its end
+                Event::BodyChunk(_) | Event::PartEnd => {}
+            }
+        }
+    }
+
+    fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<Result<Event, Error>> {
+        loop {
+            if let Some(event) = self.parser.next_event()? {
+                return Poll::Ready(Ok(event));
+            }
+            let mut buf = [0; READ_SIZE];
+            let mut buf = ReadBuf::new(&mut buf);
+            ready!(Pin::new(&mut self.reader).poll_read(cx, &mut buf))?;
+            match buf.filled() {
+                [] => self.parser.feed_eof(),
+                data => self.parser.feed(data),
+            }
+        }
+    }
+}
+
+/// A field of a `MultipartStream`.  Its body is read by polling it as a `Stream`, or with
+/// `chunk()`.
+pub struct Field<'a, R> {
+    headers: HeaderMap,
+    stream: &'a mut MultipartStream<R>,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> Field<'_, R> {
+    /// The headers of the field
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Mime content-type specified in the header
+    pub fn content_type(&self) -> Option<Mime> {
+        let value = self.headers.get(CONTENT_TYPE)?.to_str().ok()?;
+        Mime::from_str(value).ok()
+    }
+
+    /// The filename from the `Content-Disposition` header, if any
+    pub fn filename(&self) -> Result<Option<String>, Error> {
+        match self.headers.get(CONTENT_DISPOSITION) {
+            Some(cd) => get_content_disposition_filename(cd),
+            None => Ok(None),
+        }
+    }
+
+    /// Read the next chunk of the body, or `None` at its end.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, Error> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_next(cx))
+            .await
+            .transpose()
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for Field<'_, R> {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let result = ready!(self.stream.poll_event(cx));
+        match result {
+            Ok(Event::BodyChunk(chunk)) => Poll::Ready(Some(Ok(Bytes::from(chunk)))),
+            Ok(_) => {
+                self.done = true;
+                Poll::Ready(None)
+            }
+            Err(err) => {
+                self.done = true;
+                Poll::Ready(Some(Err(err)))
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::multipart_subtype;
+use http::header::HeaderMap;
+use std::fmt;
+
+/// The subtype of a `multipart/*` body, which says how its parts relate to each other.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MultipartSubtype {
+    /// `mixed` (RFC 2046): independent parts, presented in order.
+    Mixed,
+    /// `alternative` (RFC 2046): versions of the same content, in increasing order of
+    /// preference.  Present the last one which can be presented.
+    Alternative,
+    /// `digest` (RFC 2046): like `mixed`, but parts without a Content-Type are
+    /// `message/rfc822` messages.
+    Digest,
+    /// `parallel` (RFC 2046): independent parts whose order is not significant, which may be
+    /// presented simultaneously.
+    Parallel,
+    /// `related` (RFC 2387): a root part with the parts it refers to.  See `Related`.
+    Related,
+    /// `form-data` (RFC 7578): the fields of a submitted form.
+    FormData,
+    /// `signed` (RFC 1847): content followed by its signature.
+    Signed,
+    /// `encrypted` (RFC 1847): control information followed by encrypted content.
+    Encrypted,
+    /// `report` (RFC 6522): a human readable message followed by a machine readable report.
+    Report,
+    /// `byteranges` (RFC 9110): ranges of a single resource.
+    ByteRanges,
+    /// Any other subtype, in lowercase, which is to be treated as `mixed` (RFC 2046).
+    Other(String),
+}
+
+impl MultipartSubtype {
+    /// The subtype of the `multipart/*` Content-Type in `headers`, or `None` if there is no
+    /// valid multipart Content-Type.
+    pub fn from_headers(headers: &HeaderMap) -> Option<MultipartSubtype> {
+        multipart_subtype(headers).map(|subtype| MultipartSubtype::from_name(&subtype))
+    }
+
+    /// The subtype with the given name, which is case-insensitive.
+    pub fn from_name(name: &str) -> MultipartSubtype {
+        match &*name.to_ascii_lowercase() {
+            "mixed" => MultipartSubtype::Mixed,
+            "alternative" => MultipartSubtype::Alternative,
+            "digest" => MultipartSubtype::Digest,
+            "parallel" => MultipartSubtype::Parallel,
+            "related" => MultipartSubtype::Related,
+            "form-data" => MultipartSubtype::FormData,
+            "signed" => MultipartSubtype::Signed,
+            "encrypted" => MultipartSubtype::Encrypted,
+            "report" => MultipartSubtype::Report,
+            "byteranges" => MultipartSubtype::ByteRanges,
+            other => MultipartSubtype::Other(other.to_owned()),
+        }
+    }
+
+    /// The name of the subtype, in lowercase
+    pub fn as_str(&self) -> &str {
+        match *self {
+            MultipartSubtype::Mixed => "mixed",
+            MultipartSubtype::Alternative => "alternative",
+            MultipartSubtype::Digest => "digest",
+            MultipartSubtype::Parallel => "parallel",
+            MultipartSubtype::Related => "related",
+            MultipartSubtype::FormData => "form-data",
+            MultipartSubtype::Signed => "signed",
+            MultipartSubtype::Encrypted => "encrypted",
+            MultipartSubtype::Report => "report",
+            MultipartSubtype::ByteRanges => "byteranges",
+            MultipartSubtype::Other(ref other) => other,
+        }
+    }
+
+    /// The Content-Type of parts which do not declare one: `message/rfc822` within a
+    /// `digest`, and `text/plain` otherwise (RFC 2046).
+    pub fn default_part_type(&self) -> mime::Mime {
+        match *self {
+            MultipartSubtype::Digest => "message/rfc822".parse().unwrap(),
+            _ => mime::TEXT_PLAIN,
+        }
+    }
+
+    /// Whether the order of the parts is significant.  Only `parallel` says it is not.
+    pub fn is_ordered(&self) -> bool {
+        *self != MultipartSubtype::Parallel
+    }
+}
+
+impl fmt::Display for MultipartSubtype {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+#[cfg(feature = "parse")]
+use super::read_multipart_body;
+use super::{generate_boundary, get_multipart_boundary, multipart_content_type};
+use super::{write_multipart, Error, FilePart, Node, Part, Related};
+use http::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use mime::Mime;
+#[cfg(feature = "parse")]
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+// The Content-ID given to the envelope by `SwaBuilder`
+const ROOT_CONTENT_ID: &str = "<soap-envelope@mime-multipart>";
+
+/// A SOAP with Attachments message (W3C note, 2000): a `multipart/related` body whose root
+/// part is the `text/xml` SOAP envelope, followed by attachments which the envelope refers
+/// to by `Content-ID`.  Build one with `SwaBuilder`, or read one with `SwaMessage::read()`.
+#[derive(Debug)]
+pub struct SwaMessage {
+    /// The top-level headers, including the `multipart/related` `Content-Type`
+    pub headers: HeaderMap,
+    /// The envelope and attachments
+    pub nodes: Vec<Node>,
+}
+
+impl SwaMessage {
+    /// Read a message body from `stream`, given the request's `headers`.  Attachments
+    /// which look like file uploads are streamed to files, as by `read_multipart_body()`.
+    #[cfg(feature = "parse")]
+    pub fn read<S: Read + ?Sized>(
+        stream: &mut S,
+        headers: &HeaderMap,
+    ) -> Result<SwaMessage, Error> {
+        Ok(SwaMessage {
+            headers: headers.clone(),
+            nodes: read_multipart_body(stream, headers, false)?,
+        })
+    }
+
+    /// The part holding the SOAP envelope: the one named by the `start` parameter, or else
+    /// the first.
+    pub fn root(&self) -> Option<&Node> {
+        Related::new(&self.headers, &self.nodes).root()
+    }
+
+    /// The attachment with the given Content-ID, which may be given as it is referenced
+    /// from the envelope (`cid:...`), or with or without angle brackets.
+    pub fn attachment(&self, content_id: &str) -> Option<&Node> {
+        let related = Related::new(&self.headers, &self.nodes);
+        match content_id.get(..4) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("cid:") => related.resolve(content_id),
+            _ => related.by_content_id(content_id),
+        }
+    }
+
+    /// Write the message body to `stream`.  The top-level `headers` are not written.
+    /// Returns the number of bytes written.
+    pub fn write<S: Write + ?Sized>(&self, stream: &mut S) -> Result<usize, Error> {
+        let boundary = get_multipart_boundary(&self.headers)?.split_off(2);
+        write_multipart(stream, &boundary, &self.nodes)
+    }
+}
+
+/// Builds a `SwaMessage` from a SOAP envelope and attachments.
+pub struct SwaBuilder {
+    envelope: Vec<u8>,
+    attachments: Vec<Node>,
+}
+
+impl SwaBuilder {
+    /// Start a message with the given SOAP envelope, which is sent as
+    /// `text/xml; charset=UTF-8`.
+    pub fn new(envelope: Vec<u8>) -> SwaBuilder {
+        SwaBuilder {
+            envelope,
+            attachments: Vec::new(),
+        }
+    }
+
+    /// Add an attachment held in memory, which the envelope refers to as `cid:content_id`.
+    pub fn attachment(
+        mut self,
+        content_id: &str,
+        content_type: &Mime,
+        body: Vec<u8>,
+    ) -> Result<SwaBuilder, Error> {
+        let headers = attachment_headers(content_id, content_type)?;
+        self.attachments.push(Node::Part(Part { headers, body }));
+        Ok(self)
+    }
+
+    /// Add an attachment streamed from the file at `path` when the message is written.
+    pub fn attachment_file(
+        mut self,
+        content_id: &str,
+        content_type: &Mime,
+        path: &Path,
+    ) -> Result<SwaBuilder, Error> {
+        let headers = attachment_headers(content_id, content_type)?;
+        self.attachments
+            .push(Node::File(FilePart::new(headers, path)));
+        Ok(self)
+    }
+
+    /// Build the message, with a freshly generated boundary.
+    pub fn build(self) -> Result<SwaMessage, Error> {
+        let boundary = generate_boundary();
+        let mut content_type = multipart_content_type("related", &boundary)?
+            .as_bytes()
+            .to_vec();
+        content_type.extend(b"; type=\"text/xml\"; start=\"");
+        content_type.extend(ROOT_CONTENT_ID.as_bytes());
+        content_type.extend(b"\"");
+        let mut headers = HeaderMap::new();
+        match HeaderValue::from_bytes(&content_type) {
+            Ok(value) => headers.insert(CONTENT_TYPE, value),
+            Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+        };
+
+        let mut root = HeaderMap::new();
+        root.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/xml; charset=UTF-8"),
+        );
+        root.insert(
+            "content-transfer-encoding",
+            HeaderValue::from_static("8bit"),
+        );
+        root.insert("content-id", HeaderValue::from_static(ROOT_CONTENT_ID));
+
+        let mut nodes = vec![Node::Part(Part {
+            headers: root,
+            body: self.envelope,
+        })];
+        nodes.extend(self.attachments);
+        Ok(SwaMessage { headers, nodes })
+    }
+}
+
+fn attachment_headers(content_id: &str, content_type: &Mime) -> Result<HeaderMap, Error> {
+    let content_id = content_id.trim_start_matches('<').trim_end_matches('>');
+    let mut headers = HeaderMap::new();
+    match HeaderValue::from_str(content_type.as_ref()) {
+        Ok(value) => headers.insert(CONTENT_TYPE, value),
+        Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+    };
+    headers.insert(
+        "content-transfer-encoding",
+        HeaderValue::from_static("binary"),
+    );
+    match HeaderValue::from_str(&format!("<{}>", content_id)) {
+        Ok(value) => headers.insert("content-id", value),
+        Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+    };
+    Ok(headers)
+}
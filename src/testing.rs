@@ -0,0 +1,214 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Builders for synthetic multipart bodies, for testing code which handles them without
+//! writing out byte literals by hand.
+
+use super::multipart_content_type;
+use http::header::{HeaderMap, CONTENT_TYPE};
+
+/// The line terminator a `BodyBuilder` uses throughout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    /// CRLF, as RFC 2046 requires
+    #[default]
+    CrLf,
+    /// A bare LF, as produced by some clients and by text files
+    Lf,
+}
+
+impl LineEnding {
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::CrLf => b"\r\n",
+            LineEnding::Lf => b"\n",
+        }
+    }
+}
+
+/// Damage done to a built body, applied in the order given after the body is laid out.
+/// Offsets beyond the end of the body are ignored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Corruption {
+    /// Cut the body to the given length
+    Truncate(usize),
+    /// Invert every bit of the byte at the given offset
+    FlipByte(usize),
+    /// Insert bytes at the given offset
+    Insert(usize, Vec<u8>),
+    /// Remove the line terminator preceding the close delimiter, as some buggy clients do
+    NoLineBeforeClose,
+}
+
+// A part's headers, in order, and body
+type RawPart = (Vec<(String, String)>, Vec<u8>);
+
+/// Builds a multipart body from fields, files and raw parts.
+#[derive(Clone, Debug)]
+pub struct BodyBuilder {
+    boundary: String,
+    parts: Vec<RawPart>,
+    line_ending: LineEnding,
+    preamble: Vec<u8>,
+    epilogue: Vec<u8>,
+    final_boundary: bool,
+    corruptions: Vec<Corruption>,
+}
+
+impl Default for BodyBuilder {
+    fn default() -> BodyBuilder {
+        BodyBuilder::new()
+    }
+}
+
+impl BodyBuilder {
+    /// An empty `multipart/form-data` body with boundary `AaB03x`, using CRLF and ending in
+    /// a close delimiter.
+    pub fn new() -> BodyBuilder {
+        BodyBuilder {
+            boundary: "AaB03x".to_owned(),
+            parts: Vec::new(),
+            line_ending: LineEnding::CrLf,
+            preamble: Vec::new(),
+            epilogue: Vec::new(),
+            final_boundary: true,
+            corruptions: Vec::new(),
+        }
+    }
+
+    /// Use another boundary.
+    pub fn boundary(mut self, boundary: &str) -> BodyBuilder {
+        self.boundary = boundary.to_owned();
+        self
+    }
+
+    /// Add a form field.
+    pub fn field(self, name: &str, value: &str) -> BodyBuilder {
+        let disposition = format!("form-data; name=\"{}\"", name);
+        self.part(&[("Content-Disposition", &disposition)], value.as_bytes())
+    }
+
+    /// Add a file upload.
+    pub fn file(
+        self,
+        name: &str,
+        filename: &str,
+        content_type: &str,
+        content: &[u8],
+    ) -> BodyBuilder {
+        let disposition = format!("form-data; name=\"{}\"; filename=\"{}\"", name, filename);
+        self.part(
+            &[
+                ("Content-Disposition", &disposition),
+                ("Content-Type", content_type),
+            ],
+            content,
+        )
+    }
+
+    /// Add a part with the given headers and body, written as given.
+    pub fn part(mut self, headers: &[(&str, &str)], body: &[u8]) -> BodyBuilder {
+        let headers = headers
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+        self.parts.push((headers, body.to_vec()));
+        self
+    }
+
+    /// Use the given line terminator throughout.
+    pub fn line_ending(mut self, line_ending: LineEnding) -> BodyBuilder {
+        self.line_ending = line_ending;
+        self
+    }
+
+    /// Put bytes before the first boundary.
+    pub fn preamble(mut self, preamble: &[u8]) -> BodyBuilder {
+        self.preamble = preamble.to_vec();
+        self
+    }
+
+    /// Put bytes after the close delimiter.
+    pub fn epilogue(mut self, epilogue: &[u8]) -> BodyBuilder {
+        self.epilogue = epilogue.to_vec();
+        self
+    }
+
+    /// Whether to end the last part with a close delimiter.  Without one, the body ends with
+    /// the last part's content, as when a client gives up.
+    pub fn final_boundary(mut self, final_boundary: bool) -> BodyBuilder {
+        self.final_boundary = final_boundary;
+        self
+    }
+
+    /// Damage the body once it is laid out.
+    pub fn corrupt(mut self, corruption: Corruption) -> BodyBuilder {
+        self.corruptions.push(corruption);
+        self
+    }
+
+    /// Headers carrying the `multipart/form-data` Content-Type for the body.
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let content_type = multipart_content_type("form-data", self.boundary.as_bytes())
+            .expect("BodyBuilder boundary is not a valid boundary");
+        headers.insert(CONTENT_TYPE, content_type);
+        headers
+    }
+
+    /// Lay out the body.
+    pub fn build(&self) -> Vec<u8> {
+        let lt = self.line_ending.as_bytes();
+        let mut body = self.preamble.clone();
+        let mut close = None;
+        for (headers, content) in &self.parts {
+            body.extend(b"--");
+            body.extend(self.boundary.as_bytes());
+            body.extend(lt);
+            for (name, value) in headers {
+                body.extend(name.as_bytes());
+                body.extend(b": ");
+                body.extend(value.as_bytes());
+                body.extend(lt);
+            }
+            body.extend(lt);
+            body.extend(content);
+            close = Some(body.len());
+            body.extend(lt);
+        }
+        if self.final_boundary {
+            body.extend(b"--");
+            body.extend(self.boundary.as_bytes());
+            body.extend(b"--");
+            body.extend(&self.epilogue);
+        } else if close.is_some() {
+            body.truncate(body.len() - lt.len());
+        }
+
+        for corruption in &self.corruptions {
+            match *corruption {
+                Corruption::Truncate(len) => body.truncate(len),
+                Corruption::FlipByte(offset) => {
+                    if let Some(byte) = body.get_mut(offset) {
+                        *byte = !*byte;
+                    }
+                }
+                Corruption::Insert(offset, ref bytes) => {
+                    if offset <= body.len() {
+                        body.splice(offset..offset, bytes.iter().cloned());
+                    }
+                }
+                Corruption::NoLineBeforeClose => {
+                    if let Some(close) = close.filter(|_| self.final_boundary) {
+                        body.drain(close..close + lt.len());
+                    }
+                }
+            }
+        }
+        body
+    }
+}
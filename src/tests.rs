@@ -7,7 +7,8 @@
 
 use super::*;
 
-use http::header::{HeaderMap, HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE};
+use http::header::{HeaderMap, HeaderValue, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE};
+use std::sync::{Arc, Mutex};
 
 #[test]
 fn parser() {
@@ -86,7 +87,7 @@ fn parser() {
             if let Node::File(ref filepart) = nodes[1] {
                 assert_eq!(filepart.size, Some(30));
                 assert_eq!(filepart.filename().unwrap().unwrap(), "image.gif");
-                assert_eq!(filepart.content_type().unwrap(), mime::IMAGE_GIF);
+                assert_eq!(filepart.content_type().unwrap().unwrap(), mime::IMAGE_GIF);
 
                 assert!(filepart.path.exists());
                 assert!(filepart.path.is_file());
@@ -97,7 +98,7 @@ fn parser() {
             if let Node::File(ref filepart) = nodes[2] {
                 assert_eq!(filepart.size, Some(14));
                 assert_eq!(filepart.filename().unwrap().unwrap(), "file.txt");
-                assert!(filepart.content_type().is_none());
+                assert!(filepart.content_type().unwrap().is_none());
 
                 assert!(filepart.path.exists());
                 assert!(filepart.path.is_file());
@@ -198,7 +199,7 @@ fn mixed_parser() {
                 if let Node::File(ref filepart) = subnodes[0] {
                     assert_eq!(filepart.size, Some(29));
                     assert_eq!(filepart.filename().unwrap().unwrap(), "file1.txt");
-                    assert!(filepart.content_type().is_none());
+                    assert!(filepart.content_type().unwrap().is_none());
 
                     assert!(filepart.path.exists());
                     assert!(filepart.path.is_file());
@@ -209,7 +210,7 @@ fn mixed_parser() {
                 if let Node::File(ref filepart) = subnodes[1] {
                     assert_eq!(filepart.size, Some(37));
                     assert_eq!(filepart.filename().unwrap().unwrap(), "awesome_image.gif");
-                    assert_eq!(filepart.content_type().unwrap(), mime::IMAGE_GIF);
+                    assert_eq!(filepart.content_type().unwrap().unwrap(), mime::IMAGE_GIF);
 
                     assert!(filepart.path.exists());
                     assert!(filepart.path.is_file());
@@ -281,31 +282,47 @@ fn test_line_feed() {
 
     let body = input[body_start..].to_vec();
 
-    if let Err(e) = read_multipart_body(&mut &*body, &headers, false) {
-        panic!("{}", e);
+    let nodes = match read_multipart_body(&mut &*body, &headers, false) {
+        Ok(nodes) => nodes,
+        Err(e) => panic!("{}", e),
+    };
+    match nodes[2] {
+        Node::File(ref filepart) => {
+            assert_eq!(filepart.filename().unwrap().as_deref(), Some("genome.txt"))
+        }
+        _ => panic!("3rd node of wrong type"),
     }
+
+    let cd = HeaderValue::from_static("form-data; name=submit-name ; filename=a.txt; size=5");
+    assert_eq!(
+        get_content_disposition_name(&cd).as_deref(),
+        Some("submit-name")
+    );
+    let filepart = FilePart::new(
+        [(CONTENT_DISPOSITION, cd)].into_iter().collect(),
+        Path::new("a.txt"),
+    );
+    assert_eq!(filepart.filename().unwrap().as_deref(), Some("a.txt"));
 }
 
 #[inline]
 fn get_content_disposition_name(cd: &HeaderValue) -> Option<String> {
-    match cd.to_str() {
-        Ok(value) => match value.contains("name") {
-            true => match value.find("name=") {
-                Some(index) => {
-                    let start = index + "name=".len();
-                    Some(value.get(start..).unwrap().trim_matches('\"').to_owned())
-                }
-                None => match value.find("name*=UTF-8''") {
-                    Some(index) => {
-                        let start = index + "name*=UTF-8''".len();
-                        Some(value.get(start..).unwrap().trim_matches('\"').to_owned())
-                    }
-                    None => None,
-                },
-            },
-            false => None,
-        },
-        Err(_) => None,
+    let cd = ContentDisposition::from_header(cd).ok()?;
+    cd.name().map(str::to_owned)
+}
+
+// Poll a future to completion without a runtime, for futures over in-memory readers and
+// writers which never have to wait for long
+#[cfg(any(feature = "tokio", feature = "http-body"))]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::task::{Context, Poll, Waker};
+
+    let mut future = std::pin::pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
     }
 }
 
@@ -398,3 +415,4535 @@ fn test_chunked() {
 
     assert_eq!(output.len(), 557);
 }
+
+#[test]
+fn test_output_chunked_empty_bodies() {
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("form-data; name=\"empty\""),
+    );
+    let filepart = FilePart::create(headers.clone()).unwrap();
+    std::fs::write(&filepart.path, b"").unwrap();
+    let nodes = vec![
+        Node::Part(Part {
+            headers,
+            body: Vec::new(),
+        }),
+        Node::File(filepart),
+    ];
+
+    // Only the last chunk is empty, as an empty chunk anywhere else would end the body early
+    let mut output = Vec::new();
+    write_multipart_chunked(&mut output, b"AaB03x", &nodes).unwrap();
+    let last_chunks = output
+        .windows(7)
+        .filter(|window| window == b"\r\n0\r\n\r\n")
+        .count();
+    assert_eq!(last_chunks, 1);
+    assert!(output.ends_with(b"2\r\n--\r\n0\r\n\r\n"));
+}
+
+#[test]
+fn test_output_content_length() {
+    let mut output: Vec<u8> = Vec::new();
+    let boundary = b"AaB03x";
+
+    let part = Part {
+        headers: {
+            let mut h = HeaderMap::new();
+            h.append(CONTENT_TYPE, HeaderValue::from_str("text/plain").unwrap());
+            h.append(CONTENT_LENGTH, HeaderValue::from_str("999").unwrap());
+            h
+        },
+        body: b"Michael".to_vec(),
+    };
+    let nodes: Vec<Node> = vec![Node::Part(part)];
+
+    let profile = WriteProfile {
+        content_length: true,
+        ..Default::default()
+    };
+    let count = write_multipart_with(&mut output, boundary, &nodes, &profile).unwrap();
+    assert_eq!(count, output.len());
+    assert_eq!(
+        output,
+        b"--AaB03x\r\n\
+          content-type: text/plain\r\n\
+          content-length: 7\r\n\
+          \r\n\
+          Michael\r\n\
+          --AaB03x--"
+    );
+}
+
+#[test]
+fn test_output_email_profile() {
+    let mut output: Vec<u8> = Vec::new();
+    let boundary = b"AaB03x";
+
+    let mut filepart = FilePart::create(HeaderMap::new()).unwrap();
+    std::fs::write(&filepart.path, b"This is a file").unwrap();
+    filepart.headers.append(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_str("attachment; filename=\"file.txt\"").unwrap(),
+    );
+    let nodes: Vec<Node> = vec![Node::File(filepart)];
+
+    write_multipart_with(&mut output, boundary, &nodes, &WriteProfile::email()).unwrap();
+    let string = String::from_utf8_lossy(&output);
+    assert!(string.contains("content-disposition: attachment; filename=\"file.txt\"; size=14\r\n"));
+    assert!(string.contains("content-transfer-encoding: binary\r\n"));
+
+    // The HTTP profile leaves the headers alone
+    output.truncate(0);
+    write_multipart_with(&mut output, boundary, &nodes, &WriteProfile::http()).unwrap();
+    let string = String::from_utf8_lossy(&output);
+    assert!(!string.contains("size="));
+    assert!(!string.contains("content-transfer-encoding"));
+}
+
+#[test]
+fn test_multipart_length() {
+    let boundary = b"AaB03x";
+    let mut filepart = FilePart::create(HeaderMap::new()).unwrap();
+    std::fs::write(&filepart.path, b"This is a file").unwrap();
+    filepart.headers.append(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment; filename=\"file.txt\""),
+    );
+    let mut nested = HeaderMap::new();
+    nested.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/alternative"),
+    );
+    let nodes: Vec<Node> = vec![
+        Node::Part(Part {
+            headers: HeaderMap::new(),
+            body: b"Michael".to_vec(),
+        }),
+        Node::File(filepart),
+        Node::Multipart((
+            nested,
+            vec![Node::Part(Part {
+                headers: HeaderMap::new(),
+                body: b"text".to_vec(),
+            })],
+        )),
+    ];
+
+    let email = WriteProfile {
+        content_length: true,
+        final_crlf: true,
+        ..WriteProfile::email()
+    };
+    for profile in [WriteProfile::http(), email] {
+        let mut output: Vec<u8> = Vec::new();
+        write_multipart_with(&mut output, boundary, &nodes, &profile).unwrap();
+        assert_eq!(
+            multipart_length(boundary, &nodes, &profile).unwrap(),
+            output.len() as u64
+        );
+    }
+    assert_eq!(
+        multipart_length(boundary, &[], &WriteProfile::http()).unwrap(),
+        10
+    );
+}
+
+#[test]
+fn test_unsafe_header_value() {
+    assert!(check_header_value(b"form-data; name=\"a\tb\"").is_ok());
+    for value in [&b"a\r\nX-Injected: 1"[..], b"a\nb", b"a\rb", b"a\0b"] {
+        match check_header_value(value) {
+            Err(Error::UnsafeHeaderValue) => {}
+            other => panic!("expected UnsafeHeaderValue, got {:?}", other),
+        }
+    }
+    assert_eq!(Error::UnsafeHeaderValue.category(), ErrorCategory::Header);
+}
+
+#[test]
+fn test_boundary_collision() {
+    let part = |body: &[u8]| {
+        Node::Part(Part {
+            headers: HeaderMap::new(),
+            body: body.to_vec(),
+        })
+    };
+
+    let nodes = vec![part(b"text\r\n--AaB03x--\r\nmore")];
+    let mut output = Vec::new();
+    match write_multipart(&mut output, b"AaB03x", &nodes) {
+        Err(Error::BoundaryCollision) => {}
+        other => panic!("expected BoundaryCollision, got {:?}", other),
+    }
+    assert!(output.is_empty());
+    assert!(matches!(
+        write_multipart_chunked(&mut output, b"AaB03x", &nodes),
+        Err(Error::BoundaryCollision)
+    ));
+
+    // An inner part must not contain the outer delimiter either
+    let mut inner = HeaderMap::new();
+    inner.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/mixed; boundary=BbC04y"),
+    );
+    let nodes = vec![Node::Multipart((inner, vec![part(b"\r\n--AaB03x\r\n")]))];
+    assert!(matches!(
+        write_multipart(&mut output, b"AaB03x", &nodes),
+        Err(Error::BoundaryCollision)
+    ));
+    assert!(write_multipart(&mut output, b"CcD05z", &nodes).is_ok());
+}
+
+#[test]
+fn test_nested_boundary_generated() {
+    let part = |body: &[u8]| {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        Node::Part(Part {
+            headers,
+            body: body.to_vec(),
+        })
+    };
+    let mut alternative = HeaderMap::new();
+    alternative.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/alternative; charset=utf-8"),
+    );
+    let nodes = vec![
+        Node::Multipart((alternative, vec![part(b"a"), part(b"b")])),
+        Node::Multipart((HeaderMap::new(), vec![part(b"c")])),
+    ];
+
+    let mut output = Vec::new();
+    write_multipart(&mut output, b"AaB03x", &nodes).unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/mixed; boundary=AaB03x"),
+    );
+    let parsed = read_multipart_body(&mut &output[..], &headers, false).unwrap();
+    assert_eq!(parsed.len(), 2);
+    match parsed[0] {
+        Node::Multipart((ref headers, ref subnodes)) => {
+            assert_eq!(multipart_subtype(headers).as_deref(), Some("alternative"));
+            let content_type = headers[CONTENT_TYPE].to_str().unwrap();
+            assert!(content_type.starts_with("multipart/alternative; charset=utf-8; boundary="));
+            assert_eq!(subnodes.len(), 2);
+        }
+        _ => panic!("1st node of wrong type"),
+    }
+    match parsed[1] {
+        Node::Multipart((ref headers, ref subnodes)) => {
+            assert_eq!(multipart_subtype(headers).as_deref(), Some("mixed"));
+            assert_eq!(subnodes.len(), 1);
+        }
+        _ => panic!("2nd node of wrong type"),
+    }
+}
+
+#[test]
+fn test_into_parts() {
+    let part = |body: &[u8]| {
+        Node::Part(Part {
+            headers: HeaderMap::new(),
+            body: body.to_vec(),
+        })
+    };
+    let filepart = FilePart::create(HeaderMap::new()).unwrap();
+    std::fs::write(&filepart.path, b"file content").unwrap();
+    let path = filepart.path.clone();
+    let nodes = vec![
+        part(b"a"),
+        Node::Multipart((
+            HeaderMap::new(),
+            vec![
+                Node::Multipart((HeaderMap::new(), vec![part(b"b")])),
+                Node::File(filepart),
+            ],
+        )),
+        Node::Multipart((HeaderMap::new(), Vec::new())),
+        part(b"c"),
+    ];
+
+    let mut parts = into_parts(nodes);
+    assert_eq!(parts.next().unwrap().into_part().unwrap().body, b"a");
+    assert_eq!(parts.next().unwrap().into_part().unwrap().body, b"b");
+    let filepart = parts.next().unwrap().into_file().unwrap();
+    assert_eq!(filepart.path, path);
+    assert_eq!(parts.next().unwrap().into_part().unwrap().body, b"c");
+    assert!(parts.next().is_none());
+
+    assert!(part(b"d").into_file().is_none());
+    assert!(part(b"d").into_multipart().is_none());
+
+    // The moved file part still owns its file
+    assert!(path.exists());
+    drop(filepart);
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_text_charset() {
+    let part = Part::text(&mime::TEXT_PLAIN_UTF_8, "J\u{fc}rgen", Charset::Iso8859_1).unwrap();
+    assert_eq!(part.body, b"J\xfcrgen");
+    assert_eq!(
+        part.headers.get(CONTENT_TYPE).unwrap(),
+        "text/plain; charset=ISO-8859-1"
+    );
+
+    let html: Mime = "text/html; format=flowed".parse().unwrap();
+    let part = Part::text(&html, "\u{20ac}5 \u{2013} caf\u{e9}", Charset::Windows1252).unwrap();
+    assert_eq!(part.body, b"\x805 \x96 caf\xe9");
+    assert_eq!(
+        part.headers.get(CONTENT_TYPE).unwrap(),
+        "text/html; format=flowed; charset=windows-1252"
+    );
+    assert_eq!(
+        Part::text(&html, "\u{20ac}", Charset::Utf8).unwrap().body,
+        "\u{20ac}".as_bytes()
+    );
+
+    for (charset, text) in [
+        (Charset::UsAscii, "caf\u{e9}"),
+        (Charset::Iso8859_1, "\u{20ac}"),
+        (Charset::Windows1252, "\u{81}"),
+    ] {
+        assert!(matches!(
+            Part::text(&mime::TEXT_PLAIN, text, charset),
+            Err(Error::Unencodable)
+        ));
+    }
+}
+
+#[test]
+fn test_extract_attachments() {
+    let part = |disposition: &'static str, content_type: &'static str, body: &[u8]| {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_DISPOSITION, HeaderValue::from_static(disposition));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        Node::Part(Part {
+            headers,
+            body: body.to_vec(),
+        })
+    };
+    let mut filepart = FilePart::create(HeaderMap::new()).unwrap();
+    std::fs::write(&filepart.path, b"file content").unwrap();
+    filepart.headers.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("form-data; name=upload; filename*=UTF-8''r%C3%A9sum%C3%A9.txt"),
+    );
+    let mut mixed = HeaderMap::new();
+    mixed.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/mixed; boundary=inner"),
+    );
+
+    let nodes = vec![
+        part("form-data; name=text", "text/plain", b"just a field"),
+        Node::Multipart((
+            mixed,
+            vec![
+                part("inline", "text/plain", b"message body"),
+                part("attachment", "application/pdf", b"%PDF"),
+                Node::File(filepart),
+            ],
+        )),
+        part("inline; filename=logo.png", "image/png", b"PNG"),
+    ];
+    let attachments = extract_attachments(&nodes);
+    let paths: Vec<&[usize]> = attachments.iter().map(|a| &a.path[..]).collect();
+    assert_eq!(paths, [&[1, 1][..], &[1, 2], &[2]]);
+
+    assert_eq!(attachments[0].filename, None);
+    assert_eq!(attachments[0].content_type, Some(mime::APPLICATION_PDF));
+    assert_eq!(attachments[0].size().unwrap(), 4);
+    assert_eq!(
+        attachments[1].filename.as_deref(),
+        Some("r\u{e9}sum\u{e9}.txt")
+    );
+    assert_eq!(attachments[1].content_type, None);
+    assert_eq!(attachments[1].size().unwrap(), 12);
+    let mut content = String::new();
+    attachments[1]
+        .reader()
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "file content");
+    assert_eq!(attachments[2].filename.as_deref(), Some("logo.png"));
+    assert_eq!(attachments[2].content_type, Some(mime::IMAGE_PNG));
+}
+
+#[test]
+fn test_diff() {
+    let parse = |body: &[u8]| {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("multipart/mixed; boundary=AaB03x"),
+        );
+        read_multipart_body(&mut &body[..], &headers, false).unwrap()
+    };
+    let golden = parse(
+        b"--AaB03x\r\n\
+          Content-Disposition: form-data; name=\"title\"\r\n\
+          X-Note: one\r\n\
+          \r\n\
+          Report\r\n\
+          --AaB03x\r\n\
+          Content-Type: multipart/mixed; boundary=\"inner1\"\r\n\
+          \r\n\
+          --inner1\r\n\
+          Content-Type: text/plain; charset=utf-8\r\n\
+          \r\n\
+          alpha\r\n\
+          --inner1--\r\n\
+          --AaB03x--",
+    );
+    // Different boundaries and quoting are not differences
+    let same = parse(
+        b"--AaB03x\r\n\
+          X-Note: one\r\n\
+          Content-Disposition: form-data; name=title\r\n\
+          \r\n\
+          Report\r\n\
+          --AaB03x\r\n\
+          Content-Type: multipart/mixed; boundary=other\r\n\
+          \r\n\
+          --other\r\n\
+          Content-Type: text/plain; charset=\"utf-8\"\r\n\
+          \r\n\
+          alpha\r\n\
+          --other--\r\n\
+          --AaB03x--",
+    );
+    assert_eq!(diff(&golden, &same).unwrap(), vec![]);
+
+    let changed = parse(
+        b"--AaB03x\r\n\
+          Content-Disposition: form-data; name=\"subject\"\r\n\
+          X-Note: two\r\n\
+          \r\n\
+          Report\r\n\
+          --AaB03x\r\n\
+          Content-Type: multipart/mixed; boundary=\"inner1\"\r\n\
+          \r\n\
+          --inner1\r\n\
+          Content-Type: text/plain; charset=utf-8\r\n\
+          \r\n\
+          bravo\r\n\
+          --inner1\r\n\
+          Content-Type: text/plain\r\n\
+          \r\n\
+          extra\r\n\
+          --inner1--\r\n\
+          --AaB03x\r\n\
+          Content-Type: text/plain\r\n\
+          \r\n\
+          more\r\n\
+          --AaB03x--",
+    );
+    let differences = diff(&golden, &changed).unwrap();
+    let kinds: Vec<(Vec<usize>, DifferenceKind)> = differences
+        .iter()
+        .map(|d| (d.path.clone(), d.kind.clone()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (
+                vec![0],
+                DifferenceKind::NameChanged(Some("title".to_owned()), Some("subject".to_owned()))
+            ),
+            (
+                vec![0],
+                DifferenceKind::HeaderChanged(
+                    "x-note".to_owned(),
+                    Some("one".to_owned()),
+                    Some("two".to_owned())
+                )
+            ),
+            (vec![1, 0], DifferenceKind::ContentChanged),
+            (vec![1, 1], DifferenceKind::Added),
+            (vec![2], DifferenceKind::Added),
+        ]
+    );
+    assert_eq!(differences[2].to_string(), "part 1.0: ContentChanged");
+
+    let shorter = parse(
+        b"--AaB03x\r\n\
+          Content-Disposition: form-data; name=\"title\"\r\n\
+          X-Note: one\r\n\
+          \r\n\
+          Rep\r\n\
+          --AaB03x--",
+    );
+    assert_eq!(
+        diff(&golden, &shorter).unwrap(),
+        vec![
+            Difference {
+                path: vec![0],
+                kind: DifferenceKind::SizeChanged(6, 3),
+            },
+            Difference {
+                path: vec![1],
+                kind: DifferenceKind::Removed,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_validate() {
+    let field = |disposition: &'static str, body: &[u8]| {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_DISPOSITION, HeaderValue::from_static(disposition));
+        Node::Part(Part {
+            headers,
+            body: body.to_vec(),
+        })
+    };
+    let mut form = HeaderMap::new();
+    form.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=BbC04y"),
+    );
+    let mut duplicate = HeaderMap::new();
+    duplicate.append(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("form-data; name=a"),
+    );
+    duplicate.append(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("form-data; name=b"),
+    );
+    let mut bad_boundary = HeaderMap::new();
+    bad_boundary.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/mixed; boundary=\"ends in space \""),
+    );
+
+    let nodes = vec![
+        field("form-data; name=\"ok\"", b"fine"),
+        Node::Multipart((
+            form,
+            vec![
+                field("form-data; name=x", b"x"),
+                field("form-data", b"no name"),
+                field("attachment; filename=a.txt", b"not a field"),
+                Node::Part(Part {
+                    headers: duplicate,
+                    body: Vec::new(),
+                }),
+            ],
+        )),
+        Node::Multipart((bad_boundary, vec![field("inline", b"too large")])),
+    ];
+    assert!(validate(&nodes[..1], &ParseProfile::default()).is_empty());
+
+    let profile = ParseProfile {
+        limits: Limits {
+            max_part_size: Some(8),
+            max_parts: Some(5),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+    let violations = validate(&nodes, &profile);
+    let found: Vec<(Vec<usize>, ViolationKind)> = violations
+        .iter()
+        .map(|violation| (violation.path.clone(), violation.kind.clone()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (vec![1, 1], ViolationKind::NoFieldName),
+            (vec![1, 2], ViolationKind::NotFormData),
+            (vec![1, 2], ViolationKind::PartTooLarge),
+            (vec![1, 3], ViolationKind::DuplicateContentDisposition),
+            (vec![2], ViolationKind::InvalidBoundary),
+            (vec![2, 0], ViolationKind::PartTooLarge),
+            (vec![], ViolationKind::TooManyParts),
+        ]
+    );
+    assert_eq!(violations[0].to_string(), "part 1.1: NoFieldName");
+
+    // Files and fields are told apart by the receiver's disposition policy
+    let profile = ParseProfile {
+        limits: Limits {
+            max_file_size: Some(10),
+            max_fields: Some(4),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+    let found: Vec<ViolationKind> = validate(&nodes, &profile)
+        .into_iter()
+        .map(|violation| violation.kind)
+        .filter(|kind| {
+            matches!(
+                kind,
+                ViolationKind::FileTooLarge | ViolationKind::TooManyFields
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [ViolationKind::FileTooLarge, ViolationKind::TooManyFields]
+    );
+}
+
+#[test]
+fn test_validate_transfer_encoding() {
+    let part = |encoding: &'static str, body: &[u8]| {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "content-transfer-encoding",
+            HeaderValue::from_static(encoding),
+        );
+        Node::Part(Part {
+            headers,
+            body: body.to_vec(),
+        })
+    };
+    let kinds = |node: Node| -> Vec<ViolationKind> {
+        validate(&[node], &ParseProfile::default())
+            .into_iter()
+            .map(|violation| violation.kind)
+            .collect()
+    };
+
+    assert!(kinds(part("7bit", b"line 1\r\nline 2\r\n")).is_empty());
+    assert!(kinds(part("8bit", "caf\u{e9}\r\n".as_bytes())).is_empty());
+    assert_eq!(
+        kinds(part("7bit", "caf\u{e9}".as_bytes())),
+        [ViolationKind::Not7Bit]
+    );
+    assert_eq!(
+        kinds(part("8BIT", b"lf only\n")),
+        [ViolationKind::NotLineOriented]
+    );
+    assert_eq!(
+        kinds(part("8bit", b"trailing cr\r")),
+        [ViolationKind::NotLineOriented]
+    );
+    let mut long = vec![b'a'; 998];
+    long.extend(b"\r\n");
+    assert!(kinds(part("7bit", &long)).is_empty());
+    long.insert(0, b'a');
+    assert_eq!(kinds(part("7bit", &long)), [ViolationKind::LineTooLong]);
+    assert!(kinds(part("binary", &long)).is_empty());
+}
+
+#[test]
+fn test_final_crlf() {
+    let boundary = b"AaB03x";
+    let part = Part {
+        headers: {
+            let mut h = HeaderMap::new();
+            h.append(
+                CONTENT_DISPOSITION,
+                HeaderValue::from_static("form-data; name=\"outer\""),
+            );
+            h
+        },
+        body: b"Larry".to_vec(),
+    };
+    let nested = {
+        let mut h = HeaderMap::new();
+        h.append(
+            CONTENT_TYPE,
+            HeaderValue::from_static("multipart/mixed; boundary=BbC04y"),
+        );
+        (h, vec![Node::Part(part.clone())])
+    };
+    let nodes: Vec<Node> = vec![Node::Part(part), Node::Multipart(nested)];
+
+    let profile = WriteProfile {
+        final_crlf: true,
+        ..Default::default()
+    };
+    let mut output: Vec<u8> = Vec::new();
+    let count = write_multipart_with(&mut output, boundary, &nodes, &profile).unwrap();
+    assert_eq!(count, output.len());
+    assert!(output.ends_with(b"\r\n--BbC04y--\r\n--AaB03x--\r\n"));
+
+    // Both forms parse back
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let with_crlf = read_multipart_body(&mut &*output, &headers, false).unwrap();
+    let without_crlf =
+        read_multipart_body(&mut &output[..output.len() - 2], &headers, false).unwrap();
+    assert_eq!(with_crlf.len(), 2);
+    assert_eq!(without_crlf.len(), 2);
+}
+
+#[test]
+fn test_lenient_close_delimiter() {
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"submit-name\"\r\n\
+                 \r\n\
+                 Larry--AaB03x--\r\n";
+
+    match read_multipart_body(&mut &body[..], &headers, false) {
+        Err(Error::EofInPart) => {}
+        other => panic!("expected EofInPart, got {:?}", other),
+    }
+
+    let nodes =
+        read_multipart_body_with(&mut &body[..], &headers, &ParseProfile::lenient()).unwrap();
+    assert_eq!(nodes.len(), 1);
+    if let Node::Part(ref part) = nodes[0] {
+        assert_eq!(part.body, b"Larry");
+    } else {
+        panic!("1st node of wrong type");
+    }
+
+    let nodes = read_multipart_body_with(
+        &mut &body[..],
+        &headers,
+        &ParseProfile {
+            disposition: DispositionPolicy::AlwaysFile,
+            ..ParseProfile::lenient()
+        },
+    )
+    .unwrap();
+    if let Node::File(ref filepart) = nodes[0] {
+        assert_eq!(filepart.size, Some(5));
+        assert_eq!(std::fs::read(&filepart.path).unwrap(), b"Larry");
+    } else {
+        panic!("1st node of wrong type");
+    }
+
+    // A part spilled to disk is cut at the close delimiter likewise
+    let nodes = read_multipart_body_with(
+        &mut &body[..],
+        &headers,
+        &ParseProfile {
+            spill_threshold: Some(2),
+            ..ParseProfile::lenient()
+        },
+    )
+    .unwrap();
+    if let Node::File(ref filepart) = nodes[0] {
+        assert_eq!(filepart.size, Some(5));
+        assert_eq!(std::fs::read(&filepart.path).unwrap(), b"Larry");
+    } else {
+        panic!("1st node of wrong type");
+    }
+}
+
+#[test]
+fn test_lenient_cr_line_endings() {
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/mixed; boundary=AaB03x"),
+    );
+
+    let body = b"--AaB03x\r\
+                 Content-Type: text/plain\r\
+                 \r\
+                 line 1\rline 2\r\
+                 --AaB03x\r\
+                 Content-Type: multipart/alternative; boundary=BbC04y\r\
+                 \r\
+                 --BbC04y\r\
+                 Content-Type: text/plain\r\
+                 \r\
+                 inner\r\
+                 --BbC04y--\r\
+                 --AaB03x--\r";
+
+    match read_multipart_body(&mut &body[..], &headers, false) {
+        Err(Error::NoCrLfAfterBoundary) => {}
+        other => panic!("expected NoCrLfAfterBoundary, got {:?}", other),
+    }
+
+    let nodes =
+        read_multipart_body_with(&mut &body[..], &headers, &ParseProfile::lenient()).unwrap();
+    assert_eq!(nodes.len(), 2);
+    match nodes[0] {
+        Node::Part(ref part) => {
+            assert_eq!(part.headers.get(CONTENT_TYPE).unwrap(), "text/plain");
+            assert_eq!(part.body, b"line 1\rline 2");
+        }
+        _ => panic!("1st node of wrong type"),
+    }
+    match nodes[1] {
+        Node::Multipart((_, ref subnodes)) => match subnodes[0] {
+            Node::Part(ref part) => assert_eq!(part.body, b"inner"),
+            _ => panic!("nested node of wrong type"),
+        },
+        _ => panic!("2nd node of wrong type"),
+    }
+
+    let body = b"--AaB03x\rContent-Type: text/plain\r\rline 1\rline 2\r--AaB03x--\r";
+    for chunk_size in 1..8 {
+        let parts = push_parse(body, chunk_size, &ParseProfile::lenient()).unwrap();
+        assert_eq!(parts[0].0.get(CONTENT_TYPE).unwrap(), "text/plain");
+        assert_eq!(parts[0].1, b"line 1\rline 2");
+    }
+}
+
+#[test]
+fn test_verify_content_length() {
+    let body = b"--AaB03x\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 text\r\n\
+                 --AaB03x--\r\n\
+                 epilogue";
+    let profile = ParseProfile {
+        verify_content_length: true,
+        ..ParseProfile::default()
+    };
+    let parse = |length: usize, body: &[u8]| {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("multipart/mixed; boundary=AaB03x"),
+        );
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(length));
+        read_multipart_body_with(&mut &body[..], &headers, &profile)
+    };
+
+    assert_eq!(parse(body.len(), body).unwrap().len(), 1);
+    assert_eq!(
+        parse(body.len() - 10, &body[..body.len() - 10])
+            .unwrap()
+            .len(),
+        1
+    );
+    for (length, input) in [
+        (body.len() + 1, &body[..]),
+        (body.len(), &body[..20]),
+        (20, &body[..]),
+    ] {
+        match parse(length, input) {
+            Err(Error::ContentLengthMismatch) => {}
+            other => panic!("expected ContentLengthMismatch, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_required_fields() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"title\"\r\n\
+                 \r\n\
+                 Holiday\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"meta\"\r\n\
+                 \r\n\
+                 {}\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"video\"; filename=\"big.mp4\"\r\n\
+                 \r\n\
+                 ...\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let profile = ParseProfile {
+        required_fields: vec!["meta".to_owned(), "title".to_owned()],
+        ..ParseProfile::default()
+    };
+    let mut reader = &body[..];
+    let (nodes, _) = read_multipart_body_counted(&mut reader, &headers, &profile).unwrap();
+    assert_eq!(nodes.len(), 2);
+    assert!(reader.starts_with(b"\r\nContent-Disposition: form-data; name=\"video\""));
+
+    let profile = ParseProfile {
+        stop_at: StopAt::Eof,
+        ..profile
+    };
+    let mut reader = &body[..];
+    let (nodes, consumed) = read_multipart_body_counted(&mut reader, &headers, &profile).unwrap();
+    assert_eq!(nodes.len(), 2);
+    assert!(reader.is_empty());
+    assert_eq!(consumed, body.len() as u64);
+
+    // Fields which never arrive do not stop the parse
+    let profile = ParseProfile {
+        required_fields: vec!["meta".to_owned(), "missing".to_owned()],
+        ..ParseProfile::default()
+    };
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    assert_eq!(nodes.len(), 3);
+}
+
+#[test]
+fn test_ignored_fields() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"title\"\r\n\
+                 \r\n\
+                 Holiday\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"files\"\r\n\
+                 Content-Type: multipart/mixed; boundary=BbC04y\r\n\
+                 \r\n\
+                 --BbC04y\r\n\
+                 Content-Disposition: attachment; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 first\r\n\
+                 --BbC04y--\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"video\"; filename=\"big.mp4\"\r\n\
+                 \r\n\
+                 ...\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"tag\"\r\n\
+                 \r\n\
+                 beach\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let profile = ParseProfile {
+        ignored_fields: vec!["files".to_owned(), "video".to_owned()],
+        disposition: DispositionPolicy::AlwaysFile,
+        limits: Limits {
+            max_files: Some(2),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+    let form = read_form_data_with(&mut &body[..], &headers, &profile).unwrap();
+    assert!(form.fields.is_empty());
+    let names: Vec<&str> = form.files.iter().map(|(name, _)| &**name).collect();
+    assert_eq!(names, ["title", "tag"]);
+
+    // Limits still apply to what is skipped
+    let profile = ParseProfile {
+        limits: Limits {
+            max_size_by_name: vec![("files".to_owned(), 10)],
+            ..Limits::default()
+        },
+        ..profile
+    };
+    assert!(matches!(
+        read_multipart_body_with(&mut &body[..], &headers, &profile),
+        Err(Error::FieldSizeExceeded(ref name)) if name == "files"
+    ));
+}
+
+#[test]
+fn test_bytes_consumed() {
+    let body = b"--AaB03x\r\n\
+                 Content-Type: multipart/mixed; boundary=BbC04y\r\n\
+                 \r\n\
+                 --BbC04y\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 text\r\n\
+                 --BbC04y--\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/mixed; boundary=AaB03x"),
+    );
+
+    let mut input = body.to_vec();
+    input.extend(b"\r\nNEXT MESSAGE");
+    let mut reader = &input[..];
+    let (nodes, consumed) =
+        read_multipart_body_counted(&mut reader, &headers, &ParseProfile::default()).unwrap();
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(consumed, body.len() as u64);
+    assert_eq!(reader, b"\r\nNEXT MESSAGE");
+
+    input.truncate(body.len());
+    input.extend(b" \r\nepilogue");
+    for (stop_at, rest) in [
+        (StopAt::CloseDelimiter, &b" \r\nepilogue"[..]),
+        (StopAt::LineEnd, b"epilogue"),
+        (StopAt::Eof, b""),
+    ] {
+        let profile = ParseProfile {
+            stop_at,
+            ..ParseProfile::default()
+        };
+        let mut reader = &input[..];
+        let (_, consumed) = read_multipart_body_counted(&mut reader, &headers, &profile).unwrap();
+        assert_eq!(reader, rest);
+        assert_eq!(consumed, (input.len() - rest.len()) as u64);
+    }
+}
+
+#[test]
+fn test_capture() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"field\"\r\n\
+                 \r\n\
+                 value\r\n\
+                 --AaB03x--\r\n";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let mut capture: Vec<u8> = Vec::new();
+    let nodes = read_multipart_body_captured(
+        &mut &body[..],
+        &headers,
+        &ParseProfile::default(),
+        &mut capture,
+    )
+    .unwrap();
+    let mut expected = b"content-type: multipart/form-data; boundary=AaB03x\r\n\r\n".to_vec();
+    expected.extend(&body[..]);
+    assert_eq!(capture, expected);
+
+    let (captured_headers, captured_nodes) = read_multipart(&mut &capture[..], false).unwrap();
+    assert_eq!(captured_headers, headers);
+    assert_eq!(diff(&captured_nodes, &nodes).unwrap(), vec![]);
+
+    // A failed parse still captures what was read
+    capture.truncate(0);
+    let truncated = &body[..20];
+    assert!(read_multipart_body_captured(
+        &mut &truncated[..],
+        &headers,
+        &ParseProfile::default(),
+        &mut capture,
+    )
+    .is_err());
+    assert!(capture.ends_with(truncated));
+}
+
+#[test]
+fn test_replay() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"field\"\r\n\
+                 \r\n\
+                 value\r\n\
+                 --AaB03x--\r\n";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let mut capture = tempfile::NamedTempFile::new().unwrap();
+    let nodes = read_multipart_body_captured(
+        &mut &body[..],
+        &headers,
+        &ParseProfile::default(),
+        &mut capture,
+    )
+    .unwrap();
+
+    let (replayed_headers, replayed) =
+        replay_file(capture.path(), &ParseProfile::default()).unwrap();
+    assert_eq!(replayed_headers, headers);
+    assert_eq!(diff(&replayed, &nodes).unwrap(), vec![]);
+
+    // Again, with stricter limits
+    let strict = ParseProfile {
+        limits: Limits {
+            max_part_size: Some(4),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+    let captured = std::fs::read(capture.path()).unwrap();
+    assert!(matches!(
+        replay(&mut &captured[..], &strict),
+        Err(Error::PartTooLarge)
+    ));
+}
+
+#[test]
+fn test_consecutive_documents() {
+    let document = |text: &str| {
+        format!(
+            "--AaB03x\r\nContent-Type: text/plain\r\n\r\n{}\r\n--AaB03x--\r\n",
+            text
+        )
+    };
+    let input = [document("one"), document("two"), document("three")].concat();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/mixed; boundary=AaB03x"),
+    );
+
+    let mut reader = BufReader::with_capacity(16, input.as_bytes());
+    let mut documents = MultipartDocuments::new(&mut reader, &headers, &ParseProfile::default());
+    let mut ends = Vec::new();
+    let mut texts = Vec::new();
+    for result in documents.by_ref() {
+        let (nodes, end) = result.unwrap();
+        ends.push(end);
+        match nodes[0] {
+            Node::Part(ref part) => texts.push(part.body.clone()),
+            _ => panic!("node of wrong type"),
+        }
+    }
+    assert_eq!(texts, [&b"one"[..], b"two", b"three"]);
+    let length = document("one").len() as u64;
+    assert_eq!(ends, [length, 2 * length, 3 * length + 2]);
+    assert_eq!(documents.position(), input.len() as u64);
+}
+
+#[test]
+fn test_preamble_limit() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/mixed; boundary=AaB03x"),
+    );
+    let mut body = vec![b'x'; 100];
+    body.extend(b"\r\n--AaB03x\r\nContent-Type: text/plain\r\n\r\ntext\r\n--AaB03x--");
+    let profile = |max| ParseProfile {
+        limits: Limits {
+            max_preamble: Some(max),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+
+    assert!(read_multipart_body_with(&mut &body[..], &headers, &profile(102)).is_ok());
+    match read_multipart_body_with(&mut &body[..], &headers, &profile(101)) {
+        Err(Error::PreambleTooLarge) => {}
+        other => panic!("expected PreambleTooLarge, got {:?}", other),
+    }
+
+    // Garbage without any boundary is refused once the limit is reached
+    let garbage = vec![b'x'; 1 << 20];
+    match read_multipart_body_with(&mut &garbage[..], &headers, &profile(1024)) {
+        Err(Error::PreambleTooLarge) => {}
+        other => panic!("expected PreambleTooLarge, got {:?}", other),
+    }
+
+    for chunk_size in [1, 7, 64] {
+        assert_eq!(
+            push_parse(&body, chunk_size, &profile(102)).unwrap().len(),
+            1
+        );
+        match push_parse(&body, chunk_size, &profile(101)) {
+            Err(Error::PreambleTooLarge) => {}
+            other => panic!("expected PreambleTooLarge, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_boundary_round_trip() {
+    for boundary in [
+        &b"AaB03x"[..],
+        b"gc0pJq0M:08jU534c0p",
+        b"simple boundary",
+        b"a?b=c",
+        b"(x)/y,z'",
+    ] {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            CONTENT_TYPE,
+            multipart_content_type("mixed", boundary).unwrap(),
+        );
+        let mut delimiter = b"--".to_vec();
+        delimiter.extend(boundary);
+        assert_eq!(get_multipart_boundary(&headers).unwrap(), delimiter);
+    }
+
+    assert_eq!(
+        multipart_content_type("mixed", b"simple boundary").unwrap(),
+        "multipart/mixed; boundary=\"simple boundary\""
+    );
+    assert_eq!(
+        multipart_content_type("form-data", b"AaB03x").unwrap(),
+        "multipart/form-data; boundary=AaB03x"
+    );
+
+    let too_long = [b'a'; 71];
+    for boundary in [
+        &b""[..],
+        b"trailing ",
+        b"quote\"",
+        b"semi;colon",
+        &too_long[..],
+    ] {
+        match multipart_content_type("mixed", boundary) {
+            Err(Error::InvalidBoundary) => {}
+            other => panic!("expected InvalidBoundary, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_read_multipart_headers() {
+    let input = b"Host: example.domain\r\n\
+                  Content-Type: multipart/form-data; boundary=AaB03x\r\n\
+                  Content-Length: 82\r\n\
+                  Cookie: session_id=a36ZVwAAAACDQ9gzBCzDVZ1VNrnZEI1U\r\n\
+                  \r\n\
+                  --AaB03x\r\n\
+                  Content-Disposition: form-data; name=\"submit-name\"\r\n\
+                  \r\n\
+                  Larry\r\n\
+                  --AaB03x--";
+
+    let (headers, nodes) = read_multipart(&mut &input[..], false).unwrap();
+    assert_eq!(headers.get("content-length").unwrap(), "82");
+    assert_eq!(
+        headers.get("cookie").unwrap(),
+        "session_id=a36ZVwAAAACDQ9gzBCzDVZ1VNrnZEI1U"
+    );
+    assert_eq!(nodes.len(), 1);
+}
+
+#[test]
+fn test_many_fields() {
+    let mut body = Vec::new();
+    for i in 0..10_000 {
+        let field = format!(
+            "--b\r\nContent-Disposition: form-data; name=\"f{}\"\r\n\r\nv{}\r\n",
+            i, i
+        );
+        body.extend(field.as_bytes());
+    }
+    body.extend(b"--b--\r\n");
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=b"),
+    );
+
+    let profile = ParseProfile {
+        expected_parts: 10_000,
+        ..ParseProfile::default()
+    };
+    let (nodes, spans) = read_multipart_body_spans(&mut &body[..], &headers, &profile).unwrap();
+    assert_eq!(nodes.len(), 10_000);
+    assert_eq!(nodes.capacity(), 10_000);
+    assert_eq!(spans.len(), 10_000);
+    match nodes[9_999] {
+        Node::Part(ref part) => assert_eq!(part.body, b"v9999"),
+        _ => panic!("field 9999 is not in memory"),
+    }
+
+    // The reservation is capped by the limit on parts
+    let profile = ParseProfile {
+        expected_parts: usize::MAX,
+        limits: Limits {
+            max_parts: Some(20_000),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    assert_eq!(nodes.len(), 10_000);
+}
+
+#[test]
+fn test_limits() {
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"submit-name\"\r\n\
+                 \r\n\
+                 Larry\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"files\"\r\n\
+                 Content-Type: multipart/mixed; boundary=BbC04y\r\n\
+                 \r\n\
+                 --BbC04y\r\n\
+                 Content-Disposition: file; filename=\"file1.txt\"\r\n\
+                 \r\n\
+                 ... contents of file1.txt ...\r\n\
+                 --BbC04y--\r\n\
+                 --AaB03x--";
+
+    let parse = |limits: Limits| {
+        let profile = ParseProfile {
+            limits,
+            ..Default::default()
+        };
+        read_multipart_body_with(&mut &body[..], &headers, &profile)
+    };
+
+    assert!(parse(Limits::hardened()).is_ok());
+    assert!(parse(Limits {
+        max_parts: Some(3),
+        max_part_size: Some(29),
+        max_total_size: Some(34),
+        max_depth: Some(1),
+        max_part_headers: Some(2),
+        max_main_headers: Some(1),
+        max_boundary_len: Some(6),
+        max_header_block: Some(96),
+        max_preamble: Some(0),
+        max_files: Some(1),
+        max_file_size: Some(29),
+        max_fields: Some(1),
+        max_field_size: Some(5),
+        max_size_by_name: vec![("submit-name".to_owned(), 5)],
+    })
+    .is_ok());
+
+    // Fields stay small while a file may be larger than other parts
+    assert!(parse(Limits {
+        max_part_size: Some(5),
+        max_file_size: Some(1024),
+        ..Default::default()
+    })
+    .is_ok());
+
+    let checks: Vec<(Limits, &str)> = vec![
+        (
+            Limits {
+                max_parts: Some(2),
+                ..Default::default()
+            },
+            "TooManyParts",
+        ),
+        (
+            Limits {
+                max_part_size: Some(28),
+                ..Default::default()
+            },
+            "PartTooLarge",
+        ),
+        (
+            Limits {
+                max_total_size: Some(33),
+                ..Default::default()
+            },
+            "BodyTooLarge",
+        ),
+        (
+            Limits {
+                max_depth: Some(0),
+                ..Default::default()
+            },
+            "NestingTooDeep",
+        ),
+        (
+            Limits {
+                max_part_headers: Some(1),
+                ..Default::default()
+            },
+            "TooManyHeaders",
+        ),
+        (
+            Limits {
+                max_boundary_len: Some(5),
+                ..Default::default()
+            },
+            "BoundaryTooLong",
+        ),
+        (
+            Limits {
+                max_header_block: Some(95),
+                ..Default::default()
+            },
+            "HeadersTooLarge",
+        ),
+        (
+            Limits {
+                max_files: Some(0),
+                ..Default::default()
+            },
+            "TooManyFiles",
+        ),
+        (
+            Limits {
+                max_part_size: Some(1024),
+                max_file_size: Some(28),
+                ..Default::default()
+            },
+            "FileTooLarge",
+        ),
+        (
+            Limits {
+                max_fields: Some(0),
+                ..Default::default()
+            },
+            "TooManyFields",
+        ),
+        (
+            Limits {
+                max_field_size: Some(4),
+                ..Default::default()
+            },
+            "FieldTooLarge",
+        ),
+    ];
+    for (limits, expected) in checks {
+        match parse(limits.clone()) {
+            Err(e) => assert_eq!(e.to_string(), expected),
+            Ok(_) => panic!("{:?}: expected {}", limits, expected),
+        }
+    }
+
+    // Limits by field name replace the others, and errors name the field
+    let by_name = |name: &str, max| Limits {
+        max_part_size: Some(1),
+        max_size_by_name: vec![("other".to_owned(), 1), (name.to_owned(), max)],
+        ..Default::default()
+    };
+    assert!(matches!(
+        parse(by_name("submit-name", 4)),
+        Err(Error::FieldSizeExceeded(ref name)) if name == "submit-name"
+    ));
+    assert_eq!(
+        parse(by_name("submit-name", 5)).unwrap_err().to_string(),
+        "PartTooLarge"
+    );
+}
+
+#[test]
+fn test_default_part_headers() {
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"submit-name\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 Content-Language: en\r\n\
+                 Content-Location: larry.txt\r\n\
+                 Content-ID: <larry>\r\n\
+                 \r\n\
+                 Larry\r\n\
+                 --AaB03x--";
+
+    // Without a limit, parts keep to the 4 headers they always could have
+    assert!(matches!(
+        read_multipart_body(&mut &body[..], &headers, false),
+        Err(Error::Httparse(err)) if err.kind() == httparse::Error::TooManyHeaders
+    ));
+
+    let profile = ParseProfile {
+        limits: Limits {
+            max_part_headers: Some(5),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    assert_eq!(nodes.len(), 1);
+}
+
+// Compare two node trees by headers and content, rather than by file paths
+fn assert_same_nodes(a: &[Node], b: &[Node]) {
+    assert_eq!(a.len(), b.len());
+    for (a, b) in a.iter().zip(b.iter()) {
+        match (a, b) {
+            (Node::Part(a), Node::Part(b)) => assert_eq!(a, b),
+            (Node::File(a), Node::File(b)) => {
+                assert_eq!(a.headers, b.headers);
+                assert_eq!(a.size, b.size);
+                assert_eq!(
+                    std::fs::read(&a.path).unwrap(),
+                    std::fs::read(&b.path).unwrap()
+                );
+            }
+            (Node::Multipart((ah, an)), Node::Multipart((bh, bn))) => {
+                assert_eq!(ah, bh);
+                assert_same_nodes(an, bn);
+            }
+            (a, b) => panic!("node kinds differ: {:?} vs {:?}", a, b),
+        }
+    }
+}
+
+#[test]
+fn test_normalize_round_trip() {
+    let mut upload = FilePart::create(HeaderMap::new()).unwrap();
+    std::fs::write(&upload.path, b"{ \"id\": 15 }").unwrap();
+    upload.headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("  application/json\t"),
+    );
+
+    let nodes = vec![
+        Node::Part(Part {
+            headers: {
+                let mut h = HeaderMap::new();
+                h.append(
+                    CONTENT_DISPOSITION,
+                    HeaderValue::from_static("form-data; name=\"first_name\"   "),
+                );
+                h.append("x-empty", HeaderValue::from_static(" "));
+                h.append(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+                h
+            },
+            body: b"Michael\nDilger\r\n".to_vec(),
+        }),
+        Node::Part(Part {
+            headers: {
+                let mut h = HeaderMap::new();
+                h.append(
+                    CONTENT_DISPOSITION,
+                    HeaderValue::from_static("attachment; filename=\"file.txt\""),
+                );
+                h
+            },
+            body: b"This is a file".to_vec(),
+        }),
+        // Left without headers once its empty one is dropped
+        Node::Part(Part {
+            headers: {
+                let mut h = HeaderMap::new();
+                h.append("x-empty", HeaderValue::from_static(""));
+                h
+            },
+            body: b"no headers".to_vec(),
+        }),
+        Node::Multipart((
+            {
+                let mut h = HeaderMap::new();
+                h.append(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("multipart/mixed; boundary=BbC04y"),
+                );
+                h
+            },
+            vec![Node::File(upload)],
+        )),
+    ];
+
+    let normalized = normalize(nodes).unwrap();
+    assert!(matches!(normalized[1], Node::File(_)));
+
+    let boundary = generate_boundary();
+    let mut output: Vec<u8> = Vec::new();
+    write_multipart(&mut output, &boundary, &normalized).unwrap();
+
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_TYPE,
+        multipart_content_type("form-data", &boundary).unwrap(),
+    );
+    let parsed = read_multipart_body(&mut &*output, &headers, false).unwrap();
+    assert_same_nodes(&parsed, &normalized);
+}
+
+#[test]
+fn test_many_main_headers() {
+    let mut input: Vec<u8> = Vec::new();
+    for i in 0..200 {
+        input.extend(format!("X-Header-{}: {}\r\n", i, i).as_bytes());
+    }
+    input.extend(
+        b"Content-Type: multipart/form-data; boundary=AaB03x\r\n\
+          \r\n\
+          --AaB03x\r\n\
+          Content-Disposition: form-data; name=\"submit-name\"\r\n\
+          \r\n\
+          Larry\r\n\
+          --AaB03x--",
+    );
+
+    let (headers, nodes) = read_multipart(&mut &*input, false).unwrap();
+    assert_eq!(headers.len(), 201);
+    assert_eq!(nodes.len(), 1);
+
+    match read_multipart_with(&mut &*input, &ParseProfile::hardened()) {
+        Err(Error::TooManyHeaders) => {}
+        other => panic!("expected TooManyHeaders, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_strict_data_between_parts() {
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let body = b"--AaB03x \t\r\n\
+                 Content-Disposition: form-data; name=\"files\"\r\n\
+                 Content-Type: multipart/mixed; boundary=BbC04y\r\n\
+                 \r\n\
+                 --BbC04y\r\n\
+                 Content-Disposition: file; filename=\"file1.txt\"\r\n\
+                 \r\n\
+                 ... contents of file1.txt ...\r\n\
+                 --BbC04y--\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"submit-name\"\r\n\
+                 \r\n\
+                 Larry\r\n\
+                 --AaB03x--";
+
+    // Parts following a nested multipart are not lost
+    let nodes =
+        read_multipart_body_with(&mut &body[..], &headers, &ParseProfile::strict()).unwrap();
+    assert_eq!(nodes.len(), 2);
+    if let Node::Part(ref part) = nodes[1] {
+        assert_eq!(part.body, b"Larry");
+    } else {
+        panic!("2nd node of wrong type");
+    }
+
+    let smuggled: &[&[u8]] = &[
+        b"--BbC04y--\r\nsmuggled\r\n--AaB03x\r\n",
+        b"--AaB03x smuggled\r\nContent-Disposition: form-data; name=\"submit-name\"",
+    ];
+    let needles: &[&[u8]] = &[
+        b"--BbC04y--\r\n--AaB03x\r\n",
+        b"--AaB03x\r\nContent-Disposition: form-data; name=\"submit-name\"",
+    ];
+    for (needle, replacement) in needles.iter().zip(smuggled) {
+        let pos = body
+            .windows(needle.len())
+            .position(|w| w == *needle)
+            .unwrap();
+        let mut tampered = body[..pos].to_vec();
+        tampered.extend(*replacement);
+        tampered.extend(&body[pos + needle.len()..]);
+
+        assert!(read_multipart_body(&mut &*tampered, &headers, false).is_ok());
+        match read_multipart_body_with(&mut &*tampered, &headers, &ParseProfile::strict()) {
+            Err(Error::DataBetweenParts) => {}
+            other => panic!("expected DataBetweenParts, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_disk_budget() {
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 This is a file\r\n\
+                 --AaB03x--";
+
+    let budget = DiskBudget::new(20);
+    let profile = ParseProfile {
+        disk_budget: Some(budget.clone()),
+        ..Default::default()
+    };
+
+    let first = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    assert_eq!(budget.used(), 14);
+    assert_eq!(budget.available(), 6);
+
+    // A concurrent parse does not fit in what is left
+    match read_multipart_body_with(&mut &body[..], &headers, &profile) {
+        Err(Error::DiskBudgetExceeded) => {}
+        other => panic!("expected DiskBudgetExceeded, got {:?}", other),
+    }
+    assert_eq!(budget.used(), 14);
+
+    // Dropping the file parts gives their bytes back
+    drop(first);
+    assert_eq!(budget.used(), 0);
+    assert!(read_multipart_body_with(&mut &body[..], &headers, &profile).is_ok());
+    assert_eq!(budget.used(), 0);
+}
+
+// Run a parser over `body`, fed `chunk_size` bytes at a time, collecting each part's headers
+// and body
+fn push_parse(
+    body: &[u8],
+    chunk_size: usize,
+    profile: &ParseProfile,
+) -> Result<Vec<(HeaderMap, Vec<u8>)>, Error> {
+    let mut parser = MultipartParser::with_profile(b"AaB03x", profile);
+    let mut chunks = body.chunks(chunk_size);
+    let mut parts: Vec<(HeaderMap, Vec<u8>)> = Vec::new();
+    loop {
+        match parser.next_event()? {
+            Some(Event::PartHeaders(headers)) => parts.push((headers, Vec::new())),
+            Some(Event::BodyChunk(chunk)) => parts.last_mut().unwrap().1.extend(chunk),
+            Some(Event::PartEnd) => {}
+            Some(Event::Finished) => return Ok(parts),
+            None => match chunks.next() {
+                Some(chunk) => parser.feed(chunk),
+                None => parser.feed_eof(),
+            },
+        }
+    }
+}
+
+#[test]
+fn test_push_parser() {
+    let body = b"preamble\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"field\"\r\n\
+                 \r\n\
+                 value\r\n\
+                 --AaB03x\r\n\
+                 \r\n\
+                 no headers\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 This is a file\r\n\
+                 with a --AaB0 lookalike\r\n\
+                 --AaB03x--\r\n\
+                 epilogue";
+
+    for chunk_size in [1, 2, 7, body.len()] {
+        let parts = push_parse(body, chunk_size, &ParseProfile::default()).unwrap();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(
+            parts[0].0.get(CONTENT_DISPOSITION).unwrap(),
+            "form-data; name=\"field\""
+        );
+        assert_eq!(parts[0].1, b"value");
+        assert!(parts[1].0.is_empty());
+        assert_eq!(parts[1].1, b"no headers");
+        assert_eq!(parts[2].1, b"This is a file\r\nwith a --AaB0 lookalike");
+    }
+
+    // The close delimiter may lack its line terminator when lenient
+    let body = b"--AaB03x\r\n\r\nvalue--AaB03x--\r\n";
+    assert!(matches!(
+        push_parse(body, 3, &ParseProfile::default()),
+        Err(Error::EofInPart)
+    ));
+    let parts = push_parse(body, 3, &ParseProfile::lenient()).unwrap();
+    assert_eq!(parts[0].1, b"value");
+
+    // Limits apply
+    let profile = ParseProfile {
+        limits: Limits {
+            max_part_size: Some(4),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert!(matches!(
+        push_parse(body, 1, &profile),
+        Err(Error::PartTooLarge)
+    ));
+
+    // Header blocks are kept within 1 MiB even without a limit
+    let profile = ParseProfile {
+        limits: Limits::default(),
+        ..Default::default()
+    };
+    let mut body = b"--AaB03x\r\nX-Padding: ".to_vec();
+    body.resize(body.len() + 1024 * 1024, b'a');
+    assert!(matches!(
+        push_parse(&body, 4096, &profile),
+        Err(Error::HeadersTooLarge)
+    ));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_multipart_stream() {
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"skipped\"\r\n\
+                 \r\n\
+                 not read\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 This is a file\r\n\
+                 --AaB03x--";
+
+    block_on(async {
+        let mut stream = MultipartStream::new(&body[..], &headers).unwrap();
+        assert!(stream.next_field().await.unwrap().is_some());
+
+        let mut field = stream.next_field().await.unwrap().unwrap();
+        assert_eq!(field.filename().unwrap().as_deref(), Some("a.txt"));
+        assert_eq!(field.content_type(), Some(mime::TEXT_PLAIN));
+        let mut content = Vec::new();
+        while let Some(chunk) = field.chunk().await.unwrap() {
+            content.extend(chunk);
+        }
+        assert_eq!(content, b"This is a file");
+
+        assert!(stream.next_field().await.unwrap().is_none());
+    });
+}
+
+#[cfg(feature = "headers")]
+#[test]
+fn test_typed_headers() {
+    let content_type = typed::multipart_content_type("form-data", b"AaB03x").unwrap();
+    assert_eq!(
+        typed::get_multipart_boundary(&content_type).unwrap(),
+        b"--AaB03x"
+    );
+    let plain = headers::ContentType::text();
+    assert!(matches!(
+        typed::get_multipart_boundary(&plain),
+        Err(Error::NotMultipart)
+    ));
+
+    let disposition = ContentDisposition::new(DispositionType::FormData)
+        .with_param("name", "upload")
+        .with_param("filename", "a.txt");
+    let typed_disposition = typed::to_content_disposition(&disposition).unwrap();
+    assert!(typed_disposition.is_form_data());
+    let part = typed::part(
+        headers::ContentType::text(),
+        Some(typed_disposition.clone()),
+        b"text".to_vec(),
+    );
+    assert_eq!(
+        part.headers.get(CONTENT_DISPOSITION).unwrap(),
+        "form-data; name=upload; filename=a.txt"
+    );
+    assert_eq!(part.content_type(), Some(mime::TEXT_PLAIN));
+
+    let roundtrip = typed::from_content_disposition(&typed_disposition).unwrap();
+    assert_eq!(roundtrip.name(), Some("upload"));
+    assert_eq!(roundtrip.filename(), Some("a.txt"));
+}
+
+#[cfg(feature = "lettre")]
+#[test]
+fn test_mail_multipart() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"comment\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 Hello\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 Content-ID: <a@example>\r\n\
+                 \r\n\
+                 \xff\xfe\xfd\r\n\
+                 --AaB03x--";
+    let nodes = read_multipart_body(&mut &body[..], &headers, false).unwrap();
+
+    let multipart = mail::to_multipart(lettre::message::MultiPartKind::Mixed, &nodes).unwrap();
+    let formatted = String::from_utf8(multipart.formatted()).unwrap();
+    assert!(formatted.starts_with("Content-Type: multipart/mixed;"));
+    assert!(!formatted.contains("form-data"));
+    assert!(formatted.contains("Content-Type: text/plain\r\n"));
+    assert!(formatted.contains("Content-Disposition: attachment; filename=\"a.bin\"\r\n"));
+    assert!(formatted.contains("Content-ID: <a@example>\r\n"));
+    assert!(formatted.contains("Content-Transfer-Encoding: base64\r\n"));
+    assert!(formatted.contains("//79\r\n"));
+}
+
+#[cfg(feature = "hyper014")]
+#[test]
+fn test_hyper014_headers() {
+    let mut old = http02::HeaderMap::new();
+    old.append(
+        "content-type",
+        http02::header::HeaderValue::from_static("multipart/mixed; boundary=AaB03x"),
+    );
+    let body = b"--AaB03x\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 text\r\n\
+                 --AaB03x--";
+
+    let nodes = hyper014::read_multipart_body(&mut &body[..], &old, false).unwrap();
+    let headers = match nodes[0] {
+        Node::Part(ref part) => &part.headers,
+        _ => panic!("expected a Part"),
+    };
+    let converted = hyper014::to_http02(headers).unwrap();
+    assert_eq!(
+        converted.get("content-type").unwrap().as_bytes(),
+        b"text/plain"
+    );
+    assert_eq!(&hyper014::from_http02(&converted).unwrap(), headers);
+}
+
+#[test]
+fn test_content_range() {
+    for value in ["bytes 0-499/1234", "bytes 500-999/*", "bytes */1234"] {
+        let range: ContentRange = value.parse().unwrap();
+        assert_eq!(range.to_string(), value);
+        assert_eq!(range.to_header_value(), value);
+    }
+
+    let range = ContentRange::parse(b"bytes 500-999/1234").unwrap();
+    assert_eq!(range, ContentRange::new(500, 999, 1234).unwrap());
+    assert_eq!(range.len(), 500);
+    assert_eq!(range.complete_length(), Some(1234));
+    assert!(ContentRange::parse(b"bytes */1234").unwrap().is_empty());
+
+    for value in [
+        "bytes 0-499",
+        "bytes 500-499/1234",
+        "bytes 0-1234/1234",
+        "bytes +1-2/3",
+        "bytes */*",
+        "items 0-1/2",
+        "bytes 0-18446744073709551615/*",
+    ] {
+        assert!(matches!(
+            ContentRange::parse(value.as_bytes()),
+            Err(Error::InvalidContentRange)
+        ));
+    }
+    let inverted = ContentRange::Bytes {
+        first: 10,
+        last: 0,
+        complete_length: None,
+    };
+    assert_eq!(inverted.len(), 0);
+}
+
+#[test]
+fn test_range_plan() {
+    let plan =
+        |value: &'static str, length| RangePlan::new(&HeaderValue::from_static(value), length);
+
+    assert_eq!(plan("bytes=0-499", 1234), RangePlan::Partial(vec![0..=499]));
+    assert!(!plan("bytes=0-499", 1234).is_multipart());
+    assert_eq!(
+        plan("bytes=1000-2000, -100", 1234),
+        RangePlan::Partial(vec![1000..=1233])
+    );
+    assert_eq!(
+        plan("Bytes=500-, 0-9,, 20-29", 1234),
+        RangePlan::Partial(vec![500..=1233, 0..=9, 20..=29])
+    );
+    assert!(plan("bytes=0-9, 20-29", 1234).is_multipart());
+    assert_eq!(
+        plan("bytes=0-9, 10-19, 5-12, 100-", 1234),
+        RangePlan::Partial(vec![0..=19, 100..=1233])
+    );
+    assert_eq!(
+        plan("bytes=-5000", 1234),
+        RangePlan::Partial(vec![0..=1233])
+    );
+
+    for value in ["bytes=2000-", "bytes=1234-1300", "bytes=-0"] {
+        assert_eq!(
+            plan(value, 1234),
+            RangePlan::Unsatisfiable(ContentRange::Unsatisfied {
+                complete_length: 1234
+            })
+        );
+    }
+    assert!(matches!(plan("bytes=-10", 0), RangePlan::Unsatisfiable(_)));
+
+    for value in [
+        "items=0-9",
+        "bytes=9-0",
+        "bytes=a-b",
+        "bytes=-",
+        "bytes=",
+        "bytes 0-9",
+    ] {
+        assert_eq!(plan(value, 1234), RangePlan::Full);
+    }
+    let many = (0..=RangePlan::MAX_RANGES)
+        .map(|i| format!("{}-{}", i * 2, i * 2))
+        .collect::<Vec<_>>()
+        .join(",");
+    let many = HeaderValue::from_str(&format!("bytes={}", many)).unwrap();
+    assert_eq!(RangePlan::new(&many, 1234), RangePlan::Full);
+}
+
+#[cfg(feature = "http-body")]
+#[test]
+fn test_byteranges_response() {
+    use http::header::CONTENT_RANGE;
+    use http_body::Body;
+    use std::io::Write;
+    use std::task::{Context, Poll, Waker};
+
+    // The file is read synchronously, so every frame is ready at once
+    fn collect<B: Body + Unpin>(mut body: B) -> Vec<u8>
+    where
+        B::Error: std::fmt::Debug,
+    {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut content = Vec::new();
+        while let Poll::Ready(Some(frame)) = std::pin::Pin::new(&mut body).poll_frame(&mut cx) {
+            let data = frame.unwrap().into_data().ok().unwrap();
+            content.extend(bytes::Buf::chunk(&data));
+        }
+        assert!(body.is_end_stream());
+        content
+    }
+
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(b"0123456789abcdefghij").unwrap();
+
+    let response =
+        byteranges_response(file.try_clone().unwrap(), &[2..=5], &mime::TEXT_PLAIN).unwrap();
+    assert_eq!(response.status(), http::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 2-5/20");
+    assert_eq!(response.headers()[CONTENT_LENGTH], "4");
+    assert_eq!(collect(response.into_body()), b"2345");
+
+    let response = byteranges_response(
+        file.try_clone().unwrap(),
+        &[0..=1, 18..=19],
+        &mime::TEXT_PLAIN,
+    )
+    .unwrap();
+    let (parts, body) = response.into_parts();
+    let content = collect(body);
+    assert_eq!(parts.headers[CONTENT_LENGTH], content.len().to_string());
+    let nodes = read_multipart_body(&mut &content[..], &parts.headers, false).unwrap();
+    let bodies: Vec<(&[u8], &HeaderValue)> = nodes
+        .iter()
+        .map(|node| match node {
+            Node::Part(part) => (&part.body[..], &part.headers[CONTENT_RANGE]),
+            _ => panic!("expected a Part"),
+        })
+        .collect();
+    assert_eq!(
+        bodies[0],
+        (&b"01"[..], &HeaderValue::from_static("bytes 0-1/20"))
+    );
+    assert_eq!(
+        bodies[1],
+        (&b"ij"[..], &HeaderValue::from_static("bytes 18-19/20"))
+    );
+
+    assert!(matches!(
+        byteranges_response(file, &[15..=20], &mime::TEXT_PLAIN),
+        Err(Error::InvalidContentRange)
+    ));
+}
+
+#[test]
+fn test_read_form_data() {
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-www-form-urlencoded"),
+    );
+    let body = b"name=J%C3%BCrgen+Smith&empty=&flag&odd=100%&name=again";
+    let form = read_form_data(&mut &body[..], &headers).unwrap();
+    assert_eq!(form.get("name"), Some("J\u{fc}rgen Smith"));
+    assert_eq!(form.get("empty"), Some(""));
+    assert_eq!(form.get("flag"), Some(""));
+    assert_eq!(form.get("odd"), Some("100%"));
+    assert_eq!(form.fields.len(), 5);
+
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"name\"\r\n\
+                 \r\n\
+                 J\xc3\xbcrgen Smith\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"files\"\r\n\
+                 Content-Type: multipart/mixed; boundary=BbC04y\r\n\
+                 \r\n\
+                 --BbC04y\r\n\
+                 Content-Disposition: attachment; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 first\r\n\
+                 --BbC04y\r\n\
+                 Content-Disposition: attachment; filename=\"b.txt\"\r\n\
+                 \r\n\
+                 second\r\n\
+                 --BbC04y--\r\n\
+                 --AaB03x--";
+    let form = read_form_data(&mut &body[..], &headers).unwrap();
+    assert_eq!(form.get("name"), Some("J\u{fc}rgen Smith"));
+    assert_eq!(form.files.len(), 2);
+    assert_eq!(form.files[1].0, "files");
+    assert_eq!(
+        form.get_file("files")
+            .unwrap()
+            .filename()
+            .unwrap()
+            .as_deref(),
+        Some("a.txt")
+    );
+
+    assert_eq!(form.get("Name"), None);
+    assert_eq!(form.get_ignore_case("NAME"), Some("J\u{fc}rgen Smith"));
+    assert!(form.get_file_ignore_case("Files").is_some());
+
+    let mut headers = HeaderMap::new();
+    headers.append(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    assert!(matches!(
+        read_form_data(&mut &b""[..], &headers),
+        Err(Error::NotFormData)
+    ));
+
+    // Names are matched case-insensitively beyond ASCII, and can be normalized
+    let mut form = FormData {
+        fields: vec![
+            ("\u{d6}l".to_owned(), "1".to_owned()),
+            ("Cafe\u{301}".to_owned(), "2".to_owned()),
+        ],
+        files: Vec::new(),
+    };
+    assert_eq!(form.get_ignore_case("\u{f6}L"), Some("1"));
+    assert_eq!(form.get("Caf\u{e9}"), None);
+    form.map_names(|name| name.replace("e\u{301}", "\u{e9}"));
+    assert_eq!(form.get("Caf\u{e9}"), Some("2"));
+}
+
+#[test]
+fn test_form_setters() {
+    #[derive(Default)]
+    struct Post {
+        title: String,
+        count: u32,
+        tags: Vec<String>,
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"title\"\r\n\
+                 \r\n\
+                 J\xc3\xbcrgen's post\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"ignored\"; filename=\"big.bin\"\r\n\
+                 \r\n\
+                 not kept\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"tag\"\r\n\
+                 \r\n\
+                 a\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"count\"\r\n\
+                 \r\n\
+                 42\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"tag\"\r\n\
+                 \r\n\
+                 b\r\n\
+                 --AaB03x--";
+    let mut setters = FormSetters::new()
+        .field("title", |post: &mut Post, value| {
+            post.title = String::from_utf8(value)?;
+            Ok(())
+        })
+        .field("count", |post: &mut Post, value| {
+            post.count = String::from_utf8(value)?.parse().unwrap_or(0);
+            Ok(())
+        })
+        .field("tag", |post: &mut Post, value| {
+            post.tags.push(String::from_utf8(value)?);
+            Ok(())
+        });
+    let mut post = Post::default();
+    setters
+        .read(
+            &mut &body[..],
+            &headers,
+            &ParseProfile::default(),
+            &mut post,
+        )
+        .unwrap();
+    assert_eq!(post.title, "J\u{fc}rgen's post");
+    assert_eq!(post.count, 42);
+    assert_eq!(post.tags, ["a", "b"]);
+
+    // Setter errors end the read
+    let mut setters = FormSetters::new().field("title", |_: &mut Post, _| Err(Error::NotFormData));
+    assert!(matches!(
+        setters.read(
+            &mut &body[..],
+            &headers,
+            &ParseProfile::default(),
+            &mut post
+        ),
+        Err(Error::NotFormData)
+    ));
+}
+
+#[test]
+fn test_decode_uuencoded() {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    let nodes = vec![
+        Node::Part(Part {
+            headers: headers.clone(),
+            body: b"See attached.\r\n\
+                    \r\n\
+                    begin 644 cat.txt\r\n\
+                    #0V%T\r\n\
+                    `\r\n\
+                    end\r\n\
+                    begin 644 unterminated.txt\r\n\
+                    #0V%T\r\n"
+                .to_vec(),
+        }),
+        Node::Part(Part {
+            headers: HeaderMap::new(),
+            body: b"begin 600 two lines.bin\n\
+                    M86)C9&5F9VAI:FML;6YO<'%R<W1U=G=X>7I!0D-$149'2$E*2TQ-3D]045)3\n\
+                    \"5%4`\n\
+                    `\n\
+                    end\n"
+                .to_vec(),
+        }),
+    ];
+
+    let nodes = decode_uuencoded(nodes).unwrap();
+    assert_eq!(nodes.len(), 4);
+    match (&nodes[0], &nodes[1]) {
+        (Node::Part(part), Node::File(filepart)) => {
+            assert_eq!(
+                part.body,
+                b"See attached.\r\n\r\nbegin 644 unterminated.txt\r\n#0V%T\r\n"
+            );
+            assert_eq!(filepart.filename().unwrap().as_deref(), Some("cat.txt"));
+            assert_eq!(std::fs::read(&filepart.path).unwrap(), b"Cat");
+        }
+        _ => panic!("expected a Part followed by a FilePart"),
+    }
+    match nodes[3] {
+        Node::File(ref filepart) => assert_eq!(
+            std::fs::read(&filepart.path).unwrap(),
+            b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTU"
+        ),
+        _ => panic!("expected a FilePart"),
+    }
+}
+
+#[test]
+fn test_related_resolution() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/related; boundary=AaB03x; start=\"<root@x>\""),
+    );
+    headers.insert(
+        "content-location",
+        HeaderValue::from_static("http://example.com/pages/"),
+    );
+    let part = |pairs: &[(&'static str, &'static str)], body: &[u8]| {
+        let mut headers = HeaderMap::new();
+        for &(name, value) in pairs {
+            headers.insert(name, HeaderValue::from_static(value));
+        }
+        Node::Part(Part {
+            headers,
+            body: body.to_vec(),
+        })
+    };
+    let nodes = vec![
+        part(&[("content-location", "images/a.png")], b"a"),
+        part(
+            &[
+                ("content-id", "<root@x>"),
+                ("content-location", "index.html"),
+            ],
+            b"<html>",
+        ),
+        part(
+            &[("content-location", "http://cdn.example.com/s.css")],
+            b"css",
+        ),
+        part(&[("content-id", "<img 1@x>")], b"img"),
+    ];
+    let body = |node: Option<&Node>| match node {
+        Some(Node::Part(part)) => part.body.clone(),
+        _ => panic!("expected a Part"),
+    };
+
+    let related = Related::new(&headers, &nodes);
+    assert_eq!(body(related.root()), b"<html>");
+    assert_eq!(body(related.resolve("images/a.png")), b"a");
+    assert_eq!(body(related.resolve("./images/../images/a.png")), b"a");
+    assert_eq!(body(related.resolve("/pages/images/a.png")), b"a");
+    assert_eq!(
+        body(related.resolve("http://cdn.example.com/s.css")),
+        b"css"
+    );
+    assert_eq!(body(related.resolve("cid:img%201@x")), b"img");
+    assert_eq!(body(related.by_content_id("root@x")), b"<html>");
+    assert!(related.resolve("images/b.png").is_none());
+    assert!(related
+        .resolve_from("a.png", Some("http://example.com/pages/images/"))
+        .is_some());
+}
+
+#[test]
+fn test_soap_with_attachments() {
+    let message = SwaBuilder::new(b"<soap:Envelope/>".to_vec())
+        .attachment("claim@example.com", &mime::IMAGE_PNG, b"png".to_vec())
+        .unwrap()
+        .build()
+        .unwrap();
+    let mut body = Vec::new();
+    message.write(&mut body).unwrap();
+
+    let message = SwaMessage::read(&mut &body[..], &message.headers).unwrap();
+    match message.root() {
+        Some(Node::Part(part)) => {
+            assert_eq!(part.body, b"<soap:Envelope/>");
+            assert_eq!(part.content_type().unwrap().essence_str(), "text/xml");
+        }
+        _ => panic!("expected the envelope"),
+    }
+    for reference in ["cid:claim@example.com", "<claim@example.com>"] {
+        match message.attachment(reference) {
+            Some(Node::Part(part)) => assert_eq!(part.body, b"png"),
+            _ => panic!("expected the attachment"),
+        }
+    }
+    assert!(message.attachment("cid:missing@example.com").is_none());
+}
+
+#[test]
+fn test_sha256_trailer() {
+    let digest = |data: &[u8]| {
+        let mut digest = Sha256::new();
+        digest.update(data);
+        hex_digest(digest)
+    };
+    assert_eq!(
+        digest(b""),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        digest(b"abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        digest(&[b'a'; 1_000_000]),
+        "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+    );
+
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(b"file content").unwrap();
+    let nodes = vec![
+        Node::Part(Part {
+            headers: HeaderMap::new(),
+            body: b"in memory".to_vec(),
+        }),
+        Node::File(FilePart::new(HeaderMap::new(), file.path())),
+    ];
+    let profile = WriteProfile {
+        sha256_trailer: true,
+        ..Default::default()
+    };
+    let mut plain = Vec::new();
+    write_multipart_with(&mut plain, b"AaB03x", &nodes, &profile).unwrap();
+    let mut chunked = Vec::new();
+    write_multipart_chunked_with(&mut chunked, b"AaB03x", &nodes, &profile).unwrap();
+
+    let trailer = format!("0\r\n{}: {}\r\n\r\n", SHA256_TRAILER, digest(&plain));
+    assert!(chunked.ends_with(trailer.as_bytes()));
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn test_add_manifest() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(b"abc").unwrap();
+    let mut file_headers = HeaderMap::new();
+    file_headers.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("form-data; name=\"say \\\"hi\\\"\"; filename=\"a.txt\""),
+    );
+    file_headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    let nodes = vec![
+        Node::File(FilePart::new(file_headers, file.path())),
+        Node::Part(Part {
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+        }),
+    ];
+
+    let nodes = add_manifest(nodes).unwrap();
+    assert_eq!(nodes.len(), 3);
+    match nodes[0] {
+        Node::Part(ref part) => {
+            assert_eq!(part.headers[CONTENT_TYPE], "application/json");
+            assert_eq!(
+                String::from_utf8(part.body.clone()).unwrap(),
+                "{\"parts\":[\
+                 {\"name\":\"say \\\"hi\\\"\",\"filename\":\"a.txt\",\"type\":\"text/plain\",\
+                 \"size\":3,\"sha256\":\
+                 \"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\"},\
+                 {\"name\":null,\"filename\":null,\"type\":null,\"size\":0,\"sha256\":\
+                 \"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\"}]}"
+            );
+        }
+        _ => panic!("expected the manifest Part"),
+    }
+}
+
+#[test]
+fn test_suspend_resume_parser() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 This is a file\r\n\
+                 --AaB03x--";
+    let (first, second) = body.split_at(80);
+
+    let mut parser = MultipartParser::new(b"AaB03x");
+    parser.feed(first);
+    let mut content = Vec::new();
+    while let Some(event) = parser.next_event().unwrap() {
+        match event {
+            Event::PartHeaders(_) => {}
+            Event::BodyChunk(chunk) => content.extend(chunk),
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+    assert_eq!(parser.bytes_fed(), 80);
+    assert_eq!(parser.part_bytes(), content.len() as u64);
+    let snapshot = parser.suspend();
+    drop(parser);
+
+    // Another worker takes over from byte 80
+    let mut parser = MultipartParser::resume(&snapshot, &ParseProfile::default()).unwrap();
+    assert_eq!(
+        parser.current_headers().unwrap()[CONTENT_DISPOSITION],
+        "form-data; name=\"file\"; filename=\"a.txt\""
+    );
+    parser.feed(second);
+    parser.feed_eof();
+    loop {
+        match parser.next_event().unwrap().unwrap() {
+            Event::BodyChunk(chunk) => content.extend(chunk),
+            Event::PartEnd => assert!(parser.current_headers().is_none()),
+            Event::Finished => break,
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+    assert_eq!(content, b"This is a file");
+
+    assert!(matches!(
+        MultipartParser::resume(&snapshot[..snapshot.len() - 1], &ParseProfile::default()),
+        Err(Error::InvalidParserState)
+    ));
+
+    // Mid-body, the line terminator must be known: empty the "\r\n" following the magic
+    // and the delimiter
+    let at = b"mime-multipart parser 1\n".len() + 8 + b"--AaB03x".len();
+    assert_eq!(&snapshot[at..at + 10], b"\0\0\0\0\0\0\0\x02\r\n");
+    let mut emptied = snapshot[..at].to_vec();
+    emptied.extend(0u64.to_be_bytes());
+    emptied.extend(&snapshot[at + 10..]);
+    assert!(matches!(
+        MultipartParser::resume(&emptied, &ParseProfile::default()),
+        Err(Error::InvalidParserState)
+    ));
+
+    let profile = ParseProfile {
+        limits: Limits {
+            max_boundary_len: Some(4),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+    assert!(matches!(
+        MultipartParser::resume(&snapshot, &profile),
+        Err(Error::BoundaryTooLong)
+    ));
+}
+
+#[test]
+fn test_part_spans() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"field\"\r\n\
+                 \r\n\
+                 value\r\n\
+                 --AaB03x\r\n\
+                 Content-Type: multipart/mixed; boundary=BbC04y\r\n\
+                 \r\n\
+                 --BbC04y\r\n\
+                 Content-Disposition: attachment; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 This is a file\r\n\
+                 --BbC04y--\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let (nodes, spans) =
+        read_multipart_body_spans(&mut &body[..], &headers, &ParseProfile::default()).unwrap();
+    assert_eq!(nodes.len(), 2);
+    assert_eq!(spans.len(), 2);
+    let slice = |start: u64, end: u64| &body[start as usize..end as usize];
+
+    assert_eq!(slice(spans[0].body_start, spans[0].end), b"value");
+    assert!(slice(spans[0].start, spans[0].body_start).starts_with(b"Content-Disposition"));
+
+    let nested = &spans[1];
+    assert!(slice(nested.start, nested.body_start).starts_with(b"Content-Type: multipart"));
+    assert!(slice(nested.body_start, nested.end).starts_with(b"--BbC04y\r\n"));
+    assert!(slice(nested.body_start, nested.end).ends_with(b"--BbC04y--"));
+    assert_eq!(nested.parts.len(), 1);
+    let file = &nested.parts[0];
+    assert_eq!(slice(file.body_start, file.end), b"This is a file");
+    assert!(slice(file.start, file.body_start).ends_with(b"\"a.txt\"\r\n\r\n"));
+}
+
+#[test]
+fn test_disposition_policy() {
+    let body = b"--AaB03x\r\n\
+                 Content-Type: application/json\r\n\
+                 \r\n\
+                 {\"a\":1}\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 This is a file\r\n\
+                 --AaB03x\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 unwanted\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let profile = ParseProfile {
+        disposition: DispositionPolicy::custom(|headers| {
+            let is_json = headers
+                .get(CONTENT_TYPE)
+                .is_some_and(|ct| ct == "application/json");
+            if is_json {
+                Disposition::Memory
+            } else if headers.contains_key(CONTENT_DISPOSITION) {
+                Disposition::File
+            } else {
+                Disposition::Skip
+            }
+        }),
+        ..ParseProfile::default()
+    };
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    assert_eq!(nodes.len(), 2);
+    match nodes[0] {
+        Node::Part(ref part) => assert_eq!(part.body, b"{\"a\":1}"),
+        _ => panic!("1st node of wrong type"),
+    }
+    match nodes[1] {
+        Node::File(ref filepart) => {
+            assert_eq!(std::fs::read(&filepart.path).unwrap(), b"This is a file")
+        }
+        _ => panic!("2nd node of wrong type"),
+    }
+}
+
+#[test]
+fn test_spool_rules() {
+    let cd = ContentDisposition::parse("Form-Data; name=field; filename=\"a \\\"b\\\".txt\"");
+    assert_eq!(cd.disposition, DispositionType::FormData);
+    assert_eq!(cd.name(), Some("field"));
+    assert_eq!(cd.filename(), Some("a \"b\".txt"));
+
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"attachment\"\r\n\
+                 \r\n\
+                 not a file\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: inline; filename=\"logo.png\"\r\n\
+                 \r\n\
+                 inline image\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: attachment\r\n\
+                 \r\n\
+                 unnamed attachment\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/mixed; boundary=AaB03x"),
+    );
+
+    let nodes =
+        read_multipart_body_with(&mut &body[..], &headers, &ParseProfile::default()).unwrap();
+    assert!(matches!(nodes[0], Node::Part(_)));
+    assert!(matches!(nodes[1], Node::File(_)));
+    assert!(matches!(nodes[2], Node::File(_)));
+
+    let profile = ParseProfile {
+        spool_rules: SpoolRules {
+            inline_with_filename: Disposition::Memory,
+            attachment_without_filename: Disposition::Skip,
+            ..SpoolRules::default()
+        },
+        ..ParseProfile::default()
+    };
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    assert_eq!(nodes.len(), 2);
+    match nodes[1] {
+        Node::Part(ref part) => assert_eq!(part.body, b"inline image"),
+        _ => panic!("2nd node of wrong type"),
+    }
+}
+
+#[test]
+fn test_extended_params() {
+    let cd = ContentDisposition::parse("form-data; name*=UTF-8''%C3%A9t%C3%A9; name=ete");
+    assert_eq!(cd.name(), Some("ete"));
+    assert_eq!(cd.decoded_name().as_deref(), Some("\u{e9}t\u{e9}"));
+    let cd = ContentDisposition::parse("form-data; name*=iso-8859-1'fr'%E9t%E9");
+    assert_eq!(cd.decoded_name().as_deref(), Some("\u{e9}t\u{e9}"));
+
+    // RFC 2231 continuations, encoded or not
+    let cd = ContentDisposition::parse(
+        "attachment; filename*0*=UTF-8''caf%C3%A9; filename*1=\" 100%.pdf\"",
+    );
+    assert!(cd.has_filename());
+    assert_eq!(cd.decoded_filename().as_deref(), Some("caf\u{e9} 100%.pdf"));
+
+    // Unknown charsets and bad escapes fall back to the plain parameter
+    let cd = ContentDisposition::parse("form-data; name*=KOI8-R''%C1; name=fallback");
+    assert_eq!(cd.decoded_name().as_deref(), Some("fallback"));
+    let cd = ContentDisposition::parse("form-data; name*=UTF-8''%C3");
+    assert_eq!(cd.decoded_name(), None);
+
+    // Writing uses the extended form where needed
+    let cd = ContentDisposition::new(DispositionType::FormData)
+        .with_param("name", "\u{e9}t\u{e9} 1")
+        .with_param("filename", "a \"b\".txt")
+        .with_param("size", "12");
+    let value = cd.to_header_value().unwrap();
+    assert_eq!(
+        value,
+        "form-data; name*=UTF-8''%C3%A9t%C3%A9%201; filename=\"a \\\"b\\\".txt\"; size=12"
+    );
+    let parsed = ContentDisposition::from_header(&value).unwrap();
+    assert_eq!(parsed.decoded_name().as_deref(), Some("\u{e9}t\u{e9} 1"));
+    assert_eq!(parsed.decoded_filename().as_deref(), Some("a \"b\".txt"));
+
+    // Form fields are read by their decoded names
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name*=UTF-8''%C3%A9t%C3%A9\r\n\
+                 \r\n\
+                 summer\r\n\
+                 --AaB03x--";
+    let form = read_form_data(&mut &body[..], &headers).unwrap();
+    assert_eq!(form.get("\u{e9}t\u{e9}"), Some("summer"));
+}
+
+#[test]
+fn test_empty_filename() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"title\"\r\n\
+                 \r\n\
+                 Holiday\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"photo\"; filename=\"\"\r\n\
+                 Content-Type: application/octet-stream\r\n\
+                 \r\n\
+                 \r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let nodes = read_multipart_body(&mut &body[..], &headers, false).unwrap();
+    assert!(matches!(nodes[1], Node::File(_)));
+
+    let mut profile = ParseProfile::default();
+    profile.spool_rules.empty_filename = Disposition::Memory;
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    match nodes[1] {
+        Node::Part(ref part) => assert!(part.body.is_empty()),
+        _ => panic!("2nd node of wrong type"),
+    }
+
+    profile.spool_rules.empty_filename = Disposition::Skip;
+    let form = read_form_data_with(&mut &body[..], &headers, &profile).unwrap();
+    assert_eq!(form.get("title"), Some("Holiday"));
+    assert!(form.get_file("photo").is_none());
+    assert!(form.get("photo").is_none());
+}
+
+#[test]
+fn test_html_names() {
+    assert_eq!(unescape_html("a%22b%0D%0Ac"), "a\"b\r\nc");
+    assert_eq!(
+        unescape_html("&#8364;&#x41; 100% &amp; %41"),
+        "\u{20ac}A 100% &amp; %41"
+    );
+
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"say %22hi%22\"\r\n\
+                 \r\n\
+                 hi\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let form = read_form_data(&mut &body[..], &headers).unwrap();
+    assert_eq!(form.get("say %22hi%22"), Some("hi"));
+
+    let profile = ParseProfile {
+        html_names: true,
+        ..ParseProfile::default()
+    };
+    let form = read_form_data_with(&mut &body[..], &headers, &profile).unwrap();
+    assert_eq!(form.get("say \"hi\""), Some("hi"));
+}
+
+#[test]
+fn test_original_filenames() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"a\"; filename=\"../../etc/clip:1.mp4\"\r\n\
+                 \r\n\
+                 video\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"b\"; filename=\"..\"\r\n\
+                 \r\n\
+                 dots\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let profile = ParseProfile {
+        original_filenames: true,
+        ..ParseProfile::default()
+    };
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    let paths: Vec<PathBuf> = nodes
+        .iter()
+        .map(|node| match *node {
+            Node::File(ref filepart) => filepart.path.clone(),
+            _ => panic!("node of wrong type"),
+        })
+        .collect();
+    assert_eq!(paths[0].file_name().unwrap(), "clip_1.mp4");
+    assert_eq!(std::fs::read(&paths[0]).unwrap(), b"video");
+    assert_ne!(paths[1].file_name().unwrap(), "..");
+    assert_ne!(paths[0].parent(), paths[1].parent());
+
+    drop(nodes);
+    assert!(!paths[0].exists());
+    assert!(!paths[0].parent().unwrap().exists());
+}
+
+#[test]
+fn test_dedup_files() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"a\"; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 same content\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"b\"; filename=\"b.txt\"\r\n\
+                 \r\n\
+                 other content\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"c\"; filename=\"c.txt\"\r\n\
+                 \r\n\
+                 same content\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let budget = DiskBudget::new(1024);
+    let profile = ParseProfile {
+        dedup_files: true,
+        disk_budget: Some(budget.clone()),
+        ..ParseProfile::default()
+    };
+    let mut nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    assert_eq!(budget.used(), 25);
+
+    let (a, c) = match (&nodes[0], &nodes[2]) {
+        (Node::File(a), Node::File(c)) => (a.path.clone(), c.path.clone()),
+        _ => panic!("nodes of wrong type"),
+    };
+    assert_ne!(a, c);
+    assert_eq!(std::fs::read(&c).unwrap(), b"same content");
+
+    // Each part still owns its path
+    nodes.remove(0);
+    assert!(!a.exists());
+    assert_eq!(std::fs::read(&c).unwrap(), b"same content");
+}
+
+#[test]
+fn test_stream_until_delimiter() {
+    let input = b"line one\r\nline two\r\r\n--AaB03x--";
+    let mut reader = BufReader::with_capacity(4, &input[..]);
+    let mut out = Vec::new();
+    let (copied, found) = stream_until_delimiter(&mut reader, b"AaB03x", &mut out).unwrap();
+    assert!(found);
+    assert_eq!(out, b"line one\r\nline two\r");
+    assert_eq!(copied, out.len());
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"--");
+
+    let input = b"bare\n--AaB03x\n";
+    let mut out = Vec::new();
+    let (_, found) = stream_until_delimiter(&mut &input[..], b"AaB03x", &mut out).unwrap();
+    assert!(found);
+    assert_eq!(out, b"bare");
+
+    let input = b"no delimiter\r";
+    let mut out = Vec::new();
+    let (copied, found) = stream_until_delimiter(&mut &input[..], b"AaB03x", &mut out).unwrap();
+    assert!(!found);
+    assert_eq!(out, b"no delimiter\r");
+    assert_eq!(copied, 13);
+
+    let mut out = Vec::new();
+    let (copied, found) = stream_until_token(&mut &b"a::b"[..], b"::", &mut out).unwrap();
+    assert_eq!((copied, found, &out[..]), (1, true, &b"a"[..]));
+}
+
+#[test]
+fn test_header_helpers() {
+    let mut headers = HeaderMap::new();
+    assert!(!is_multipart(&headers));
+    assert_eq!(content_length(&headers), None);
+
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("Multipart/Form-Data; boundary=AaB03x"),
+    );
+    headers.insert(CONTENT_LENGTH, HeaderValue::from_static("1234"));
+    assert!(is_multipart(&headers));
+    assert_eq!(multipart_subtype(&headers).as_deref(), Some("form-data"));
+    assert_eq!(content_length(&headers), Some(1234));
+
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    headers.insert(CONTENT_LENGTH, HeaderValue::from_static("+12"));
+    assert!(!is_multipart(&headers));
+    assert_eq!(multipart_subtype(&headers), None);
+    assert_eq!(content_length(&headers), None);
+}
+
+#[test]
+fn test_error_category() {
+    let mut headers = HeaderMap::new();
+    let body = b"--AaB03x\r\nContent-Type: text/plain\r\n\r\nLarry\r\n--AaB03x--";
+    let parse = |headers: &HeaderMap, profile: &ParseProfile| {
+        read_multipart_body_with(&mut &body[..], headers, profile).unwrap_err()
+    };
+
+    let err = parse(&headers, &ParseProfile::default());
+    assert_eq!(err.category(), ErrorCategory::Header);
+
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let profile = ParseProfile {
+        limits: Limits {
+            max_part_size: Some(2),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+    assert_eq!(parse(&headers, &profile).category(), ErrorCategory::Limit);
+
+    let err = read_multipart_body(&mut &body[..10], &headers, false).unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::Parse);
+}
+
+#[test]
+fn test_redacted_error() {
+    let err = Error::FieldSizeExceeded("ssn".to_owned());
+    assert_eq!(err.to_string(), "FieldSizeExceeded: ssn");
+    let redacted = err.redacted().to_string();
+    assert!(!redacted.contains("ssn"));
+    assert_eq!(redacted, "FieldSizeExceeded: #efa5ff7e");
+    assert_eq!(
+        Error::FieldSizeExceeded("ssn".to_owned())
+            .redacted()
+            .to_string(),
+        redacted
+    );
+    assert_eq!(Error::PartTooLarge.redacted().to_string(), "PartTooLarge");
+}
+
+#[test]
+fn test_dynamic_source() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"field\"\r\n\
+                 \r\n\
+                 value\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let mut reader: Box<dyn Read> = Box::new(&body[..]);
+    let mut source: Box<dyn MultipartSource> =
+        Box::new(MultipartReader::new(&mut *reader, &headers, &ParseProfile::default()).unwrap());
+    let mut body_bytes = Vec::new();
+    let mut events = 0;
+    while let Some(event) = source.next_event().unwrap() {
+        if let Event::BodyChunk(chunk) = event {
+            body_bytes.extend(chunk);
+        }
+        events += 1;
+    }
+    assert_eq!(body_bytes, b"value");
+    assert!(events >= 4);
+    drop(source);
+
+    // The reading functions accept trait objects too
+    let mut slice = &body[..];
+    let stream: &mut dyn Read = &mut slice;
+    let nodes = read_multipart_body(stream, &headers, false).unwrap();
+    assert_eq!(nodes.len(), 1);
+}
+
+#[test]
+fn test_large_spooled_file() {
+    let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let mut body = b"--AaB03x\r\n\
+                     Content-Disposition: form-data; name=\"file\"; filename=\"big.bin\"\r\n\
+                     \r\n"
+        .to_vec();
+    body.extend(&content);
+    body.extend(b"\r\n--AaB03x--");
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let nodes = read_multipart_body(&mut &body[..], &headers, false).unwrap();
+    match nodes[0] {
+        Node::File(ref filepart) => {
+            assert_eq!(filepart.size, Some(content.len()));
+            assert_eq!(std::fs::read(&filepart.path).unwrap(), content);
+        }
+        _ => panic!("1st node of wrong type"),
+    }
+}
+
+#[test]
+fn test_sync_files() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 This is a file\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let profile = ParseProfile {
+        sync_files: true,
+        ..ParseProfile::default()
+    };
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    match nodes[0] {
+        Node::File(ref filepart) => {
+            assert_eq!(std::fs::read(&filepart.path).unwrap(), b"This is a file")
+        }
+        _ => panic!("1st node of wrong type"),
+    }
+}
+
+#[test]
+fn test_spool_file_collision() {
+    let mut filepart = FilePart::create(HeaderMap::new()).unwrap();
+    let taken = filepart.path.clone();
+    std::fs::write(&taken, b"someone else's").unwrap();
+
+    let mut file = filepart.create_file().unwrap();
+    file.write_all(b"ours").unwrap();
+    assert_ne!(filepart.path, taken);
+    assert_eq!(filepart.path.parent(), taken.parent());
+    assert_eq!(std::fs::read(&taken).unwrap(), b"someone else's");
+    assert_eq!(std::fs::read(&filepart.path).unwrap(), b"ours");
+    std::fs::remove_file(&taken).unwrap();
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_bytes_chunks() {
+    use bytes::Bytes;
+
+    let body: &[u8] = b"--AaB03x\r\n\
+                        Content-Disposition: form-data; name=\"field\"\r\n\
+                        \r\n\
+                        value\r\n\
+                        --AaB03x\r\n\
+                        Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                        \r\n\
+                        This is a file\r\n\
+                        --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    // Split everywhere, including between the CR and LF following a boundary
+    for size in 1..12 {
+        let mut chunks: BytesChunks = body.chunks(size).map(Bytes::copy_from_slice).collect();
+        let nodes =
+            read_multipart_body_buffered(&mut chunks, &headers, &ParseProfile::default()).unwrap();
+        assert_eq!(nodes.len(), 2);
+        match nodes[0] {
+            Node::Part(ref part) => assert_eq!(part.body, b"value"),
+            _ => panic!("1st node of wrong type"),
+        }
+        match nodes[1] {
+            Node::File(ref filepart) => {
+                assert_eq!(std::fs::read(&filepart.path).unwrap(), b"This is a file")
+            }
+            _ => panic!("2nd node of wrong type"),
+        }
+    }
+}
+
+#[cfg(feature = "http-body")]
+#[test]
+fn test_to_stream_body() {
+    use futures_core::Stream;
+    use std::task::{Context, Poll, Waker};
+
+    let mut field = HeaderMap::new();
+    field.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("form-data; name=\"field\""),
+    );
+    let mut file = HeaderMap::new();
+    file.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("form-data; name=\"file\"; filename=\"big.bin\""),
+    );
+    let mut filepart = FilePart::create(file).unwrap();
+    let content: Vec<u8> = (0..150_000u32).map(|i| (i % 253) as u8).collect();
+    std::fs::write(&filepart.path, &content).unwrap();
+    filepart.size = Some(content.len());
+    let mut nested = HeaderMap::new();
+    nested.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/mixed; boundary=BbC04y"),
+    );
+    let nodes = vec![
+        Node::Part(Part {
+            headers: field.clone(),
+            body: b"value".to_vec(),
+        }),
+        Node::File(filepart),
+        Node::Multipart((
+            nested,
+            vec![Node::Part(Part {
+                headers: field,
+                body: b"inner".to_vec(),
+            })],
+        )),
+    ];
+    let mut expected = Vec::new();
+    write_multipart(&mut expected, b"AaB03x", &nodes).unwrap();
+
+    // The files are read synchronously, so every frame is ready at once
+    let mut stream = to_stream_body(b"AaB03x", nodes).unwrap();
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut body = Vec::new();
+    let mut frames = 0;
+    while let Poll::Ready(Some(frame)) = std::pin::Pin::new(&mut stream).poll_next(&mut cx) {
+        body.extend(frame.unwrap().into_data().ok().unwrap());
+        frames += 1;
+    }
+    assert_eq!(body, expected);
+    assert!(frames > 3);
+}
+
+#[cfg(feature = "http-body")]
+#[test]
+fn test_stream_body_trailers() {
+    use futures_core::Stream;
+    use std::task::{Context, Poll, Waker};
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    let nodes = vec![Node::Part(Part {
+        headers,
+        body: b"abc".to_vec(),
+    })];
+    let mut expected = Vec::new();
+    write_multipart(&mut expected, b"AaB03x", &nodes).unwrap();
+
+    let mut stream = to_stream_body(b"AaB03x", nodes)
+        .unwrap()
+        .with_trailers(|info| {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("x-length", HeaderValue::from(info.length));
+            trailers.insert(
+                "x-content-sha256",
+                HeaderValue::from_str(&info.sha256).unwrap(),
+            );
+            trailers
+        });
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut body = Vec::new();
+    let mut trailers = None;
+    while let Poll::Ready(Some(frame)) = std::pin::Pin::new(&mut stream).poll_next(&mut cx) {
+        match frame.unwrap().into_data() {
+            Ok(data) => body.extend(data),
+            Err(frame) => trailers = Some(frame.into_trailers().ok().unwrap()),
+        }
+    }
+    assert_eq!(body, expected);
+    let trailers = trailers.unwrap();
+    assert_eq!(trailers["x-length"], expected.len().to_string().as_str());
+    let mut digest = Sha256::new();
+    digest.update(&expected);
+    assert_eq!(trailers["x-content-sha256"], hex_digest(digest).as_str());
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_body_builder() {
+    use testing::{BodyBuilder, Corruption, LineEnding};
+
+    let builder = BodyBuilder::new().field("submit-name", "Larry").file(
+        "file",
+        "a.txt",
+        "text/plain",
+        b"This is a file",
+    );
+    let headers = builder.headers();
+    let nodes = read_multipart_body(&mut &builder.build()[..], &headers, false).unwrap();
+    assert_eq!(nodes.len(), 2);
+
+    let body = builder.clone().line_ending(LineEnding::Lf).build();
+    assert!(!body.contains(&b'\r'));
+    let nodes = read_multipart_body(&mut &body[..], &headers, false).unwrap();
+    assert_eq!(nodes.len(), 2);
+
+    let body = builder.clone().final_boundary(false).build();
+    assert!(body.ends_with(b"This is a file"));
+    assert!(read_multipart_body(&mut &body[..], &headers, false).is_err());
+
+    let body = builder
+        .clone()
+        .corrupt(Corruption::NoLineBeforeClose)
+        .build();
+    assert!(body.ends_with(b"This is a file--AaB03x--"));
+    assert!(read_multipart_body(&mut &body[..], &headers, false).is_err());
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &ParseProfile::lenient());
+    assert_eq!(nodes.unwrap().len(), 2);
+
+    let body = builder.corrupt(Corruption::Truncate(20)).build();
+    assert_eq!(body.len(), 20);
+}
+
+#[test]
+fn test_multipart_subtype() {
+    let body = b"--AaB03x\r\n\
+                 Content-Type: multipart/parallel; boundary=BbC04y\r\n\
+                 \r\n\
+                 --BbC04y\r\n\
+                 Content-Type: audio/basic\r\n\
+                 \r\n\
+                 sound\r\n\
+                 --BbC04y\r\n\
+                 Content-Type: image/jpeg\r\n\
+                 \r\n\
+                 image\r\n\
+                 --BbC04y--\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/mixed; boundary=AaB03x"),
+    );
+    assert_eq!(
+        MultipartSubtype::from_headers(&headers),
+        Some(MultipartSubtype::Mixed)
+    );
+
+    let nodes = read_multipart_body(&mut &body[..], &headers, false).unwrap();
+    let subtype = nodes[0].multipart_subtype().unwrap();
+    assert_eq!(subtype, MultipartSubtype::Parallel);
+    assert!(!subtype.is_ordered());
+    assert_eq!(subtype.to_string(), "parallel");
+
+    assert_eq!(
+        MultipartSubtype::from_name("X-Custom"),
+        MultipartSubtype::Other("x-custom".to_owned())
+    );
+    assert_eq!(
+        MultipartSubtype::Digest.default_part_type().essence_str(),
+        "message/rfc822"
+    );
+}
+
+#[cfg(feature = "zeroize")]
+#[test]
+fn test_zeroize() {
+    // Read a byte at a time, so that the body buffer is grown many times
+    struct Trickle<'a>(&'a [u8]);
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    let secret = "hunter2".repeat(100);
+    let body = format!(
+        "--AaB03x\r\n\
+         Content-Disposition: form-data; name=\"password\"\r\n\
+         \r\n\
+         {}\r\n\
+         --AaB03x--",
+        secret
+    );
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let nodes = read_multipart_body(&mut Trickle(body.as_bytes()), &headers, false).unwrap();
+    match nodes[0] {
+        Node::Part(ref part) => assert_eq!(part.body, secret.as_bytes()),
+        _ => panic!("Expected a part"),
+    }
+    let form = read_form_data(&mut Trickle(body.as_bytes()), &headers).unwrap();
+    assert_eq!(form.fields[0].1, secret);
+
+    let mut buf = secret.into_bytes();
+    buf.truncate(7);
+    let capacity = buf.capacity();
+    crate::wipe::wipe(&mut buf);
+    assert!(buf.is_empty());
+    // SAFETY: wipe() has initialized the whole capacity
+    unsafe { buf.set_len(capacity) };
+    assert!(buf.iter().all(|&b| b == 0));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_shred_files() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"key.pem\"\r\n\
+                 \r\n\
+                 PRIVATE KEY\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let profile = ParseProfile {
+        shred_files: true,
+        ..ParseProfile::default()
+    };
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    let (path, mut file) = match nodes[0] {
+        Node::File(ref filepart) => (filepart.path.clone(), File::open(&filepart.path).unwrap()),
+        _ => panic!("1st node of wrong type"),
+    };
+    drop(nodes);
+    assert!(!path.exists());
+
+    // The open handle still reaches the unlinked file, which was overwritten
+    let mut content = Vec::new();
+    file.read_to_end(&mut content).unwrap();
+    assert_eq!(content, vec![0; 11]);
+}
+
+#[test]
+fn test_filepart_close() {
+    let mut filepart = FilePart::create(HeaderMap::new()).unwrap();
+    filepart.create_file().unwrap();
+    let dir = filepart.path.parent().unwrap().to_owned();
+    filepart.close().unwrap();
+    assert!(!dir.exists());
+
+    // Something else left in the directory keeps it from being deleted
+    let mut filepart = FilePart::create(HeaderMap::new()).unwrap();
+    filepart.create_file().unwrap();
+    let dir = filepart.path.parent().unwrap().to_owned();
+    std::fs::write(dir.join("stray"), b"").unwrap();
+    assert!(matches!(filepart.close(), Err(Error::Io(_))));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let failed = Arc::new(Mutex::new(Vec::new()));
+    let record = failed.clone();
+    let mut filepart = FilePart::create(HeaderMap::new()).unwrap();
+    filepart.set_cleanup_hook(CleanupHook::new(move |path, _| {
+        record.lock().unwrap().push(path.to_owned())
+    }));
+    filepart.create_file().unwrap();
+    let path = filepart.path.clone();
+    let dir = path.parent().unwrap().to_owned();
+    std::fs::write(dir.join("stray"), b"").unwrap();
+    drop(filepart);
+    assert!(!path.exists());
+    assert_eq!(*failed.lock().unwrap(), vec![path]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_file_part_builder() {
+    let spool = tempfile::tempdir().unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment; filename=\"../report.pdf\""),
+    );
+
+    let builder = FilePartBuilder::new()
+        .dir(spool.path())
+        .name(SpoolName::Original)
+        .preallocate(4096);
+    let (filepart, _) = builder.build(headers.clone()).unwrap();
+    assert!(filepart.path.starts_with(spool.path()));
+    assert_eq!(filepart.path.file_name().unwrap(), "report.pdf");
+    assert_eq!(std::fs::metadata(&filepart.path).unwrap().len(), 4096);
+    let dir = filepart.path.parent().unwrap().to_owned();
+    drop(filepart);
+    assert!(!dir.exists());
+
+    let builder = FilePartBuilder::new()
+        .dir(spool.path())
+        .name(SpoolName::Given("kept".to_owned()))
+        .delete_on_drop(false);
+    let (filepart, _) = builder.build(headers).unwrap();
+    let path = filepart.path.clone();
+    drop(filepart);
+    assert!(path.exists());
+    assert_eq!(path.file_name().unwrap(), "kept");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let (filepart, _) = FilePartBuilder::new()
+            .mode(0o600)
+            .build(HeaderMap::new())
+            .unwrap();
+        let mode = std::fs::metadata(&filepart.path)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // Parsed files are spooled as the profile's builder says
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 This is a file\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let profile = ParseProfile {
+        spool: FilePartBuilder::new().dir(spool.path()),
+        ..ParseProfile::default()
+    };
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    match nodes[0] {
+        Node::File(ref filepart) => assert!(filepart.path.starts_with(spool.path())),
+        _ => panic!("1st node of wrong type"),
+    }
+}
+
+#[test]
+fn test_sniff_boundary() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"field\"\r\n\
+                 \r\n\
+                 value\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data"),
+    );
+    assert!(matches!(
+        read_multipart_body(&mut &body[..], &headers, false),
+        Err(Error::BoundaryNotSpecified)
+    ));
+
+    let profile = ParseProfile {
+        sniff_boundary: true,
+        ..ParseProfile::default()
+    };
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    match nodes[0] {
+        Node::Part(ref part) => assert_eq!(part.body, b"value"),
+        _ => panic!("1st node of wrong type"),
+    }
+
+    // A body not starting with a boundary line still fails
+    let preamble = [&b"preamble\r\n"[..], &body[..]].concat();
+    assert!(matches!(
+        read_multipart_body_with(&mut &preamble[..], &headers, &profile),
+        Err(Error::BoundaryNotSpecified)
+    ));
+
+    assert_eq!(
+        sniff_boundary(b"--a'b(c) \t\r\n").as_deref(),
+        Some("a'b(c)")
+    );
+    assert_eq!(sniff_boundary(b"--\r\n"), None);
+    assert_eq!(sniff_boundary(b"--a;b\r\n"), None);
+    assert_eq!(sniff_boundary(b"--unterminated"), None);
+}
+
+#[test]
+fn test_multiple_content_types() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"field\"\r\n\
+                 \r\n\
+                 value\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.append(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    headers.append(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    assert!(matches!(
+        read_multipart_body(&mut &body[..], &headers, false),
+        Err(Error::NotMultipart)
+    ));
+
+    let mut profile = ParseProfile {
+        content_type_choice: ContentTypeChoice::FirstMultipart,
+        ..ParseProfile::default()
+    };
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    assert_eq!(nodes.len(), 1);
+
+    profile.content_type_choice = ContentTypeChoice::Reject;
+    assert!(matches!(
+        read_multipart_body_with(&mut &body[..], &headers, &profile),
+        Err(Error::ConflictingContentTypes)
+    ));
+
+    // Repeats of the same value are not a conflict
+    let mut headers = HeaderMap::new();
+    for _ in 0..2 {
+        headers.append(
+            CONTENT_TYPE,
+            HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+        );
+    }
+    assert!(read_multipart_body_with(&mut &body[..], &headers, &profile).is_ok());
+}
+
+#[test]
+fn test_header_scratch_reuse() {
+    let extra: String = (0..20)
+        .map(|i| format!("X-Extra-{}: {}\r\n", i, i))
+        .collect();
+    let body = format!(
+        "--AaB03x\r\n\
+         Content-Disposition: form-data; name=\"a\"\r\n\
+         {extra}\r\n\
+         a\r\n\
+         --AaB03x\r\n\
+         Content-Type: multipart/mixed; boundary=BbC04y\r\n\
+         \r\n\
+         --BbC04y\r\n\
+         Content-Type: text/plain\r\n\
+         {extra}\r\n\
+         b\r\n\
+         --BbC04y--\r\n\
+         --AaB03x\r\n\
+         Content-Disposition: form-data; name=\"c\"\r\n\
+         \r\n\
+         c\r\n\
+         --AaB03x--",
+        extra = extra
+    );
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    // Without a limit, parts keep to the 4 headers they always could have
+    assert!(matches!(
+        read_multipart_body(&mut body.as_bytes(), &headers, false),
+        Err(Error::Httparse(err)) if err.kind() == httparse::Error::TooManyHeaders
+    ));
+
+    let profile = ParseProfile {
+        limits: Limits {
+            max_part_headers: Some(64),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+    let nodes = read_multipart_body_with(&mut body.as_bytes(), &headers, &profile).unwrap();
+    assert_eq!(nodes.len(), 3);
+    assert_eq!(nodes[0].headers().len(), 21);
+    match nodes[1] {
+        Node::Multipart((_, ref subnodes)) => assert_eq!(subnodes[0].headers().len(), 21),
+        _ => panic!("2nd node of wrong type"),
+    }
+    assert_eq!(nodes[2].headers().len(), 1);
+
+    // The scratch space grown for many headers is kept
+    let memory = recycle(vec![httparse::EMPTY_HEADER; 32]);
+    assert!(memory.is_empty());
+    assert!(memory.capacity() >= 32);
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn test_log_records() {
+    struct Recorder;
+    static RECORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    impl log::Log for Recorder {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+        fn log(&self, record: &log::Record) {
+            RECORDS.lock().unwrap().push(record.args().to_string());
+        }
+        fn flush(&self) {}
+    }
+    log::set_logger(&Recorder).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let body = b"--LogB0undary\r\n\
+                 Content-Disposition: form-data; name=\"empty\"\r\n\
+                 \r\n\
+                 \r\n\
+                 --LogB0undary--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=LogB0undary"),
+    );
+    read_multipart_body(&mut &body[..], &headers, false).unwrap();
+
+    let records = RECORDS.lock().unwrap();
+    let found = |text: &str| records.iter().any(|record| record.contains(text));
+    assert!(found("boundary \"LogB0undary\" found at depth 0"));
+    assert!(found("(Some(\"empty\")) at depth 0: Memory"));
+    assert!(found(": 0 bytes in memory"));
+    assert!(found("close delimiter at depth 0"));
+}
+
+#[test]
+fn test_multipart_iter() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"token\"\r\n\
+                 \r\n\
+                 abc\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 This is a file\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"broken\"\r\n\
+                 \r\n\
+                 never terminated";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let profile = ParseProfile::default();
+
+    // Stopping early never reaches the broken part
+    let mut iter = MultipartIter::new(&body[..], &headers, &profile).unwrap();
+    let token = iter
+        .find(|node| {
+            let node = node.as_ref().unwrap();
+            let name = get_content_disposition_name(&node.headers()[CONTENT_DISPOSITION]);
+            name.unwrap() == "token"
+        })
+        .unwrap()
+        .unwrap();
+    match token {
+        Node::Part(ref part) => assert_eq!(part.body, b"abc"),
+        _ => panic!("Expected a part"),
+    }
+
+    let mut iter = MultipartIter::new(&body[..], &headers, &profile).unwrap();
+    assert!(matches!(iter.next(), Some(Ok(Node::Part(_)))));
+    assert!(matches!(iter.next(), Some(Ok(Node::File(_)))));
+    assert!(matches!(iter.next(), Some(Err(Error::EofInPart))));
+    assert!(iter.next().is_none());
+
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"token\"\r\n\
+                 \r\n\
+                 abc\r\n\
+                 --AaB03x--";
+    let iter = MultipartIter::new(&body[..], &headers, &profile).unwrap();
+    assert_eq!(iter.map(Result::unwrap).count(), 1);
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn test_json_form() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"pet[species]\"\r\n\
+                 \r\n\
+                 Dahut\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"pet[tags][]\"\r\n\
+                 \r\n\
+                 a\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"pet[tags][]\"\r\n\
+                 \r\n\
+                 b\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"bad[\"\r\n\
+                 \r\n\
+                 c\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"photo\"; filename=\"a.txt\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 hello\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let form = read_form_data(&mut &body[..], &headers).unwrap();
+
+    let json = form.to_json_form(JsonFiles::Base64).unwrap();
+    assert_eq!(
+        json.to_string(),
+        "{\"pet\":{\"species\":\"Dahut\",\"tags\":[\"a\",\"b\"]},\"bad[\":\"c\",\
+         \"photo\":{\"type\":\"text/plain\",\"name\":\"a.txt\",\"body\":\"aGVsbG8=\"}}"
+    );
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&json.to_string()).unwrap(),
+        json
+    );
+
+    let parsed = FormData::from_json_form(&json).unwrap();
+    assert_eq!(parsed.get("pet[species]"), Some("Dahut"));
+    let tags: Vec<&str> = parsed
+        .fields
+        .iter()
+        .filter(|(name, _)| name == "pet[tags]")
+        .map(|(_, value)| &**value)
+        .collect();
+    assert_eq!(tags, ["a", "b"]);
+    assert_eq!(parsed.get("bad["), Some("c"));
+    let photo = parsed.get_file("photo").unwrap();
+    assert_eq!(std::fs::read(&photo.path).unwrap(), b"hello");
+    assert_eq!(photo.filename().unwrap(), Some("a.txt".to_owned()));
+    assert_eq!(parsed.to_json_form(JsonFiles::Base64).unwrap(), json);
+
+    // References to files on the host are not taken from clients
+    let json = form.to_json_form(JsonFiles::Reference).unwrap();
+    assert!(matches!(
+        FormData::from_json_form(&json),
+        Err(Error::InvalidJson)
+    ));
+}
+
+#[test]
+fn test_duplicate_part_headers() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"first\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 Content-Disposition: form-data; name=\"second\"; filename=\"a.exe\"\r\n\
+                 \r\n\
+                 abc\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let nodes = read_multipart_body(&mut &body[..], &headers, false).unwrap();
+    match nodes[0] {
+        Node::Part(ref part) => {
+            let values: Vec<_> = part.headers.get_all(CONTENT_DISPOSITION).iter().collect();
+            assert_eq!(values, ["form-data; name=\"first\""]);
+        }
+        _ => panic!("Expected a part"),
+    }
+
+    let profile = ParseProfile {
+        mode: ParseMode::Strict,
+        ..ParseProfile::default()
+    };
+    match read_multipart_body_with(&mut &body[..], &headers, &profile) {
+        Err(Error::DuplicateHeader(name)) => assert_eq!(name, "content-disposition"),
+        other => panic!("Expected DuplicateHeader, got {:?}", other),
+    }
+    let mut parser = MultipartParser::from_headers(&headers, &profile).unwrap();
+    parser.feed(body);
+    parser.feed_eof();
+    let result = loop {
+        match parser.next_event() {
+            Ok(Some(_)) => continue,
+            result => break result,
+        }
+    };
+    assert!(matches!(result, Err(Error::DuplicateHeader(_))));
+}
+
+#[test]
+fn test_crlf_encoder() {
+    let body = b"preamble\n\
+                 --AaB03x\n\
+                 Content-Disposition: form-data; name=\"a\"\n\
+                 \n\
+                 one\ntwo\n\
+                 --AaB03x\n\
+                 Content-Type: multipart/mixed; boundary=inner\n\
+                 \n\
+                 --inner\n\
+                 Content-Type: text/plain\n\
+                 \n\
+                 x\n\
+                 --inner--\n\
+                 --AaB03x--\n";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+
+    let mut encoder = CrlfEncoder::new(&headers, Vec::new()).unwrap();
+    for chunk in body.chunks(5) {
+        encoder.write_all(chunk).unwrap();
+    }
+    let output = encoder.finish().unwrap();
+    assert_eq!(
+        std::str::from_utf8(&output).unwrap(),
+        "--AaB03x\r\n\
+         content-disposition: form-data; name=\"a\"\r\n\
+         \r\n\
+         one\ntwo\r\n\
+         --AaB03x\r\n\
+         content-type: multipart/mixed; boundary=inner\r\n\
+         \r\n\
+         --inner\r\n\
+         content-type: text/plain\r\n\
+         \r\n\
+         x\r\n\
+         --inner--\r\n\
+         --AaB03x--"
+    );
+
+    // Canonical input comes out the same
+    let mut encoder = CrlfEncoder::new(&headers, Vec::new()).unwrap();
+    encoder.encode(&output).unwrap();
+    assert_eq!(encoder.finish().unwrap(), output);
+
+    let mut encoder = CrlfEncoder::new(&headers, Vec::new()).unwrap();
+    encoder.encode(&body[..40]).unwrap();
+    assert!(encoder.finish().is_err());
+}
+
+#[test]
+fn test_spill_threshold() {
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"small\"\r\n\
+                 \r\n\
+                 abc\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"large\"\r\n\
+                 \r\n\
+                 This field is much too large to keep in memory\r\n\
+                 --AaB03x--";
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let profile = ParseProfile {
+        spill_threshold: Some(8),
+        ..ParseProfile::default()
+    };
+
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    assert!(matches!(nodes[0], Node::Part(ref part) if part.body == b"abc"));
+    match nodes[1] {
+        Node::File(ref filepart) => {
+            assert_eq!(filepart.size, Some(46));
+            assert_eq!(
+                std::fs::read(&filepart.path).unwrap(),
+                b"This field is much too large to keep in memory"
+            );
+        }
+        _ => panic!("Expected a file"),
+    }
+
+    // Spilled parts are still fields as far as the limits go
+    let profile = ParseProfile {
+        spill_threshold: Some(8),
+        limits: Limits {
+            max_field_size: Some(16),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+    assert!(matches!(
+        read_multipart_body_with(&mut &body[..], &headers, &profile),
+        Err(Error::FieldTooLarge)
+    ));
+}
+
+#[test]
+fn test_filepart_content_type_raw() {
+    let mut headers = HeaderMap::new();
+    let filepart = FilePart::new(headers.clone(), Path::new("unused"));
+    assert!(filepart.content_type().unwrap().is_none());
+    assert_eq!(filepart.content_type_raw(), None);
+
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/csv; charset=windows-1252; header=present"),
+    );
+    let filepart = FilePart::new(headers.clone(), Path::new("unused"));
+    let mime = filepart.content_type().unwrap().unwrap();
+    assert_eq!(mime.get_param("header").unwrap(), "present");
+    assert_eq!(
+        filepart.content_type_raw().unwrap(),
+        "text/csv; charset=windows-1252; header=present"
+    );
+
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("not a mime type"));
+    let filepart = FilePart::new(headers, Path::new("unused"));
+    assert!(matches!(
+        filepart.content_type(),
+        Err(Error::HeaderValueNotMime)
+    ));
+    assert_eq!(filepart.content_type_raw().unwrap(), "not a mime type");
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn test_nodes_from_json() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(b"on disk").unwrap();
+    let json = serde_json::json!({
+        "title": "Report",
+        "draft": false,
+        "tags": ["a", "b"],
+        "meta": {"pages": 3, "note": null},
+        "inline": {"type": "text/plain", "name": "a.txt", "body": "aGk="},
+        "attachment": {"type": "text/csv", "name": "b.csv", "path": file.path()},
+    });
+    let nodes = nodes_from_json(&json).unwrap();
+
+    let fields: Vec<(String, Vec<u8>)> = nodes
+        .iter()
+        .filter_map(|node| match node {
+            Node::Part(part) => Some((
+                get_content_disposition_name(&part.headers[CONTENT_DISPOSITION]).unwrap(),
+                part.body.clone(),
+            )),
+            _ => None,
+        })
+        .collect();
+    let expected: Vec<(String, Vec<u8>)> = [
+        ("title", &b"Report"[..]),
+        ("draft", b"false"),
+        ("tags", b"a"),
+        ("tags", b"b"),
+        ("meta[pages]", b"3"),
+        ("inline", b"hi"),
+    ]
+    .iter()
+    .map(|&(name, body)| (name.to_owned(), body.to_vec()))
+    .collect();
+    assert_eq!(fields, expected);
+
+    match nodes.last().unwrap() {
+        Node::File(filepart) => {
+            assert_eq!(filepart.path, file.path());
+            assert_eq!(filepart.filename().unwrap(), Some("b.csv".to_owned()));
+            assert_eq!(filepart.content_type_raw().unwrap(), "text/csv");
+        }
+        _ => panic!("Expected a file"),
+    }
+    let mut out = Vec::new();
+    write_multipart(&mut out, b"b0undary", &nodes).unwrap();
+    assert!(out.windows(7).any(|window| window == b"on disk"));
+
+    assert!(nodes_from_json(&serde_json::json!([1])).is_err());
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_read_multipart_async() {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, ReadBuf};
+
+    // Hands over a few bytes at a time, so that everything is split across reads
+    struct Trickle<'a>(&'a [u8]);
+
+    impl AsyncRead for Trickle<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            let count = self.0.len().min(5).min(buf.remaining());
+            buf.put_slice(&self.0[..count]);
+            self.0 = &self.0[count..];
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    let input = b"Host: example.domain\r\n\
+                  Content-Type: multipart/mixed; boundary=\"abcdefg\"\r\n\
+                  \r\n\
+                  --abcdefg\r\n\
+                  Content-Type: application/json\r\n\
+                  \r\n\
+                  {\"id\": 15}\r\n\
+                  --abcdefg\r\n\
+                  Content-Disposition: Attachment; filename=\"image.gif\"\r\n\
+                  Content-Type: image/gif\r\n\
+                  \r\n\
+                  This is a file\r\n\
+                  --abcdefg\r\n\
+                  Content-Type: multipart/mixed; boundary=inner\r\n\
+                  \r\n\
+                  --inner\r\n\
+                  \r\n\
+                  nested\r\n\
+                  --inner--\r\n\
+                  --abcdefg--";
+
+    let (headers, nodes) = block_on(read_multipart_async(&mut Trickle(input), false)).unwrap();
+    assert_eq!(headers.get("host").unwrap(), "example.domain");
+    assert_eq!(nodes.len(), 3);
+    match nodes[0] {
+        Node::Part(ref part) => assert_eq!(part.body, b"{\"id\": 15}"),
+        _ => panic!("Expected a part"),
+    }
+    match nodes[1] {
+        Node::File(ref filepart) => {
+            assert_eq!(filepart.size, Some(14));
+            assert_eq!(std::fs::read(&filepart.path).unwrap(), b"This is a file");
+        }
+        _ => panic!("Expected a file"),
+    }
+    match nodes[2] {
+        Node::Multipart((_, ref subnodes)) => match subnodes[..] {
+            [Node::Part(ref part)] => assert_eq!(part.body, b"nested"),
+            _ => panic!("Expected one nested part"),
+        },
+        _ => panic!("Expected a multipart"),
+    }
+
+    let profile = ParseProfile {
+        limits: Limits {
+            max_part_size: Some(8),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+    let body = &input[input.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4..];
+    let result = block_on(read_multipart_body_async_with(
+        &mut Trickle(body),
+        &headers,
+        &profile,
+    ));
+    assert!(matches!(result, Err(Error::PartTooLarge)));
+    let result = block_on(read_multipart_body_async(
+        &mut Trickle(&body[..body.len() - 3]),
+        &headers,
+        false,
+    ));
+    assert!(result.is_err());
+
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"title\"\r\n\
+                 \r\n\
+                 Holiday\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"a\"; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 same content\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"b\"; filename=\"b.txt\"\r\n\
+                 \r\n\
+                 same content\r\n\
+                 --AaB03x--\r\n\
+                 epilogue";
+    let with_length = |length: usize| {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("multipart/form-data"),
+        );
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(length));
+        headers
+    };
+    let budget = DiskBudget::new(1024);
+    let profile = ParseProfile {
+        sniff_boundary: true,
+        spill_threshold: Some(4),
+        dedup_files: true,
+        verify_content_length: true,
+        disk_budget: Some(budget.clone()),
+        ..ParseProfile::default()
+    };
+    let mut reader = Trickle(body);
+    let headers = with_length(body.len());
+    let nodes = block_on(read_multipart_body_async_with(
+        &mut reader,
+        &headers,
+        &profile,
+    ))
+    .unwrap();
+    assert!(reader.0.is_empty());
+    // The field spilled to a file, and the second file shares the content of the first
+    match nodes[..] {
+        [Node::File(ref title), Node::File(_), Node::File(ref b)] => {
+            assert_eq!(std::fs::read(&title.path).unwrap(), b"Holiday");
+            assert_eq!(std::fs::read(&b.path).unwrap(), b"same content");
+        }
+        _ => panic!("Expected three files"),
+    }
+    assert_eq!(budget.used(), 19);
+
+    // Nothing past the Content-Length is read
+    let mut reader = Trickle(body);
+    let headers = with_length(body.len() - 8);
+    let result = block_on(read_multipart_body_async_with(
+        &mut reader,
+        &headers,
+        &profile,
+    ));
+    assert_eq!(result.unwrap().len(), 3);
+    assert_eq!(reader.0, b"epilogue");
+    let headers = with_length(body.len() + 1);
+    let result = block_on(read_multipart_body_async_with(
+        &mut Trickle(body),
+        &headers,
+        &profile,
+    ));
+    assert!(matches!(result, Err(Error::ContentLengthMismatch)));
+
+    let profile = ParseProfile {
+        sniff_boundary: true,
+        required_fields: vec!["title".to_owned()],
+        ..ParseProfile::default()
+    };
+    let mut reader = Trickle(body);
+    let result = block_on(read_multipart_body_async_with(
+        &mut reader,
+        &headers,
+        &profile,
+    ));
+    assert_eq!(result.unwrap().len(), 1);
+    assert!(reader.0.len() > 100);
+    let profile = ParseProfile {
+        stop_at: StopAt::Eof,
+        ..profile
+    };
+    let mut reader = Trickle(body);
+    let result = block_on(read_multipart_body_async_with(
+        &mut reader,
+        &headers,
+        &profile,
+    ));
+    assert_eq!(result.unwrap().len(), 1);
+    assert!(reader.0.is_empty());
+}
+
+#[test]
+fn test_split_parts() {
+    let input = b"preamble\r\n--AaB03x  \r\nContent-Type: text/plain\r\n\r\none\r\n\
+                  --AaB03x\r\n\r\ntwo\r\nlines\r\n--AaB03x--\r\nepilogue";
+    let parts: Vec<Vec<u8>> = split_parts(&input[..], b"AaB03x")
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        parts,
+        vec![
+            b"Content-Type: text/plain\r\n\r\none".to_vec(),
+            b"\r\ntwo\r\nlines".to_vec(),
+        ]
+    );
+
+    let input = b"--AaB03x\nX-Raw: 1\n\nbare\n--AaB03x--";
+    let parts: Vec<Vec<u8>> = split_parts(&input[..], b"AaB03x")
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(parts, vec![b"X-Raw: 1\n\nbare".to_vec()]);
+
+    let mut iter = split_parts(&b"--AaB03x\r\n\r\ncut short"[..], b"AaB03x");
+    assert!(matches!(iter.next(), Some(Err(Error::EofInPart))));
+    assert!(iter.next().is_none());
+
+    let mut iter = split_parts(&b"no delimiter"[..], b"AaB03x");
+    assert!(matches!(
+        iter.next(),
+        Some(Err(Error::EofBeforeFirstBoundary))
+    ));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_write_multipart_async() {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::AsyncWrite;
+
+    // Takes a few bytes at a time, and is not ready every other time
+    struct Trickle {
+        out: Vec<u8>,
+        ready: bool,
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                return Poll::Pending;
+            }
+            let count = buf.len().min(7);
+            self.out.extend_from_slice(&buf[..count]);
+            Poll::Ready(Ok(count))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    let mut part_headers = HeaderMap::new();
+    part_headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    let mut filepart = FilePart::create(HeaderMap::new()).unwrap();
+    filepart
+        .create_file()
+        .unwrap()
+        .write_all(&b"file content ".repeat(10000))
+        .unwrap();
+    let mut nested_headers = HeaderMap::new();
+    nested_headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/mixed; boundary=inner"),
+    );
+    let nodes = vec![
+        Node::Part(Part {
+            headers: part_headers,
+            body: b"in memory".to_vec(),
+        }),
+        Node::File(filepart),
+        Node::Multipart((
+            nested_headers,
+            vec![Node::Part(Part {
+                headers: HeaderMap::new(),
+                body: Vec::new(),
+            })],
+        )),
+    ];
+
+    let mut expected = Vec::new();
+    write_multipart(&mut expected, b"AaB03x", &nodes).unwrap();
+    let mut writer = Trickle {
+        out: Vec::new(),
+        ready: false,
+    };
+    let count = block_on(write_multipart_async(&mut writer, b"AaB03x", &nodes)).unwrap();
+    assert_eq!(writer.out, expected);
+    assert_eq!(count, expected.len());
+
+    let profile = WriteProfile {
+        sha256_trailer: true,
+        ..WriteProfile::default()
+    };
+    let mut writer = Trickle {
+        out: Vec::new(),
+        ready: false,
+    };
+    block_on(write_multipart_chunked_async_with(
+        &mut writer,
+        b"AaB03x",
+        &nodes,
+        &profile,
+    ))
+    .unwrap();
+    let mut rest = &writer.out[..];
+    let mut content = Vec::new();
+    loop {
+        let line = rest.windows(2).position(|w| w == b"\r\n").unwrap();
+        let size = usize::from_str_radix(std::str::from_utf8(&rest[..line]).unwrap(), 16).unwrap();
+        rest = &rest[line + 2..];
+        if size == 0 {
+            break;
+        }
+        content.extend_from_slice(&rest[..size]);
+        assert_eq!(&rest[size..size + 2], b"\r\n");
+        rest = &rest[size + 2..];
+    }
+    assert_eq!(content, expected);
+    let mut digest = Sha256::new();
+    digest.update(&expected);
+    let trailer = format!("{}: {}\r\n\r\n", SHA256_TRAILER, hex_digest(digest));
+    assert_eq!(rest, trailer.as_bytes());
+
+    let collision = vec![Node::Part(Part {
+        headers: HeaderMap::new(),
+        body: b"\r\n--AaB03x\r\n".to_vec(),
+    })];
+    let mut writer = Trickle {
+        out: Vec::new(),
+        ready: false,
+    };
+    let result = block_on(write_multipart_async(&mut writer, b"AaB03x", &collision));
+    assert!(matches!(result, Err(Error::BoundaryCollision)));
+    assert!(writer.out.is_empty());
+}
+
+#[test]
+fn test_temp_name_strategy() {
+    let spool = tempfile::tempdir().unwrap();
+    let builder = FilePartBuilder::new()
+        .dir(spool.path())
+        .name(SpoolName::Strategy(NameStrategy::new(SequentialName::new(
+            "upload-",
+        ))));
+    for expected in ["upload-0", "upload-1"] {
+        let (filepart, _) = builder.build(HeaderMap::new()).unwrap();
+        assert_eq!(filepart.path.file_name().unwrap(), expected);
+    }
+
+    let name = UuidName.file_name(&HeaderMap::new());
+    let groups: Vec<&str> = name.split('-').collect();
+    assert_eq!(
+        groups.iter().map(|group| group.len()).collect::<Vec<_>>(),
+        [8, 4, 4, 4, 12]
+    );
+    assert!(groups[2].starts_with('4'));
+    assert!(matches!(groups[3].as_bytes()[0], b'8' | b'9' | b'a' | b'b'));
+    assert_ne!(name, UuidName.file_name(&HeaderMap::new()));
+
+    // Names from the caller are sanitized, and fall back to random ones
+    let strategy = |headers: &HeaderMap| match headers.get(CONTENT_DISPOSITION) {
+        Some(_) => "../shard-7/part".to_owned(),
+        None => "..".to_owned(),
+    };
+    let profile = ParseProfile {
+        disposition: DispositionPolicy::AlwaysFile,
+        spool: FilePartBuilder::new().name(SpoolName::Strategy(NameStrategy::new(strategy))),
+        ..ParseProfile::default()
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"a\"\r\n\
+                 \r\n\
+                 one\r\n\
+                 --AaB03x\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 two\r\n\
+                 --AaB03x--";
+    let nodes = read_multipart_body_with(&mut &body[..], &headers, &profile).unwrap();
+    let names: Vec<String> = nodes
+        .iter()
+        .map(|node| match *node {
+            Node::File(ref filepart) => filepart.path.file_name().unwrap().to_string_lossy().into(),
+            _ => panic!("Expected a file"),
+        })
+        .collect();
+    assert_eq!(names[0], "part");
+    assert_eq!(names[1].len(), 32);
+}
+
+#[test]
+fn test_injected_entropy() {
+    // A counter, so that every run draws the same bytes
+    fn counter() -> Entropy {
+        let next = std::sync::atomic::AtomicU8::new(0);
+        Entropy::new(move |dest: &mut [u8]| {
+            for byte in dest {
+                *byte = next.fetch_add(7, std::sync::atomic::Ordering::Relaxed);
+            }
+        })
+    }
+
+    let boundary = generate_boundary_with(&counter());
+    assert_eq!(boundary, generate_boundary_with(&counter()));
+    assert_eq!(boundary.len(), generate_boundary().len());
+    validate_boundary(&boundary).unwrap();
+
+    let mut nested_headers = HeaderMap::new();
+    nested_headers.insert(CONTENT_TYPE, HeaderValue::from_static("multipart/mixed"));
+    let nodes = vec![Node::Multipart((
+        nested_headers,
+        vec![Node::Part(Part {
+            headers: HeaderMap::new(),
+            body: b"nested".to_vec(),
+        })],
+    ))];
+    let write = || {
+        let profile = WriteProfile {
+            entropy: Some(counter()),
+            ..WriteProfile::default()
+        };
+        let mut output = Vec::new();
+        write_multipart_with(&mut output, b"AaB03x", &nodes, &profile).unwrap();
+        output
+    };
+    let output = write();
+    assert_eq!(output, write());
+    let inner = String::from_utf8(generate_boundary_with(&counter())).unwrap();
+    assert!(String::from_utf8(output).unwrap().contains(&inner));
+
+    let spool = |dir: &Path| {
+        let builder = FilePartBuilder::new().dir(dir).entropy(counter());
+        let (filepart, _) = builder.build(HeaderMap::new()).unwrap();
+        filepart.path.strip_prefix(dir).unwrap().to_owned()
+    };
+    let (first, second) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+    let path = spool(first.path());
+    assert_eq!(path, spool(second.path()));
+    assert!(path.to_string_lossy().starts_with("mime_multipart"));
+    assert_eq!(path.file_name().unwrap().len(), 32);
+}
+
+#[cfg(feature = "http-body")]
+#[test]
+fn test_multipart_body() {
+    use http_body::Body;
+    use std::task::{Context, Poll, Waker};
+
+    let mut field = HeaderMap::new();
+    field.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("form-data; name=\"field\""),
+    );
+    let mut file = HeaderMap::new();
+    file.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("form-data; name=\"file\"; filename=\"big.bin\""),
+    );
+    let filepart = FilePart::create(file).unwrap();
+    let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&filepart.path, &content).unwrap();
+    let nodes = vec![
+        Node::Part(Part {
+            headers: field,
+            body: b"value".to_vec(),
+        }),
+        Node::File(filepart),
+    ];
+
+    let mut body = MultipartBody::new(nodes).unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, body.content_type("form-data").unwrap());
+    assert_eq!(
+        get_multipart_boundary(&headers).unwrap()[2..],
+        *body.boundary()
+    );
+    let length = body.size_hint().exact().unwrap();
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut output = Vec::new();
+    while let Poll::Ready(Some(frame)) = std::pin::Pin::new(&mut body).poll_frame(&mut cx) {
+        output.extend(frame.unwrap().into_data().ok().unwrap());
+        assert_eq!(body.size_hint().exact(), Some(length - output.len() as u64));
+    }
+    assert!(body.is_end_stream());
+    assert_eq!(output.len() as u64, length);
+
+    let nodes = read_multipart_body(&mut &output[..], &headers, false).unwrap();
+    match nodes[..] {
+        [Node::Part(ref part), Node::File(ref filepart)] => {
+            assert_eq!(part.body, b"value");
+            assert_eq!(std::fs::read(&filepart.path).unwrap(), content);
+        }
+        _ => panic!("Expected a part and a file"),
+    }
+}
+
+#[cfg(feature = "http-body")]
+#[test]
+fn test_read_multipart_http_body() {
+    use bytes::Bytes;
+    use http_body::{Body, Frame};
+    use std::collections::VecDeque;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    // Hands over the body a few bytes a frame, ending with trailers or an error
+    struct Frames {
+        frames: VecDeque<Bytes>,
+        fail: bool,
+    }
+
+    impl Frames {
+        fn new(body: &[u8], fail: bool) -> Frames {
+            Frames {
+                frames: body.chunks(5).map(Bytes::copy_from_slice).collect(),
+                fail,
+            }
+        }
+    }
+
+    impl Body for Frames {
+        type Data = Bytes;
+        type Error = std::io::Error;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, std::io::Error>>> {
+            Poll::Ready(match self.frames.pop_front() {
+                Some(data) => Some(Ok(Frame::data(data))),
+                None if self.fail => Some(Err(std::io::Error::other("connection reset"))),
+                None => None,
+            })
+        }
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("multipart/form-data; boundary=AaB03x"),
+    );
+    let body = b"--AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"field\"\r\n\
+                 \r\n\
+                 value\r\n\
+                 --AaB03x\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 \r\n\
+                 file content\r\n\
+                 --AaB03x--";
+
+    let nodes = block_on(read_multipart_http_body(
+        Frames::new(body, false),
+        &headers,
+        false,
+    ))
+    .unwrap();
+    match nodes[..] {
+        [Node::Part(ref part), Node::File(ref filepart)] => {
+            assert_eq!(part.body, b"value");
+            assert_eq!(std::fs::read(&filepart.path).unwrap(), b"file content");
+        }
+        _ => panic!("Expected a part and a file"),
+    }
+
+    let result = block_on(read_multipart_http_body(
+        Frames::new(&body[..40], true),
+        &headers,
+        false,
+    ));
+    assert!(matches!(result, Err(Error::Io(ref err)) if err.to_string() == "connection reset"));
+
+    let profile = ParseProfile {
+        limits: Limits {
+            max_part_size: Some(8),
+            ..Limits::default()
+        },
+        ..ParseProfile::default()
+    };
+    let result = block_on(read_multipart_http_body_with(
+        Frames::new(body, false),
+        &headers,
+        &profile,
+    ));
+    assert!(matches!(result, Err(Error::PartTooLarge)));
+
+    // No more frames are polled once the required fields are read
+    let profile = ParseProfile {
+        required_fields: vec!["field".to_owned()],
+        ..ParseProfile::default()
+    };
+    let mut frames = Frames::new(body, false);
+    let result = block_on(read_multipart_http_body_with(
+        &mut frames,
+        &headers,
+        &profile,
+    ));
+    assert_eq!(result.unwrap().len(), 1);
+    assert!(!frames.frames.is_empty());
+
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(body.len() - 1));
+    let profile = ParseProfile {
+        verify_content_length: true,
+        ..ParseProfile::default()
+    };
+    let result = block_on(read_multipart_http_body_with(
+        Frames::new(body, false),
+        &headers,
+        &profile,
+    ));
+    assert!(matches!(result, Err(Error::ContentLengthMismatch)));
+}
@@ -0,0 +1,158 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::Error;
+use buf_read_ext::BufReadExt;
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// Copy bytes from `reader` to `out` until `token` is found, as the parser does to find
+/// boundaries.  The token is consumed but not copied, and may be split across any number of
+/// reads.  Returns the number of bytes copied, and whether the token was found before the end
+/// of the input.  An empty token is found at once.
+pub fn stream_until_token<R: BufRead + ?Sized, W: Write>(
+    reader: &mut R,
+    token: &[u8],
+    out: &mut W,
+) -> Result<(usize, bool), Error> {
+    if token.is_empty() {
+        return Ok((0, true));
+    }
+    Ok((&mut *reader).stream_until_token(token, out)?)
+}
+
+/// Copy bytes from `reader` to `out` until a multipart delimiter for `boundary` is found: a
+/// line terminator, either CRLF or a bare LF, followed by `--` and the boundary.  The line
+/// terminator is part of the delimiter (RFC 2046 section 5.1.1), so it is not copied.  The
+/// delimiter is consumed, leaving `reader` at what follows it, which is `--` for a close
+/// delimiter.  Returns the number of bytes copied, and whether a delimiter was found before
+/// the end of the input.
+pub fn stream_until_delimiter<R: BufRead + ?Sized, W: Write>(
+    reader: &mut R,
+    boundary: &[u8],
+    out: &mut W,
+) -> Result<(usize, bool), Error> {
+    let mut token = Vec::with_capacity(3 + boundary.len());
+    token.extend(b"\n--");
+    token.extend(boundary);
+
+    let mut out = HoldCr {
+        inner: out,
+        held: false,
+        written: 0,
+    };
+    let (_, found) = (&mut *reader).stream_until_token(&token, &mut out)?;
+    if !found && out.held {
+        out.inner.write_all(b"\r")?;
+        out.written += 1;
+    }
+    Ok((out.written, found))
+}
+
+/// Split the multipart body read from `reader` at the delimiters for `boundary`, yielding
+/// each part's raw bytes, its headers and body together, without interpreting them.  This
+/// is for tools which only split parts and pass them on verbatim.  The preamble, transport
+/// padding and epilogue are skipped, and the line terminator before each delimiter is not
+/// part of the bytes yielded.  Iteration ends after the close delimiter, or after the first
+/// error: `Error::EofBeforeFirstBoundary` if there is no delimiter, or `Error::EofInPart` if
+/// the body ends before the close delimiter.
+pub fn split_parts<R: Read>(reader: R, boundary: &[u8]) -> SplitParts<R> {
+    SplitParts {
+        // A delimiter is found after a line terminator, which the first may lack
+        reader: BufReader::new(Read::chain(&b"\n"[..], reader)),
+        boundary: boundary.to_vec(),
+        started: false,
+        done: false,
+    }
+}
+
+/// The iterator `split_parts()` returns.
+pub struct SplitParts<R> {
+    reader: BufReader<std::io::Chain<&'static [u8], R>>,
+    boundary: Vec<u8>,
+    started: bool,
+    done: bool,
+}
+
+impl<R: Read> SplitParts<R> {
+    fn next_part(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        if !self.started {
+            self.started = true;
+            if self.boundary.is_empty() {
+                return Err(Error::InvalidBoundary);
+            }
+            let (_, found) =
+                stream_until_delimiter(&mut self.reader, &self.boundary, &mut std::io::sink())?;
+            if !found {
+                return Err(Error::EofBeforeFirstBoundary);
+            }
+        }
+
+        // The rest of the delimiter line: `--` closes the body, anything else up to the line
+        // terminator is transport padding
+        let mut line = Vec::new();
+        let (_, found) = stream_until_token(&mut self.reader, b"\n", &mut line)?;
+        if line.starts_with(b"--") {
+            return Ok(None);
+        }
+        if !found {
+            return Err(Error::EofInPart);
+        }
+
+        let mut part = Vec::new();
+        match stream_until_delimiter(&mut self.reader, &self.boundary, &mut part)? {
+            (_, true) => Ok(Some(part)),
+            (_, false) => Err(Error::EofInPart),
+        }
+    }
+}
+
+impl<R: Read> Iterator for SplitParts<R> {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_part();
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result.transpose()
+    }
+}
+
+// A writer which holds back a trailing CR until it knows whether more follows, so that the CR
+// of a CRLF preceding a delimiter is not written
+struct HoldCr<'a, W: Write> {
+    inner: &'a mut W,
+    held: bool,
+    written: usize,
+}
+
+impl<W: Write> Write for HoldCr<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.held {
+            self.inner.write_all(b"\r")?;
+            self.written += 1;
+        }
+        let (body, held) = match buf.strip_suffix(b"\r") {
+            Some(body) => (body, true),
+            None => (buf, false),
+        };
+        self.inner.write_all(body)?;
+        self.written += body.len();
+        self.held = held;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
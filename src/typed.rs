@@ -0,0 +1,93 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Interoperation with the typed headers of the `headers` crate, for code which handles
+//! `ContentType` and `ContentDisposition` values rather than their strings.
+
+use super::{ContentDisposition, Error, FilePart, Part};
+use headers::{Header, HeaderMapExt};
+use http::header::{HeaderMap, HeaderValue};
+use std::iter;
+use std::path::Path;
+
+/// Like `get_multipart_boundary()`, but taking a typed `Content-Type`.  The boundary is
+/// returned with its leading `--`.
+pub fn get_multipart_boundary(content_type: &headers::ContentType) -> Result<Vec<u8>, Error> {
+    let mut headers = HeaderMap::new();
+    headers.typed_insert(content_type.clone());
+    super::get_multipart_boundary(&headers)
+}
+
+/// Like `multipart_content_type()`, but producing a typed `Content-Type`.
+pub fn multipart_content_type(
+    subtype: &str,
+    boundary: &[u8],
+) -> Result<headers::ContentType, Error> {
+    let value = super::multipart_content_type(subtype, boundary)?;
+    decode(&value)
+}
+
+/// Convert a typed `Content-Disposition` into this crate's `ContentDisposition`, for access
+/// to its parameters.
+pub fn from_content_disposition(
+    disposition: &headers::ContentDisposition,
+) -> Result<ContentDisposition, Error> {
+    ContentDisposition::from_header(&encode(disposition))
+}
+
+/// Convert this crate's `ContentDisposition` into a typed `Content-Disposition`.
+pub fn to_content_disposition(
+    disposition: &ContentDisposition,
+) -> Result<headers::ContentDisposition, Error> {
+    decode(&disposition.to_header_value()?)
+}
+
+/// Build a part held in memory, with the given typed headers.
+pub fn part(
+    content_type: headers::ContentType,
+    disposition: Option<headers::ContentDisposition>,
+    body: Vec<u8>,
+) -> Part {
+    Part {
+        headers: part_headers(content_type, disposition),
+        body,
+    }
+}
+
+/// Build a part streamed from the file at `path`, with the given typed headers.
+pub fn file_part(
+    content_type: headers::ContentType,
+    disposition: Option<headers::ContentDisposition>,
+    path: &Path,
+) -> FilePart {
+    FilePart::new(part_headers(content_type, disposition), path)
+}
+
+fn part_headers(
+    content_type: headers::ContentType,
+    disposition: Option<headers::ContentDisposition>,
+) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.typed_insert(content_type);
+    if let Some(disposition) = disposition {
+        headers.typed_insert(disposition);
+    }
+    headers
+}
+
+fn encode<H: Header>(header: &H) -> HeaderValue {
+    let mut values = Vec::with_capacity(1);
+    header.encode(&mut values);
+    values.swap_remove(0)
+}
+
+fn decode<H: Header>(value: &HeaderValue) -> Result<H, Error> {
+    match H::decode(&mut iter::once(value)) {
+        Ok(header) => Ok(header),
+        Err(_) => Err(Error::InvalidHeaderNameOrValue),
+    }
+}
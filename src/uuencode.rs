@@ -0,0 +1,157 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{Error, FilePart, Node, Part};
+use http::header::{HeaderMap, HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE};
+use std::io::Write;
+
+/// Find uuencoded files (`begin <mode> <name>` ... `end` blocks) in the plain text parts of
+/// `nodes`, as delivered by some old mail gateways, and expose each as a synthetic
+/// `FilePart` following the part it was found in.  The blocks are removed from the text.
+/// The files carry `Content-Disposition: attachment` with the name from the `begin` line,
+/// and `Content-Type: application/octet-stream`.
+///
+/// Parts with no `Content-Type` count as plain text, as RFC 2046 specifies.  Blocks which are
+/// malformed or not terminated by `end` are left in the text.  Nested multiparts are
+/// searched recursively.
+pub fn decode_uuencoded(nodes: Vec<Node>) -> Result<Vec<Node>, Error> {
+    let mut output = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        match node {
+            Node::Part(mut part) => {
+                let is_text = match part.content_type() {
+                    Some(mime) => mime.essence_str() == "text/plain",
+                    None => true,
+                };
+                if !is_text {
+                    output.push(Node::Part(part));
+                    continue;
+                }
+                let (body, files) = extract_blocks(&part.body);
+                output.push(Node::Part(Part {
+                    headers: std::mem::take(&mut part.headers),
+                    body,
+                }));
+                for (name, content) in files {
+                    output.push(Node::File(synthetic_file_part(&name, &content)?));
+                }
+            }
+            Node::File(filepart) => output.push(Node::File(filepart)),
+            Node::Multipart((headers, subnodes)) => {
+                output.push(Node::Multipart((headers, decode_uuencoded(subnodes)?)))
+            }
+        }
+    }
+    Ok(output)
+}
+
+fn synthetic_file_part(name: &str, content: &[u8]) -> Result<FilePart, Error> {
+    let mut headers = HeaderMap::new();
+    let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+    let disposition = format!("attachment; filename=\"{}\"", name);
+    match HeaderValue::from_str(&disposition) {
+        Ok(value) => headers.insert(CONTENT_DISPOSITION, value),
+        Err(_) => return Err(Error::InvalidHeaderNameOrValue),
+    };
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    let mut filepart = FilePart::create(headers)?;
+    filepart.create_file()?.write_all(content)?;
+    filepart.size = Some(content.len());
+    Ok(filepart)
+}
+
+// Split `text` into the text outside uuencoded blocks, and the name and content of each block
+fn extract_blocks(text: &[u8]) -> (Vec<u8>, Vec<(String, Vec<u8>)>) {
+    let lines: Vec<&[u8]> = text.split_inclusive(|&ch| ch == b'\n').collect();
+    let mut remaining = Vec::with_capacity(text.len());
+    let mut files = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let block = begin_name(trim_line(lines[index]))
+            .and_then(|name| decode_block(&lines[index + 1..]).map(|block| (name, block)));
+        match block {
+            Some((name, (content, used))) => {
+                files.push((name, content));
+                index += used + 1;
+            }
+            None => {
+                remaining.extend(lines[index]);
+                index += 1;
+            }
+        }
+    }
+    (remaining, files)
+}
+
+// The file name of a `begin <mode> <name>` line
+fn begin_name(line: &[u8]) -> Option<String> {
+    let line = std::str::from_utf8(line).ok()?;
+    let rest = line.strip_prefix("begin ")?;
+    let (mode, name) = rest.split_once(' ')?;
+    let is_mode = !mode.is_empty() && mode.bytes().all(|ch| (b'0'..=b'7').contains(&ch));
+    match is_mode && !name.is_empty() {
+        true => Some(name.to_owned()),
+        false => None,
+    }
+}
+
+// Decode the lines following a `begin` line, up to and including the `end` line.  Returns the
+// content and the number of lines used.
+fn decode_block(lines: &[&[u8]]) -> Option<(Vec<u8>, usize)> {
+    let mut content = Vec::new();
+    let mut ended = false;
+    for (index, line) in lines.iter().enumerate() {
+        let line = trim_line(line);
+        if ended {
+            return match line == b"end" {
+                true => Some((content, index + 1)),
+                false => None,
+            };
+        }
+        if line == b"end" {
+            return Some((content, index + 1));
+        }
+
+        // The first character encodes the number of bytes on the line
+        let len = decode_char(*line.first()?)? as usize;
+        if len == 0 {
+            ended = true;
+            continue;
+        }
+        let mut decoded = Vec::with_capacity(len + 2);
+        for group in line[1..].chunks(4) {
+            let mut sextets = [0u8; 4];
+            for (sextet, &ch) in sextets.iter_mut().zip(group) {
+                *sextet = decode_char(ch)?;
+            }
+            decoded.push((sextets[0] << 2) | (sextets[1] >> 4));
+            decoded.push((sextets[1] << 4) | (sextets[2] >> 2));
+            decoded.push((sextets[2] << 6) | sextets[3]);
+        }
+        if decoded.len() < len {
+            return None;
+        }
+        content.extend(&decoded[..len]);
+    }
+    None
+}
+
+// Characters run from space (or backquote, its common substitute) for 0 to underscore for 63
+fn decode_char(ch: u8) -> Option<u8> {
+    match ch {
+        b' '..=b'`' => Some((ch - b' ') & 0x3f),
+        _ => None,
+    }
+}
+
+fn trim_line(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
@@ -0,0 +1,349 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use super::{check_header_value, get_multipart_boundary, multipart_subtype, part_disposition};
+use super::{part_limit, validate_boundary, ContentDisposition, Disposition, DispositionType};
+use super::{Error, Node, ParseProfile};
+use http::header::{HeaderMap, HeaderValue, CONTENT_DISPOSITION};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// A problem found by `validate()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// Where the problem is: the index of the node, then of the node within it, and so on.
+    /// Empty for problems with the tree as a whole.
+    pub path: Vec<usize>,
+    /// What the problem is
+    pub kind: ViolationKind,
+}
+
+/// The kinds of `Violation`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// A part has more than one `Content-Disposition` header.
+    DuplicateContentDisposition,
+    /// A part of a `multipart/form-data` has no `form-data` `Content-Disposition` (RFC 7578).
+    NotFormData,
+    /// A `form-data` `Content-Disposition` has no `name` parameter (RFC 7578).
+    NoFieldName,
+    /// A nested multipart's `Content-Type` is not a valid `multipart/*` type.
+    NotMultipart,
+    /// A nested multipart's boundary is not permitted by RFC 2046.
+    InvalidBoundary,
+    /// A header value contains CR, LF or NUL.
+    UnsafeHeaderValue,
+    /// A file part's file cannot be read.
+    UnreadableFile,
+    /// A part declared `Content-Transfer-Encoding: 7bit` has octets above 127.
+    Not7Bit,
+    /// A part declared `7bit` or `8bit` has a NUL, or a CR or LF which is not part of a
+    /// CRLF (RFC 2045 section 2.7).
+    NotLineOriented,
+    /// A part declared `7bit` or `8bit` has a line longer than 998 octets.
+    LineTooLong,
+    /// A part body is larger than `Limits::max_part_size`.
+    PartTooLarge,
+    /// A part the receiver would stream to a file is larger than `Limits::max_file_size`.
+    FileTooLarge,
+    /// A part the receiver would keep in memory is larger than `Limits::max_field_size`.
+    FieldTooLarge,
+    /// A part is larger than the limit for its field name in `Limits::max_size_by_name`.
+    FieldSizeExceeded,
+    /// A part has more headers than `Limits::max_part_headers`.
+    TooManyHeaders,
+    /// A part's header block is larger than `Limits::max_header_block`.
+    HeadersTooLarge,
+    /// A multipart is nested deeper than `Limits::max_depth`.
+    NestingTooDeep,
+    /// There are more parts than `Limits::max_parts`.
+    TooManyParts,
+    /// There are more parts the receiver would stream to files than `Limits::max_files`.
+    TooManyFiles,
+    /// There are more parts the receiver would keep in memory than `Limits::max_fields`.
+    TooManyFields,
+    /// The part bodies together are larger than `Limits::max_total_size`.
+    BodyTooLarge,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            return write!(f, "{:?}", self.kind);
+        }
+        let path: Vec<String> = self.path.iter().map(|index| index.to_string()).collect();
+        write!(f, "part {}: {:?}", path.join("."), self.kind)
+    }
+}
+
+/// Check `nodes` before writing them: for RFC 2046 and RFC 7578 compliance, and against the
+/// `Limits` of the `ParseProfile` the receiver is expected to parse with.  Returns every
+/// problem found, in order, or nothing if there are none.  The content of parts declaring a
+/// `7bit` or `8bit` transfer encoding is checked to be so, as must be done before relaying
+/// them over SMTP; file parts are otherwise checked by their metadata only.  Whether the
+/// top-level nodes are form fields is not known, so only nested `multipart/form-data` parts
+/// are required to be fields.
+pub fn validate(nodes: &[Node], profile: &ParseProfile) -> Vec<Violation> {
+    let mut check = Check {
+        profile,
+        violations: Vec::new(),
+        path: Vec::new(),
+        parts: 0,
+        files: 0,
+        fields: 0,
+        body_bytes: 0,
+    };
+    check.nodes(nodes, false);
+
+    let limits = &profile.limits;
+    if limits.max_parts.is_some_and(|max| check.parts > max) {
+        check.add_global(ViolationKind::TooManyParts);
+    }
+    if limits.max_files.is_some_and(|max| check.files > max) {
+        check.add_global(ViolationKind::TooManyFiles);
+    }
+    if limits.max_fields.is_some_and(|max| check.fields > max) {
+        check.add_global(ViolationKind::TooManyFields);
+    }
+    if limits
+        .max_total_size
+        .is_some_and(|max| check.body_bytes > max)
+    {
+        check.add_global(ViolationKind::BodyTooLarge);
+    }
+    check.violations
+}
+
+// The state of a `validate()` walk
+struct Check<'p> {
+    profile: &'p ParseProfile,
+    violations: Vec<Violation>,
+    // The path of the node being checked
+    path: Vec<usize>,
+    parts: usize,
+    // The parts the receiver's disposition policy would spool to files and keep in memory
+    files: usize,
+    fields: usize,
+    body_bytes: u64,
+}
+
+impl Check<'_> {
+    fn add(&mut self, kind: ViolationKind) {
+        self.violations.push(Violation {
+            path: self.path.clone(),
+            kind,
+        });
+    }
+
+    fn add_global(&mut self, kind: ViolationKind) {
+        self.violations.push(Violation {
+            path: Vec::new(),
+            kind,
+        });
+    }
+
+    fn nodes(&mut self, nodes: &[Node], form_data: bool) {
+        for (index, node) in nodes.iter().enumerate() {
+            self.path.push(index);
+            self.parts += 1;
+            self.headers(node.headers(), form_data);
+            let mut encoding = LineCheck::for_headers(node.headers());
+            let disposition = match *node {
+                Node::Multipart(_) => Disposition::Skip,
+                _ => part_disposition(node.headers(), self.profile).unwrap_or(Disposition::Memory),
+            };
+            match disposition {
+                Disposition::File => self.files += 1,
+                Disposition::Memory => self.fields += 1,
+                Disposition::Skip => {}
+            }
+            match *node {
+                Node::Part(ref part) => {
+                    self.body(part.body.len() as u64, disposition, &part.headers);
+                    if let Some(ref mut encoding) = encoding {
+                        encoding.update(&part.body);
+                    }
+                }
+                Node::File(ref filepart) => match std::fs::metadata(&filepart.path) {
+                    Ok(metadata) => {
+                        self.body(metadata.len(), disposition, &filepart.headers);
+                        if let Some(ref mut encoding) = encoding {
+                            if encoding.update_from(&filepart.path).is_err() {
+                                self.add(ViolationKind::UnreadableFile);
+                            }
+                        }
+                    }
+                    Err(_) => self.add(ViolationKind::UnreadableFile),
+                },
+                Node::Multipart((ref headers, ref subnodes)) => {
+                    self.multipart(headers, subnodes);
+                }
+            }
+            if let Some(encoding) = encoding {
+                for kind in encoding.finish() {
+                    self.add(kind);
+                }
+            }
+            self.path.pop();
+        }
+    }
+
+    fn multipart(&mut self, headers: &HeaderMap, nodes: &[Node]) {
+        // A missing boundary is generated when writing
+        match get_multipart_boundary(headers) {
+            Ok(boundary) if validate_boundary(&boundary[2..]).is_err() => {
+                self.add(ViolationKind::InvalidBoundary)
+            }
+            Ok(_) | Err(Error::BoundaryNotSpecified) | Err(Error::NoRequestContentType) => {}
+            Err(_) => self.add(ViolationKind::NotMultipart),
+        }
+        let max_depth = self.profile.limits.max_depth;
+        if max_depth.is_some_and(|max| self.path.len() == max + 1) {
+            self.add(ViolationKind::NestingTooDeep);
+        }
+        let form_data = multipart_subtype(headers).as_deref() == Some("form-data");
+        self.nodes(nodes, form_data);
+    }
+
+    fn headers(&mut self, headers: &HeaderMap, form_data: bool) {
+        let limits = &self.profile.limits;
+        if limits
+            .max_part_headers
+            .is_some_and(|max| headers.len() > max)
+        {
+            self.add(ViolationKind::TooManyHeaders);
+        }
+        // The header block as written, ending in a blank line
+        let block: usize = headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len() + 4)
+            .sum::<usize>()
+            + 2;
+        if limits.max_header_block.is_some_and(|max| block > max) {
+            self.add(ViolationKind::HeadersTooLarge);
+        }
+        let unsafe_value = |value: &HeaderValue| check_header_value(value.as_bytes()).is_err();
+        if headers.values().any(unsafe_value) {
+            self.add(ViolationKind::UnsafeHeaderValue);
+        }
+
+        let mut dispositions = headers.get_all(CONTENT_DISPOSITION).iter();
+        let disposition = dispositions.next();
+        if dispositions.next().is_some() {
+            self.add(ViolationKind::DuplicateContentDisposition);
+        }
+        let disposition = disposition.and_then(|cd| ContentDisposition::from_header(cd).ok());
+        match disposition {
+            Some(cd) if cd.disposition == DispositionType::FormData => match cd.decoded_name() {
+                Some(_) => {}
+                None => self.add(ViolationKind::NoFieldName),
+            },
+            _ if form_data => self.add(ViolationKind::NotFormData),
+            _ => {}
+        }
+    }
+
+    fn body(&mut self, size: u64, disposition: Disposition, headers: &HeaderMap) {
+        self.body_bytes += size;
+        match part_limit(self.profile, disposition, headers) {
+            Some((max, error)) if size > max => self.add(match error {
+                Error::FileTooLarge => ViolationKind::FileTooLarge,
+                Error::FieldTooLarge => ViolationKind::FieldTooLarge,
+                Error::FieldSizeExceeded(_) => ViolationKind::FieldSizeExceeded,
+                _ => ViolationKind::PartTooLarge,
+            }),
+            _ => {}
+        }
+    }
+}
+
+// Checks content against the `7bit` or `8bit` transfer encoding (RFC 2045 sections 2.7 and
+// 2.8), fed a piece at a time
+struct LineCheck {
+    seven_bit: bool,
+    // The length of the current line, and whether it ended in a CR so far
+    line: usize,
+    cr: bool,
+    not_7bit: bool,
+    not_line_oriented: bool,
+    too_long: bool,
+}
+
+impl LineCheck {
+    // A check for the transfer encoding the headers declare, if it is `7bit` or `8bit`
+    fn for_headers(headers: &HeaderMap) -> Option<LineCheck> {
+        let encoding = headers
+            .get("content-transfer-encoding")?
+            .to_str()
+            .ok()?
+            .trim();
+        let seven_bit = match encoding.to_ascii_lowercase().as_str() {
+            "7bit" => true,
+            "8bit" => false,
+            _ => return None,
+        };
+        Some(LineCheck {
+            seven_bit,
+            line: 0,
+            cr: false,
+            not_7bit: false,
+            not_line_oriented: false,
+            too_long: false,
+        })
+    }
+
+    fn update(&mut self, content: &[u8]) {
+        for &ch in content {
+            match ch {
+                b'\n' if self.cr => {
+                    self.line = 0;
+                    self.cr = false;
+                    continue;
+                }
+                b'\n' | 0 => self.not_line_oriented = true,
+                _ if self.cr => self.not_line_oriented = true,
+                ch if ch > 127 && self.seven_bit => self.not_7bit = true,
+                _ => {}
+            }
+            self.cr = ch == b'\r';
+            if !self.cr {
+                self.line += 1;
+                if self.line > 998 {
+                    self.too_long = true;
+                }
+            }
+        }
+    }
+
+    fn update_from(&mut self, path: &Path) -> std::io::Result<()> {
+        let mut file = File::open(path)?;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.update(&buf[..read]);
+        }
+    }
+
+    fn finish(self) -> Vec<ViolationKind> {
+        let mut kinds = Vec::new();
+        if self.not_7bit {
+            kinds.push(ViolationKind::Not7Bit);
+        }
+        if self.not_line_oriented || self.cr {
+            kinds.push(ViolationKind::NotLineOriented);
+        }
+        if self.too_long {
+            kinds.push(ViolationKind::LineTooLong);
+        }
+        kinds
+    }
+}
@@ -0,0 +1,48 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+// Overwriting buffers with zeros before they are freed, for the `zeroize` feature.
+
+#[cfg(feature = "parse")]
+use std::io::{self, Write};
+use std::ptr;
+use std::sync::atomic::{compiler_fence, Ordering};
+
+// Zero all of `buf`, including its spare capacity, and empty it.  The writes are volatile
+// so that they are not optimized away although the buffer is about to be freed.
+pub(crate) fn wipe(buf: &mut Vec<u8>) {
+    buf.clear();
+    for byte in buf.spare_capacity_mut() {
+        // SAFETY: the pointer comes from a live `&mut MaybeUninit<u8>`, and any byte is valid
+        unsafe { ptr::write_volatile(byte.as_mut_ptr(), 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+// A writer appending to a Vec, which grows it by copying into a larger allocation and wiping
+// the old one, where `Vec` itself would free the old allocation with the bytes still in it
+#[cfg(feature = "parse")]
+pub(crate) struct Wiping<'a>(pub(crate) &'a mut Vec<u8>);
+
+#[cfg(feature = "parse")]
+impl Write for Wiping<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let needed = self.0.len() + data.len();
+        if needed > self.0.capacity() {
+            let mut grown = Vec::with_capacity(needed.max(self.0.capacity() * 2));
+            grown.extend_from_slice(self.0);
+            wipe(self.0);
+            *self.0 = grown;
+        }
+        self.0.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+// Copyright 2016-2025 mime-multipart Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+// Runs the mime-multipart binary, as built with `--features cli`.
+
+use http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use mime_multipart_hyper1::{read_multipart, Node};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/request.txt");
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mime-multipart"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_split() {
+    let outdir = tempfile::tempdir().unwrap();
+    let output = run(&["split", "-o", outdir.path().to_str().unwrap(), FIXTURE]);
+    assert!(output.status.success());
+
+    let manifest = fs::read_to_string(outdir.path().join("manifest.txt")).unwrap();
+    assert_eq!(
+        manifest,
+        "[document]\n\
+         host: example.com\n\
+         content-type: multipart/form-data; boundary=AaB03x\n\
+         \n\
+         [part-001]\n\
+         content-disposition: form-data; name=\"title\"\n\
+         \n\
+         [part-002]\n\
+         content-disposition: form-data; name=\"file\"; filename=\"a.txt\"\n\
+         content-type: text/plain\n"
+    );
+    assert_eq!(fs::read(outdir.path().join("part-001")).unwrap(), b"Report");
+    assert_eq!(fs::read(outdir.path().join("part-002")).unwrap(), b"hello");
+}
+
+#[test]
+fn test_compose() {
+    let indir = tempfile::tempdir().unwrap();
+    fs::write(indir.path().join("a.txt"), "hello").unwrap();
+    fs::write(indir.path().join("b.csv"), "x,y").unwrap();
+    let manifest = indir.path().join("parts.manifest");
+    fs::write(
+        &manifest,
+        "# fixture\n\
+         a\";filename=\"evil.sh text/plain a.txt\n\
+         \n\
+         table - b.csv\n",
+    )
+    .unwrap();
+    let composed = indir.path().join("composed.txt");
+    let output = run(&[
+        "compose",
+        "-o",
+        composed.to_str().unwrap(),
+        manifest.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    let body = fs::read(&composed).unwrap();
+    assert!(body.starts_with(b"Content-Type: multipart/form-data; boundary="));
+    let (_, nodes) = read_multipart(&mut &body[..], false).unwrap();
+    let parts: Vec<[String; 3]> = nodes
+        .iter()
+        .map(|node| match node {
+            Node::File(filepart) => [
+                filepart.headers[CONTENT_DISPOSITION]
+                    .to_str()
+                    .unwrap()
+                    .to_owned(),
+                filepart
+                    .headers
+                    .get(CONTENT_TYPE)
+                    .map(|ct| ct.to_str().unwrap().to_owned())
+                    .unwrap_or_default(),
+                fs::read_to_string(&filepart.path).unwrap(),
+            ],
+            _ => panic!("Expected a file"),
+        })
+        .collect();
+    // Names are quoted, so that they cannot add parameters of their own
+    assert_eq!(
+        parts,
+        [
+            [
+                "form-data; name=\"a\\\";filename=\\\"evil.sh\"; filename=\"a.txt\"",
+                "text/plain",
+                "hello"
+            ],
+            ["form-data; name=\"table\"; filename=\"b.csv\"", "", "x,y"],
+        ]
+    );
+
+    // A directory gives a part for each file in it, named after the file
+    fs::remove_file(&manifest).unwrap();
+    fs::remove_file(&composed).unwrap();
+    let output = run(&[
+        "compose",
+        "-t",
+        "mixed",
+        "--body-only",
+        indir.path().to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"--"));
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.contains("content-disposition: attachment; filename=\"a.txt\"\r\n"));
+    assert!(text.contains("content-disposition: attachment; filename=\"b.csv\"\r\n"));
+}
+
+#[test]
+fn test_errors() {
+    // Usage errors exit with 2
+    let output = run(&[]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Usage:"));
+    assert_eq!(run(&["unknown"]).status.code(), Some(2));
+    assert_eq!(run(&["--help"]).status.code(), Some(0));
+
+    // Failures exit with 1, saying what went wrong
+    let missing = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/missing.txt");
+    let output = run(&["split", missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("mime-multipart: "));
+
+    let indir = tempfile::tempdir().unwrap();
+    let manifest = indir.path().join("parts.manifest");
+    fs::write(&manifest, "name-only\n").unwrap();
+    let output = run(&["compose", manifest.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "mime-multipart: manifest line 1: expected NAME TYPE FILE\n"
+    );
+
+    let output = run(&["split", "-o"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "mime-multipart: -o requires a directory\n"
+    );
+}